@@ -11,7 +11,9 @@ extern crate fsuipc;
 use std::io;
 use std::process;
 
+#[cfg(windows)]
 use fsuipc::user::*;
+#[cfg(windows)]
 use fsuipc::*;
 
 fn main() {
@@ -24,6 +26,14 @@ fn main() {
     }
 }
 
+#[cfg(not(windows))]
+fn run() -> io::Result<()> {
+    Err(io::Error::other(
+        "FSUIPC user mode is only available on Windows",
+    ))
+}
+
+#[cfg(windows)]
 fn run() -> io::Result<()> {
     let mut handle = UserHandle::new()?;
     let mut session = handle.session();
@@ -0,0 +1,164 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Derive macros for the `fsuipc` crate
+//! Use them through the `derive` feature of `fsuipc` rather than depending on this crate.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, LitInt, Token};
+
+/// Derive `fsuipc::FsuipcStruct` for a struct whose fields are mapped to offsets
+/// Each mapped field is annotated with `#[offset(0x0560)]`, or `#[offset(0x0560, len = 8)]` to
+/// transfer fewer bytes than the size of the field. Fields with no annotation are ignored.
+///
+/// The struct also gets an `apply(&fsuipc::offsets::RawOffsets)` method, which decodes the
+/// mapped fields from offsets already read, failing with `InvalidData` if any is missing.
+#[proc_macro_derive(FsuipcStruct, attributes(offset))]
+pub fn derive_fsuipc_struct(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+struct FieldOffset {
+    address: LitInt,
+    len: Option<Expr>,
+}
+
+impl syn::parse::Parse for FieldOffset {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let address = input.parse()?;
+        let mut len = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            let key: syn::Ident = input.parse()?;
+            if key != "len" {
+                return Err(syn::Error::new(key.span(), "expected `len = <bytes>`"));
+            }
+            input.parse::<Token![=]>()?;
+            len = Some(input.parse()?);
+        }
+        Ok(FieldOffset { address, len })
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    input,
+                    "FsuipcStruct requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "FsuipcStruct can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let mut applies = Vec::new();
+    let mut checks = Vec::new();
+    for field in fields.iter() {
+        let attr = match field.attrs.iter().find(|a| a.path().is_ident("offset")) {
+            Some(attr) => attr,
+            None => continue,
+        };
+        let FieldOffset { address, len } = attr.parse_args()?;
+        let name = &field.ident;
+        let ty = &field.ty;
+        let len = match len {
+            Some(len) => {
+                checks.push(quote! {
+                    assert!(
+                        #len <= ::std::mem::size_of::<#ty>(),
+                        "offset length exceeds the size of the field"
+                    );
+                });
+                quote! { #len }
+            }
+            None => quote! { ::std::mem::size_of::<#ty>() },
+        };
+        reads.push(quote! {
+            session.read_bytes(#address, &mut self.#name as *mut #ty as *mut u8, #len)?;
+        });
+        writes.push(quote! {
+            session.write_bytes(#address, &self.#name as *const #ty as *const u8, #len)?;
+        });
+        applies.push(quote! {
+            let bytes = raw.get(#address, #len).ok_or_else(|| {
+                ::std::io::Error::new(
+                    ::std::io::ErrorKind::InvalidData,
+                    format!("offset 0x{:04X} is not in the raw offsets", #address),
+                )
+            })?;
+            unsafe {
+                ::std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    &mut self.#name as *mut #ty as *mut u8,
+                    bytes.len(),
+                );
+            }
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // The lengths are checked in an associated const rather than in a free const item, which
+    // could not name the generic parameters of the struct. It is evaluated at compile time for
+    // every type the struct is used with.
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #[doc(hidden)]
+            const __FSUIPC_CHECK_LENGTHS: () = {
+                #(#checks)*
+            };
+
+            /// Decode the mapped fields from the given raw offsets.
+            pub fn apply(
+                &mut self,
+                raw: &::fsuipc::offsets::RawOffsets,
+            ) -> ::std::io::Result<()> {
+                let () = Self::__FSUIPC_CHECK_LENGTHS;
+                #(#applies)*
+                Ok(())
+            }
+        }
+
+        impl #impl_generics ::fsuipc::FsuipcStruct for #name #ty_generics #where_clause {
+            fn register_reads<S: ::fsuipc::Session>(
+                &mut self,
+                session: &mut S,
+            ) -> ::std::io::Result<()> {
+                let () = Self::__FSUIPC_CHECK_LENGTHS;
+                #(#reads)*
+                Ok(())
+            }
+
+            fn register_writes<S: ::fsuipc::Session>(
+                &self,
+                session: &mut S,
+            ) -> ::std::io::Result<()> {
+                let () = Self::__FSUIPC_CHECK_LENGTHS;
+                #(#writes)*
+                Ok(())
+            }
+        }
+    })
+}
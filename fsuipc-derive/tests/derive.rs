@@ -0,0 +1,106 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+
+use fsuipc::offsets::RawOffsets;
+use fsuipc::{FsuipcStruct, Session};
+
+/// A session that records the requested offsets and fills the reads with 0xAB bytes
+#[derive(Default)]
+struct RecordingSession {
+    reads: Vec<(u16, usize)>,
+    writes: Vec<(u16, Vec<u8>)>,
+}
+
+impl Session for RecordingSession {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.reads.push((offset, len));
+        for i in 0..len {
+            unsafe { *dest.add(i) = 0xAB };
+        }
+        Ok(len)
+    }
+
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        let bytes = unsafe { std::slice::from_raw_parts(src, len) };
+        self.writes.push((offset, bytes.to_vec()));
+        Ok(len)
+    }
+
+    fn process(self) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+#[derive(Default, FsuipcStruct)]
+struct Aircraft {
+    #[offset(0x0560)]
+    latitude: i64,
+    #[offset(0x0238)]
+    hour: u8,
+    #[offset(0x3D00, len = 4)]
+    title: [u8; 8],
+    not_mapped: u32,
+}
+
+#[test]
+fn should_register_reads_of_mapped_fields() {
+    let mut aircraft = Aircraft::default();
+    let mut session = RecordingSession::default();
+    aircraft.register_reads(&mut session).unwrap();
+    assert_eq!(session.reads, vec![(0x0560, 8), (0x0238, 1), (0x3D00, 4)]);
+    assert_eq!(aircraft.hour, 0xAB);
+    assert_eq!(aircraft.title, [0xAB, 0xAB, 0xAB, 0xAB, 0, 0, 0, 0]);
+    assert_eq!(aircraft.not_mapped, 0);
+}
+
+#[test]
+fn should_register_writes_of_mapped_fields() {
+    let aircraft = Aircraft {
+        hour: 12,
+        title: *b"B737-800",
+        ..Aircraft::default()
+    };
+    let mut session = RecordingSession::default();
+    aircraft.register_writes(&mut session).unwrap();
+    assert_eq!(session.writes[1], (0x0238, vec![12]));
+    assert_eq!(session.writes[2], (0x3D00, b"B737".to_vec()));
+}
+
+#[test]
+fn should_apply_raw_offsets() {
+    let mut bytes = vec![0u8; 0x3D08 - 0x0238];
+    bytes[0] = 12;
+    bytes[0x0560 - 0x0238..0x0568 - 0x0238].copy_from_slice(&42i64.to_le_bytes());
+    bytes[0x3D00 - 0x0238..0x3D08 - 0x0238].copy_from_slice(b"A320-200");
+    let mut aircraft = Aircraft::default();
+    aircraft.apply(&RawOffsets::new(0x0238, &bytes)).unwrap();
+    assert_eq!(aircraft.latitude, 42);
+    assert_eq!(aircraft.hour, 12);
+    assert_eq!(aircraft.title, *b"A320\0\0\0\0");
+
+    let error = aircraft
+        .apply(&RawOffsets::new(0x0560, &bytes[0x0560 - 0x0238..]))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[derive(Default, FsuipcStruct)]
+struct Position<T: Copy + Default> {
+    #[offset(0x0560, len = 8)]
+    latitude: T,
+}
+
+#[test]
+fn should_derive_generic_structs() {
+    let mut position = Position::<i64>::default();
+    let mut session = RecordingSession::default();
+    position.register_reads(&mut session).unwrap();
+    assert_eq!(session.reads, vec![(0x0560, 8)]);
+}
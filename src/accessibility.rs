@@ -0,0 +1,293 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A textual state summary for screen readers
+//! `FlightState::describe()` puts the selected instruments into plain sentences, such as
+//! "Altitude 3500 feet. Heading 270.", which a screen reader can speak as they are. The
+//! summary can be served by `SummaryServer` as plain text over HTTP, so any tool of the pilot
+//! (a browser, a script around `curl`, an add-on of the screen reader) can fetch it.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::cancel::CancellationToken;
+use super::geo::{altitude_metres, FEET_PER_METRE};
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An instrument that can be part of the summary
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instrument {
+    Altitude,
+    Airspeed,
+    Heading,
+    VerticalSpeed,
+    Gear,
+    Flaps,
+    Autopilot,
+}
+
+impl Instrument {
+    /// The instruments of the default summary.
+    pub const ALL: [Instrument; 7] = [
+        Instrument::Altitude,
+        Instrument::Airspeed,
+        Instrument::Heading,
+        Instrument::VerticalSpeed,
+        Instrument::Gear,
+        Instrument::Flaps,
+        Instrument::Autopilot,
+    ];
+}
+
+/// The flight data the summary is made of
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlightState {
+    pub altitude: i64,
+    pub indicated_airspeed: u32,
+    pub heading: u32,
+    pub magnetic_variation: i16,
+    pub vertical_speed: i32,
+    pub gear: u32,
+    pub flaps: u32,
+    pub autopilot: u32,
+    pub on_ground: u16,
+}
+
+impl FlightState {
+    /// The summary of the given instruments, a sentence each.
+    /// The numbers are rounded to what a pilot would read aloud: altitudes to 10 feet and
+    /// vertical speeds to 50 feet per minute.
+    pub fn describe(&self, instruments: &[Instrument]) -> String {
+        let sentences: Vec<String> = instruments
+            .iter()
+            .filter_map(|instrument| self.sentence(*instrument))
+            .collect();
+        sentences.join(" ")
+    }
+
+    fn sentence(&self, instrument: Instrument) -> Option<String> {
+        let sentence = match instrument {
+            Instrument::Altitude => {
+                let feet = altitude_metres(self.altitude) * FEET_PER_METRE;
+                format!("Altitude {} feet.", round_to(feet, 10.0))
+            }
+            Instrument::Airspeed => {
+                let knots = self.indicated_airspeed as f64 / 128.0;
+                format!("Airspeed {} knots.", knots.round())
+            }
+            Instrument::Heading => {
+                let heading = self.heading as f64 * 360.0 / 65536.0 / 65536.0
+                    - self.magnetic_variation as f64 * 360.0 / 65536.0;
+                let heading = (heading.round() as i64).rem_euclid(360);
+                format!("Heading {:03}.", if heading == 0 { 360 } else { heading })
+            }
+            Instrument::VerticalSpeed => {
+                if self.on_ground != 0 {
+                    return None;
+                }
+                let fpm = self.vertical_speed as f64 / 256.0 * FEET_PER_METRE * 60.0;
+                let fpm = round_to(fpm, 50.0);
+                match fpm {
+                    0 => "Level.".to_string(),
+                    fpm if fpm > 0 => format!("Climbing {} feet per minute.", fpm),
+                    fpm => format!("Descending {} feet per minute.", -fpm),
+                }
+            }
+            Instrument::Gear => match self.gear {
+                0 => "Gear up.".to_string(),
+                16383 => "Gear down.".to_string(),
+                _ => "Gear in transit.".to_string(),
+            },
+            Instrument::Flaps => match self.flaps {
+                0 => "Flaps up.".to_string(),
+                flaps => format!("Flaps {} percent.", flaps * 100 / 16383),
+            },
+            Instrument::Autopilot => match self.autopilot {
+                0 => "Autopilot off.".to_string(),
+                _ => "Autopilot on.".to_string(),
+            },
+        };
+        Some(sentence)
+    }
+}
+
+fn round_to(value: f64, step: f64) -> i64 {
+    ((value / step).round() * step) as i64
+}
+
+impl FsuipcStruct for FlightState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::ALTITUDE, &mut self.altitude)?;
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(offsets::HEADING, &mut self.heading)?;
+        session.read_offset(offsets::MAGNETIC_VARIATION, &mut self.magnetic_variation)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::GEAR_CONTROL, &mut self.gear)?;
+        session.read_offset(offsets::FLAPS_CONTROL, &mut self.flaps)?;
+        session.read_offset(offsets::AUTOPILOT_MASTER, &mut self.autopilot)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An HTTP server of the summary as plain text
+/// It serves the last summary given to `publish()` at any path from a worker thread, until
+/// dropped.
+pub struct SummaryServer {
+    address: SocketAddr,
+    summary: Arc<Mutex<String>>,
+    cancel: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SummaryServer {
+    /// Serve the summary at the given address.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let summary = Arc::new(Mutex::new("No flight data yet.".to_string()));
+        let cancel = CancellationToken::new();
+        let thread_summary = summary.clone();
+        let thread_cancel = cancel.clone();
+        let thread = thread::Builder::new()
+            .name("fsuipc-summary".to_string())
+            .spawn(move || serve(listener, thread_summary, thread_cancel))?;
+        Ok(SummaryServer {
+            address,
+            summary,
+            cancel,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop serving when the given token is cancelled.
+    pub fn with_cancellation(self, token: &CancellationToken) -> Self {
+        token.attach(&self.cancel);
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Publish a new summary.
+    pub fn publish(&self, summary: &str) {
+        *self.summary.lock().unwrap() = summary.to_string();
+    }
+}
+
+impl Drop for SummaryServer {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, summary: Arc<Mutex<String>>, cancel: CancellationToken) {
+    while !cancel.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // A misbehaving client must not bring the server down.
+                let _ = respond(stream, &summary);
+            }
+            Err(_) => {
+                cancel.wait_timeout(ACCEPT_INTERVAL);
+            }
+        }
+    }
+}
+
+fn respond(stream: TcpStream, summary: &Mutex<String>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(stream);
+    // Skip the request, up to the empty line.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let body = format!("{}\n", summary.lock().unwrap());
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::io::Read;
+
+    fn cruise() -> FlightState {
+        FlightState {
+            altitude: (3504.0 / FEET_PER_METRE * 4_294_967_296.0) as i64,
+            indicated_airspeed: 120 * 128,
+            heading: (272.0 / 360.0 * 4_294_967_296.0) as u32,
+            magnetic_variation: (2.0 / 360.0 * 65536.0) as i16,
+            vertical_speed: (-510.0 / FEET_PER_METRE / 60.0 * 256.0) as i32,
+            gear: 16383,
+            flaps: 8192,
+            autopilot: 1,
+            on_ground: 0,
+        }
+    }
+
+    #[test]
+    fn should_describe_instruments() {
+        assert_eq!(
+            cruise().describe(&Instrument::ALL),
+            "Altitude 3500 feet. Airspeed 120 knots. Heading 270. \
+             Descending 500 feet per minute. Gear down. Flaps 50 percent. Autopilot on."
+        );
+        let parked = FlightState {
+            on_ground: 1,
+            heading: 0,
+            magnetic_variation: 0,
+            ..cruise()
+        };
+        assert_eq!(
+            parked.describe(&[Instrument::Heading, Instrument::VerticalSpeed]),
+            "Heading 360."
+        );
+    }
+
+    #[test]
+    fn should_serve_summary() {
+        let server = SummaryServer::bind("127.0.0.1:0").unwrap();
+        server.publish("Altitude 3500 feet.");
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nAltitude 3500 feet.\n"));
+    }
+}
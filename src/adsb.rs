@@ -0,0 +1,287 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An ADS-B style feed of the own aircraft
+//! `FeedServer` serves the aircraft in the `aircraft.json` format of dump1090 and readsb over
+//! HTTP, so ADS-B map frontends (e.g. tar1090) can display it with no custom code. Point the
+//! frontend to `http://<address>/data/aircraft.json`.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::cancel::CancellationToken;
+use super::geo::{Position, RawPosition, FEET_PER_METRE, KNOTS_PER_METRE_PER_SECOND, POSITION};
+use super::offsets::{self, FsuipcStruct};
+use super::strings;
+use super::units::bcd;
+use super::Session;
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The data of the own aircraft published in the feed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OwnShip {
+    pub position: RawPosition,
+    pub heading: u32,
+    pub ground_speed: u32,
+    pub vertical_speed: i32,
+    pub transponder: u16,
+    pub atc_id: [u8; 12],
+}
+
+impl OwnShip {
+    /// The callsign, from the ATC identifier.
+    pub fn callsign(&self) -> String {
+        strings::decode(&self.atc_id)
+    }
+
+    /// Format the aircraft as the `aircraft.json` document of dump1090, with the given
+    /// 24-bit address and time.
+    /// The track is the true heading, since FSUIPC gives no ground track.
+    pub fn aircraft_json(&self, hex: u32, now: SystemTime) -> String {
+        let position = Position::from(self.position);
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"now\":{:.1},\"messages\":0,\"aircraft\":[{{\"hex\":\"{:06x}\",\"flight\":\"{}\",\
+             \"alt_baro\":{:.0},\"gs\":{:.1},\"track\":{:.1},\"baro_rate\":{:.0},",
+            now,
+            hex & 0xFF_FFFF,
+            escape(&self.callsign()),
+            position.altitude_feet(),
+            self.ground_speed as f64 / 65536.0 * KNOTS_PER_METRE_PER_SECOND,
+            self.heading as f64 * 360.0 / 65536.0 / 65536.0,
+            self.vertical_speed as f64 / 256.0 * FEET_PER_METRE * 60.0,
+        );
+        if let Some(squawk) = bcd::decode(self.transponder) {
+            let _ = write!(json, "\"squawk\":\"{:04}\",", squawk);
+        }
+        let _ = write!(
+            json,
+            "\"lat\":{:.6},\"lon\":{:.6},\"seen\":0,\"seen_pos\":0,\"messages\":0,\"rssi\":0}}]}}",
+            position.latitude, position.longitude,
+        );
+        json
+    }
+}
+
+impl FsuipcStruct for OwnShip {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(POSITION, &mut self.position)?;
+        session.read_offset(offsets::HEADING, &mut self.heading)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::TRANSPONDER_CODE, &mut self.transponder)?;
+        session.read_offset(offsets::ATC_ID, &mut self.atc_id)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An HTTP server of the `aircraft.json` feed
+/// It serves the last aircraft given to `publish()` from a worker thread, until dropped.
+pub struct FeedServer {
+    hex: u32,
+    address: SocketAddr,
+    document: Arc<Mutex<String>>,
+    cancel: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FeedServer {
+    /// Serve the feed at the given address.
+    /// `hex` is the 24-bit ICAO address of the aircraft in the feed.
+    pub fn bind<A: ToSocketAddrs>(address: A, hex: u32) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let document = Arc::new(Mutex::new(empty_document(SystemTime::now())));
+        let cancel = CancellationToken::new();
+        let thread_document = document.clone();
+        let thread_cancel = cancel.clone();
+        let thread = thread::Builder::new()
+            .name("fsuipc-adsb-feed".to_string())
+            .spawn(move || serve(listener, thread_document, thread_cancel))?;
+        Ok(FeedServer {
+            hex,
+            address,
+            document,
+            cancel,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop serving when the given token is cancelled.
+    pub fn with_cancellation(self, token: &CancellationToken) -> Self {
+        token.attach(&self.cancel);
+        self
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Publish the current state of the aircraft.
+    pub fn publish(&self, own_ship: &OwnShip) {
+        let document = own_ship.aircraft_json(self.hex, SystemTime::now());
+        *self.document.lock().unwrap() = document;
+    }
+}
+
+impl Drop for FeedServer {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, document: Arc<Mutex<String>>, cancel: CancellationToken) {
+    while !cancel.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // A misbehaving client must not bring the feed down.
+                let _ = respond(stream, &document);
+            }
+            Err(_) => {
+                cancel.wait_timeout(ACCEPT_INTERVAL);
+            }
+        }
+    }
+}
+
+fn respond(stream: TcpStream, document: &Mutex<String>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers, up to the empty line.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next(), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    let mut stream = reader.into_inner();
+    if method == Some("GET") && path.ends_with("/aircraft.json") {
+        let body = document.lock().unwrap().clone();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+    } else {
+        write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+    }
+    stream.flush()
+}
+
+fn empty_document(now: SystemTime) -> String {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    format!("{{\"now\":{:.1},\"messages\":0,\"aircraft\":[]}}", now)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::Read;
+
+    use super::*;
+
+    fn own_ship() -> OwnShip {
+        let mut atc_id = [0; 12];
+        atc_id[..6].copy_from_slice(b"EC-ABC");
+        OwnShip {
+            position: Position {
+                latitude: 40.4719,
+                longitude: -3.5626,
+                altitude: 3048.0,
+            }
+            .to_raw(),
+            heading: 1 << 30,
+            ground_speed: (250.0 / KNOTS_PER_METRE_PER_SECOND * 65536.0) as u32,
+            vertical_speed: 0,
+            transponder: 0x7000,
+            atc_id,
+        }
+    }
+
+    #[test]
+    fn should_format_aircraft_json() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let json = own_ship().aircraft_json(0xABC123, now);
+        assert_eq!(
+            json,
+            "{\"now\":1700000000.0,\"messages\":0,\"aircraft\":[{\"hex\":\"abc123\",\
+             \"flight\":\"EC-ABC\",\"alt_baro\":10000,\"gs\":250.0,\"track\":90.0,\
+             \"baro_rate\":0,\"squawk\":\"7000\",\"lat\":40.471900,\"lon\":-3.562600,\
+             \"seen\":0,\"seen_pos\":0,\"messages\":0,\"rssi\":0}]}"
+        );
+    }
+
+    #[test]
+    fn should_escape_callsign() {
+        assert_eq!(escape("a\"b\\\n"), "a\\\"b\\\\\\u000a");
+    }
+
+    #[test]
+    fn should_serve_published_aircraft() {
+        let server = FeedServer::bind("127.0.0.1:0", 0xABC123).unwrap();
+        server.publish(&own_ship());
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/data/aircraft.json");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\"flight\":\"EC-ABC\""));
+        assert!(get("/other").starts_with("HTTP/1.1 404"));
+    }
+}
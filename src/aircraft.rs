@@ -0,0 +1,127 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A snapshot of the attitude, position and speeds of the aircraft
+//! `AircraftState` registers all its offsets in a single session, so they are read in the same
+//! transaction and describe the aircraft at the same instant. It is usually read through
+//! `Session::read_aircraft_state()`, and decoded once the session is processed.
+
+use std::io;
+
+use super::geo::{self, Position, RawPosition, POSITION};
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The attitude, position and speeds of the aircraft
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AircraftState {
+    position: RawPosition,
+    pitch: i32,
+    bank: i32,
+    heading: u32,
+    magnetic_variation: i16,
+    ground_speed: u32,
+    true_airspeed: u32,
+    indicated_airspeed: u32,
+}
+
+impl AircraftState {
+    /// The pitch, in degrees (positive nose up).
+    pub fn pitch(&self) -> f64 {
+        geo::pitch_degrees(self.pitch)
+    }
+
+    /// The bank, in degrees (positive right wing down).
+    pub fn bank(&self) -> f64 {
+        geo::bank_degrees(self.bank)
+    }
+
+    /// The true heading, in degrees.
+    pub fn true_heading(&self) -> f64 {
+        geo::heading_degrees(self.heading)
+    }
+
+    /// The magnetic heading, in degrees.
+    pub fn magnetic_heading(&self) -> f64 {
+        let variation = self.magnetic_variation as f64 * 360.0 / 65536.0;
+        (self.true_heading() - variation).rem_euclid(360.0)
+    }
+
+    /// The latitude and longitude, in degrees, and the altitude, in metres.
+    pub fn position(&self) -> Position {
+        Position::from(self.position)
+    }
+
+    /// The ground speed, in knots.
+    pub fn ground_speed(&self) -> f64 {
+        self.ground_speed as f64 / 65536.0 * 3600.0 / 1852.0
+    }
+
+    /// The true airspeed, in knots.
+    pub fn true_airspeed(&self) -> f64 {
+        self.true_airspeed as f64 / 128.0
+    }
+
+    /// The indicated airspeed, in knots.
+    pub fn indicated_airspeed(&self) -> f64 {
+        self.indicated_airspeed as f64 / 128.0
+    }
+}
+
+impl FsuipcStruct for AircraftState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(POSITION, &mut self.position)?;
+        session.read_offset(offsets::PITCH, &mut self.pitch)?;
+        session.read_offset(offsets::BANK, &mut self.bank)?;
+        session.read_offset(offsets::HEADING, &mut self.heading)?;
+        session.read_offset(offsets::MAGNETIC_VARIATION, &mut self.magnetic_variation)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::TRUE_AIRSPEED, &mut self.true_airspeed)?;
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_read_aircraft_state() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0560, &((10_001_750.0 * 65536.0 * 65536.0 / 2.0) as i64));
+        handle.seed_value(0x0578, &(-(geo::ANGLE_UNITS_PER_DEGREE * 5.0) as i32));
+        handle.seed_value(0x057C, &(-(geo::ANGLE_UNITS_PER_DEGREE * 20.0) as i32));
+        handle.seed_value(0x0580, &((geo::ANGLE_UNITS_PER_DEGREE * 10.0) as u32));
+        handle.seed_value(0x02A0, &(-(65536 / 360 * 15) as i16));
+        handle.seed_value(0x02B4, &((250.0 * 1852.0 / 3600.0 * 65536.0) as u32));
+        handle.seed_value(0x02B8, &(280u32 * 128));
+        handle.seed_value(0x02BC, &(240u32 * 128));
+        let mut state = AircraftState::default();
+        {
+            let mut session = handle.session();
+            session.read_aircraft_state(&mut state).unwrap();
+            session.process().unwrap();
+        }
+        assert!((state.pitch() - 5.0).abs() < 1e-6);
+        assert!((state.bank() - 20.0).abs() < 1e-6);
+        assert!((state.true_heading() - 10.0).abs() < 1e-6);
+        assert!((state.magnetic_heading() - 25.0).abs() < 0.1);
+        assert!((state.position().latitude - 45.0).abs() < 1e-6);
+        assert!((state.ground_speed() - 250.0).abs() < 0.01);
+        assert_eq!(state.true_airspeed(), 280.0);
+        assert_eq!(state.indicated_airspeed(), 240.0);
+    }
+}
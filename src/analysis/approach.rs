@@ -0,0 +1,285 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+
+use crate::geo::{altitude_metres, FEET_PER_METRE};
+use crate::offsets::{self, FsuipcStruct};
+use crate::Session;
+
+const GEAR_DOWN: u32 = 16383;
+const GLIDESLOPE_FULL_SCALE: f64 = 119.0;
+
+/// The criteria of a stabilized approach
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ApproachCriteria {
+    /// The height above ground below which the approach must be stabilized, in feet.
+    pub gate_height: f64,
+    /// The approach speed, in knots.
+    pub target_speed: f64,
+    /// The tolerance below the approach speed, in knots.
+    pub speed_below: f64,
+    /// The tolerance above the approach speed, in knots.
+    pub speed_above: f64,
+    /// The maximum sink rate, in feet per minute.
+    pub max_sink_rate: f64,
+    /// The maximum glideslope deflection, as a fraction of full scale.
+    pub max_glideslope_deflection: f64,
+    /// The minimum flaps control position (from 0 to 16383) for the landing configuration.
+    pub min_flaps: u32,
+}
+
+impl ApproachCriteria {
+    /// The usual airline criteria for the given approach speed: stabilized at 1000 ft AGL,
+    /// from -5 to +10 knots, sink rate up to 1000 fpm, within 1 dot of glideslope and with
+    /// gear and some flaps extended.
+    pub fn with_speed(target_speed: f64) -> Self {
+        ApproachCriteria {
+            gate_height: 1000.0,
+            target_speed,
+            speed_below: 5.0,
+            speed_above: 10.0,
+            max_sink_rate: 1000.0,
+            max_glideslope_deflection: 0.5,
+            min_flaps: 1,
+        }
+    }
+}
+
+/// A criterion of a stabilized approach that was not met
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Violation {
+    Speed,
+    SinkRate,
+    Glidepath,
+    Configuration,
+}
+
+/// The final verdict of an approach, given at touchdown
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApproachVerdict {
+    /// Whether all the criteria were met all the way from the gate to touchdown.
+    pub stabilized: bool,
+    /// The percentage of samples below the gate meeting all the criteria.
+    pub score: f64,
+    /// The number of samples below the gate violating each criterion.
+    pub violations: Vec<(Violation, usize)>,
+}
+
+/// The flight data sampled by the stabilized approach monitor
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApproachSample {
+    pub indicated_airspeed: u32,
+    pub vertical_speed: i32,
+    pub altitude: i64,
+    pub ground_altitude: i32,
+    pub glideslope: i8,
+    pub gear: u32,
+    pub flaps: u32,
+    pub on_ground: u16,
+}
+
+impl ApproachSample {
+    /// The indicated airspeed, in knots.
+    pub fn speed(&self) -> f64 {
+        self.indicated_airspeed as f64 / 128.0
+    }
+
+    /// The sink rate, in feet per minute (positive when descending).
+    pub fn sink_rate(&self) -> f64 {
+        -(self.vertical_speed as f64 / 256.0) * FEET_PER_METRE * 60.0
+    }
+
+    /// The height above ground, in feet.
+    pub fn height(&self) -> f64 {
+        let altitude = altitude_metres(self.altitude);
+        let ground = self.ground_altitude as f64 / 256.0;
+        (altitude - ground) * FEET_PER_METRE
+    }
+}
+
+impl FsuipcStruct for ApproachSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::ALTITUDE, &mut self.altitude)?;
+        session.read_offset(offsets::GROUND_ALTITUDE, &mut self.ground_altitude)?;
+        session.read_offset(offsets::NAV1_GLIDESLOPE_NEEDLE, &mut self.glideslope)?;
+        session.read_offset(offsets::GEAR_CONTROL, &mut self.gear)?;
+        session.read_offset(offsets::FLAPS_CONTROL, &mut self.flaps)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A monitor of stabilized approaches
+/// It scores every sample taken below the gate height while airborne, and gives its verdict
+/// when the aircraft touches down. It is then ready for the next approach.
+#[derive(Clone, Debug)]
+pub struct StabilizedApproach {
+    criteria: ApproachCriteria,
+    samples: usize,
+    stabilized: usize,
+    violations: Vec<(Violation, usize)>,
+    current: Vec<Violation>,
+}
+
+impl StabilizedApproach {
+    pub fn new(criteria: ApproachCriteria) -> Self {
+        StabilizedApproach {
+            criteria,
+            samples: 0,
+            stabilized: 0,
+            violations: Vec::new(),
+            current: Vec::new(),
+        }
+    }
+
+    /// Feed a new sample, obtaining the verdict on touchdown.
+    pub fn update(&mut self, sample: &ApproachSample) -> Option<ApproachVerdict> {
+        if sample.on_ground != 0 {
+            if self.samples == 0 {
+                return None;
+            }
+            let verdict = ApproachVerdict {
+                stabilized: self.stabilized == self.samples,
+                score: self.score(),
+                violations: self.violations.clone(),
+            };
+            *self = StabilizedApproach::new(self.criteria);
+            return Some(verdict);
+        }
+        self.current = self.check(sample);
+        if sample.height() <= self.criteria.gate_height {
+            self.samples += 1;
+            if self.current.is_empty() {
+                self.stabilized += 1;
+            }
+            for violation in self.current.iter() {
+                match self.violations.iter_mut().find(|(v, _)| v == violation) {
+                    Some((_, count)) => *count += 1,
+                    None => self.violations.push((*violation, 1)),
+                }
+            }
+        }
+        None
+    }
+
+    /// The percentage of samples below the gate meeting all the criteria so far.
+    pub fn score(&self) -> f64 {
+        if self.samples == 0 {
+            100.0
+        } else {
+            self.stabilized as f64 * 100.0 / self.samples as f64
+        }
+    }
+
+    /// The criteria violated by the last sample, whether below the gate or not.
+    pub fn violations(&self) -> &[Violation] {
+        &self.current
+    }
+
+    fn check(&self, sample: &ApproachSample) -> Vec<Violation> {
+        let criteria = &self.criteria;
+        let mut violations = Vec::new();
+        let speed = sample.speed() - criteria.target_speed;
+        if speed < -criteria.speed_below || speed > criteria.speed_above {
+            violations.push(Violation::Speed);
+        }
+        if sample.sink_rate() > criteria.max_sink_rate {
+            violations.push(Violation::SinkRate);
+        }
+        let glideslope = (sample.glideslope as f64 / GLIDESLOPE_FULL_SCALE).abs();
+        if glideslope > criteria.max_glideslope_deflection {
+            violations.push(Violation::Glidepath);
+        }
+        if sample.gear < GEAR_DOWN || sample.flaps < criteria.min_flaps {
+            violations.push(Violation::Configuration);
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn sample(height: f64, speed: f64, sink_rate: f64) -> ApproachSample {
+        ApproachSample {
+            indicated_airspeed: (speed * 128.0) as u32,
+            vertical_speed: (-sink_rate / FEET_PER_METRE / 60.0 * 256.0) as i32,
+            altitude: (height / FEET_PER_METRE * 4_294_967_296.0) as i64,
+            ground_altitude: 0,
+            glideslope: 0,
+            gear: GEAR_DOWN,
+            flaps: 8192,
+            on_ground: 0,
+        }
+    }
+
+    fn touchdown() -> ApproachSample {
+        ApproachSample {
+            on_ground: 1,
+            ..sample(0.0, 135.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn should_convert_sample_units() {
+        let s = sample(500.0, 140.0, 700.0);
+        assert!((s.height() - 500.0).abs() < 0.1);
+        assert!((s.speed() - 140.0).abs() < 0.01);
+        assert!((s.sink_rate() - 700.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn should_pass_stabilized_approach() {
+        let mut monitor = StabilizedApproach::new(ApproachCriteria::with_speed(140.0));
+        assert_eq!(monitor.update(&sample(3000.0, 200.0, 2000.0)), None);
+        assert_eq!(monitor.update(&sample(900.0, 142.0, 750.0)), None);
+        assert_eq!(monitor.update(&sample(100.0, 138.0, 700.0)), None);
+        let verdict = monitor.update(&touchdown()).unwrap();
+        assert!(verdict.stabilized);
+        assert_eq!(verdict.score, 100.0);
+        assert!(verdict.violations.is_empty());
+    }
+
+    #[test]
+    fn should_score_unstabilized_approach() {
+        let mut monitor = StabilizedApproach::new(ApproachCriteria::with_speed(140.0));
+        monitor.update(&sample(900.0, 160.0, 1400.0));
+        assert_eq!(
+            monitor.violations(),
+            &[Violation::Speed, Violation::SinkRate]
+        );
+        let gear_up = ApproachSample {
+            gear: 0,
+            ..sample(500.0, 140.0, 700.0)
+        };
+        monitor.update(&gear_up);
+        monitor.update(&sample(100.0, 140.0, 700.0));
+        monitor.update(&sample(50.0, 140.0, 700.0));
+        assert_eq!(monitor.score(), 50.0);
+        let verdict = monitor.update(&touchdown()).unwrap();
+        assert!(!verdict.stabilized);
+        assert_eq!(
+            verdict.violations,
+            vec![
+                (Violation::Speed, 1),
+                (Violation::SinkRate, 1),
+                (Violation::Configuration, 1)
+            ]
+        );
+        assert_eq!(monitor.update(&touchdown()), None);
+    }
+}
@@ -0,0 +1,222 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+
+use crate::offsets::{self, FsuipcStruct};
+use crate::Session;
+
+/// The full deflection of the control axes, as reported by FSUIPC.
+const FULL_DEFLECTION: i32 = 16383;
+
+/// A flight control axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Elevator,
+    Aileron,
+    Rudder,
+}
+
+/// A direction of deflection of a control axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Negative,
+    Positive,
+}
+
+/// The result of a control check
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlCheckEvent {
+    /// All the axes were swept in both directions while on ground.
+    Passed,
+    /// The aircraft took off without sweeping the given axes and directions.
+    Failed { missing: Vec<(Axis, Direction)> },
+}
+
+/// The control inputs sampled for the control check
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ControlSample {
+    pub elevator: i16,
+    pub aileron: i16,
+    pub rudder: i16,
+    pub on_ground: u16,
+}
+
+impl FsuipcStruct for ControlSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::ELEVATOR_CONTROL, &mut self.elevator)?;
+        session.read_offset(offsets::AILERON_CONTROL, &mut self.aileron)?;
+        session.read_offset(offsets::RUDDER_CONTROL, &mut self.rudder)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A detector of the flight control check performed before takeoff
+/// The check passes once every axis has been deflected beyond the threshold in both
+/// directions while on ground. It fails if the aircraft takes off before. A detector first fed
+/// an airborne sample waits for the aircraft to be on ground. After landing the detector
+/// starts over for the next takeoff.
+#[derive(Clone, Debug)]
+pub struct ControlCheck {
+    threshold: i32,
+    reached: [[bool; 2]; 3],
+    reported: bool,
+    airborne: bool,
+    ground_seen: bool,
+}
+
+impl ControlCheck {
+    /// Create a new detector requiring the given fraction of full deflection (e.g. 0.9).
+    pub fn new(threshold: f64) -> Self {
+        ControlCheck {
+            threshold: (threshold.clamp(0.0, 1.0) * FULL_DEFLECTION as f64) as i32,
+            reached: [[false; 2]; 3],
+            reported: false,
+            airborne: false,
+            ground_seen: false,
+        }
+    }
+
+    /// Feed a new sample, obtaining the result of the check when it is known.
+    pub fn update(&mut self, sample: &ControlSample) -> Option<ControlCheckEvent> {
+        let on_ground = sample.on_ground != 0;
+        if on_ground && self.airborne {
+            self.reached = [[false; 2]; 3];
+            self.reported = false;
+        }
+        self.airborne = !on_ground;
+        self.ground_seen |= on_ground;
+        if self.reported || !self.ground_seen {
+            return None;
+        }
+        if !on_ground {
+            self.reported = true;
+            return Some(ControlCheckEvent::Failed {
+                missing: self.missing(),
+            });
+        }
+        let values = [sample.elevator, sample.aileron, sample.rudder];
+        for (reached, value) in self.reached.iter_mut().zip(values.iter()) {
+            let value = *value as i32;
+            reached[0] |= value <= -self.threshold;
+            reached[1] |= value >= self.threshold;
+        }
+        if self.missing().is_empty() {
+            self.reported = true;
+            return Some(ControlCheckEvent::Passed);
+        }
+        None
+    }
+
+    /// The axes and directions not swept yet.
+    pub fn missing(&self) -> Vec<(Axis, Direction)> {
+        let axes = [Axis::Elevator, Axis::Aileron, Axis::Rudder];
+        let directions = [Direction::Negative, Direction::Positive];
+        let mut missing = Vec::new();
+        for (axis, reached) in axes.iter().zip(self.reached.iter()) {
+            for (direction, reached) in directions.iter().zip(reached.iter()) {
+                if !reached {
+                    missing.push((*axis, *direction));
+                }
+            }
+        }
+        missing
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    fn sample(elevator: i16, aileron: i16, rudder: i16, on_ground: bool) -> ControlSample {
+        ControlSample {
+            elevator,
+            aileron,
+            rudder,
+            on_ground: on_ground as u16,
+        }
+    }
+
+    #[test]
+    fn should_pass_after_full_sweeps() {
+        let mut check = ControlCheck::new(0.9);
+        let sweeps = [
+            sample(16383, 0, 0, true),
+            sample(-16383, 0, 0, true),
+            sample(0, 15000, 0, true),
+            sample(0, -15000, 0, true),
+            sample(0, 0, 16000, true),
+        ];
+        for s in sweeps.iter() {
+            assert_eq!(check.update(s), None);
+        }
+        assert_eq!(
+            check.update(&sample(0, 0, -16000, true)),
+            Some(ControlCheckEvent::Passed)
+        );
+        assert_eq!(check.update(&sample(0, 0, 0, false)), None);
+    }
+
+    #[test]
+    fn should_fail_on_takeoff_without_sweeps() {
+        let mut check = ControlCheck::new(0.9);
+        check.update(&sample(16383, -16383, 0, true));
+        check.update(&sample(-16383, 16383, 0, true));
+        assert_eq!(
+            check.update(&sample(0, 0, 0, false)),
+            Some(ControlCheckEvent::Failed {
+                missing: vec![
+                    (Axis::Rudder, Direction::Negative),
+                    (Axis::Rudder, Direction::Positive)
+                ]
+            })
+        );
+        assert_eq!(check.update(&sample(0, 0, 0, false)), None);
+    }
+
+    #[test]
+    fn should_start_over_after_landing() {
+        let mut check = ControlCheck::new(0.9);
+        check.update(&sample(16383, 0, 0, true));
+        check.update(&sample(0, 0, 0, false));
+        check.update(&sample(0, 0, 0, true));
+        assert_eq!(check.missing().len(), 6);
+    }
+
+    #[test]
+    fn should_wait_for_ground_when_started_airborne() {
+        let mut check = ControlCheck::new(0.9);
+        assert_eq!(check.update(&sample(0, 0, 0, false)), None);
+        check.update(&sample(0, 0, 0, true));
+        assert!(matches!(
+            check.update(&sample(0, 0, 0, false)),
+            Some(ControlCheckEvent::Failed { .. })
+        ));
+    }
+
+    #[test]
+    fn should_not_write_controls() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            sample(16383, 0, 0, true)
+                .register_writes(&mut session)
+                .unwrap();
+            session.process().unwrap();
+        }
+        assert!(handle.writes().is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::time::Duration;
+
+use crate::geo::{self, altitude_metres, FEET_PER_METRE};
+use crate::offsets::{self, FsuipcStruct};
+use crate::Session;
+
+const LOCALIZER_FULL_SCALE: f64 = 127.0;
+const GLIDESLOPE_FULL_SCALE: f64 = 119.0;
+/// The height below which the pitch is watched for the flare, in feet.
+const FLARE_WINDOW: f64 = 100.0;
+/// The nose up pitch over the approach pitch that marks the flare, in degrees.
+const FLARE_PITCH_INCREASE: f64 = 1.0;
+
+/// The flight data sampled by the landing capture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LandingSample {
+    pub altitude: i64,
+    pub ground_altitude: i32,
+    pub vertical_speed: i32,
+    pub pitch: i32,
+    pub localizer: i8,
+    pub glideslope: i8,
+    pub on_ground: u16,
+    pub autopilot: u32,
+}
+
+impl LandingSample {
+    /// The height above ground, in feet.
+    pub fn height(&self) -> f64 {
+        let altitude = altitude_metres(self.altitude);
+        let ground = self.ground_altitude as f64 / 256.0;
+        (altitude - ground) * FEET_PER_METRE
+    }
+
+    /// The sink rate, in feet per minute (positive when descending).
+    pub fn sink_rate(&self) -> f64 {
+        -(self.vertical_speed as f64 / 256.0) * FEET_PER_METRE * 60.0
+    }
+
+    /// The pitch, in degrees (positive nose up).
+    pub fn nose_up(&self) -> f64 {
+        geo::pitch_degrees(self.pitch)
+    }
+}
+
+impl FsuipcStruct for LandingSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::ALTITUDE, &mut self.altitude)?;
+        session.read_offset(offsets::GROUND_ALTITUDE, &mut self.ground_altitude)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::PITCH, &mut self.pitch)?;
+        session.read_offset(offsets::NAV1_LOCALIZER_NEEDLE, &mut self.localizer)?;
+        session.read_offset(offsets::NAV1_GLIDESLOPE_NEEDLE, &mut self.glideslope)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        session.read_offset(offsets::AUTOPILOT_MASTER, &mut self.autopilot)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The ILS deviations at some height of the approach
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deviation {
+    /// The height above ground, in feet.
+    pub height: f64,
+    /// The localizer needle deflection, as a fraction of full scale (positive to the right).
+    pub localizer: f64,
+    /// The glideslope needle deflection, as a fraction of full scale (positive down).
+    pub glideslope: f64,
+}
+
+/// The key parameters of an approach and landing
+/// They are the same for autolands and manual landings, so both can be compared.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApproachReport {
+    /// Whether the autopilot was engaged at touchdown.
+    pub autoland: bool,
+    /// The ILS deviations below the capture height, from the highest sample down.
+    pub deviations: Vec<Deviation>,
+    /// The height the flare was started at, in feet, if a flare was detected.
+    pub flare_height: Option<f64>,
+    /// The sink rate at touchdown, in feet per minute.
+    pub touchdown_sink_rate: f64,
+    /// The pitch at touchdown, in degrees (positive nose up).
+    pub touchdown_pitch: f64,
+    /// The rate the nose was lowered at after touchdown, in degrees per second.
+    pub derotation_rate: Option<f64>,
+}
+
+impl ApproachReport {
+    /// The largest localizer deflection either side, as a fraction of full scale.
+    pub fn max_localizer(&self) -> f64 {
+        self.deviations
+            .iter()
+            .map(|d| d.localizer.abs())
+            .fold(0.0, f64::max)
+    }
+
+    /// The largest glideslope deflection either side, as a fraction of full scale.
+    pub fn max_glideslope(&self) -> f64 {
+        self.deviations
+            .iter()
+            .map(|d| d.glideslope.abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+struct Touchdown {
+    report: ApproachReport,
+    last_pitch: f64,
+    derotating: Duration,
+}
+
+/// A capture of the approach and landing parameters
+/// It records every sample taken below the capture height while airborne. Once on the ground,
+/// it follows the derotation until the nose stops going down, and then gives the report. It
+/// is then ready for the next approach.
+pub struct LandingCapture {
+    capture_height: f64,
+    deviations: Vec<Deviation>,
+    approach_pitch: Option<f64>,
+    flare_height: Option<f64>,
+    touchdown: Option<Touchdown>,
+}
+
+impl LandingCapture {
+    /// Record the approach from the given height above ground, in feet.
+    pub fn new(capture_height: f64) -> Self {
+        LandingCapture {
+            capture_height,
+            deviations: Vec::new(),
+            approach_pitch: None,
+            flare_height: None,
+            touchdown: None,
+        }
+    }
+
+    /// Feed a new sample, taken `dt` after the previous one, obtaining the report once the
+    /// derotation is over.
+    pub fn update(&mut self, sample: &LandingSample, dt: Duration) -> Option<ApproachReport> {
+        if sample.on_ground == 0 {
+            self.touchdown = None;
+            self.airborne(sample);
+            return None;
+        }
+        let touchdown = match self.touchdown.as_mut() {
+            Some(touchdown) => touchdown,
+            None => {
+                if self.deviations.is_empty() {
+                    return None;
+                }
+                let report = ApproachReport {
+                    autoland: sample.autopilot != 0,
+                    deviations: std::mem::take(&mut self.deviations),
+                    flare_height: self.flare_height,
+                    touchdown_sink_rate: sample.sink_rate(),
+                    touchdown_pitch: sample.nose_up(),
+                    derotation_rate: None,
+                };
+                self.touchdown = Some(Touchdown {
+                    report,
+                    last_pitch: sample.nose_up(),
+                    derotating: Duration::default(),
+                });
+                return None;
+            }
+        };
+        let pitch = sample.nose_up();
+        if pitch < touchdown.last_pitch {
+            touchdown.derotating += dt;
+            touchdown.last_pitch = pitch;
+            return None;
+        }
+        let touchdown = self.touchdown.take().unwrap();
+        let mut report = touchdown.report;
+        let seconds = touchdown.derotating.as_secs_f64();
+        if seconds > 0.0 {
+            report.derotation_rate =
+                Some((report.touchdown_pitch - touchdown.last_pitch) / seconds);
+        }
+        *self = LandingCapture::new(self.capture_height);
+        Some(report)
+    }
+
+    fn airborne(&mut self, sample: &LandingSample) {
+        let height = sample.height();
+        if height > self.capture_height {
+            return;
+        }
+        self.deviations.push(Deviation {
+            height,
+            localizer: sample.localizer as f64 / LOCALIZER_FULL_SCALE,
+            glideslope: sample.glideslope as f64 / GLIDESLOPE_FULL_SCALE,
+        });
+        if height > FLARE_WINDOW {
+            self.approach_pitch = Some(sample.nose_up());
+        } else if let (None, Some(approach)) = (self.flare_height, self.approach_pitch) {
+            if sample.nose_up() >= approach + FLARE_PITCH_INCREASE {
+                self.flare_height = Some(height);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn sample(height: f64, nose_up: f64, localizer: i8) -> LandingSample {
+        LandingSample {
+            altitude: (height / FEET_PER_METRE * 4_294_967_296.0) as i64,
+            vertical_speed: (-700.0 / FEET_PER_METRE / 60.0 * 256.0) as i32,
+            pitch: (-nose_up * geo::ANGLE_UNITS_PER_DEGREE) as i32,
+            localizer,
+            glideslope: 12,
+            autopilot: 1,
+            ..LandingSample::default()
+        }
+    }
+
+    fn ground(nose_up: f64) -> LandingSample {
+        LandingSample {
+            on_ground: 1,
+            ..sample(0.0, nose_up, 0)
+        }
+    }
+
+    #[test]
+    fn should_report_autoland() {
+        let mut capture = LandingCapture::new(1500.0);
+        let dt = Duration::from_millis(500);
+        assert_eq!(capture.update(&sample(3000.0, 2.5, 100), dt), None);
+        capture.update(&sample(1000.0, 2.5, -64), dt);
+        capture.update(&sample(150.0, 2.5, 10), dt);
+        capture.update(&sample(60.0, 3.0, 0), dt);
+        capture.update(&sample(40.0, 4.0, 0), dt);
+        capture.update(&sample(20.0, 5.0, 0), dt);
+        assert_eq!(capture.update(&ground(5.0), dt), None);
+        assert_eq!(capture.update(&ground(3.0), dt), None);
+        assert_eq!(capture.update(&ground(1.0), dt), None);
+        let report = capture.update(&ground(1.0), dt).unwrap();
+
+        assert!(report.autoland);
+        assert_eq!(report.deviations.len(), 5);
+        assert!((report.deviations[0].height - 1000.0).abs() < 0.1);
+        assert!((report.max_localizer() - 64.0 / 127.0).abs() < 1e-9);
+        assert!((report.max_glideslope() - 12.0 / 119.0).abs() < 1e-9);
+        assert!((report.flare_height.unwrap() - 40.0).abs() < 0.1);
+        assert!((report.touchdown_sink_rate - 700.0).abs() < 1.0);
+        assert!((report.touchdown_pitch - 5.0).abs() < 1e-6);
+        assert!((report.derotation_rate.unwrap() - 4.0).abs() < 1e-6);
+        assert_eq!(capture.update(&ground(1.0), dt), None);
+    }
+
+    #[test]
+    fn should_report_manual_landing_without_flare_nor_derotation() {
+        let mut capture = LandingCapture::new(1500.0);
+        let dt = Duration::from_millis(500);
+        let manual = |height| LandingSample {
+            autopilot: 0,
+            ..sample(height, 2.0, 0)
+        };
+        capture.update(&manual(500.0), dt);
+        capture.update(&manual(50.0), dt);
+        assert_eq!(
+            capture.update(
+                &LandingSample {
+                    autopilot: 0,
+                    ..ground(2.0)
+                },
+                dt
+            ),
+            None
+        );
+        let report = capture.update(&ground(2.0), dt).unwrap();
+        assert!(!report.autoland);
+        assert_eq!(report.flare_height, None);
+        assert_eq!(report.derotation_rate, None);
+    }
+}
@@ -0,0 +1,16 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Analysis of the flight from sampled offsets
+//! These helpers do not talk to FSUIPC by themselves. The application reads their samples from
+//! a session (they implement `FsuipcStruct`) and feeds them in on every update.
+
+pub mod approach;
+pub mod control_check;
+pub mod landing;
+pub mod scoring;
@@ -0,0 +1,435 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::io;
+
+use crate::geo::{self, altitude_metres, FEET_PER_METRE};
+use crate::offsets::{self, FsuipcStruct};
+use crate::Session;
+
+/// The flight data sampled by the scoring rules
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlightSample {
+    pub indicated_airspeed: u32,
+    pub barber_pole_airspeed: u32,
+    pub vertical_speed: i32,
+    pub altitude: i64,
+    pub ground_altitude: i32,
+    pub bank: i32,
+    pub g_force: i16,
+    pub on_ground: u16,
+}
+
+impl FlightSample {
+    /// The indicated airspeed, in knots.
+    pub fn speed(&self) -> f64 {
+        self.indicated_airspeed as f64 / 128.0
+    }
+
+    /// The maximum allowed airspeed, in knots.
+    pub fn max_speed(&self) -> f64 {
+        self.barber_pole_airspeed as f64 / 128.0
+    }
+
+    /// The vertical speed, in feet per minute.
+    pub fn vertical_speed(&self) -> f64 {
+        self.vertical_speed as f64 / 256.0 * FEET_PER_METRE * 60.0
+    }
+
+    /// The height above ground, in feet.
+    pub fn height(&self) -> f64 {
+        let altitude = altitude_metres(self.altitude);
+        let ground = self.ground_altitude as f64 / 256.0;
+        (altitude - ground) * FEET_PER_METRE
+    }
+
+    /// The bank angle, in degrees (positive right wing down).
+    pub fn bank(&self) -> f64 {
+        geo::bank_degrees(self.bank)
+    }
+
+    /// The G force.
+    pub fn g_force(&self) -> f64 {
+        self.g_force as f64 / 625.0
+    }
+
+    /// Whether the aircraft is on ground.
+    pub fn on_ground(&self) -> bool {
+        self.on_ground != 0
+    }
+}
+
+impl FsuipcStruct for FlightSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(
+            offsets::BARBER_POLE_AIRSPEED,
+            &mut self.barber_pole_airspeed,
+        )?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::ALTITUDE, &mut self.altitude)?;
+        session.read_offset(offsets::GROUND_ALTITUDE, &mut self.ground_altitude)?;
+        session.read_offset(offsets::BANK, &mut self.bank)?;
+        session.read_offset(offsets::G_FORCE, &mut self.g_force)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A penalty applied by a scoring rule
+#[derive(Clone, Debug, PartialEq)]
+pub struct Penalty {
+    /// The name of the rule.
+    pub rule: String,
+    /// The points subtracted from the score.
+    pub points: u32,
+    /// What happened, e.g. `bank of 38 degrees at 320 ft`.
+    pub description: String,
+}
+
+impl fmt::Display for Penalty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} (-{})", self.rule, self.description, self.points)
+    }
+}
+
+/// A scoring rule
+/// Rules see every sample of the flight and return a penalty when they detect an exceedance.
+/// They are expected to penalize each exceedance once, not on every sample it lasts.
+pub trait Rule {
+    fn check(&mut self, sample: &FlightSample) -> Option<Penalty>;
+}
+
+/// Tracks the start of exceedances, so each one is penalized once
+#[derive(Clone, Debug, Default)]
+struct Exceedance {
+    active: bool,
+}
+
+impl Exceedance {
+    fn started(&mut self, exceeded: bool) -> bool {
+        let started = exceeded && !self.active;
+        self.active = exceeded;
+        started
+    }
+}
+
+/// Flying faster than the maximum allowed airspeed
+#[derive(Clone, Debug)]
+pub struct Overspeed {
+    points: u32,
+    exceedance: Exceedance,
+}
+
+impl Overspeed {
+    pub fn new(points: u32) -> Self {
+        Overspeed {
+            points,
+            exceedance: Exceedance::default(),
+        }
+    }
+}
+
+impl Rule for Overspeed {
+    fn check(&mut self, sample: &FlightSample) -> Option<Penalty> {
+        let exceeded = sample.max_speed() > 0.0 && sample.speed() > sample.max_speed();
+        if self.exceedance.started(exceeded) {
+            Some(Penalty {
+                rule: "overspeed".to_string(),
+                points: self.points,
+                description: format!(
+                    "{:.0} kt with a maximum of {:.0} kt",
+                    sample.speed(),
+                    sample.max_speed()
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Pulling more G than the limit, either positive or negative
+#[derive(Clone, Debug)]
+pub struct OverG {
+    limit: f64,
+    points: u32,
+    exceedance: Exceedance,
+}
+
+impl OverG {
+    pub fn new(limit: f64, points: u32) -> Self {
+        OverG {
+            limit,
+            points,
+            exceedance: Exceedance::default(),
+        }
+    }
+}
+
+impl Rule for OverG {
+    fn check(&mut self, sample: &FlightSample) -> Option<Penalty> {
+        if self.exceedance.started(sample.g_force().abs() > self.limit) {
+            Some(Penalty {
+                rule: "over-g".to_string(),
+                points: self.points,
+                description: format!(
+                    "{:.1} G with a limit of {:.1} G",
+                    sample.g_force(),
+                    self.limit
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Banking more than allowed close to the ground
+#[derive(Clone, Debug)]
+pub struct LowAltitudeBank {
+    max_bank: f64,
+    below: f64,
+    points: u32,
+    exceedance: Exceedance,
+}
+
+impl LowAltitudeBank {
+    /// Penalize banks over `max_bank` degrees below `below` feet above ground.
+    pub fn new(max_bank: f64, below: f64, points: u32) -> Self {
+        LowAltitudeBank {
+            max_bank,
+            below,
+            points,
+            exceedance: Exceedance::default(),
+        }
+    }
+}
+
+impl Rule for LowAltitudeBank {
+    fn check(&mut self, sample: &FlightSample) -> Option<Penalty> {
+        let exceeded = !sample.on_ground()
+            && sample.height() < self.below
+            && sample.bank().abs() > self.max_bank;
+        if self.exceedance.started(exceeded) {
+            Some(Penalty {
+                rule: "low altitude bank".to_string(),
+                points: self.points,
+                description: format!(
+                    "bank of {:.0} degrees at {:.0} ft",
+                    sample.bank().abs(),
+                    sample.height()
+                ),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Touching down with a sink rate over the limit
+#[derive(Clone, Debug)]
+pub struct HardLanding {
+    max_sink_rate: f64,
+    points: u32,
+    last_airborne: Option<f64>,
+}
+
+impl HardLanding {
+    /// Penalize touchdowns with a sink rate over `max_sink_rate` feet per minute.
+    pub fn new(max_sink_rate: f64, points: u32) -> Self {
+        HardLanding {
+            max_sink_rate,
+            points,
+            last_airborne: None,
+        }
+    }
+}
+
+impl Rule for HardLanding {
+    fn check(&mut self, sample: &FlightSample) -> Option<Penalty> {
+        if !sample.on_ground() {
+            self.last_airborne = Some(-sample.vertical_speed());
+            return None;
+        }
+        match self.last_airborne.take() {
+            Some(sink_rate) if sink_rate > self.max_sink_rate => Some(Penalty {
+                rule: "hard landing".to_string(),
+                points: self.points,
+                description: format!("touchdown at {:.0} fpm", sink_rate),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The score of a flight
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreReport {
+    /// The final score, which is negative if the penalties exceed the initial score.
+    pub score: i64,
+    /// All the penalties, in the order they were applied.
+    pub penalties: Vec<Penalty>,
+}
+
+/// A scorer that accumulates the penalties of its rules over a flight
+pub struct Scorer {
+    initial: u32,
+    rules: Vec<Box<dyn Rule + Send>>,
+    penalties: Vec<Penalty>,
+}
+
+impl Scorer {
+    /// Create a scorer with no rules, starting with the given score.
+    pub fn new(initial: u32) -> Self {
+        Scorer {
+            initial,
+            rules: Vec::new(),
+            penalties: Vec::new(),
+        }
+    }
+
+    /// A scorer starting from 100 points with the usual exceedance rules.
+    pub fn standard() -> Self {
+        Scorer::new(100)
+            .rule(Overspeed::new(10))
+            .rule(OverG::new(2.5, 10))
+            .rule(LowAltitudeBank::new(30.0, 500.0, 10))
+            .rule(HardLanding::new(600.0, 20))
+    }
+
+    /// Add a rule to the scorer.
+    pub fn rule<R: Rule + Send + 'static>(mut self, rule: R) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Feed a new sample, obtaining the penalties it caused.
+    pub fn update(&mut self, sample: &FlightSample) -> &[Penalty] {
+        let first = self.penalties.len();
+        for rule in self.rules.iter_mut() {
+            if let Some(penalty) = rule.check(sample) {
+                self.penalties.push(penalty);
+            }
+        }
+        &self.penalties[first..]
+    }
+
+    /// The score so far.
+    pub fn report(&self) -> ScoreReport {
+        let points: i64 = self.penalties.iter().map(|p| p.points as i64).sum();
+        ScoreReport {
+            score: self.initial as i64 - points,
+            penalties: self.penalties.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn airborne(height: f64) -> FlightSample {
+        FlightSample {
+            indicated_airspeed: 250 * 128,
+            barber_pole_airspeed: 340 * 128,
+            altitude: (height / FEET_PER_METRE * 4_294_967_296.0) as i64,
+            g_force: 625,
+            ..FlightSample::default()
+        }
+    }
+
+    #[test]
+    fn should_penalize_each_exceedance_once() {
+        let mut scorer = Scorer::standard();
+        let fast = FlightSample {
+            indicated_airspeed: 350 * 128,
+            ..airborne(10000.0)
+        };
+        assert_eq!(scorer.update(&fast).len(), 1);
+        assert!(scorer.update(&fast).is_empty());
+        assert!(scorer.update(&airborne(10000.0)).is_empty());
+        assert_eq!(scorer.update(&fast)[0].rule, "overspeed");
+        assert_eq!(scorer.report().score, 80);
+    }
+
+    #[test]
+    fn should_penalize_bank_only_close_to_ground() {
+        let mut scorer = Scorer::standard();
+        let degrees = |d: f64| (-d * geo::ANGLE_UNITS_PER_DEGREE) as i32;
+        let high = FlightSample {
+            bank: degrees(40.0),
+            ..airborne(3000.0)
+        };
+        let low = FlightSample {
+            bank: degrees(-40.0),
+            ..airborne(300.0)
+        };
+        assert!(scorer.update(&high).is_empty());
+        let penalties = scorer.update(&low);
+        assert_eq!(penalties.len(), 1);
+        assert_eq!(penalties[0].description, "bank of 40 degrees at 300 ft");
+    }
+
+    #[test]
+    fn should_decode_right_bank_as_positive() {
+        let right = FlightSample {
+            bank: -(1 << 26),
+            ..airborne(3000.0)
+        };
+        assert!((right.bank() - 5.625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_penalize_over_g_and_hard_landing() {
+        let mut scorer = Scorer::standard();
+        let pull = FlightSample {
+            g_force: 2000,
+            ..airborne(5000.0)
+        };
+        scorer.update(&pull);
+        let flare = FlightSample {
+            vertical_speed: (-900.0 / FEET_PER_METRE / 60.0 * 256.0) as i32,
+            ..airborne(5.0)
+        };
+        scorer.update(&flare);
+        let touchdown = FlightSample {
+            on_ground: 1,
+            ..airborne(0.0)
+        };
+        assert_eq!(scorer.update(&touchdown)[0].rule, "hard landing");
+        let report = scorer.report();
+        assert_eq!(report.score, 70);
+        assert_eq!(report.penalties[0].rule, "over-g");
+    }
+
+    #[test]
+    fn should_accept_custom_rules() {
+        struct Always;
+        impl Rule for Always {
+            fn check(&mut self, _sample: &FlightSample) -> Option<Penalty> {
+                Some(Penalty {
+                    rule: "always".to_string(),
+                    points: 1,
+                    description: String::new(),
+                })
+            }
+        }
+        let mut scorer = Scorer::new(2).rule(Always);
+        for _ in 0..3 {
+            scorer.update(&airborne(1000.0));
+        }
+        assert_eq!(scorer.report().score, -1);
+    }
+}
@@ -0,0 +1,178 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Angle of attack and stall margin
+//! The simulator gives the angle of attack at offset 0x2ED0, but not the angle the wing stalls
+//! at, which depends on the aircraft. It is given by a `StallCalibration`, saved per aircraft
+//! into a `ValueStore` under `aoa.<title>.`, so `read_snapshot()` can tell how far the aircraft
+//! is from the stall, as AoA indexers and stall warning tools need.
+
+use std::io;
+
+use super::offsets;
+use super::store::{aircraft_key, ValueStore};
+use super::strings;
+use super::{Handle, Session};
+
+/// The angles of attack the stall margin of an aircraft is computed from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StallCalibration {
+    /// The angle of attack of zero lift, in degrees.
+    pub zero_lift: f64,
+    /// The angle of attack of the stall, in degrees.
+    pub stall: f64,
+}
+
+impl Default for StallCalibration {
+    /// Typical of a light aircraft with the flaps up: zero lift at -2 degrees, stall at 16.
+    fn default() -> Self {
+        StallCalibration {
+            zero_lift: -2.0,
+            stall: 16.0,
+        }
+    }
+}
+
+impl StallCalibration {
+    /// Load the calibration of the given aircraft, if it was ever saved.
+    pub fn load(store: &ValueStore, aircraft: &str) -> Option<Self> {
+        let zero_lift = store.get(&aircraft_key("aoa", aircraft, "zero_lift"))?;
+        let stall = store.get(&aircraft_key("aoa", aircraft, "stall"))?;
+        Some(StallCalibration {
+            zero_lift: zero_lift as f64 / 100.0,
+            stall: stall as f64 / 100.0,
+        })
+    }
+
+    /// Save the calibration of the given aircraft, to hundredths of a degree.
+    /// The store is not saved to disk.
+    pub fn save(&self, store: &mut ValueStore, aircraft: &str) -> io::Result<()> {
+        if self.stall <= self.zero_lift {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the stall angle of attack must be above the zero lift one",
+            ));
+        }
+        let hundredths = |degrees: f64| (degrees * 100.0).round() as i32;
+        store.set(
+            &aircraft_key("aoa", aircraft, "zero_lift"),
+            hundredths(self.zero_lift),
+        )?;
+        store.set(
+            &aircraft_key("aoa", aircraft, "stall"),
+            hundredths(self.stall),
+        )
+    }
+}
+
+/// The angle of attack of the aircraft and its margin to the stall
+#[derive(Clone, Debug, PartialEq)]
+pub struct AoaSnapshot {
+    /// The title of the aircraft.
+    pub aircraft: String,
+    /// The angle of attack, in degrees, or `None` on the ground, where it is meaningless.
+    pub angle_of_attack: Option<f64>,
+    /// The calibration the margin is computed with.
+    pub calibration: StallCalibration,
+    /// Whether the calibration was saved for the aircraft, rather than the default.
+    pub calibrated: bool,
+}
+
+impl AoaSnapshot {
+    /// The degrees of angle of attack left before the stall, negative once stalled.
+    pub fn stall_margin(&self) -> Option<f64> {
+        Some(self.calibration.stall - self.angle_of_attack?)
+    }
+
+    /// The angle of attack as a fraction of the lifting range: 0 at zero lift and 1 at the
+    /// stall. This is what AoA indexers display, the same for every aircraft once calibrated.
+    pub fn normalized(&self) -> Option<f64> {
+        let range = self.calibration.stall - self.calibration.zero_lift;
+        Some((self.angle_of_attack? - self.calibration.zero_lift) / range)
+    }
+}
+
+/// Read the angle of attack, with the calibration of the loaded aircraft if saved in the store,
+/// or `default` otherwise.
+pub fn read_snapshot<H>(
+    handle: &mut H,
+    store: &ValueStore,
+    default: StallCalibration,
+) -> io::Result<AoaSnapshot>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut title = [0u8; 256];
+    let mut aoa = 0f64;
+    let mut on_ground = 0u16;
+    let mut session = handle.session();
+    session.read_offset(offsets::AIRCRAFT_NAME, &mut title)?;
+    session.read_offset(offsets::ANGLE_OF_ATTACK, &mut aoa)?;
+    session.read_offset(offsets::ON_GROUND, &mut on_ground)?;
+    session.process()?;
+
+    let aircraft = strings::decode(&title);
+    let saved = StallCalibration::load(store, &aircraft);
+    Ok(AoaSnapshot {
+        angle_of_attack: if on_ground == 0 {
+            Some(aoa.to_degrees())
+        } else {
+            None
+        },
+        calibration: saved.unwrap_or(default),
+        calibrated: saved.is_some(),
+        aircraft,
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_compute_stall_margin_with_aircraft_calibration() {
+        let mut store = ValueStore::in_memory();
+        let calibration = StallCalibration {
+            zero_lift: -3.0,
+            stall: 15.5,
+        };
+        calibration.save(&mut store, "Boeing 737-800").unwrap();
+        assert_eq!(store.get("aoa.Boeing 737-800.stall"), Some(1550));
+
+        let mut handle = MockHandle::new();
+        handle.seed(0x3D00, b"Boeing 737-800\0");
+        handle.seed_value(0x2ED0, &8f64.to_radians());
+        let snapshot = read_snapshot(&mut handle, &store, StallCalibration::default()).unwrap();
+        assert!(snapshot.calibrated);
+        assert_eq!(snapshot.calibration, calibration);
+        assert!((snapshot.stall_margin().unwrap() - 7.5).abs() < 1e-9);
+        assert!((snapshot.normalized().unwrap() - 11.0 / 18.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_fall_back_to_default_calibration() {
+        let store = ValueStore::in_memory();
+        let mut handle = MockHandle::new();
+        handle.seed(0x3D00, b"Cessna Skyhawk\0");
+        handle.seed_value(0x0366, &1u16);
+        let snapshot = read_snapshot(&mut handle, &store, StallCalibration::default()).unwrap();
+        assert!(!snapshot.calibrated);
+        assert_eq!(snapshot.angle_of_attack, None);
+        assert_eq!(snapshot.stall_margin(), None);
+
+        let inverted = StallCalibration {
+            zero_lift: 5.0,
+            stall: 5.0,
+        };
+        assert!(inverted
+            .save(&mut ValueStore::in_memory(), "Cessna")
+            .is_err());
+    }
+}
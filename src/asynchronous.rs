@@ -0,0 +1,256 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use tokio::task;
+
+use super::{Handle, Session};
+
+/// A handle to FSUIPC usable from async code
+/// It wraps a regular handle and runs the blocking IPC exchange in the blocking thread pool of
+/// Tokio (`spawn_blocking`), so the async runtime is never stalled waiting for FSUIPC. It can
+/// be cloned to share the underlying handle among tasks; their transactions are serialized.
+pub struct AsyncHandle<H> {
+    handle: Arc<Mutex<H>>,
+}
+
+/// An async handle using the user mode of FSUIPC
+#[cfg(windows)]
+pub type AsyncUserHandle = AsyncHandle<super::user::UserHandle>;
+
+impl<H> Clone for AsyncHandle<H> {
+    fn clone(&self) -> Self {
+        AsyncHandle {
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<H> AsyncHandle<H>
+where
+    H: for<'h> Handle<'h> + Send + 'static,
+{
+    pub fn new(handle: H) -> Self {
+        AsyncHandle {
+            handle: Arc::new(Mutex::new(handle)),
+        }
+    }
+
+    /// Create a new session from this handle
+    pub fn session<'a>(&self) -> AsyncSession<'a, H> {
+        AsyncSession {
+            handle: self.handle.clone(),
+            requests: Vec::new(),
+            targets: Vec::new(),
+            _targets: PhantomData,
+        }
+    }
+}
+
+enum Request {
+    Read { offset: u16, len: usize },
+    Write { offset: u16, data: Vec<u8> },
+}
+
+struct Target {
+    dest: *mut u8,
+    len: usize,
+}
+
+// The target memory is exclusively borrowed by the session for its whole lifetime.
+unsafe impl Send for Target {}
+
+/// A session of read & write operations from/to FSUIPC for async code
+/// It has the same semantics as `Session`: requests are queued by `read()` and `write()`, and
+/// they are executed when `process()` is awaited.
+pub struct AsyncSession<'a, H> {
+    handle: Arc<Mutex<H>>,
+    requests: Vec<Request>,
+    targets: Vec<Target>,
+    _targets: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a, H> AsyncSession<'a, H>
+where
+    H: for<'h> Handle<'h> + Send + 'static,
+{
+    pub fn read_bytes(&mut self, offset: u16, dest: &'a mut [u8]) -> io::Result<usize> {
+        self.requests.push(Request::Read {
+            offset,
+            len: dest.len(),
+        });
+        self.targets.push(Target {
+            dest: dest.as_mut_ptr(),
+            len: dest.len(),
+        });
+        Ok(dest.len())
+    }
+
+    pub fn write_bytes(&mut self, offset: u16, src: &[u8]) -> io::Result<usize> {
+        self.requests.push(Request::Write {
+            offset,
+            data: src.to_vec(),
+        });
+        Ok(src.len())
+    }
+
+    pub fn read<T>(&mut self, offset: u16, result: &'a mut T) -> io::Result<usize> {
+        let dest =
+            unsafe { std::slice::from_raw_parts_mut(result as *mut T as *mut u8, size_of::<T>()) };
+        self.read_bytes(offset, dest)
+    }
+
+    pub fn write<T>(&mut self, offset: u16, value: &T) -> io::Result<usize> {
+        let src =
+            unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+        self.write_bytes(offset, src)
+    }
+
+    pub async fn process(self) -> io::Result<usize> {
+        let AsyncSession {
+            handle,
+            requests,
+            targets,
+            ..
+        } = self;
+        let (nbytes, results) = task::spawn_blocking(move || process_blocking(&handle, requests))
+            .await
+            .map_err(io::Error::other)??;
+        for (target, data) in targets.into_iter().zip(results) {
+            unsafe { ptr::copy_nonoverlapping(data.as_ptr(), target.dest, target.len) };
+        }
+        Ok(nbytes)
+    }
+}
+
+fn process_blocking<H>(
+    handle: &Mutex<H>,
+    requests: Vec<Request>,
+) -> io::Result<(usize, Vec<Vec<u8>>)>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut handle = handle
+        .lock()
+        .map_err(|_| io::Error::other("FSUIPC handle was poisoned by a panic"))?;
+    let mut results: Vec<Vec<u8>> = requests
+        .iter()
+        .filter_map(|request| match request {
+            Request::Read { len, .. } => Some(vec![0; *len]),
+            Request::Write { .. } => None,
+        })
+        .collect();
+    let mut session = handle.session();
+    let mut buffers = results.iter_mut();
+    for request in &requests {
+        match request {
+            Request::Read { offset, len } => {
+                let buffer = buffers.next().expect("a buffer for each read");
+                session.read_bytes(*offset, buffer.as_mut_ptr(), *len)?;
+            }
+            Request::Write { offset, data } => {
+                session.write_bytes(*offset, data.as_ptr(), data.len())?;
+            }
+        }
+    }
+    let nbytes = session.process()?;
+    Ok((nbytes, results))
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// A handle to an in-memory offset space
+    #[derive(Default)]
+    struct FakeHandle {
+        memory: HashMap<u16, u8>,
+    }
+
+    struct FakeSession<'a> {
+        memory: &'a mut HashMap<u16, u8>,
+        reads: Vec<(u16, *mut u8, usize)>,
+    }
+
+    impl<'a> Handle<'a> for FakeHandle {
+        type Sess = FakeSession<'a>;
+
+        fn session(&'a mut self) -> FakeSession<'a> {
+            FakeSession {
+                memory: &mut self.memory,
+                reads: Vec::new(),
+            }
+        }
+    }
+
+    impl<'a> Session for FakeSession<'a> {
+        fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            self.reads.push((offset, dest, len));
+            Ok(len)
+        }
+
+        fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+            for i in 0..len {
+                self.memory
+                    .insert(offset + i as u16, unsafe { *src.add(i) });
+            }
+            Ok(len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            for (offset, dest, len) in self.reads {
+                for i in 0..len {
+                    let value = self.memory.get(&(offset + i as u16)).cloned();
+                    unsafe { *dest.add(i) = value.unwrap_or(0) };
+                }
+            }
+            Ok(0)
+        }
+    }
+
+    #[tokio::test]
+    async fn should_write_and_read_back() {
+        let handle = AsyncHandle::new(FakeHandle::default());
+        let mut session = handle.session();
+        session.write(0x0330, &0x3FC0u16).unwrap();
+        session.process().await.unwrap();
+
+        let mut qnh = 0u16;
+        let mut session = handle.session();
+        session.read(0x0330, &mut qnh).unwrap();
+        session.process().await.unwrap();
+        assert_eq!(qnh, 0x3FC0);
+    }
+
+    #[tokio::test]
+    async fn should_process_from_spawned_tasks() {
+        let handle = AsyncHandle::new(FakeHandle::default());
+        let writer = handle.clone();
+        tokio::spawn(async move {
+            let mut session = writer.session();
+            session.write(0x0238, &[12u8, 34, 56]).unwrap();
+            session.process().await.unwrap();
+        })
+        .await
+        .unwrap();
+
+        let mut time = [0u8; 3];
+        let mut session = handle.session();
+        session.read_bytes(0x0238, &mut time).unwrap();
+        session.process().await.unwrap();
+        assert_eq!(time, [12, 34, 56]);
+    }
+}
@@ -0,0 +1,230 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A facade over the autopilot offsets
+//! `ApState` reads the modes and the selected values of the autopilot at once and converts them
+//! to the usual units: the altitude from metres * 65536 to feet, the heading from 65536ths of a
+//! turn to degrees and the Mach number from 65536ths. The `write_*()` functions convert and
+//! write a single mode or value.
+
+use std::io;
+
+use super::geo::FEET_PER_METRE;
+use super::offsets::{self, FsuipcStruct, Offset};
+use super::Session;
+
+/// A mode of the autopilot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApMode {
+    Master,
+    Heading,
+    Altitude,
+    Airspeed,
+    Mach,
+    VerticalSpeed,
+    Approach,
+}
+
+impl ApMode {
+    /// The offset of the switch of the mode.
+    pub fn offset(self) -> Offset<u32> {
+        match self {
+            ApMode::Master => offsets::AUTOPILOT_MASTER,
+            ApMode::Heading => offsets::AUTOPILOT_HEADING_HOLD,
+            ApMode::Altitude => offsets::AUTOPILOT_ALTITUDE_HOLD,
+            ApMode::Airspeed => offsets::AUTOPILOT_AIRSPEED_HOLD,
+            ApMode::Mach => offsets::AUTOPILOT_MACH_HOLD,
+            ApMode::VerticalSpeed => offsets::AUTOPILOT_VERTICAL_SPEED_HOLD,
+            ApMode::Approach => offsets::AUTOPILOT_APPROACH_HOLD,
+        }
+    }
+}
+
+/// The state of the autopilot
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ApState {
+    master: u32,
+    heading_hold: u32,
+    heading: u16,
+    altitude_hold: u32,
+    altitude: u32,
+    airspeed_hold: u32,
+    airspeed: u16,
+    mach_hold: u32,
+    mach: u32,
+    vertical_speed_hold: u32,
+    vertical_speed: i16,
+    approach_hold: u32,
+}
+
+impl ApState {
+    /// Whether the given mode is engaged.
+    pub fn engaged(&self, mode: ApMode) -> bool {
+        let switch = match mode {
+            ApMode::Master => self.master,
+            ApMode::Heading => self.heading_hold,
+            ApMode::Altitude => self.altitude_hold,
+            ApMode::Airspeed => self.airspeed_hold,
+            ApMode::Mach => self.mach_hold,
+            ApMode::VerticalSpeed => self.vertical_speed_hold,
+            ApMode::Approach => self.approach_hold,
+        };
+        switch != 0
+    }
+
+    /// The selected heading, in degrees.
+    pub fn heading(&self) -> f64 {
+        self.heading as f64 * 360.0 / 65536.0
+    }
+
+    /// The selected altitude, in feet.
+    pub fn altitude_feet(&self) -> f64 {
+        self.altitude as f64 / 65536.0 * FEET_PER_METRE
+    }
+
+    /// The selected airspeed, in knots.
+    pub fn airspeed(&self) -> u16 {
+        self.airspeed
+    }
+
+    /// The selected Mach number.
+    pub fn mach(&self) -> f64 {
+        self.mach as f64 / 65536.0
+    }
+
+    /// The selected vertical speed, in feet per minute.
+    pub fn vertical_speed(&self) -> i16 {
+        self.vertical_speed
+    }
+}
+
+impl FsuipcStruct for ApState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::AUTOPILOT_MASTER, &mut self.master)?;
+        session.read_offset(offsets::AUTOPILOT_HEADING_HOLD, &mut self.heading_hold)?;
+        session.read_offset(offsets::AUTOPILOT_HEADING, &mut self.heading)?;
+        session.read_offset(offsets::AUTOPILOT_ALTITUDE_HOLD, &mut self.altitude_hold)?;
+        session.read_offset(offsets::AUTOPILOT_ALTITUDE, &mut self.altitude)?;
+        session.read_offset(offsets::AUTOPILOT_AIRSPEED_HOLD, &mut self.airspeed_hold)?;
+        session.read_offset(offsets::AUTOPILOT_AIRSPEED, &mut self.airspeed)?;
+        session.read_offset(offsets::AUTOPILOT_MACH_HOLD, &mut self.mach_hold)?;
+        session.read_offset(offsets::AUTOPILOT_MACH, &mut self.mach)?;
+        session.read_offset(
+            offsets::AUTOPILOT_VERTICAL_SPEED_HOLD,
+            &mut self.vertical_speed_hold,
+        )?;
+        session.read_offset(offsets::AUTOPILOT_VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::AUTOPILOT_APPROACH_HOLD, &mut self.approach_hold)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Request engaging or disengaging a mode.
+pub fn write_mode<S: Session>(session: &mut S, mode: ApMode, engaged: bool) -> io::Result<usize> {
+    session.write_offset(mode.offset(), &(engaged as u32))
+}
+
+/// Request selecting a heading, in degrees, normalized to a turn.
+pub fn write_heading<S: Session>(session: &mut S, degrees: f64) -> io::Result<usize> {
+    let heading = (degrees.rem_euclid(360.0) * 65536.0 / 360.0).round() as u32 as u16;
+    session.write_offset(offsets::AUTOPILOT_HEADING, &heading)
+}
+
+/// Request selecting an altitude, in feet, from 0 to 65535 metres.
+pub fn write_altitude<S: Session>(session: &mut S, feet: f64) -> io::Result<usize> {
+    let metres = feet / FEET_PER_METRE;
+    if !(0.0..65536.0).contains(&metres) {
+        return Err(invalid_value("altitude", feet));
+    }
+    let altitude = (metres * 65536.0).round() as u32;
+    session.write_offset(offsets::AUTOPILOT_ALTITUDE, &altitude)
+}
+
+/// Request selecting an airspeed, in knots.
+pub fn write_airspeed<S: Session>(session: &mut S, knots: u16) -> io::Result<usize> {
+    session.write_offset(offsets::AUTOPILOT_AIRSPEED, &knots)
+}
+
+/// Request selecting a Mach number, from 0 to 5.
+pub fn write_mach<S: Session>(session: &mut S, mach: f64) -> io::Result<usize> {
+    if !(0.0..=5.0).contains(&mach) {
+        return Err(invalid_value("Mach number", mach));
+    }
+    let mach = (mach * 65536.0).round() as u32;
+    session.write_offset(offsets::AUTOPILOT_MACH, &mach)
+}
+
+/// Request selecting a vertical speed, in feet per minute.
+pub fn write_vertical_speed<S: Session>(
+    session: &mut S,
+    feet_per_minute: i16,
+) -> io::Result<usize> {
+    session.write_offset(offsets::AUTOPILOT_VERTICAL_SPEED, &feet_per_minute)
+}
+
+fn invalid_value(name: &str, value: f64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid autopilot {} {}", name, value),
+    )
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_decode_autopilot() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x07BC, &1u32);
+        handle.seed_value(0x07D0, &1u32);
+        handle.seed_value(0x07CC, &0x4000u16);
+        handle.seed_value(0x07D4, &(3048u32 * 65536));
+        handle.seed_value(0x07E8, &(0x8000u32 * 3 / 2));
+        handle.seed_value(0x07F2, &(-700i16));
+        let mut state = ApState::default();
+        {
+            let mut session = handle.session();
+            state.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        assert!(state.engaged(ApMode::Master));
+        assert!(state.engaged(ApMode::Altitude));
+        assert!(!state.engaged(ApMode::Approach));
+        assert_eq!(state.heading(), 90.0);
+        assert!((state.altitude_feet() - 10000.0).abs() < 0.1);
+        assert_eq!(state.mach(), 0.75);
+        assert_eq!(state.vertical_speed(), -700);
+    }
+
+    #[test]
+    fn should_write_autopilot() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_mode(&mut session, ApMode::Heading, true).unwrap();
+            write_heading(&mut session, -90.0).unwrap();
+            write_altitude(&mut session, 10000.0).unwrap();
+            write_mach(&mut session, 0.78).unwrap();
+            assert!(write_altitude(&mut session, -100.0).is_err());
+            assert!(write_mach(&mut session, 7.0).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u32>(0x07C8), 1);
+        assert_eq!(handle.value::<u16>(0x07CC), 0xC000);
+        assert_eq!(handle.value::<u32>(0x07D4) / 65536, 3047);
+        assert_eq!(handle.value::<u32>(0x07E8), 51118);
+    }
+}
@@ -0,0 +1,393 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::FsuipcError;
+use super::offsets::{self, Offset};
+use super::strings;
+use super::{Handle, Session};
+
+static NEXT_BATCH_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// The interval between reads of the 18 Hz tick counter while waiting for the next tick.
+const TICK_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// A value requested to a `BatchReader`, available once the batch is processed
+/// It is just a token: the value is obtained from the `BatchResults` of its batch.
+#[derive(Debug)]
+pub struct Pending<T> {
+    batch: usize,
+    position: usize,
+    _type: PhantomData<fn() -> T>,
+}
+
+/// A string requested to a `BatchReader`, available once the batch is processed
+#[derive(Debug)]
+pub struct PendingString {
+    batch: usize,
+    position: usize,
+    len: usize,
+}
+
+/// A batch of reads that does not need variables declared in advance
+/// Every `read()` returns a `Pending<T>` token. Once the batch is processed, the tokens are
+/// resolved into values from the results. The batch owns the memory the values are read into,
+/// so no reference to the caller's variables is retained in the session.
+pub struct BatchReader {
+    id: usize,
+    requests: Vec<(u16, usize, usize)>,
+    len: usize,
+}
+
+impl Default for BatchReader {
+    fn default() -> Self {
+        BatchReader::new()
+    }
+}
+
+impl BatchReader {
+    pub fn new() -> Self {
+        BatchReader {
+            id: NEXT_BATCH_ID.fetch_add(1, Ordering::Relaxed),
+            requests: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Request reading a value of type `T` from the given offset.
+    pub fn read<T: Copy>(&mut self, offset: u16) -> Pending<T> {
+        let position = self.len;
+        self.requests.push((offset, position, size_of::<T>()));
+        self.len += size_of::<T>();
+        Pending {
+            batch: self.id,
+            position,
+            _type: PhantomData,
+        }
+    }
+
+    /// Request reading a catalog offset.
+    pub fn read_offset<T: Copy>(&mut self, offset: Offset<T>) -> Pending<T> {
+        self.read(offset.address())
+    }
+
+    /// Request reading a string offset of up to `max_len` bytes.
+    pub fn read_string(&mut self, offset: u16, max_len: usize) -> PendingString {
+        let position = self.len;
+        self.requests.push((offset, position, max_len));
+        self.len += max_len;
+        PendingString {
+            batch: self.id,
+            position,
+            len: max_len,
+        }
+    }
+
+    /// Process all the reads in a single session of the given handle.
+    pub fn process<'a, H: Handle<'a>>(self, handle: &'a mut H) -> io::Result<BatchResults> {
+        let mut buffer = vec![0u8; self.len];
+        let mut session = handle.session();
+        for (offset, position, len) in self.requests.iter() {
+            let dest = buffer[*position..].as_mut_ptr();
+            session.read_bytes(*offset, dest, *len)?;
+        }
+        session.process()?;
+        Ok(BatchResults {
+            batch: self.id,
+            buffer,
+        })
+    }
+}
+
+/// The values read by a `BatchReader`
+pub struct BatchResults {
+    batch: usize,
+    buffer: Vec<u8>,
+}
+
+impl BatchResults {
+    /// Obtain the value of a pending read.
+    /// It panics if the token was obtained from a different batch.
+    pub fn get<T: Copy>(&self, pending: &Pending<T>) -> T {
+        assert_eq!(
+            pending.batch, self.batch,
+            "pending read from a different batch"
+        );
+        let bytes = &self.buffer[pending.position..pending.position + size_of::<T>()];
+        unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+    }
+
+    /// Obtain the value of a pending string read, decoded by `strings::decode()`.
+    /// It panics if the token was obtained from a different batch.
+    pub fn get_string(&self, pending: &PendingString) -> String {
+        assert_eq!(
+            pending.batch, self.batch,
+            "pending read from a different batch"
+        );
+        strings::decode(&self.buffer[pending.position..pending.position + pending.len])
+    }
+}
+
+/// A set of requests prepared once and processed as many times as needed
+/// It is meant for poll loops requesting the same offsets on every cycle. The requests and the
+/// memory they are read into or written from are laid out once, when the session is prepared,
+/// so each cycle only replays them into a session of the handle. Like in a `BatchReader`, the
+/// values are accessed with `Pending<T>` tokens, which remain valid across executions.
+pub struct PreparedSession {
+    id: usize,
+    requests: Vec<(Direction, u16, usize, usize)>,
+    buffer: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Read,
+    Write,
+}
+
+impl Default for PreparedSession {
+    fn default() -> Self {
+        PreparedSession::new()
+    }
+}
+
+impl PreparedSession {
+    pub fn new() -> Self {
+        PreparedSession {
+            id: NEXT_BATCH_ID.fetch_add(1, Ordering::Relaxed),
+            requests: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Request reading a value of type `T` from the given offset on every execution.
+    pub fn read<T: Copy>(&mut self, offset: u16) -> Pending<T> {
+        let position = self.allocate(Direction::Read, offset, size_of::<T>());
+        Pending {
+            batch: self.id,
+            position,
+            _type: PhantomData,
+        }
+    }
+
+    /// Request reading a catalog offset on every execution.
+    pub fn read_offset<T: Copy>(&mut self, offset: Offset<T>) -> Pending<T> {
+        self.read(offset.address())
+    }
+
+    /// Request reading a string offset of up to `max_len` bytes on every execution.
+    pub fn read_string(&mut self, offset: u16, max_len: usize) -> PendingString {
+        let position = self.allocate(Direction::Read, offset, max_len);
+        PendingString {
+            batch: self.id,
+            position,
+            len: max_len,
+        }
+    }
+
+    /// Request writing a value into the given offset on every execution.
+    /// The value written is `initial` until it is changed with `set()`.
+    pub fn write<T: Copy>(&mut self, offset: u16, initial: T) -> Pending<T> {
+        let position = self.allocate(Direction::Write, offset, size_of::<T>());
+        let pending = Pending {
+            batch: self.id,
+            position,
+            _type: PhantomData,
+        };
+        self.set(&pending, initial);
+        pending
+    }
+
+    /// Process all the requests in a single session of the given handle.
+    pub fn execute<'a, H: Handle<'a>>(&mut self, handle: &'a mut H) -> io::Result<usize> {
+        let mut session = handle.session();
+        for (direction, offset, position, len) in self.requests.iter() {
+            let data = self.buffer[*position..].as_mut_ptr();
+            match direction {
+                Direction::Read => session.read_bytes(*offset, data, *len)?,
+                Direction::Write => session.write_bytes(*offset, data, *len)?,
+            };
+        }
+        session.process()
+    }
+
+    /// Process all the requests right after the 18 Hz tick counter of the simulator changes.
+    /// The counter is not per frame: it changes about every 55 ms, so this only aligns the
+    /// requests to that granularity. A maneuver written to several offsets, as a reposition,
+    /// is then processed early in a tick rather than at any time. It fails with
+    /// `FsuipcError::Timeout` if the counter does not change within `timeout`, e.g. while paused.
+    pub fn execute_on_tick18<H>(&mut self, handle: &mut H, timeout: Duration) -> io::Result<usize>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let start = Instant::now();
+        let first = read_tick(handle)?;
+        while read_tick(handle)? == first {
+            if start.elapsed() >= timeout {
+                return Err(FsuipcError::Timeout(timeout).into());
+            }
+            thread::sleep(TICK_POLL_INTERVAL);
+        }
+        self.execute(handle)
+    }
+
+    /// Obtain the value of a pending read as of the last execution, or the value to write.
+    /// It panics if the token was obtained from a different session.
+    pub fn get<T: Copy>(&self, pending: &Pending<T>) -> T {
+        assert_eq!(
+            pending.batch, self.id,
+            "pending read from a different batch"
+        );
+        let bytes = &self.buffer[pending.position..pending.position + size_of::<T>()];
+        unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+    }
+
+    /// Obtain the value of a pending string read as of the last execution.
+    /// It panics if the token was obtained from a different session.
+    pub fn get_string(&self, pending: &PendingString) -> String {
+        assert_eq!(
+            pending.batch, self.id,
+            "pending read from a different batch"
+        );
+        strings::decode(&self.buffer[pending.position..pending.position + pending.len])
+    }
+
+    /// Change the value written by the next executions.
+    /// It panics if the token was obtained from a different session.
+    pub fn set<T: Copy>(&mut self, pending: &Pending<T>, value: T) {
+        assert_eq!(
+            pending.batch, self.id,
+            "pending write from a different batch"
+        );
+        let bytes = &mut self.buffer[pending.position..pending.position + size_of::<T>()];
+        unsafe { ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, value) };
+    }
+
+    fn allocate(&mut self, direction: Direction, offset: u16, len: usize) -> usize {
+        let position = self.buffer.len();
+        self.requests.push((direction, offset, position, len));
+        self.buffer.resize(position + len, 0);
+        position
+    }
+}
+
+fn read_tick<H>(handle: &mut H) -> io::Result<u32>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut tick = 0;
+    let mut session = handle.session();
+    session.read_offset(offsets::TICK18, &mut tick)?;
+    session.process()?;
+    Ok(tick)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::offsets;
+    use crate::Session;
+
+    #[test]
+    fn should_resolve_pending_reads() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0238, &12u8);
+        handle.seed_value(0x0239, &34u8);
+        handle.seed_value(0x3324, &1500i32);
+
+        let mut batch = BatchReader::new();
+        let hour = batch.read_offset(offsets::SIM_LOCAL_HOUR);
+        let minute = batch.read::<u8>(0x0239);
+        let altitude = batch.read::<i32>(0x3324);
+        let results = batch.process(&mut handle).unwrap();
+
+        assert_eq!(results.get(&hour), 12);
+        assert_eq!(results.get(&minute), 34);
+        assert_eq!(results.get(&altitude), 1500);
+        assert_eq!(handle.transactions(), 1);
+    }
+
+    #[test]
+    fn should_read_and_write_strings() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            session.write_string(0x313C, "EC-MYA", 12).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(handle.bytes(0x313C, 12), b"EC-MYA\0\0\0\0\0\0".to_vec());
+
+        let mut batch = BatchReader::new();
+        let atc_id = batch.read_string(0x313C, 12);
+        let results = batch.process(&mut handle).unwrap();
+        assert_eq!(results.get_string(&atc_id), "EC-MYA");
+    }
+
+    #[test]
+    #[should_panic(expected = "different batch")]
+    fn should_reject_pending_reads_from_other_batches() {
+        let mut handle = MockHandle::new();
+        let mut first = BatchReader::new();
+        let hour = first.read::<u8>(0x0238);
+        let second = BatchReader::new();
+        let results = second.process(&mut handle).unwrap();
+        results.get(&hour);
+    }
+
+    #[test]
+    fn should_execute_prepared_sessions_repeatedly() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x3324, &1500i32);
+
+        let mut prepared = PreparedSession::new();
+        let qnh = prepared.write(0x0330, 16211u16);
+        let altitude = prepared.read::<i32>(0x3324);
+        let read_back = prepared.read::<u16>(0x0330);
+        prepared.execute(&mut handle).unwrap();
+        assert_eq!(prepared.get(&altitude), 1500);
+        assert_eq!(prepared.get(&read_back), 16211);
+
+        handle.seed_value(0x3324, &1600i32);
+        prepared.set(&qnh, 16000);
+        prepared.execute(&mut handle).unwrap();
+        assert_eq!(prepared.get(&altitude), 1600);
+        assert_eq!(prepared.get(&read_back), 16000);
+        assert_eq!(prepared.get(&qnh), 16000);
+        assert_eq!(handle.transactions(), 2);
+    }
+
+    #[test]
+    fn should_execute_prepared_sessions_on_tick18() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0310, &100u32);
+        handle.schedule_seed(3, 0x0310, &101u32.to_le_bytes());
+
+        let mut prepared = PreparedSession::new();
+        prepared.write(0x0560, 1i64);
+        prepared.write(0x0568, 2i64);
+        prepared
+            .execute_on_tick18(&mut handle, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(handle.value::<i64>(0x0568), 2);
+        assert_eq!(handle.transactions(), 5);
+
+        let error = prepared
+            .execute_on_tick18(&mut handle, Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,298 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Braking action and runway remaining
+//! The simulator does not tell how well the aircraft brakes, but it can be estimated from the
+//! type and condition of the surface under it: `BrakingSample` reads them along with the ground
+//! speed and the brake inputs. A `RunwayMonitor`, given the length of the runway, tracks the
+//! distance rolled since touchdown and calls out the runway remaining, warning when the
+//! aircraft is not expected to stop before its end. These are heuristics for landing
+//! performance tools, not certified figures.
+
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use super::geo::FEET_PER_METRE;
+use super::icing::SurfaceCondition;
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The standard acceleration of gravity, in metres/second².
+const GRAVITY: f64 = 9.80665;
+
+/// The deceleration of the rolling friction alone, as a fraction of gravity.
+const ROLLING_FRICTION: f64 = 0.02;
+
+/// The ground speed below which the aircraft is taken as stopped, in knots.
+const STOPPED_SPEED: f64 = 5.0;
+
+/// How well the aircraft brakes, as reported to pilots
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BrakingAction {
+    Nil,
+    Poor,
+    Medium,
+    Good,
+}
+
+impl BrakingAction {
+    /// The deceleration with full brakes, as a fraction of gravity.
+    pub fn friction(self) -> f64 {
+        match self {
+            BrakingAction::Good => 0.4,
+            BrakingAction::Medium => 0.25,
+            BrakingAction::Poor => 0.1,
+            BrakingAction::Nil => 0.05,
+        }
+    }
+}
+
+impl fmt::Display for BrakingAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self {
+            BrakingAction::Good => "good",
+            BrakingAction::Medium => "medium",
+            BrakingAction::Poor => "poor",
+            BrakingAction::Nil => "nil",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+/// The flight data the braking performance is estimated from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BrakingSample {
+    pub on_ground: u16,
+    pub ground_speed: u32,
+    pub surface_type: u32,
+    pub surface_condition: u32,
+    pub left_brake: i16,
+    pub right_brake: i16,
+}
+
+impl BrakingSample {
+    /// The ground speed, in knots.
+    pub fn ground_speed_knots(&self) -> f64 {
+        self.ground_speed as f64 / 65536.0 * 3600.0 / 1852.0
+    }
+
+    /// The brake input, from 0 (released) to 1 (full), as the mean of both toe brakes.
+    pub fn brake_input(&self) -> f64 {
+        let input = (self.left_brake as f64 + self.right_brake as f64) / 2.0 / 16383.0;
+        input.clamp(0.0, 1.0)
+    }
+
+    /// The braking action of the surface, the worst of its type and its condition.
+    pub fn braking_action(&self) -> BrakingAction {
+        let by_type = match self.surface_type {
+            // Concrete, asphalt, urban, bituminous, brick, macadam, tarmac
+            0 | 4 | 10 | 17 | 18 | 19 | 23 => BrakingAction::Good,
+            // Water
+            2 => BrakingAction::Nil,
+            // Snow, ice
+            8 | 9 => BrakingAction::Poor,
+            _ => BrakingAction::Medium,
+        };
+        let by_condition = match SurfaceCondition::from(self.surface_condition) {
+            SurfaceCondition::Normal | SurfaceCondition::Other(_) => BrakingAction::Good,
+            SurfaceCondition::Wet => BrakingAction::Medium,
+            SurfaceCondition::Snow => BrakingAction::Poor,
+            SurfaceCondition::Icy => BrakingAction::Nil,
+        };
+        by_type.min(by_condition)
+    }
+
+    /// The expected deceleration with the current brake input, in metres/second².
+    pub fn deceleration(&self) -> f64 {
+        let friction = self.braking_action().friction() * self.brake_input();
+        (friction + ROLLING_FRICTION) * GRAVITY
+    }
+
+    /// The distance to stop with the current brake input, in feet.
+    pub fn stopping_distance(&self) -> f64 {
+        let speed = self.ground_speed as f64 / 65536.0;
+        speed * speed / (2.0 * self.deceleration()) * FEET_PER_METRE
+    }
+}
+
+impl FsuipcStruct for BrakingSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::SURFACE_TYPE, &mut self.surface_type)?;
+        session.read_offset(offsets::SURFACE_CONDITION, &mut self.surface_condition)?;
+        session.read_offset(offsets::LEFT_BRAKE, &mut self.left_brake)?;
+        session.read_offset(offsets::RIGHT_BRAKE, &mut self.right_brake)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An advisory of a `RunwayMonitor`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunwayAdvisory {
+    /// The runway remaining, in feet: the multiple of the callout interval just passed.
+    Remaining(u32),
+    /// The aircraft is not expected to stop before the end of the runway with the current
+    /// braking. It is given once per landing roll.
+    Overrun {
+        braking_action: BrakingAction,
+        stopping_distance: f64,
+        remaining: f64,
+    },
+}
+
+impl RunwayAdvisory {
+    /// The advisory as a message to show or speak.
+    pub fn message(&self) -> String {
+        match self {
+            RunwayAdvisory::Remaining(feet) => format!("{} remaining", feet),
+            RunwayAdvisory::Overrun { braking_action, .. } => {
+                format!("Braking action {}, runway overrun", braking_action)
+            }
+        }
+    }
+}
+
+/// A tracker of the runway remaining during the landing roll
+#[derive(Clone, Copy, Debug)]
+pub struct RunwayMonitor {
+    length: f64,
+    interval: f64,
+    rolled: Option<f64>,
+    warned: bool,
+}
+
+impl RunwayMonitor {
+    /// Track a runway of the given length, in feet, from touchdown, calling out every 1000 feet.
+    pub fn new(length: f64) -> Self {
+        RunwayMonitor {
+            length,
+            interval: 1000.0,
+            rolled: None,
+            warned: false,
+        }
+    }
+
+    /// Call out the runway remaining every given feet.
+    pub fn with_callout_interval(mut self, feet: f64) -> Self {
+        self.interval = feet;
+        self
+    }
+
+    /// The runway remaining, in feet, or `None` if not on the runway.
+    pub fn remaining(&self) -> Option<f64> {
+        Some(self.length - self.rolled?)
+    }
+
+    /// Update the roll with a sample taken `elapsed` after the previous one.
+    /// The roll starts when the aircraft touches down, and ends once it is stopped or airborne.
+    pub fn update(&mut self, sample: &BrakingSample, elapsed: Duration) -> Vec<RunwayAdvisory> {
+        let mut advisories = Vec::new();
+        if sample.on_ground == 0 || sample.ground_speed_knots() < STOPPED_SPEED {
+            self.rolled = None;
+            self.warned = false;
+            return advisories;
+        }
+        let before = match self.rolled {
+            Some(rolled) => rolled,
+            None => {
+                self.rolled = Some(0.0);
+                return advisories;
+            }
+        };
+        let speed = sample.ground_speed as f64 / 65536.0 * FEET_PER_METRE;
+        let rolled = before + speed * elapsed.as_secs_f64();
+        self.rolled = Some(rolled);
+
+        let remaining = self.length - rolled;
+        if self.interval > 0.0 && remaining > 0.0 {
+            let mark = (remaining / self.interval).ceil();
+            if mark < ((self.length - before) / self.interval).ceil() {
+                advisories.push(RunwayAdvisory::Remaining((mark * self.interval) as u32));
+            }
+        }
+        let stopping_distance = sample.stopping_distance();
+        if !self.warned && stopping_distance > remaining {
+            self.warned = true;
+            advisories.push(RunwayAdvisory::Overrun {
+                braking_action: sample.braking_action(),
+                stopping_distance,
+                remaining,
+            });
+        }
+        advisories
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn rolling(knots: f64, surface_condition: u32, brakes: i16) -> BrakingSample {
+        BrakingSample {
+            on_ground: 1,
+            ground_speed: (knots * 1852.0 / 3600.0 * 65536.0) as u32,
+            surface_type: 4,
+            surface_condition,
+            left_brake: brakes,
+            right_brake: brakes,
+        }
+    }
+
+    #[test]
+    fn should_estimate_braking_action() {
+        assert_eq!(rolling(60.0, 0, 0).braking_action(), BrakingAction::Good);
+        assert_eq!(rolling(60.0, 1, 0).braking_action(), BrakingAction::Medium);
+        assert_eq!(rolling(60.0, 2, 0).braking_action(), BrakingAction::Nil);
+        let grass = BrakingSample {
+            surface_type: 1,
+            ..rolling(60.0, 3, 0)
+        };
+        assert_eq!(grass.braking_action(), BrakingAction::Poor);
+
+        let dry = rolling(100.0, 0, 16383).stopping_distance();
+        let wet = rolling(100.0, 1, 16383).stopping_distance();
+        let coasting = rolling(100.0, 0, 0).stopping_distance();
+        assert!(dry < wet && wet < coasting);
+        assert!((dry - 1053.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn should_call_out_runway_remaining() {
+        let mut monitor = RunwayMonitor::new(6000.0);
+        let second = Duration::from_secs(1);
+        assert!(monitor.update(&rolling(120.0, 0, 0), second).is_empty());
+        assert_eq!(monitor.remaining(), Some(6000.0));
+
+        // 120 knots roll about 203 feet per second.
+        let advisories: Vec<RunwayAdvisory> = (0..6)
+            .flat_map(|_| monitor.update(&rolling(120.0, 0, 16383), second))
+            .collect();
+        assert_eq!(advisories, vec![RunwayAdvisory::Remaining(5000)]);
+        assert_eq!(advisories[0].message(), "5000 remaining");
+
+        let advisories = monitor.update(&rolling(120.0, 2, 16383), second);
+        match advisories.as_slice() {
+            [RunwayAdvisory::Overrun {
+                braking_action: BrakingAction::Nil,
+                ..
+            }] => {}
+            other => panic!("unexpected advisories {:?}", other),
+        }
+        assert!(monitor.update(&rolling(120.0, 2, 16383), second).is_empty());
+
+        monitor.update(&rolling(0.0, 0, 0), second);
+        assert_eq!(monitor.remaining(), None);
+    }
+}
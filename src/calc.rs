@@ -0,0 +1,93 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! MSFS calculator code
+//! Many MSFS aircraft are only operated through the gauge calculator language, an RPN such as
+//! `1 (>L:A32NX_OVHD_APU_START_PB_IS_ON)` or `(>H:A320_Neo_CDU_1_BTN_EXEC)`. FSUIPC7 passes
+//! the code written to offset 0x7C50 to its WASM module, which executes it in the simulator.
+//! HVars have no value: they are activated by the code `(>H:<name>)`.
+
+use std::io;
+
+use super::lua::write_command;
+use super::offsets;
+use super::Session;
+
+/// Request executing calculator code.
+/// It fails with `InvalidInput` if the code is empty or longer than the 255 bytes the offset
+/// takes.
+pub fn execute_calc<S: Session + ?Sized>(session: &mut S, code: &str) -> io::Result<usize> {
+    let code = code.trim();
+    if code.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the calculator code is empty",
+        ));
+    }
+    write_command(session, offsets::CALCULATOR_CODE, code)
+}
+
+/// Request activating the HVar with the given name.
+/// The name may have the `H:` prefix or not.
+pub fn activate_hvar<S: Session + ?Sized>(session: &mut S, name: &str) -> io::Result<usize> {
+    let name = name.trim();
+    let name = name.strip_prefix("H:").unwrap_or(name);
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ')') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid HVar name '{}'", name),
+        ));
+    }
+    execute_calc(session, &format!("(>H:{})", name))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::strings;
+    use crate::Handle;
+
+    #[test]
+    fn should_write_calculator_code() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            session
+                .execute_calc(" 1 (>K:TOGGLE_BEACON_LIGHTS) ")
+                .unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(
+            strings::decode(&handle.bytes(0x7C50, 256)),
+            "1 (>K:TOGGLE_BEACON_LIGHTS)"
+        );
+
+        {
+            let mut session = handle.session();
+            activate_hvar(&mut session, "H:A320_Neo_CDU_1_BTN_EXEC").unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(
+            strings::decode(&handle.bytes(0x7C50, 256)),
+            "(>H:A320_Neo_CDU_1_BTN_EXEC)"
+        );
+    }
+
+    #[test]
+    fn should_refuse_invalid_code() {
+        let mut handle = MockHandle::new();
+        let mut session = handle.session();
+        assert!(session.execute_calc("  ").is_err());
+        assert!(session.execute_calc(&"1".repeat(256)).is_err());
+        assert!(session.execute_calc(&"1".repeat(255)).is_ok());
+        assert!(activate_hvar(&mut session, "H:").is_err());
+        assert!(activate_hvar(&mut session, "A) (>K:PAUSE_ON").is_err());
+    }
+}
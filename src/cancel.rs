@@ -0,0 +1,165 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Cancellation of long-running operations
+//! A `CancellationToken` is shared by an application with the pollers, servers and handles it
+//! runs, through their `with_cancellation()` methods. Cancelling it stops all of them at once:
+//! their worker threads wait on the token rather than sleeping, so they wake up and finish
+//! as soon as it is cancelled. Each of them works on a child of the given token, so dropping
+//! one does not cancel the others.
+
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+struct State {
+    cancelled: bool,
+    children: Vec<Weak<Inner>>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl Inner {
+    fn cancel(&self) {
+        let children = {
+            let mut state = self.state.lock().unwrap();
+            if state.cancelled {
+                return;
+            }
+            state.cancelled = true;
+            mem::take(&mut state.children)
+        };
+        self.wake.notify_all();
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+/// A token to cancel long-running operations
+/// Clones of a token share its state: cancelling any of them cancels all.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    cancelled: false,
+                    children: Vec::new(),
+                }),
+                wake: Condvar::new(),
+            }),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// A token cancelled along with this one, that can also be cancelled on its own.
+    pub fn child(&self) -> Self {
+        let child = CancellationToken::new();
+        self.attach(&child);
+        child
+    }
+
+    /// Cancel the given token along with this one, at once if this one is already cancelled.
+    pub fn attach(&self, child: &CancellationToken) {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if !state.cancelled {
+                state.children.retain(|child| child.strong_count() > 0);
+                state.children.push(Arc::downgrade(&child.inner));
+                return;
+            }
+        }
+        child.cancel();
+    }
+
+    /// Cancel the token and its children, waking up whoever waits on them.
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.state.lock().unwrap().cancelled
+    }
+
+    /// Wait until the token is cancelled, at most `timeout`.
+    /// It returns whether the token is cancelled, so it replaces sleeps in cancellable loops.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.inner.state.lock().unwrap();
+        while !state.cancelled {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self
+                .inner
+                .wake
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        state.cancelled
+    }
+
+    /// Wait until the token is cancelled.
+    pub fn wait(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        while !state.cancelled {
+            state = self.inner.wake.wait(state).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn should_cancel_children() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let sibling = parent.child();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(!sibling.is_cancelled());
+
+        parent.cancel();
+        assert!(sibling.is_cancelled());
+        assert!(parent.child().is_cancelled());
+    }
+
+    #[test]
+    fn should_wake_up_waiters_when_cancelled() {
+        let parent = CancellationToken::new();
+        let child = parent.child();
+        let waiter = thread::spawn(move || child.wait_timeout(Duration::from_secs(30)));
+        thread::sleep(Duration::from_millis(20));
+        let start = Instant::now();
+        parent.cancel();
+        assert!(waiter.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!CancellationToken::new().wait_timeout(Duration::from_millis(1)));
+    }
+}
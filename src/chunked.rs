@@ -0,0 +1,193 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::slice;
+
+use super::error::FsuipcError;
+use super::{Handle, Session};
+
+enum Request {
+    Read {
+        offset: u16,
+        dest: *mut u8,
+        len: usize,
+    },
+    Write {
+        offset: u16,
+        data: Vec<u8>,
+    },
+}
+
+/// A session with no limit on the number of requests
+/// The requests are accumulated and, when processed, split into as many sessions of the
+/// underlying handle as needed: whenever a request does not fit in the buffer of the current
+/// session (it fails with `FsuipcError::BufferOverflow`), that session is processed and the
+/// request goes to a new one. The requests are processed in order, but not atomically: offsets
+/// that must come from the same frame are better read as a `ConsistencyGroup`.
+pub struct ChunkedSession<'a, H> {
+    handle: &'a mut H,
+    requests: Vec<Request>,
+}
+
+impl<'a, H> ChunkedSession<'a, H>
+where
+    H: for<'h> Handle<'h>,
+{
+    pub fn new(handle: &'a mut H) -> Self {
+        ChunkedSession {
+            handle,
+            requests: Vec::new(),
+        }
+    }
+}
+
+impl<'a, H> Session for ChunkedSession<'a, H>
+where
+    H: for<'h> Handle<'h>,
+{
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.requests.push(Request::Read { offset, dest, len });
+        Ok(len)
+    }
+
+    // As for any other session, the caller guarantees `src` points to `len` readable bytes
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        let data = unsafe { slice::from_raw_parts(src, len) }.to_vec();
+        self.requests.push(Request::Write { offset, data });
+        Ok(len)
+    }
+
+    fn process(self) -> io::Result<usize> {
+        let mut processed = 0;
+        let mut requests = self.requests.iter().peekable();
+        while requests.peek().is_some() {
+            let mut session = self.handle.session();
+            let mut queued = 0;
+            while let Some(request) = requests.peek() {
+                let result = match request {
+                    Request::Read { offset, dest, len } => session.read_bytes(*offset, *dest, *len),
+                    Request::Write { offset, data } => {
+                        session.write_bytes(*offset, data.as_ptr(), data.len())
+                    }
+                };
+                match result {
+                    Ok(_) => {
+                        queued += 1;
+                        requests.next();
+                    }
+                    Err(ref e) if queued > 0 && is_overflow(e) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            processed += session.process()?;
+        }
+        Ok(processed)
+    }
+}
+
+fn is_overflow(error: &io::Error) -> bool {
+    matches!(
+        FsuipcError::from_io(error),
+        Some(FsuipcError::BufferOverflow { .. })
+    )
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::{MockHandle, MockSession};
+
+    // A mock handle whose sessions only take `capacity` bytes of requests
+    struct LimitedHandle {
+        inner: MockHandle,
+        capacity: usize,
+    }
+
+    struct LimitedSession<'a> {
+        inner: MockSession<'a>,
+        available: usize,
+    }
+
+    impl<'a> Handle<'a> for LimitedHandle {
+        type Sess = LimitedSession<'a>;
+
+        fn session(&'a mut self) -> LimitedSession<'a> {
+            LimitedSession {
+                available: self.capacity,
+                inner: self.inner.session(),
+            }
+        }
+    }
+
+    impl<'a> LimitedSession<'a> {
+        fn reserve(&mut self, needed: usize) -> io::Result<()> {
+            if needed > self.available {
+                return Err(FsuipcError::BufferOverflow {
+                    needed,
+                    available: self.available,
+                }
+                .into());
+            }
+            self.available -= needed;
+            Ok(())
+        }
+    }
+
+    impl<'a> Session for LimitedSession<'a> {
+        fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            self.reserve(len)?;
+            self.inner.read_bytes(offset, dest, len)
+        }
+
+        fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+            self.reserve(len)?;
+            self.inner.write_bytes(offset, src, len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            self.inner.process()
+        }
+    }
+
+    #[test]
+    fn should_split_requests_into_several_sessions() {
+        let mut handle = LimitedHandle {
+            inner: MockHandle::new(),
+            capacity: 8,
+        };
+        let mut values = [0u32; 5];
+        {
+            let mut session = ChunkedSession::new(&mut handle);
+            for (i, value) in (0..5u32).enumerate() {
+                session.write(0x66C0 + 4 * i as u16, &value).unwrap();
+            }
+            for (i, value) in values.iter_mut().enumerate() {
+                session.read(0x66C0 + 4 * i as u16, value).unwrap();
+            }
+            assert_eq!(session.process().unwrap(), 40);
+        }
+        assert_eq!(values, [0, 1, 2, 3, 4]);
+        assert_eq!(handle.inner.transactions(), 5);
+    }
+
+    #[test]
+    fn should_fail_with_requests_larger_than_a_session() {
+        let mut handle = LimitedHandle {
+            inner: MockHandle::new(),
+            capacity: 8,
+        };
+        let mut title = [0u8; 16];
+        let mut session = ChunkedSession::new(&mut handle);
+        session.read(0x3D00, &mut title).unwrap();
+        let error = session.process().err().unwrap();
+        assert!(is_overflow(&error));
+    }
+}
@@ -0,0 +1,269 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+/// The offsets documented by FSUIPC as free for general use by any application.
+pub const GENERAL_USE_OFFSETS: Range<u16> = 0x66C0..0x6700;
+
+/// A range of offsets claimed by some part of an application
+#[derive(Clone, Debug, PartialEq)]
+pub struct Claim {
+    owner: String,
+    offset: u16,
+    len: usize,
+}
+
+impl Claim {
+    /// The name of the component that claimed the offsets.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// The first claimed offset.
+    pub fn offset(&self) -> u16 {
+        self.offset
+    }
+
+    /// The number of claimed bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this claim covers no bytes at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn end(&self) -> usize {
+        self.offset as usize + self.len
+    }
+
+    fn overlaps(&self, offset: u16, len: usize) -> bool {
+        (offset as usize) < self.end() && (self.offset as usize) < offset as usize + len
+    }
+}
+
+impl fmt::Display for Claim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "0x{:04X}..0x{:04X} claimed by `{}`",
+            self.offset,
+            self.end(),
+            self.owner
+        )
+    }
+}
+
+/// A registry of the offsets claimed by the components of an application
+/// Components that store their own data in FSUIPC offsets (typically in the general use area)
+/// register their ranges here, so two of them cannot silently overwrite each other's data.
+#[derive(Clone, Debug, Default)]
+pub struct OffsetClaims {
+    claims: Vec<Claim>,
+}
+
+impl OffsetClaims {
+    pub fn new() -> Self {
+        OffsetClaims::default()
+    }
+
+    /// Claim `len` bytes from `offset` on behalf of `owner`.
+    /// It fails with `AddrInUse` if any of the bytes is already claimed, even by `owner` itself.
+    pub fn claim(&mut self, owner: &str, offset: u16, len: usize) -> io::Result<()> {
+        if offset as usize + len > 0x10000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "`{}` cannot claim {} bytes from 0x{:04X}: out of the offset space",
+                    owner, len, offset
+                ),
+            ));
+        }
+        if let Some(conflict) = self.claims.iter().find(|c| c.overlaps(offset, len)) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "offset conflict: `{}` requested 0x{:04X}..0x{:04X}, but 0x{:04X}..0x{:04X} is already claimed by `{}`",
+                    owner,
+                    offset,
+                    offset as usize + len,
+                    conflict.offset,
+                    conflict.end(),
+                    conflict.owner
+                ),
+            ));
+        }
+        self.claims.push(Claim {
+            owner: owner.to_string(),
+            offset,
+            len,
+        });
+        Ok(())
+    }
+
+    /// Claim the first free gap of `len` bytes within `area` on behalf of `owner`.
+    /// It returns the offset of the claimed bytes, or fails with `InvalidInput` if `area` ends
+    /// before it starts.
+    pub fn allocate(&mut self, owner: &str, len: usize, area: Range<u16>) -> io::Result<u16> {
+        if area.start > area.end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "`{}` cannot allocate in 0x{:04X}..0x{:04X}: the area ends before it starts",
+                    owner, area.start, area.end
+                ),
+            ));
+        }
+        let mut candidate = area.start as usize;
+        while candidate + len <= area.end as usize {
+            match self
+                .claims
+                .iter()
+                .filter(|c| c.overlaps(candidate as u16, len))
+                .map(Claim::end)
+                .max()
+            {
+                Some(end) => candidate = end,
+                None => {
+                    self.claim(owner, candidate as u16, len)?;
+                    return Ok(candidate as u16);
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!(
+                "`{}` cannot allocate {} bytes in 0x{:04X}..0x{:04X}: no free gap left ({})",
+                owner,
+                len,
+                area.start,
+                area.end,
+                self.describe(area.clone())
+            ),
+        ))
+    }
+
+    /// Release all the offsets claimed by `owner`.
+    pub fn release(&mut self, owner: &str) {
+        self.claims.retain(|c| c.owner != owner);
+    }
+
+    /// Obtain the claim covering the given offset, if any.
+    pub fn claim_at(&self, offset: u16) -> Option<&Claim> {
+        self.claims.iter().find(|c| c.overlaps(offset, 1))
+    }
+
+    /// Iterate over all the registered claims.
+    pub fn iter(&self) -> impl Iterator<Item = &Claim> {
+        self.claims.iter()
+    }
+
+    fn describe(&self, area: Range<u16>) -> String {
+        let claims: Vec<String> = self
+            .claims
+            .iter()
+            .filter(|c| c.overlaps(area.start, (area.end - area.start) as usize))
+            .map(Claim::to_string)
+            .collect();
+        claims.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn should_claim_disjoint_ranges() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 8).unwrap();
+        claims.claim("irs", 0x66C8, 4).unwrap();
+        assert_eq!(claims.claim_at(0x66C7).unwrap().owner(), "store");
+        assert_eq!(claims.claim_at(0x66C8).unwrap().owner(), "irs");
+        assert!(claims.claim_at(0x66CC).is_none());
+    }
+
+    #[test]
+    fn should_detect_overlapping_claims() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 8).unwrap();
+        let error = claims.claim("irs", 0x66C4, 4).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AddrInUse);
+        assert!(error.to_string().contains("`store`"));
+        assert!(error.to_string().contains("`irs`"));
+    }
+
+    #[test]
+    fn should_reject_overlapping_claims_of_the_same_owner() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 8).unwrap();
+        let error = claims.claim("store", 0x66C4, 4).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn should_reject_reversed_areas() {
+        let mut claims = OffsetClaims::new();
+        let reversed = Range {
+            start: 0x6700,
+            end: 0x66C0,
+        };
+        let error = claims.allocate("store", 4, reversed).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(claims.iter().count(), 0);
+    }
+
+    #[test]
+    fn should_reject_claims_out_of_offset_space() {
+        let mut claims = OffsetClaims::new();
+        let error = claims.claim("store", 0xFFFE, 4).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn should_allocate_first_free_gap() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 4).unwrap();
+        claims.claim("irs", 0x66C6, 2).unwrap();
+        assert_eq!(
+            claims.allocate("lvars", 2, GENERAL_USE_OFFSETS).unwrap(),
+            0x66C4
+        );
+        assert_eq!(
+            claims.allocate("lvars", 4, GENERAL_USE_OFFSETS).unwrap(),
+            0x66C8
+        );
+    }
+
+    #[test]
+    fn should_fail_to_allocate_in_full_area() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 64).unwrap();
+        let error = claims
+            .allocate("lvars", 1, GENERAL_USE_OFFSETS)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::AddrNotAvailable);
+    }
+
+    #[test]
+    fn should_release_claims_by_owner() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 8).unwrap();
+        claims.release("store");
+        claims.claim("irs", 0x66C0, 8).unwrap();
+        assert_eq!(claims.iter().count(), 1);
+    }
+}
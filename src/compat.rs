@@ -0,0 +1,58 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::sys::{self, WindowHandle};
+
+/// Obtain the version of Wine (or Proton) this process runs on, if any
+/// Wine exports `wine_get_version()` from its `ntdll.dll`, while genuine Windows does not.
+pub fn wine_version() -> Option<String> {
+    sys::wine_version()
+}
+
+/// Check whether this process runs on Wine (or Proton)
+pub fn is_wine() -> bool {
+    wine_version().is_some()
+}
+
+/// A hint to append to connection errors when running on Wine
+/// Under Wine, FSUIPC windows and global atoms are only visible to processes sharing the same
+/// wineserver, i.e. launched in the same prefix (and with the same Proton build).
+pub(crate) fn connection_hint() -> String {
+    match wine_version() {
+        Some(version) => format!(
+            " (running on Wine {}: make sure the simulator runs in the same Wine prefix)",
+            version
+        ),
+        None => String::new(),
+    }
+}
+
+/// Obtain the mandatory integrity level of the given process (e.g. 0x2000 for medium,
+/// 0x3000 for high, i.e. elevated)
+pub fn integrity_level(process_id: u32) -> Option<u32> {
+    sys::integrity_level(process_id)
+}
+
+/// Explain why messages to the given window are blocked, if it is due to an integrity mismatch
+/// Windows silently drops messages sent to windows of processes with a higher integrity level
+/// (User Interface Privilege Isolation), which happens when only the simulator is elevated.
+pub(crate) fn integrity_mismatch(window: WindowHandle) -> Option<String> {
+    let sim_process_id = sys::window_process_id(window);
+    let sim_level = integrity_level(sim_process_id)?;
+    let own_level = integrity_level(sys::current_process_id())?;
+    if sim_level > own_level {
+        Some(format!(
+            "the simulator (process {}) runs elevated (integrity level 0x{:X}) but this process \
+             does not (integrity level 0x{:X}); Windows blocks messages between them, so run \
+             both as administrator or neither",
+            sim_process_id, sim_level, own_level
+        ))
+    } else {
+        None
+    }
+}
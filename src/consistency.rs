@@ -0,0 +1,154 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Offsets read together
+//! FSUIPC answers all the requests of a transaction at once, so the offsets read in a single
+//! session come from the same frame of the simulator. Reads split across sessions, as the ones
+//! of a `ChunkedSession`, may mix frames: a motion platform could see the position of a frame
+//! with the attitude of the next. A `ConsistencyGroup` is a set of offsets that is always read
+//! in a session of its own, and never partially.
+
+use std::io;
+use std::mem::size_of;
+use std::ptr;
+
+use super::error::FsuipcError;
+use super::ipc::{RSD_HEADER_LEN, TERMINATION_MARK_LEN};
+use super::offsets::{self, Offset};
+use super::{Handle, Session};
+
+/// A set of offsets read in a single transaction
+/// The group owns the memory the offsets are read into. Their values are obtained with
+/// `value()` once the group has been read.
+#[derive(Clone, Debug, Default)]
+pub struct ConsistencyGroup {
+    /// The offset, position in `data` and length of each member.
+    members: Vec<(u16, usize, usize)>,
+    data: Vec<u8>,
+}
+
+impl ConsistencyGroup {
+    pub fn new() -> Self {
+        ConsistencyGroup::default()
+    }
+
+    /// The group of the position and attitude of the aircraft: latitude, longitude, altitude,
+    /// pitch, bank and heading.
+    pub fn position_attitude() -> Self {
+        ConsistencyGroup::new()
+            .with(offsets::LATITUDE)
+            .with(offsets::LONGITUDE)
+            .with(offsets::ALTITUDE)
+            .with(offsets::PITCH)
+            .with(offsets::BANK)
+            .with(offsets::HEADING)
+    }
+
+    /// Add a catalog offset to the group.
+    pub fn with<T>(self, offset: Offset<T>) -> Self {
+        self.with_bytes(offset.address(), offset.len())
+    }
+
+    /// Add `len` bytes at the given offset to the group.
+    pub fn with_bytes(mut self, offset: u16, len: usize) -> Self {
+        self.members.push((offset, self.data.len(), len));
+        self.data.resize(self.data.len() + len, 0);
+        self
+    }
+
+    /// The bytes the group takes in the buffer shared with FSUIPC.
+    pub fn transaction_len(&self) -> usize {
+        self.members
+            .iter()
+            .map(|(_, _, len)| RSD_HEADER_LEN + len)
+            .sum::<usize>()
+            + TERMINATION_MARK_LEN
+    }
+
+    /// Check that the group fits in a session of a handle with the given capacity.
+    /// It fails with `FsuipcError::BufferOverflow` otherwise. Checking the group against
+    /// `UserHandle::capacity()` when it is set up avoids finding out on the first read.
+    pub fn check_capacity(&self, capacity: usize) -> io::Result<()> {
+        let needed = self.transaction_len();
+        if needed > capacity {
+            return Err(FsuipcError::BufferOverflow {
+                needed,
+                available: capacity,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Read the whole group in a new session of the handle.
+    /// If the group does not fit in the session, it fails with `FsuipcError::BufferOverflow`
+    /// and nothing is read, so the values of the previous read are kept.
+    pub fn read<H>(&mut self, handle: &mut H) -> io::Result<usize>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut data = vec![0u8; self.data.len()];
+        {
+            let mut session = handle.session();
+            for (offset, position, len) in self.members.iter() {
+                // The session is dropped unprocessed if a member does not fit
+                session.read_bytes(*offset, data[*position..].as_mut_ptr(), *len)?;
+            }
+            session.process()?;
+        }
+        self.data = data;
+        Ok(self.data.len())
+    }
+
+    /// The value of a member as of the last read, or `None` if it is not in the group.
+    pub fn value<T: Copy>(&self, offset: Offset<T>) -> Option<T> {
+        self.members
+            .iter()
+            .find(|(address, _, len)| *address == offset.address() && *len == size_of::<T>())
+            .map(|(_, position, _)| unsafe {
+                ptr::read_unaligned(self.data[*position..].as_ptr() as *const T)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_read_group_in_one_transaction() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x0560, &12_345i64.to_le_bytes());
+        handle.seed(0x057C, &(-300i32).to_le_bytes());
+        let mut group = ConsistencyGroup::position_attitude();
+        assert_eq!(group.read(&mut handle).unwrap(), 36);
+        assert_eq!(handle.transactions(), 1);
+        assert_eq!(group.value(offsets::LATITUDE), Some(12_345));
+        assert_eq!(group.value(offsets::BANK), Some(-300));
+        assert_eq!(group.value(offsets::ON_GROUND), None);
+    }
+
+    #[test]
+    fn should_check_group_capacity() {
+        let group = ConsistencyGroup::position_attitude();
+        assert_eq!(
+            group.transaction_len(),
+            6 * RSD_HEADER_LEN + 36 + TERMINATION_MARK_LEN
+        );
+        assert!(group.check_capacity(group.transaction_len()).is_ok());
+        let error = group.check_capacity(64).err().unwrap();
+        match FsuipcError::from_io(&error) {
+            Some(FsuipcError::BufferOverflow { needed, available }) => {
+                assert_eq!((*needed, *available), (group.transaction_len(), 64))
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+}
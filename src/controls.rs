@@ -0,0 +1,102 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Simulator controls
+//! Most actions of the cockpit (toggling the gear, pausing, engaging the autopilot) are better
+//! done by sending the control the simulator binds to them than by writing the offsets of
+//! their state. FSUIPC sends the control written to offset 0x3110, with the parameter at
+//! 0x3114. `Session::send_control()` writes both in a single request, so FSUIPC never sees the
+//! control with the parameter of a previous one.
+
+use std::io;
+
+use super::offsets;
+use super::Session;
+
+/// A standard FS control, by its number in the controls list of the simulator
+/// Controls not listed here can be sent with `EventId::Other`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventId {
+    StrobesToggle,
+    PauseToggle,
+    GearToggle,
+    PitotHeatToggle,
+    ApMaster,
+    Brakes,
+    ElevTrimDn,
+    ElevTrimUp,
+    ParkingBrakes,
+    FlapsDecr,
+    FlapsIncr,
+    AutopilotOff,
+    AutopilotOn,
+    GearUp,
+    GearDown,
+    /// Any other control, by number.
+    Other(u32),
+}
+
+impl EventId {
+    /// The number of the control.
+    pub fn number(self) -> u32 {
+        match self {
+            EventId::StrobesToggle => 65560,
+            EventId::PauseToggle => 65561,
+            EventId::GearToggle => 65570,
+            EventId::PitotHeatToggle => 65579,
+            EventId::ApMaster => 65580,
+            EventId::Brakes => 65588,
+            EventId::ElevTrimDn => 65607,
+            EventId::ElevTrimUp => 65615,
+            EventId::ParkingBrakes => 65752,
+            EventId::FlapsDecr => 65758,
+            EventId::FlapsIncr => 65759,
+            EventId::AutopilotOff => 65791,
+            EventId::AutopilotOn => 65792,
+            EventId::GearUp => 66079,
+            EventId::GearDown => 66080,
+            EventId::Other(number) => number,
+        }
+    }
+}
+
+impl From<u32> for EventId {
+    fn from(number: u32) -> Self {
+        EventId::Other(number)
+    }
+}
+
+/// Request sending a control with the given parameter (0 for controls that take none).
+pub fn send<S: Session + ?Sized>(session: &mut S, event: EventId, param: i32) -> io::Result<usize> {
+    session.write_offset(offsets::CONTROL, &[event.number(), param as u32])
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_write_control_and_parameter_at_once() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            session.send_control(EventId::GearToggle, 0).unwrap();
+            session.send_control(EventId::Other(66587), -1).unwrap();
+            session.process().unwrap();
+        }
+        let writes = handle.writes();
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].0, 0x3110);
+        assert_eq!(writes[0].1, [65570u32.to_le_bytes(), [0; 4]].concat());
+        assert_eq!(handle.value::<u32>(0x3110), 66587);
+        assert_eq!(handle.value::<i32>(0x3114), -1);
+    }
+}
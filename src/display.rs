@@ -0,0 +1,190 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Messages and menus shown in the simulator
+//! FSUIPC shows the text written to offset 0x3380 once the display control at 0x32FA is
+//! written with the seconds to show it for. A menu is written the same way, as its title
+//! followed by its items, each zero-terminated. For menus, FSUIPC answers through the control:
+//! it clears it once the menu is shown, and then sets it to the number of the item selected,
+//! from 1, or to a negative value if the menu was cancelled.
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::FsuipcError;
+use super::offsets;
+use super::{Handle, Session};
+
+/// The most items a menu can have.
+pub const MAX_MENU_ITEMS: usize = 10;
+
+const MESSAGE_LEN: usize = 128;
+
+/// The time between checks of the display control.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The outcome of a menu
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuSelection {
+    /// The item at the given index was selected.
+    Item(usize),
+    /// The menu was dismissed in the simulator.
+    Cancelled,
+    /// No item was selected in time.
+    TimedOut,
+}
+
+/// Request showing a message for the given seconds (0 to show it until replaced).
+/// The text is truncated to the 127 characters FSUIPC can show.
+pub fn show_message<S: Session>(session: &mut S, text: &str, seconds: u16) -> io::Result<()> {
+    session.write_string(offsets::MESSAGE_TEXT.address(), text, MESSAGE_LEN)?;
+    session.write_offset(offsets::MESSAGE_CONTROL, &display_time(seconds))?;
+    Ok(())
+}
+
+/// Show a menu for the given seconds and wait for the selection, at most `timeout`.
+/// It fails with `FsuipcError::Timeout` if FSUIPC does not show the menu within `timeout`, and
+/// with `InvalidInput` if there are no items, more than `MAX_MENU_ITEMS`, or they do not fit
+/// in the message text.
+pub fn show_menu<H>(
+    handle: &mut H,
+    title: &str,
+    items: &[&str],
+    seconds: u16,
+    timeout: Duration,
+) -> io::Result<MenuSelection>
+where
+    H: for<'h> Handle<'h>,
+{
+    let text = menu_text(title, items)?;
+    let mut session = handle.session();
+    session.write_bytes(offsets::MESSAGE_TEXT.address(), text.as_ptr(), text.len())?;
+    session.write_offset(offsets::MESSAGE_CONTROL, &display_time(seconds))?;
+    session.process()?;
+
+    let start = Instant::now();
+    while read_control(handle)? != 0 {
+        if start.elapsed() >= timeout {
+            return Err(FsuipcError::Timeout(timeout).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    loop {
+        match read_control(handle)? {
+            0 => {}
+            n if n > 0 && n as usize <= items.len() => {
+                return Ok(MenuSelection::Item(n as usize - 1))
+            }
+            _ => return Ok(MenuSelection::Cancelled),
+        }
+        if start.elapsed() >= timeout {
+            return Ok(MenuSelection::TimedOut);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The message text of a menu: the title and the items, each zero-terminated, and a final zero.
+fn menu_text(title: &str, items: &[&str]) -> io::Result<Vec<u8>> {
+    if items.is_empty() || items.len() > MAX_MENU_ITEMS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a menu must have 1 to {} items", MAX_MENU_ITEMS),
+        ));
+    }
+    let mut text = Vec::new();
+    for line in Some(&title).into_iter().chain(items.iter()) {
+        text.extend_from_slice(line.as_bytes());
+        text.push(0);
+    }
+    text.push(0);
+    if text.len() > MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the menu takes {} bytes out of {}", text.len(), MESSAGE_LEN),
+        ));
+    }
+    Ok(text)
+}
+
+fn display_time(seconds: u16) -> i16 {
+    seconds.min(i16::MAX as u16) as i16
+}
+
+fn read_control<H>(handle: &mut H) -> io::Result<i16>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut control = 0i16;
+    let mut session = handle.session();
+    session.read_offset(offsets::MESSAGE_CONTROL, &mut control)?;
+    session.process()?;
+    Ok(control)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::strings;
+
+    #[test]
+    fn should_show_message() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            show_message(&mut session, "Cleared to land", 10).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(
+            strings::decode(&handle.bytes(0x3380, 128)),
+            "Cleared to land"
+        );
+        assert_eq!(handle.value::<i16>(0x32FA), 10);
+    }
+
+    #[test]
+    fn should_wait_for_menu_selection() {
+        let mut handle = MockHandle::new();
+        handle.schedule_seed(1, 0x32FA, &0i16.to_le_bytes());
+        handle.schedule_seed(3, 0x32FA, &2i16.to_le_bytes());
+        let selection = show_menu(
+            &mut handle,
+            "Pushback",
+            &["Left", "Right", "Straight"],
+            30,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(selection, MenuSelection::Item(1));
+        assert_eq!(
+            handle.bytes(0x3380, 30),
+            b"Pushback\0Left\0Right\0Straight\0\0".to_vec()
+        );
+    }
+
+    #[test]
+    fn should_report_cancelled_and_unanswered_menus() {
+        let mut handle = MockHandle::new();
+        handle.schedule_seed(1, 0x32FA, &0i16.to_le_bytes());
+        handle.schedule_seed(2, 0x32FA, &(-1i16).to_le_bytes());
+        let timeout = Duration::from_secs(5);
+        let selection = show_menu(&mut handle, "Doors", &["Open"], 30, timeout).unwrap();
+        assert_eq!(selection, MenuSelection::Cancelled);
+
+        let mut handle = MockHandle::new();
+        let timeout = Duration::from_millis(100);
+        let error = show_menu(&mut handle, "Doors", &["Open"], 30, timeout)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(show_menu(&mut handle, "Doors", &[], 30, timeout).is_err());
+    }
+}
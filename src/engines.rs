@@ -0,0 +1,265 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A facade over the engine offsets
+//! FSUIPC gives every engine a block of offsets, the one of engine 1 at 0x0888 and the ones of
+//! engines 2 to 4 each 0x98 bytes after the previous. `read_engines()` reads the blocks of the
+//! engines the aircraft has in a single session, and `EngineData` converts their values to the
+//! usual units. The `write_*()` functions move the levers of an engine, given by its number
+//! from 1.
+
+use std::io;
+
+use super::offsets::{self, Offset};
+use super::{Handle, Session};
+
+/// The number of engines with offsets.
+pub const MAX_ENGINES: usize = 4;
+
+/// The distance between the offsets of consecutive engines.
+const ENGINE_STRIDE: u16 = 0x98;
+
+/// The value of a lever at its full position.
+const LEVER_FULL: f64 = 16384.0;
+
+/// The state of an engine
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EngineData {
+    throttle: i16,
+    propeller: i16,
+    mixture: u16,
+    combustion: u16,
+    n2: u16,
+    n1: u16,
+    oil_temperature: u16,
+    oil_pressure: u16,
+    egt: u16,
+    rpm_scaler: u16,
+    fuel_flow: f64,
+}
+
+impl EngineData {
+    /// Whether the engine is running.
+    pub fn running(&self) -> bool {
+        self.combustion != 0
+    }
+
+    /// The throttle lever, from -0.25 (full reverse) to 1 (full).
+    pub fn throttle(&self) -> f64 {
+        self.throttle as f64 / LEVER_FULL
+    }
+
+    /// The propeller lever, from -0.25 to 1 (full).
+    pub fn propeller(&self) -> f64 {
+        self.propeller as f64 / LEVER_FULL
+    }
+
+    /// The mixture lever, from 0 (cut-off) to 1 (full rich).
+    pub fn mixture(&self) -> f64 {
+        self.mixture as f64 / LEVER_FULL
+    }
+
+    /// The N1 of a jet, in percent.
+    pub fn n1(&self) -> f64 {
+        self.n1 as f64 * 100.0 / 16384.0
+    }
+
+    /// The N2 of a jet, in percent.
+    pub fn n2(&self) -> f64 {
+        self.n2 as f64 * 100.0 / 16384.0
+    }
+
+    /// The RPM of a propeller engine.
+    pub fn rpm(&self) -> f64 {
+        self.n1 as f64 * self.rpm_scaler as f64 / 65536.0
+    }
+
+    /// The fuel flow, in pounds per hour.
+    pub fn fuel_flow(&self) -> f64 {
+        self.fuel_flow
+    }
+
+    /// The exhaust gas temperature, in degrees Celsius.
+    pub fn egt_celsius(&self) -> f64 {
+        self.egt as f64 * 860.0 / 16384.0
+    }
+
+    /// The oil temperature, in degrees Celsius.
+    pub fn oil_temperature_celsius(&self) -> f64 {
+        self.oil_temperature as f64 * 140.0 / 16384.0
+    }
+
+    /// The oil pressure, in psi.
+    pub fn oil_pressure_psi(&self) -> f64 {
+        self.oil_pressure as f64 * 55.0 / 16384.0
+    }
+
+    fn register_reads<S: Session>(&mut self, session: &mut S, index: usize) -> io::Result<()> {
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_THROTTLE, index),
+            &mut self.throttle,
+        )?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_PROPELLER, index),
+            &mut self.propeller,
+        )?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_MIXTURE, index),
+            &mut self.mixture,
+        )?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_COMBUSTION, index),
+            &mut self.combustion,
+        )?;
+        session.read_offset(engine_offset(offsets::ENGINE1_N2, index), &mut self.n2)?;
+        session.read_offset(engine_offset(offsets::ENGINE1_N1, index), &mut self.n1)?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_OIL_TEMPERATURE, index),
+            &mut self.oil_temperature,
+        )?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_OIL_PRESSURE, index),
+            &mut self.oil_pressure,
+        )?;
+        session.read_offset(engine_offset(offsets::ENGINE1_EGT, index), &mut self.egt)?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_RPM_SCALER, index),
+            &mut self.rpm_scaler,
+        )?;
+        session.read_offset(
+            engine_offset(offsets::ENGINE1_FUEL_FLOW, index),
+            &mut self.fuel_flow,
+        )?;
+        Ok(())
+    }
+}
+
+/// The offset of engine 1 moved to the engine of the given index, from 0.
+fn engine_offset<T>(offset: Offset<T>, index: usize) -> Offset<T> {
+    Offset::new(offset.address() + index as u16 * ENGINE_STRIDE)
+}
+
+/// Read the state of the engines of the aircraft, from engine 1.
+pub fn read_engines<H>(handle: &mut H) -> io::Result<Vec<EngineData>>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut count = 0u16;
+    let mut engines = [EngineData::default(); MAX_ENGINES];
+    {
+        let mut session = handle.session();
+        session.read_offset(offsets::ENGINE_COUNT, &mut count)?;
+        for (index, engine) in engines.iter_mut().enumerate() {
+            engine.register_reads(&mut session, index)?;
+        }
+        session.process()?;
+    }
+    let count = (count as usize).min(MAX_ENGINES);
+    Ok(engines[..count].to_vec())
+}
+
+/// Request moving the throttle lever of an engine, from -0.25 (full reverse) to 1 (full).
+pub fn write_throttle<S: Session>(session: &mut S, engine: usize, value: f64) -> io::Result<usize> {
+    let lever = lever("throttle", value, -0.25)?;
+    session.write_offset(
+        engine_offset(offsets::ENGINE1_THROTTLE, index(engine)?),
+        &lever,
+    )
+}
+
+/// Request moving the propeller lever of an engine, from -0.25 to 1 (full).
+pub fn write_propeller<S: Session>(
+    session: &mut S,
+    engine: usize,
+    value: f64,
+) -> io::Result<usize> {
+    let lever = lever("propeller", value, -0.25)?;
+    session.write_offset(
+        engine_offset(offsets::ENGINE1_PROPELLER, index(engine)?),
+        &lever,
+    )
+}
+
+/// Request moving the mixture lever of an engine, from 0 (cut-off) to 1 (full rich).
+pub fn write_mixture<S: Session>(session: &mut S, engine: usize, value: f64) -> io::Result<usize> {
+    let lever = lever("mixture", value, 0.0)? as u16;
+    session.write_offset(
+        engine_offset(offsets::ENGINE1_MIXTURE, index(engine)?),
+        &lever,
+    )
+}
+
+fn index(engine: usize) -> io::Result<usize> {
+    if engine == 0 || engine > MAX_ENGINES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid engine {}", engine),
+        ));
+    }
+    Ok(engine - 1)
+}
+
+fn lever(name: &str, value: f64, min: f64) -> io::Result<i16> {
+    if !(min..=1.0).contains(&value) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid {} lever {}", name, value),
+        ));
+    }
+    Ok((value * LEVER_FULL).round() as i16)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_read_engines() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0AEC, &2u16);
+        handle.seed_value(0x0894, &1u16);
+        handle.seed_value(0x0898, &8192u16);
+        handle.seed_value(0x08BE, &8192u16);
+        handle.seed_value(0x0918, &2400.0f64);
+        handle.seed_value(0x088C + 0x98, &(-4096i16));
+        handle.seed_value(0x0898 + 0x98, &16384u16);
+        handle.seed_value(0x08C8 + 0x98, &10000u16);
+        handle.seed_value(0x0894 + 0x98 * 2, &1u16);
+
+        let engines = read_engines(&mut handle).unwrap();
+        assert_eq!(engines.len(), 2);
+        assert!(engines[0].running());
+        assert_eq!(engines[0].n1(), 50.0);
+        assert_eq!(engines[0].egt_celsius(), 430.0);
+        assert_eq!(engines[0].fuel_flow(), 2400.0);
+        assert!(!engines[1].running());
+        assert_eq!(engines[1].throttle(), -0.25);
+        assert_eq!(engines[1].rpm(), 2500.0);
+    }
+
+    #[test]
+    fn should_write_levers() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_throttle(&mut session, 1, 1.0).unwrap();
+            write_propeller(&mut session, 2, 0.5).unwrap();
+            write_mixture(&mut session, 4, 0.25).unwrap();
+            assert!(write_throttle(&mut session, 0, 1.0).is_err());
+            assert!(write_throttle(&mut session, 5, 1.0).is_err());
+            assert!(write_mixture(&mut session, 1, -0.1).is_err());
+            assert!(write_throttle(&mut session, 1, 1.5).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<i16>(0x088C), 16384);
+        assert_eq!(handle.value::<i16>(0x088E + 0x98), 8192);
+        assert_eq!(handle.value::<u16>(0x0890 + 0x98 * 3), 4096);
+    }
+}
@@ -0,0 +1,158 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// An error produced while talking to FSUIPC
+/// The API of the crate returns `io::Result` for backwards compatibility. The `io::Error`
+/// values wrap one of these, which can be obtained back with `FsuipcError::from_io()`.
+#[derive(Debug)]
+pub enum FsuipcError {
+    /// The connection to FSUIPC could not be established.
+    ConnectionRefused(String),
+    /// No FSUIPC window was found: the simulator (or FSUIPC itself) is not running.
+    SimNotRunning(String),
+    /// The requests do not fit in the buffer used to exchange them with FSUIPC.
+    BufferOverflow { needed: usize, available: usize },
+    /// FSUIPC rejected the transaction with the given FS6IPC return code.
+    ProtocolRejection(isize),
+    /// The memory shared with FSUIPC could not be set up.
+    Mapping(String),
+    /// Windows does not let this process talk to FSUIPC (e.g. elevation mismatch).
+    AccessDenied(String),
+    /// FSUIPC did not answer within the given time (e.g. the simulator is hung in a dialog).
+    Timeout(Duration),
+    /// A verified write to the given offset did not read back the value written.
+    WriteMismatch(u16),
+    /// The operation was cancelled through a `CancellationToken`.
+    Cancelled,
+    /// The given facility needs a registered FSUIPC.
+    Unregistered(String),
+}
+
+impl FsuipcError {
+    /// Obtain the `FsuipcError` wrapped by the given `io::Error`, if any.
+    pub fn from_io(error: &io::Error) -> Option<&FsuipcError> {
+        error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<FsuipcError>())
+    }
+
+    /// The `io::ErrorKind` this error is reported with.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            FsuipcError::ConnectionRefused(_) => io::ErrorKind::ConnectionRefused,
+            FsuipcError::SimNotRunning(_) => io::ErrorKind::ConnectionRefused,
+            FsuipcError::BufferOverflow { .. } => io::ErrorKind::InvalidInput,
+            FsuipcError::ProtocolRejection(_) => io::ErrorKind::InvalidData,
+            FsuipcError::Mapping(_) => io::ErrorKind::ConnectionRefused,
+            FsuipcError::AccessDenied(_) => io::ErrorKind::PermissionDenied,
+            FsuipcError::Timeout(_) => io::ErrorKind::TimedOut,
+            FsuipcError::WriteMismatch(_) => io::ErrorKind::InvalidData,
+            FsuipcError::Cancelled => io::ErrorKind::Interrupted,
+            FsuipcError::Unregistered(_) => io::ErrorKind::PermissionDenied,
+        }
+    }
+}
+
+impl fmt::Display for FsuipcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsuipcError::ConnectionRefused(reason) => {
+                write!(f, "cannot connect to FSUIPC: {}", reason)
+            }
+            FsuipcError::SimNotRunning(reason) => {
+                write!(f, "cannot connect to FSUIPC: {}", reason)
+            }
+            FsuipcError::BufferOverflow { needed, available } => write!(
+                f,
+                "FSUIPC buffer overflow: {} bytes needed but only {} available",
+                needed, available
+            ),
+            FsuipcError::ProtocolRejection(code) => write!(
+                f,
+                "FSUIPC rejected the requests with error {}; possible buffer corruption",
+                code
+            ),
+            FsuipcError::Mapping(reason) => {
+                write!(f, "cannot connect to FSUIPC: {}", reason)
+            }
+            FsuipcError::AccessDenied(reason) => {
+                write!(f, "FSUIPC did not receive the requests: {}", reason)
+            }
+            FsuipcError::Timeout(timeout) => {
+                write!(f, "FSUIPC did not answer within {} ms", timeout.as_millis())
+            }
+            FsuipcError::WriteMismatch(offset) => write!(
+                f,
+                "the value written to offset 0x{:04X} did not read back",
+                offset
+            ),
+            FsuipcError::Cancelled => write!(f, "the operation was cancelled"),
+            FsuipcError::Unregistered(facility) => {
+                write!(f, "{} need a registered FSUIPC", facility)
+            }
+        }
+    }
+}
+
+impl Error for FsuipcError {}
+
+impl From<FsuipcError> for io::Error {
+    fn from(error: FsuipcError) -> io::Error {
+        io::Error::new(error.kind(), error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_convert_into_io_error() {
+        let error: io::Error = FsuipcError::ProtocolRejection(0).into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "FSUIPC rejected the requests with error 0; possible buffer corruption"
+        );
+    }
+
+    #[test]
+    fn should_recover_from_io_error() {
+        let error: io::Error = FsuipcError::BufferOverflow {
+            needed: 32,
+            available: 16,
+        }
+        .into();
+        match FsuipcError::from_io(&error) {
+            Some(FsuipcError::BufferOverflow { needed, available }) => {
+                assert_eq!(*needed, 32);
+                assert_eq!(*available, 16);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_report_timeout_as_timed_out() {
+        let error: io::Error = FsuipcError::Timeout(Duration::from_secs(2)).into();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(error.to_string(), "FSUIPC did not answer within 2000 ms");
+    }
+
+    #[test]
+    fn should_not_recover_from_foreign_io_error() {
+        let error = io::Error::other("foreign");
+        assert!(FsuipcError::from_io(&error).is_none());
+    }
+}
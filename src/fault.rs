@@ -0,0 +1,341 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use super::error::FsuipcError;
+use super::{Handle, Session};
+
+/// The time an injected `Timeout` fault reports to have waited for FSUIPC.
+const INJECTED_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A failure that can be injected in the processing of a session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// FSUIPC rejects the transaction with the given return code.
+    Rejection(isize),
+    /// FSUIPC does not answer in time, failing with `FsuipcError::Timeout`.
+    Timeout,
+    /// Only the first `n` requests of the session reach FSUIPC. The rest are silently dropped.
+    PartialResponse(usize),
+    /// The connection is lost, failing with `FsuipcError::SimNotRunning` as a window that is gone
+    /// does. Every further transaction fails until `reconnect()` is called.
+    Disconnect,
+}
+
+/// The policy that decides which faults are injected and when
+#[derive(Clone, Debug)]
+pub struct FaultInjector {
+    scheduled: Vec<(usize, Fault)>,
+    random: Vec<(Fault, f64)>,
+    rng_state: u64,
+    transactions: usize,
+    disconnected: bool,
+}
+
+impl FaultInjector {
+    /// Create a new injector that injects no faults at all.
+    /// The given seed makes the random faults reproducible.
+    pub fn new(seed: u64) -> Self {
+        FaultInjector {
+            scheduled: Vec::new(),
+            random: Vec::new(),
+            rng_state: seed | 1,
+            transactions: 0,
+            disconnected: false,
+        }
+    }
+
+    /// Inject `fault` in the transaction of the n-th session created (counting from 0).
+    /// Faults are drawn when a session is created, so sessions dropped without being processed
+    /// count as well.
+    pub fn on_transaction(mut self, n: usize, fault: Fault) -> Self {
+        self.scheduled.push((n, fault));
+        self
+    }
+
+    /// Inject `fault` in any transaction with the given probability (from 0.0 to 1.0).
+    pub fn with_probability(mut self, fault: Fault, probability: f64) -> Self {
+        self.random.push((fault, probability));
+        self
+    }
+
+    /// Recover from a previously injected `Disconnect` fault.
+    pub fn reconnect(&mut self) {
+        self.disconnected = false;
+    }
+
+    /// The number of sessions created so far, faulty or not.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    fn next_fault(&mut self) -> Option<Fault> {
+        let n = self.transactions;
+        self.transactions += 1;
+        if self.disconnected {
+            return Some(Fault::Disconnect);
+        }
+        let fault = match self.scheduled.iter().find(|(at, _)| *at == n) {
+            Some((_, fault)) => Some(*fault),
+            None => {
+                let random = self.random.clone();
+                random
+                    .into_iter()
+                    .find(|(_, probability)| self.next_random() < *probability)
+                    .map(|(fault, _)| fault)
+            }
+        };
+        if fault == Some(Fault::Disconnect) {
+            self.disconnected = true;
+        }
+        fault
+    }
+
+    fn next_random(&mut self) -> f64 {
+        // xorshift64
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 7;
+        self.rng_state ^= self.rng_state << 17;
+        (self.rng_state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A handle that wraps another one injecting faults in its sessions
+/// This is intended to test how applications cope with FSUIPC failures. It can wrap any handle,
+/// so faults can be injected either on a real connection or on a test double.
+pub struct FaultyHandle<H> {
+    inner: H,
+    injector: FaultInjector,
+}
+
+impl<H> FaultyHandle<H> {
+    pub fn new(inner: H, injector: FaultInjector) -> Self {
+        FaultyHandle { inner, injector }
+    }
+
+    /// Obtain the fault injector, e.g. to reconnect after a `Disconnect` fault.
+    pub fn injector(&mut self) -> &mut FaultInjector {
+        &mut self.injector
+    }
+
+    /// Obtain the wrapped handle.
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+impl<'a, H: Handle<'a>> Handle<'a> for FaultyHandle<H> {
+    type Sess = FaultySession<'a, H::Sess>;
+
+    fn session(&'a mut self) -> Self::Sess {
+        let FaultyHandle { inner, injector } = self;
+        FaultySession {
+            inner: inner.session(),
+            fault: injector.next_fault(),
+            requests: 0,
+            _injector: PhantomData,
+        }
+    }
+}
+
+/// A session of a `FaultyHandle`
+/// The fault of its transaction is drawn when the session is created, so a partial response
+/// can drop the requests as they are made.
+pub struct FaultySession<'a, S> {
+    inner: S,
+    fault: Option<Fault>,
+    requests: usize,
+    _injector: PhantomData<&'a mut FaultInjector>,
+}
+
+impl<'a, S: Session> FaultySession<'a, S> {
+    fn accepts_request(&mut self) -> bool {
+        let accepted = match self.fault {
+            Some(Fault::PartialResponse(n)) => self.requests < n,
+            _ => true,
+        };
+        self.requests += 1;
+        accepted
+    }
+}
+
+impl<'a, S: Session> Session for FaultySession<'a, S> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        if self.accepts_request() {
+            self.inner.read_bytes(offset, dest, len)
+        } else {
+            Ok(len)
+        }
+    }
+
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        if self.accepts_request() {
+            self.inner.write_bytes(offset, src, len)
+        } else {
+            Ok(len)
+        }
+    }
+
+    fn process(self) -> io::Result<usize> {
+        match self.fault {
+            None | Some(Fault::PartialResponse(_)) => self.inner.process(),
+            Some(Fault::Rejection(code)) => Err(FsuipcError::ProtocolRejection(code).into()),
+            Some(Fault::Timeout) => Err(FsuipcError::Timeout(INJECTED_TIMEOUT).into()),
+            Some(Fault::Disconnect) => Err(FsuipcError::SimNotRunning(
+                "the connection to FSUIPC was lost".to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    /// A session that answers every read with 0xFF bytes
+    struct FakeSession<'a> {
+        requests: &'a mut usize,
+        reads: Vec<(*mut u8, usize)>,
+    }
+
+    impl<'a> Session for FakeSession<'a> {
+        fn read_bytes(&mut self, _offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            *self.requests += 1;
+            self.reads.push((dest, len));
+            Ok(len)
+        }
+
+        fn write_bytes(&mut self, _offset: u16, _src: *const u8, len: usize) -> io::Result<usize> {
+            *self.requests += 1;
+            Ok(len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            for (dest, len) in self.reads {
+                for i in 0..len {
+                    unsafe { *dest.add(i) = 0xFF };
+                }
+            }
+            Ok(0)
+        }
+    }
+
+    struct FakeHandle {
+        requests: usize,
+    }
+
+    impl<'a> Handle<'a> for FakeHandle {
+        type Sess = FakeSession<'a>;
+
+        fn session(&'a mut self) -> FakeSession<'a> {
+            FakeSession {
+                requests: &mut self.requests,
+                reads: Vec::new(),
+            }
+        }
+    }
+
+    fn process_read(handle: &mut FaultyHandle<FakeHandle>) -> io::Result<u8> {
+        let mut value = 0u8;
+        let mut session = handle.session();
+        session.read(0x3304, &mut value)?;
+        session.process()?;
+        Ok(value)
+    }
+
+    #[test]
+    fn should_process_without_faults() {
+        let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, FaultInjector::new(0));
+        assert_eq!(process_read(&mut handle).unwrap(), 0xFF);
+        assert_eq!(handle.injector().transactions(), 1);
+    }
+
+    #[test]
+    fn should_inject_scheduled_faults() {
+        let injector = FaultInjector::new(0)
+            .on_transaction(1, Fault::Rejection(0))
+            .on_transaction(2, Fault::Timeout);
+        let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, injector);
+        assert!(process_read(&mut handle).is_ok());
+        assert_eq!(
+            process_read(&mut handle).err().unwrap().kind(),
+            ErrorKind::InvalidData
+        );
+        let error = process_read(&mut handle).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(matches!(
+            FsuipcError::from_io(&error),
+            Some(FsuipcError::Timeout(_))
+        ));
+        assert!(process_read(&mut handle).is_ok());
+    }
+
+    #[test]
+    fn should_stay_disconnected_until_reconnect() {
+        let injector = FaultInjector::new(0).on_transaction(0, Fault::Disconnect);
+        let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, injector);
+        for _ in 0..3 {
+            let error = process_read(&mut handle).err().unwrap();
+            assert!(matches!(
+                FsuipcError::from_io(&error),
+                Some(FsuipcError::SimNotRunning(_))
+            ));
+        }
+        handle.injector().reconnect();
+        assert!(process_read(&mut handle).is_ok());
+    }
+
+    #[test]
+    fn should_drop_requests_on_partial_response() {
+        let injector = FaultInjector::new(0).on_transaction(0, Fault::PartialResponse(1));
+        let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, injector);
+        let mut first = 0u8;
+        let mut second = 0u8;
+        {
+            let mut session = handle.session();
+            session.read(0x0238, &mut first).unwrap();
+            session.read(0x0239, &mut second).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(first, 0xFF);
+        assert_eq!(second, 0);
+        assert_eq!(handle.into_inner().requests, 1);
+    }
+
+    #[test]
+    fn should_drop_requests_on_random_partial_response() {
+        let injector = FaultInjector::new(7).with_probability(Fault::PartialResponse(0), 1.0);
+        let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, injector);
+        for _ in 0..3 {
+            assert_eq!(process_read(&mut handle).unwrap(), 0);
+        }
+        assert_eq!(handle.into_inner().requests, 0);
+    }
+
+    #[test]
+    fn should_inject_random_faults_reproducibly() {
+        let outcomes = |seed| {
+            let injector = FaultInjector::new(seed).with_probability(Fault::Timeout, 0.5);
+            let mut handle = FaultyHandle::new(FakeHandle { requests: 0 }, injector);
+            (0..32)
+                .map(|_| process_read(&mut handle).is_ok())
+                .collect::<Vec<_>>()
+        };
+        let first = outcomes(42);
+        assert_eq!(first, outcomes(42));
+        assert!(first.iter().any(|ok| *ok));
+        assert!(first.iter().any(|ok| !*ok));
+    }
+}
@@ -0,0 +1,240 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A facade over the fuel tank offsets
+//! FSUIPC gives the level of each tank as a fraction of its capacity * 128 * 65536, and the
+//! capacity in US gallons. `FuelState` reads all the tanks at once along with the weight of the
+//! fuel, and obtains the fuel of each tank the aircraft has in gallons, pounds and kilograms.
+
+use std::io;
+
+use super::geo::KILOGRAMS_PER_POUND;
+use super::offsets::{self, FsuipcStruct, Offset};
+use super::Session;
+
+/// The level of a full tank.
+const FULL_TANK: f64 = 128.0 * 65536.0;
+
+/// A fuel tank
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Tank {
+    Centre,
+    LeftMain,
+    LeftAux,
+    LeftTip,
+    RightMain,
+    RightAux,
+    RightTip,
+    Centre2,
+    Centre3,
+    External1,
+    External2,
+}
+
+impl Tank {
+    /// All the tanks, in the order of `FuelState::tanks()`.
+    pub const ALL: [Tank; 11] = [
+        Tank::Centre,
+        Tank::LeftMain,
+        Tank::LeftAux,
+        Tank::LeftTip,
+        Tank::RightMain,
+        Tank::RightAux,
+        Tank::RightTip,
+        Tank::Centre2,
+        Tank::Centre3,
+        Tank::External1,
+        Tank::External2,
+    ];
+
+    /// The offset of the level of the tank, as a fraction * 128 * 65536.
+    /// The capacity, in US gallons, follows it.
+    pub fn level_offset(self) -> Offset<u32> {
+        let address = match self {
+            Tank::Centre => 0x0B74,
+            Tank::LeftMain => 0x0B7C,
+            Tank::LeftAux => 0x0B84,
+            Tank::LeftTip => 0x0B8C,
+            Tank::RightMain => 0x0B94,
+            Tank::RightAux => 0x0B9C,
+            Tank::RightTip => 0x0BA4,
+            Tank::Centre2 => 0x1244,
+            Tank::Centre3 => 0x124C,
+            Tank::External1 => 0x1254,
+            Tank::External2 => 0x125C,
+        };
+        Offset::new(address)
+    }
+
+    /// The offset of the capacity of the tank, in US gallons.
+    pub fn capacity_offset(self) -> Offset<u32> {
+        Offset::new(self.level_offset().address() + 4)
+    }
+}
+
+/// The fuel in a tank
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TankFuel {
+    pub tank: Tank,
+    /// The level, from 0 (empty) to 1 (full).
+    pub level: f64,
+    /// The capacity, in US gallons.
+    pub capacity: f64,
+    /// The weight of the fuel, in pounds per US gallon.
+    pub weight_per_gallon: f64,
+}
+
+impl TankFuel {
+    /// The fuel, in US gallons.
+    pub fn gallons(&self) -> f64 {
+        self.level * self.capacity
+    }
+
+    /// The fuel, in pounds.
+    pub fn pounds(&self) -> f64 {
+        self.gallons() * self.weight_per_gallon
+    }
+
+    /// The fuel, in kilograms.
+    pub fn kilograms(&self) -> f64 {
+        self.pounds() * KILOGRAMS_PER_POUND
+    }
+}
+
+/// The fuel of the aircraft
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FuelState {
+    weight: u16,
+    levels: [u32; 11],
+    capacities: [u32; 11],
+}
+
+impl FuelState {
+    /// The weight of the fuel, in pounds per US gallon.
+    pub fn weight_per_gallon(&self) -> f64 {
+        self.weight as f64 / 256.0
+    }
+
+    /// The fuel of each tank the aircraft has, that is, with some capacity.
+    pub fn tanks(&self) -> Vec<TankFuel> {
+        Tank::ALL
+            .iter()
+            .zip(self.levels.iter().zip(self.capacities.iter()))
+            .filter(|(_, (_, capacity))| **capacity > 0)
+            .map(|(&tank, (&level, &capacity))| TankFuel {
+                tank,
+                level: level as f64 / FULL_TANK,
+                capacity: capacity as f64,
+                weight_per_gallon: self.weight_per_gallon(),
+            })
+            .collect()
+    }
+
+    /// The fuel of the given tank, or `None` if the aircraft does not have it.
+    pub fn tank(&self, tank: Tank) -> Option<TankFuel> {
+        self.tanks().into_iter().find(|fuel| fuel.tank == tank)
+    }
+
+    /// The fuel of all the tanks, in US gallons.
+    pub fn total_gallons(&self) -> f64 {
+        self.tanks().iter().map(TankFuel::gallons).sum()
+    }
+
+    /// The fuel of all the tanks, in pounds.
+    pub fn total_pounds(&self) -> f64 {
+        self.tanks().iter().map(TankFuel::pounds).sum()
+    }
+
+    /// The fuel of all the tanks, in kilograms.
+    pub fn total_kilograms(&self) -> f64 {
+        self.tanks().iter().map(TankFuel::kilograms).sum()
+    }
+
+    /// The capacity of all the tanks, in US gallons.
+    pub fn total_capacity(&self) -> f64 {
+        self.capacities
+            .iter()
+            .map(|&capacity| capacity as f64)
+            .sum()
+    }
+}
+
+impl FsuipcStruct for FuelState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::FUEL_WEIGHT, &mut self.weight)?;
+        let tanks = self.levels.iter_mut().zip(self.capacities.iter_mut());
+        for (tank, (level, capacity)) in Tank::ALL.iter().zip(tanks) {
+            session.read_offset(tank.level_offset(), level)?;
+            session.read_offset(tank.capacity_offset(), capacity)?;
+        }
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Request setting the level of a tank, from 0 (empty) to 1 (full).
+pub fn set_tank_level<S: Session>(session: &mut S, tank: Tank, level: f64) -> io::Result<usize> {
+    if !(0.0..=1.0).contains(&level) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid tank level {}", level),
+        ));
+    }
+    let level = (level * FULL_TANK).round() as u32;
+    session.write_offset(tank.level_offset(), &level)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_decode_fuel() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0AF4, &(6u16 * 256));
+        handle.seed_value(0x0B7C, &(FULL_TANK as u32 / 2));
+        handle.seed_value(0x0B80, &100u32);
+        handle.seed_value(0x0B94, &(FULL_TANK as u32));
+        handle.seed_value(0x0B98, &100u32);
+        let mut state = FuelState::default();
+        {
+            let mut session = handle.session();
+            state.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        let tanks = state.tanks();
+        assert_eq!(tanks.len(), 2);
+        assert_eq!(tanks[0].tank, Tank::LeftMain);
+        assert_eq!(tanks[0].gallons(), 50.0);
+        assert_eq!(state.tank(Tank::RightMain).unwrap().pounds(), 600.0);
+        assert_eq!(state.tank(Tank::Centre), None);
+        assert_eq!(state.total_gallons(), 150.0);
+        assert_eq!(state.total_pounds(), 900.0);
+        assert!((state.total_kilograms() - 408.233).abs() < 0.001);
+        assert_eq!(state.total_capacity(), 200.0);
+    }
+
+    #[test]
+    fn should_set_tank_levels() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            set_tank_level(&mut session, Tank::Centre2, 0.25).unwrap();
+            assert!(set_tank_level(&mut session, Tank::Centre, 1.5).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u32>(0x1244), 2_097_152);
+        assert_eq!(handle.writes().len(), 1);
+    }
+}
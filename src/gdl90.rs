@@ -0,0 +1,377 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A GDL90 broadcaster for EFB apps
+//! Tablet apps such as ForeFlight or Garmin Pilot display the traffic and the own aircraft
+//! received as GDL90 messages over UDP (port 4000 by default). `Gdl90Broadcaster` sends the
+//! heartbeat, ownship, geometric altitude, AHRS and traffic messages built from the state read
+//! from FSUIPC. Call `send()` once per second, as the heartbeat expects.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use super::geo::{self, Position, RawPosition, FEET_PER_METRE, POSITION};
+use super::offsets::{self, FsuipcStruct};
+use super::strings;
+use super::Session;
+
+/// The UDP port EFB apps listen to.
+pub const GDL90_PORT: u16 = 4000;
+
+const FLAG: u8 = 0x7E;
+const ESCAPE: u8 = 0x7D;
+const HEARTBEAT: u8 = 0x00;
+const OWNSHIP_REPORT: u8 = 0x0A;
+const OWNSHIP_GEOMETRIC_ALTITUDE: u8 = 0x0B;
+const TRAFFIC_REPORT: u8 = 0x14;
+const FOREFLIGHT: u8 = 0x65;
+const FOREFLIGHT_AHRS: u8 = 0x01;
+
+/// An aircraft reported by a GDL90 ownship or traffic report
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Traffic {
+    /// The 24-bit ICAO address.
+    pub address: u32,
+    /// The latitude, in degrees.
+    pub latitude: f64,
+    /// The longitude, in degrees.
+    pub longitude: f64,
+    /// The pressure altitude, in feet.
+    pub altitude: f64,
+    /// The ground speed, in knots.
+    pub ground_speed: f64,
+    /// The vertical speed, in feet per minute.
+    pub vertical_speed: f64,
+    /// The true track, in degrees.
+    pub track: f64,
+    /// The callsign, up to 8 characters.
+    pub callsign: String,
+    pub airborne: bool,
+}
+
+/// The state of the own aircraft broadcast as GDL90
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlightState {
+    pub position: RawPosition,
+    pub pitch: i32,
+    pub bank: i32,
+    pub heading: u32,
+    pub ground_speed: u32,
+    pub true_airspeed: u32,
+    pub indicated_airspeed: u32,
+    pub vertical_speed: i32,
+    pub on_ground: u16,
+    pub zulu_hour: u8,
+    pub zulu_minute: u8,
+    pub second: u8,
+    pub atc_id: [u8; 12],
+}
+
+impl FlightState {
+    /// The own aircraft as reported in the ownship report, with the given ICAO address.
+    /// The track is the true heading, since FSUIPC gives no ground track.
+    pub fn report(&self, address: u32) -> Traffic {
+        let position = Position::from(self.position);
+        Traffic {
+            address,
+            latitude: position.latitude,
+            longitude: position.longitude,
+            altitude: position.altitude_feet(),
+            ground_speed: self.ground_speed as f64 / 65536.0 * 3600.0 / 1852.0,
+            vertical_speed: self.vertical_speed as f64 / 256.0 * FEET_PER_METRE * 60.0,
+            track: geo::heading_degrees(self.heading),
+            callsign: strings::decode(&self.atc_id),
+            airborne: self.on_ground == 0,
+        }
+    }
+
+    /// The seconds since midnight UTC.
+    pub fn zulu_seconds(&self) -> u32 {
+        self.zulu_hour as u32 * 3600 + self.zulu_minute as u32 * 60 + self.second as u32
+    }
+
+    /// The roll, in degrees (positive with the right wing down).
+    pub fn roll(&self) -> f64 {
+        geo::bank_degrees(self.bank)
+    }
+
+    /// The pitch, in degrees (positive with the nose up).
+    pub fn pitch(&self) -> f64 {
+        geo::pitch_degrees(self.pitch)
+    }
+}
+
+impl FsuipcStruct for FlightState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(POSITION, &mut self.position)?;
+        session.read_offset(offsets::PITCH, &mut self.pitch)?;
+        session.read_offset(offsets::BANK, &mut self.bank)?;
+        session.read_offset(offsets::HEADING, &mut self.heading)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::TRUE_AIRSPEED, &mut self.true_airspeed)?;
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        session.read_offset(offsets::ZULU_HOUR, &mut self.zulu_hour)?;
+        session.read_offset(offsets::ZULU_MINUTE, &mut self.zulu_minute)?;
+        session.read_offset(offsets::SIM_LOCAL_SECOND, &mut self.second)?;
+        session.read_offset(offsets::ATC_ID, &mut self.atc_id)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A broadcaster of GDL90 messages over UDP
+pub struct Gdl90Broadcaster {
+    socket: UdpSocket,
+    target: SocketAddr,
+    address: u32,
+}
+
+impl Gdl90Broadcaster {
+    /// Send the messages to `target`, which may be a broadcast address such as
+    /// `255.255.255.255:4000`, reporting the own aircraft with the given ICAO address.
+    pub fn new<A: ToSocketAddrs>(target: A, address: u32) -> io::Result<Self> {
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to send GDL90 to")
+        })?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_broadcast(true)?;
+        Ok(Gdl90Broadcaster {
+            socket,
+            target,
+            address,
+        })
+    }
+
+    /// Send the state of the own aircraft and the given traffic.
+    pub fn send(&self, state: &FlightState, traffic: &[Traffic]) -> io::Result<()> {
+        let report = state.report(self.address);
+        let mut messages = vec![
+            heartbeat(state.zulu_seconds()),
+            ownship(&report),
+            geometric_altitude(report.altitude),
+            ahrs(
+                state.roll(),
+                state.pitch(),
+                report.track,
+                state.indicated_airspeed as f64 / 128.0,
+                state.true_airspeed as f64 / 128.0,
+            ),
+        ];
+        messages.extend(traffic.iter().map(traffic_report));
+        for message in messages {
+            self.socket.send_to(&frame(&message), self.target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Build a heartbeat message, with the seconds since midnight UTC.
+pub fn heartbeat(zulu_seconds: u32) -> Vec<u8> {
+    let timestamp = zulu_seconds % 86400;
+    let status = 0x01 | ((timestamp >> 9) & 0x80) as u8;
+    vec![
+        HEARTBEAT,
+        0x81,
+        status,
+        timestamp as u8,
+        (timestamp >> 8) as u8,
+        0,
+        0,
+    ]
+}
+
+/// Build the ownship report of the own aircraft.
+pub fn ownship(report: &Traffic) -> Vec<u8> {
+    encode_report(OWNSHIP_REPORT, report)
+}
+
+/// Build a traffic report.
+pub fn traffic_report(report: &Traffic) -> Vec<u8> {
+    encode_report(TRAFFIC_REPORT, report)
+}
+
+/// Build the ownship geometric altitude message, with the altitude in feet.
+pub fn geometric_altitude(altitude: f64) -> Vec<u8> {
+    let altitude = clamp(altitude / 5.0, i16::MIN as f64, i16::MAX as f64) as i16;
+    let [high, low] = altitude.to_be_bytes();
+    vec![OWNSHIP_GEOMETRIC_ALTITUDE, high, low, 0x00, 0x0A]
+}
+
+/// Build the ForeFlight AHRS message, with angles in degrees and speeds in knots.
+pub fn ahrs(roll: f64, pitch: f64, heading: f64, ias: f64, tas: f64) -> Vec<u8> {
+    let mut message = vec![FOREFLIGHT, FOREFLIGHT_AHRS];
+    message.extend_from_slice(&((roll * 10.0).round() as i16).to_be_bytes());
+    message.extend_from_slice(&((pitch * 10.0).round() as i16).to_be_bytes());
+    let heading = (heading.rem_euclid(360.0) * 10.0).round() as u16 & 0x7FFF;
+    message.extend_from_slice(&heading.to_be_bytes());
+    message.extend_from_slice(&(ias.round() as u16).to_be_bytes());
+    message.extend_from_slice(&(tas.round() as u16).to_be_bytes());
+    message
+}
+
+/// Frame a message: append its CRC, escape the control characters and add the flags.
+pub fn frame(message: &[u8]) -> Vec<u8> {
+    let crc = crc(message);
+    let mut framed = vec![FLAG];
+    for &byte in message.iter().chain(&[crc as u8, (crc >> 8) as u8]) {
+        if byte == FLAG || byte == ESCAPE {
+            framed.push(ESCAPE);
+            framed.push(byte ^ 0x20);
+        } else {
+            framed.push(byte);
+        }
+    }
+    framed.push(FLAG);
+    framed
+}
+
+fn encode_report(id: u8, report: &Traffic) -> Vec<u8> {
+    let mut message = vec![id, 0x00];
+    push_u24(&mut message, report.address);
+    push_u24(&mut message, semicircles(report.latitude));
+    push_u24(&mut message, semicircles(report.longitude));
+    let altitude = clamp((report.altitude + 1000.0) / 25.0, 0.0, 0xFFE as f64) as u16;
+    let misc = if report.airborne { 0x9 } else { 0x1 };
+    message.push((altitude >> 4) as u8);
+    message.push(((altitude & 0xF) as u8) << 4 | misc);
+    message.push(0x89);
+    let speed = clamp(report.ground_speed, 0.0, 0xFFE as f64) as u16;
+    let vertical = clamp(report.vertical_speed / 64.0, -510.0, 510.0) as i16 as u16 & 0xFFF;
+    message.push((speed >> 4) as u8);
+    message.push(((speed & 0xF) as u8) << 4 | (vertical >> 8) as u8);
+    message.push(vertical as u8);
+    message.push((report.track.rem_euclid(360.0) * 256.0 / 360.0) as u8);
+    message.push(0x01);
+    let callsign = report.callsign.as_bytes();
+    for i in 0..8 {
+        let c = callsign.get(i).copied().unwrap_or(b' ');
+        message.push(if c.is_ascii_alphanumeric() { c } else { b' ' });
+    }
+    message.push(0x00);
+    message
+}
+
+fn semicircles(degrees: f64) -> u32 {
+    (degrees * (1 << 23) as f64 / 180.0) as i32 as u32 & 0xFF_FFFF
+}
+
+fn push_u24(message: &mut Vec<u8>, value: u32) {
+    message.extend_from_slice(&value.to_be_bytes()[1..]);
+}
+
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.max(min).min(max)
+}
+
+fn crc(message: &[u8]) -> u16 {
+    message.iter().fold(0u16, |crc, &byte| {
+        crc_table_entry(crc >> 8) ^ (crc << 8) ^ byte as u16
+    })
+}
+
+/// The entry of the CRC-CCITT table of the GDL90 specification for the given byte.
+fn crc_table_entry(byte: u16) -> u16 {
+    (0..8).fold(byte << 8, |crc, _| {
+        if crc & 0x8000 != 0 {
+            (crc << 1) ^ 0x1021
+        } else {
+            crc << 1
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_frame_messages_with_crc() {
+        let message = [0x00, 0x81, 0x41, 0xDB, 0xD0, 0x08, 0x02];
+        assert_eq!(
+            frame(&message),
+            vec![0x7E, 0x00, 0x81, 0x41, 0xDB, 0xD0, 0x08, 0x02, 0xB3, 0x8B, 0x7E]
+        );
+    }
+
+    #[test]
+    fn should_escape_control_characters() {
+        let framed = frame(&[0x7E, 0x7D]);
+        assert_eq!(&framed[..5], &[0x7E, 0x7D, 0x5E, 0x7D, 0x5D]);
+    }
+
+    #[test]
+    fn should_encode_traffic_report() {
+        let report = Traffic {
+            address: 0xAB4549,
+            latitude: 44.90708,
+            longitude: -122.99488,
+            altitude: 5000.0,
+            ground_speed: 123.0,
+            vertical_speed: 64.0,
+            track: 45.0,
+            callsign: "N825V".to_string(),
+            airborne: true,
+        };
+        let message = traffic_report(&report);
+        assert_eq!(message.len(), 28);
+        assert_eq!(
+            &message[..16],
+            &[
+                0x14, 0x00, 0xAB, 0x45, 0x49, 0x1F, 0xEF, 0x15, 0xA8, 0x89, 0x78, 0x0F, 0x09, 0x89,
+                0x07, 0xB0
+            ]
+        );
+        assert_eq!(message[16], 0x01);
+        assert_eq!(message[17], 0x20);
+        assert_eq!(&message[19..27], b"N825V   ");
+    }
+
+    #[test]
+    fn should_encode_right_bank_as_positive_roll() {
+        let state = FlightState {
+            bank: -(geo::ANGLE_UNITS_PER_DEGREE * 15.0) as i32,
+            ..FlightState::default()
+        };
+        assert!((state.roll() - 15.0).abs() < 1e-6);
+        let message = ahrs(state.roll(), state.pitch(), 0.0, 0.0, 0.0);
+        assert_eq!(i16::from_be_bytes([message[2], message[3]]), 150);
+    }
+
+    #[test]
+    fn should_encode_heartbeat_timestamp() {
+        let message = heartbeat(70000);
+        assert_eq!(message[2], 0x81);
+        assert_eq!(
+            u16::from_le_bytes([message[3], message[4]]),
+            (70000 & 0xFFFF) as u16
+        );
+    }
+
+    #[test]
+    fn should_broadcast_messages() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let broadcaster = Gdl90Broadcaster::new(receiver.local_addr().unwrap(), 0xABCDEF).unwrap();
+        broadcaster
+            .send(&FlightState::default(), &[Traffic::default()])
+            .unwrap();
+        let mut ids = Vec::new();
+        let mut buffer = [0; 128];
+        for _ in 0..5 {
+            let len = receiver.recv(&mut buffer).unwrap();
+            assert_eq!((buffer[0], buffer[len - 1]), (FLAG, FLAG));
+            ids.push(buffer[1]);
+        }
+        assert_eq!(ids, vec![0x00, 0x0A, 0x0B, 0x65, 0x14]);
+    }
+}
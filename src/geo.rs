@@ -0,0 +1,222 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversions of the position of the aircraft
+//! FSUIPC encodes latitude, longitude and altitude in 64-bit fixed point units, in three
+//! consecutive offsets from 0x0560. `POSITION` reads the three of them at once, and
+//! `Session::read_position()` reads them into a `PositionState` decoded once the session is
+//! processed.
+
+use std::io;
+
+use super::offsets::{FsuipcStruct, Offset};
+use super::Session;
+
+/// The number of feet in a metre.
+pub const FEET_PER_METRE: f64 = 3.28084;
+
+/// The number of knots in a metre per second.
+pub const KNOTS_PER_METRE_PER_SECOND: f64 = 1.943_844;
+
+/// The number of kilograms in a pound.
+pub const KILOGRAMS_PER_POUND: f64 = 0.453_592_37;
+
+const LATITUDE_UNITS_PER_DEGREE: f64 = 10_001_750.0 * 65536.0 * 65536.0 / 90.0;
+const LONGITUDE_UNITS_PER_DEGREE: f64 = 65536.0 * 65536.0 * 65536.0 * 65536.0 / 360.0;
+const ALTITUDE_UNITS_PER_METRE: f64 = 65536.0 * 65536.0;
+
+/// The units of the pitch, bank and heading offsets (0x0578 to 0x0580) in a degree.
+pub const ANGLE_UNITS_PER_DEGREE: f64 = 65536.0 * 65536.0 / 360.0;
+
+/// The raw contents of the latitude, longitude and altitude offsets
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RawPosition {
+    pub latitude: i64,
+    pub longitude: i64,
+    pub altitude: i64,
+}
+
+/// The latitude, longitude and altitude offsets, read or written at once.
+pub const POSITION: Offset<RawPosition> = Offset::new(0x0560);
+
+/// A position
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    /// The latitude, in degrees (positive to the north).
+    pub latitude: f64,
+    /// The longitude, in degrees (positive to the east).
+    pub longitude: f64,
+    /// The altitude above mean sea level, in metres.
+    pub altitude: f64,
+}
+
+impl Position {
+    /// The altitude above mean sea level, in feet.
+    pub fn altitude_feet(&self) -> f64 {
+        self.altitude * FEET_PER_METRE
+    }
+
+    /// Encode the position in FSUIPC units.
+    pub fn to_raw(&self) -> RawPosition {
+        RawPosition {
+            latitude: (self.latitude * LATITUDE_UNITS_PER_DEGREE) as i64,
+            longitude: (self.longitude * LONGITUDE_UNITS_PER_DEGREE) as i64,
+            altitude: (self.altitude * ALTITUDE_UNITS_PER_METRE) as i64,
+        }
+    }
+}
+
+impl From<RawPosition> for Position {
+    fn from(raw: RawPosition) -> Self {
+        Position {
+            latitude: latitude_degrees(raw.latitude),
+            longitude: longitude_degrees(raw.longitude),
+            altitude: altitude_metres(raw.altitude),
+        }
+    }
+}
+
+/// The position of the aircraft, as read by `Session::read_position()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PositionState {
+    position: RawPosition,
+}
+
+impl PositionState {
+    /// The latitude and longitude, in degrees, and the altitude, in metres.
+    pub fn position(&self) -> Position {
+        Position::from(self.position)
+    }
+
+    /// The position in FSUIPC units, as read.
+    pub fn raw(&self) -> RawPosition {
+        self.position
+    }
+}
+
+impl FsuipcStruct for PositionState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(POSITION, &mut self.position)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decode the latitude offset (0x0560) into degrees.
+pub fn latitude_degrees(raw: i64) -> f64 {
+    raw as f64 / LATITUDE_UNITS_PER_DEGREE
+}
+
+/// Decode the longitude offset (0x0568) into degrees.
+pub fn longitude_degrees(raw: i64) -> f64 {
+    raw as f64 / LONGITUDE_UNITS_PER_DEGREE
+}
+
+/// Decode the altitude offset (0x0570) into metres.
+pub fn altitude_metres(raw: i64) -> f64 {
+    raw as f64 / ALTITUDE_UNITS_PER_METRE
+}
+
+/// Decode the pitch offset (0x0578) into degrees, positive nose up.
+/// FSUIPC stores the pitch negative nose up, so the raw value is negated (subtracted from zero,
+/// so a level attitude decodes to 0.0 and not -0.0).
+pub fn pitch_degrees(raw: i32) -> f64 {
+    0.0 - raw as f64 / ANGLE_UNITS_PER_DEGREE
+}
+
+/// Decode the bank offset (0x057C) into degrees, positive for a right bank.
+/// FSUIPC stores the bank negative to the right, so the raw value is negated as the pitch is.
+pub fn bank_degrees(raw: i32) -> f64 {
+    0.0 - raw as f64 / ANGLE_UNITS_PER_DEGREE
+}
+
+/// Decode the true heading offset (0x0580) into degrees.
+pub fn heading_degrees(raw: u32) -> f64 {
+    raw as f64 / ANGLE_UNITS_PER_DEGREE
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::batch::BatchReader;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_convert_positions() {
+        let position = Position {
+            latitude: 40.4719,
+            longitude: -3.5626,
+            altitude: 609.6,
+        };
+        let decoded = Position::from(position.to_raw());
+        assert!((decoded.latitude - position.latitude).abs() < 1e-9);
+        assert!((decoded.longitude - position.longitude).abs() < 1e-9);
+        assert!((decoded.altitude - position.altitude).abs() < 1e-6);
+        assert!((decoded.altitude_feet() - 2000.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn should_decode_known_values() {
+        assert_eq!(latitude_degrees(10_001_750 * 65536 * 65536), 90.0);
+        assert_eq!(longitude_degrees(i64::MIN), -180.0);
+        assert_eq!(altitude_metres(100 << 32), 100.0);
+        assert_eq!(pitch_degrees(-(1 << 30)), 90.0);
+        assert_eq!(bank_degrees(1 << 29), -45.0);
+        assert_eq!(heading_degrees(1 << 31), 180.0);
+        assert_eq!(format!("{}", bank_degrees(0)), "0");
+    }
+
+    #[test]
+    fn should_read_position_at_once() {
+        let mut handle = MockHandle::new();
+        let raw = Position {
+            latitude: 51.4775,
+            longitude: -0.4614,
+            altitude: 25.0,
+        }
+        .to_raw();
+        handle.seed_value(0x0560, &raw.latitude);
+        handle.seed_value(0x0568, &raw.longitude);
+        handle.seed_value(0x0570, &raw.altitude);
+
+        let mut batch = BatchReader::new();
+        let position = batch.read_offset(POSITION);
+        let results = batch.process(&mut handle).unwrap();
+        let position = Position::from(results.get(&position));
+        assert!((position.latitude - 51.4775).abs() < 1e-9);
+        assert!((position.altitude - 25.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn should_read_position_in_session() {
+        let mut handle = MockHandle::new();
+        let raw = Position {
+            latitude: 40.4719,
+            longitude: -3.5626,
+            altitude: 609.6,
+        }
+        .to_raw();
+        handle.seed_value(0x0560, &raw);
+
+        let mut state = PositionState::default();
+        {
+            let mut session = handle.session();
+            session.read_position(&mut state).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(state.raw(), raw);
+        assert!((state.position().latitude - 40.4719).abs() < 1e-9);
+        assert!((state.position().altitude_feet() - 2000.0).abs() < 0.01);
+    }
+}
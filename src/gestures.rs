@@ -0,0 +1,182 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Gesture detection for panel hardware
+//! Dense hardware panels give more than one function to each button: a short press, a long
+//! press or a double press, and turning an encoder while its knob is pushed. `GestureDetector`
+//! turns the raw button and encoder inputs into distinct gestures that can each be mapped to
+//! an action.
+//!
+//! The times are given by the application as the time elapsed since any fixed instant, so the
+//! detector can be driven from recorded inputs as well as from live hardware.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A gesture done on a button or encoder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// The button was pressed and released once.
+    ShortPress,
+    /// The button was held down for the long press time. It is reported while still held.
+    LongPress,
+    /// The button was pressed twice within the double press time.
+    DoublePress,
+    /// The encoder was turned the given steps (positive clockwise) with its knob released.
+    Turn(i32),
+    /// The encoder was turned the given steps (positive clockwise) with its knob pushed.
+    PushTurn(i32),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Button {
+    pressed_at: Option<Duration>,
+    released_at: Option<Duration>,
+    consumed: bool,
+}
+
+/// A detector of gestures on buttons and push encoders, identified by number
+#[derive(Clone, Debug)]
+pub struct GestureDetector {
+    long_press: Duration,
+    double_press: Duration,
+    buttons: BTreeMap<u32, Button>,
+}
+
+impl Default for GestureDetector {
+    /// A detector with a long press of 600 ms and a double press within 300 ms.
+    fn default() -> Self {
+        GestureDetector::new(Duration::from_millis(600), Duration::from_millis(300))
+    }
+}
+
+impl GestureDetector {
+    pub fn new(long_press: Duration, double_press: Duration) -> Self {
+        GestureDetector {
+            long_press,
+            double_press,
+            buttons: BTreeMap::new(),
+        }
+    }
+
+    /// Feed the state of a button at `now`, obtaining the gesture it completes, if any.
+    /// Short presses are only known once the double press time has passed with no second
+    /// press, so they are reported by `poll()`.
+    pub fn button(&mut self, id: u32, pressed: bool, now: Duration) -> Option<Gesture> {
+        let double_press = self.double_press;
+        let button = self.buttons.entry(id).or_default();
+        match (pressed, button.pressed_at) {
+            (true, None) => {
+                button.pressed_at = Some(now);
+                button.consumed = false;
+                None
+            }
+            (false, Some(_)) => {
+                button.pressed_at = None;
+                if button.consumed {
+                    return None;
+                }
+                match button.released_at.take() {
+                    Some(released) if now.saturating_sub(released) <= double_press => {
+                        Some(Gesture::DoublePress)
+                    }
+                    _ => {
+                        button.released_at = Some(now);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Feed the steps an encoder was turned, whose knob is the button `id`.
+    /// Turning it while pushed cancels the press gestures of the knob.
+    pub fn turn(&mut self, id: u32, steps: i32) -> Gesture {
+        let button = self.buttons.entry(id).or_default();
+        if button.pressed_at.is_some() {
+            button.consumed = true;
+            button.released_at = None;
+            Gesture::PushTurn(steps)
+        } else {
+            Gesture::Turn(steps)
+        }
+    }
+
+    /// Obtain the gestures completed by the passing of time at `now`: long presses of held
+    /// buttons, and short presses whose double press time has passed.
+    pub fn poll(&mut self, now: Duration) -> Vec<(u32, Gesture)> {
+        let mut gestures = Vec::new();
+        for (id, button) in self.buttons.iter_mut() {
+            if let Some(pressed) = button.pressed_at {
+                if !button.consumed && now.saturating_sub(pressed) >= self.long_press {
+                    button.consumed = true;
+                    button.released_at = None;
+                    gestures.push((*id, Gesture::LongPress));
+                }
+            }
+            if let Some(released) = button.released_at {
+                if button.pressed_at.is_none() && now.saturating_sub(released) > self.double_press {
+                    button.released_at = None;
+                    gestures.push((*id, Gesture::ShortPress));
+                }
+            }
+        }
+        gestures
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn should_detect_short_and_double_presses() {
+        let mut detector = GestureDetector::default();
+        assert_eq!(detector.button(1, true, ms(0)), None);
+        assert_eq!(detector.button(1, false, ms(100)), None);
+        assert!(detector.poll(ms(300)).is_empty());
+        assert_eq!(detector.poll(ms(401)), vec![(1, Gesture::ShortPress)]);
+
+        detector.button(1, true, ms(1000));
+        detector.button(1, false, ms(1100));
+        detector.button(1, true, ms(1200));
+        assert_eq!(
+            detector.button(1, false, ms(1300)),
+            Some(Gesture::DoublePress)
+        );
+        assert!(detector.poll(ms(2000)).is_empty());
+    }
+
+    #[test]
+    fn should_detect_long_presses_while_held() {
+        let mut detector = GestureDetector::default();
+        detector.button(2, true, ms(0));
+        assert!(detector.poll(ms(500)).is_empty());
+        assert_eq!(detector.poll(ms(600)), vec![(2, Gesture::LongPress)]);
+        assert!(detector.poll(ms(700)).is_empty());
+        assert_eq!(detector.button(2, false, ms(800)), None);
+        assert!(detector.poll(ms(2000)).is_empty());
+    }
+
+    #[test]
+    fn should_detect_push_and_turn() {
+        let mut detector = GestureDetector::default();
+        assert_eq!(detector.turn(3, 2), Gesture::Turn(2));
+        detector.button(3, true, ms(100));
+        assert_eq!(detector.turn(3, -1), Gesture::PushTurn(-1));
+        assert!(detector.poll(ms(1000)).is_empty());
+        assert_eq!(detector.button(3, false, ms(1100)), None);
+        assert!(detector.poll(ms(2000)).is_empty());
+    }
+}
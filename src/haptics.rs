@@ -0,0 +1,241 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Haptic output
+//! `HapticEffects` turns the flight state into the intensity of two vibration motors: a low
+//! frequency one shaken by the stall buffet, and a high frequency one by the ground roll,
+//! stronger on rough surfaces and at speed. The intensities are sent to a `RumbleOutput`,
+//! either the rumble of a gamepad through XInput, with the `xinput` feature, or motors driven
+//! by a serial board.
+
+use std::io::{self, Write};
+
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The flight data the effects are computed from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HapticSample {
+    pub stall_warning: u8,
+    pub on_ground: u16,
+    pub ground_speed: u32,
+    pub surface_type: u32,
+}
+
+impl HapticSample {
+    /// The ground speed, in knots.
+    pub fn ground_speed_knots(&self) -> f64 {
+        self.ground_speed as f64 / 65536.0 * 3600.0 / 1852.0
+    }
+
+    /// How rough the surface is, from 0 (smooth) to 1.
+    pub fn roughness(&self) -> f64 {
+        match self.surface_type {
+            // Concrete, asphalt, urban, bituminous, brick, macadam, tarmac
+            0 | 4 | 10 | 17 | 18 | 19 | 23 => 0.2,
+            // Water
+            2 => 0.0,
+            // Short grass, hard turf, snow, ice, oil treated, steel mats
+            5 | 7 | 8 | 9 | 15 | 16 => 0.5,
+            _ => 1.0,
+        }
+    }
+}
+
+impl FsuipcStruct for HapticSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::STALL_WARNING, &mut self.stall_warning)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::SURFACE_TYPE, &mut self.surface_type)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The intensity of the two vibration motors, from 0 (stopped) to 1 (full speed)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rumble {
+    /// The low frequency motor, the left one of XInput gamepads.
+    pub low: f64,
+    /// The high frequency motor, the right one of XInput gamepads.
+    pub high: f64,
+}
+
+/// The vibration effects of the flight state
+#[derive(Clone, Copy, Debug)]
+pub struct HapticEffects {
+    stall_buffet: f64,
+    ground_roll: f64,
+    full_roll_speed: f64,
+}
+
+impl Default for HapticEffects {
+    /// Full stall buffet, and full ground roll on rough surfaces from 60 knots.
+    fn default() -> Self {
+        HapticEffects {
+            stall_buffet: 1.0,
+            ground_roll: 1.0,
+            full_roll_speed: 60.0,
+        }
+    }
+}
+
+impl HapticEffects {
+    pub fn new() -> Self {
+        HapticEffects::default()
+    }
+
+    /// Shake the low frequency motor with the given intensity while the stall warning is on.
+    pub fn with_stall_buffet(mut self, intensity: f64) -> Self {
+        self.stall_buffet = intensity;
+        self
+    }
+
+    /// Shake the high frequency motor with at most the given intensity while rolling on the
+    /// ground, reached on the roughest surfaces at `full_speed` knots.
+    pub fn with_ground_roll(mut self, intensity: f64, full_speed: f64) -> Self {
+        self.ground_roll = intensity;
+        self.full_roll_speed = full_speed;
+        self
+    }
+
+    /// The rumble for the given flight state.
+    pub fn rumble(&self, sample: &HapticSample) -> Rumble {
+        let mut rumble = Rumble::default();
+        if sample.stall_warning != 0 {
+            rumble.low = self.stall_buffet;
+        }
+        if sample.on_ground != 0 && self.full_roll_speed > 0.0 {
+            let speed = (sample.ground_speed_knots() / self.full_roll_speed).min(1.0);
+            rumble.high = self.ground_roll * sample.roughness() * speed;
+        }
+        rumble.low = rumble.low.clamp(0.0, 1.0);
+        rumble.high = rumble.high.clamp(0.0, 1.0);
+        rumble
+    }
+}
+
+/// A device that vibrates
+pub trait RumbleOutput {
+    /// Set the intensity of the motors until the next call.
+    fn rumble(&mut self, rumble: Rumble) -> io::Result<()>;
+}
+
+/// Motors driven by a board on a serial port
+/// Each rumble is sent as a line `R<low>,<high>`, with the intensities from 0 to 255, which
+/// is easy to parse from the firmware of a microcontroller. The port is anything writable,
+/// e.g. the file of a COM port already set up with the baud rate of the board.
+pub struct SerialMotors<W> {
+    port: W,
+}
+
+impl<W: Write> SerialMotors<W> {
+    pub fn new(port: W) -> Self {
+        SerialMotors { port }
+    }
+
+    /// Obtain the port back.
+    pub fn into_inner(self) -> W {
+        self.port
+    }
+}
+
+impl<W: Write> RumbleOutput for SerialMotors<W> {
+    fn rumble(&mut self, rumble: Rumble) -> io::Result<()> {
+        let low = (rumble.low * 255.0).round() as u8;
+        let high = (rumble.high * 255.0).round() as u8;
+        writeln!(self.port, "R{},{}", low, high)?;
+        self.port.flush()
+    }
+}
+
+/// The rumble of an XInput gamepad
+/// The motors are stopped when dropped.
+#[cfg(all(windows, feature = "xinput"))]
+pub struct XInputRumble {
+    user: u32,
+}
+
+#[cfg(all(windows, feature = "xinput"))]
+impl XInputRumble {
+    /// Drive the gamepad of the given user, from 0 to 3.
+    pub fn new(user: u32) -> Self {
+        XInputRumble { user }
+    }
+}
+
+#[cfg(all(windows, feature = "xinput"))]
+impl RumbleOutput for XInputRumble {
+    fn rumble(&mut self, rumble: Rumble) -> io::Result<()> {
+        let low = (rumble.low * 65535.0).round() as u16;
+        let high = (rumble.high * 65535.0).round() as u16;
+        if !super::sys::set_rumble(self.user, low, high) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("no XInput gamepad connected for user {}", self.user),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(windows, feature = "xinput"))]
+impl Drop for XInputRumble {
+    fn drop(&mut self) {
+        super::sys::set_rumble(self.user, 0, 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn rolling(knots: f64, surface_type: u32) -> HapticSample {
+        HapticSample {
+            on_ground: 1,
+            ground_speed: (knots * 1852.0 / 3600.0 * 65536.0) as u32,
+            surface_type,
+            ..HapticSample::default()
+        }
+    }
+
+    #[test]
+    fn should_rumble_with_stall_and_ground_roll() {
+        let effects = HapticEffects::new().with_stall_buffet(0.8);
+        assert_eq!(effects.rumble(&HapticSample::default()), Rumble::default());
+
+        let stall = HapticSample {
+            stall_warning: 1,
+            ..HapticSample::default()
+        };
+        assert_eq!(effects.rumble(&stall).low, 0.8);
+
+        assert!((effects.rumble(&rolling(30.0, 1)).high - 0.5).abs() < 1e-3);
+        assert!((effects.rumble(&rolling(120.0, 1)).high - 1.0).abs() < 1e-9);
+        assert!((effects.rumble(&rolling(120.0, 0)).high - 0.2).abs() < 1e-9);
+        assert_eq!(effects.rumble(&rolling(120.0, 2)).high, 0.0);
+    }
+
+    #[test]
+    fn should_send_rumble_to_serial_motors() {
+        let mut motors = SerialMotors::new(Vec::new());
+        motors
+            .rumble(Rumble {
+                low: 1.0,
+                high: 0.5,
+            })
+            .unwrap();
+        motors.rumble(Rumble::default()).unwrap();
+        assert_eq!(motors.into_inner(), b"R255,128\nR0,0\n");
+    }
+}
@@ -0,0 +1,201 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Head tracking input
+//! DIY head trackers usually feed opentrack, whose "UDP over network" output sends the head
+//! pose as six little-endian doubles: the position in centimetres and the yaw, pitch and roll
+//! in degrees. `HeadTracker` receives those datagrams, and `write_view()` writes the pose to
+//! the view offsets of the simulator. These offsets differ between simulators and FSUIPC
+//! versions, so the application tells which block to write to.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use super::Session;
+
+/// The UDP port opentrack sends to by default.
+pub const OPENTRACK_PORT: u16 = 4242;
+
+const DATAGRAM_LEN: usize = 48;
+
+/// A head pose as sent by opentrack
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HeadPose {
+    /// Lateral position, in centimetres (positive to the right).
+    pub x: f64,
+    /// Vertical position, in centimetres (positive up).
+    pub y: f64,
+    /// Longitudinal position, in centimetres (positive backwards).
+    pub z: f64,
+    /// Yaw, in degrees (positive to the right).
+    pub yaw: f64,
+    /// Pitch, in degrees (positive up).
+    pub pitch: f64,
+    /// Roll, in degrees (positive to the right).
+    pub roll: f64,
+}
+
+impl HeadPose {
+    /// Decode an opentrack datagram, if it has the expected length.
+    pub fn from_datagram(datagram: &[u8]) -> Option<Self> {
+        if datagram.len() != DATAGRAM_LEN {
+            return None;
+        }
+        let mut values = [0.0; 6];
+        for (value, bytes) in values.iter_mut().zip(datagram.chunks(8)) {
+            let mut double = [0u8; 8];
+            double.copy_from_slice(bytes);
+            *value = f64::from_le_bytes(double);
+        }
+        let [x, y, z, yaw, pitch, roll] = values;
+        Some(HeadPose {
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+            roll,
+        })
+    }
+
+    /// Encode the pose as an opentrack datagram.
+    pub fn to_datagram(&self) -> [u8; DATAGRAM_LEN] {
+        let mut datagram = [0u8; DATAGRAM_LEN];
+        let values = [self.x, self.y, self.z, self.yaw, self.pitch, self.roll];
+        for (bytes, value) in datagram.chunks_mut(8).zip(values.iter()) {
+            bytes.copy_from_slice(&value.to_le_bytes());
+        }
+        datagram
+    }
+}
+
+/// A receiver of the head poses sent by opentrack
+pub struct HeadTracker {
+    socket: UdpSocket,
+}
+
+impl HeadTracker {
+    /// Listen to the given address, e.g. `("0.0.0.0", OPENTRACK_PORT)`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(HeadTracker {
+            socket: UdpSocket::bind(addr)?,
+        })
+    }
+
+    /// The address the tracker listens to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Wait for the next pose, at most `timeout` if given.
+    /// Datagrams that are not head poses are ignored. It fails with `TimedOut` or
+    /// `WouldBlock`, depending on the platform, if no pose arrives in time.
+    pub fn receive(&self, timeout: Option<Duration>) -> io::Result<HeadPose> {
+        self.socket.set_read_timeout(timeout)?;
+        let mut buffer = [0u8; 64];
+        loop {
+            let len = self.socket.recv(&mut buffer)?;
+            if let Some(pose) = HeadPose::from_datagram(&buffer[..len]) {
+                return Ok(pose);
+            }
+        }
+    }
+
+    /// The most recent pose received since the last call, without waiting.
+    /// Older poses queued in the socket are skipped, so the view never lags behind the head.
+    pub fn latest(&self) -> io::Result<Option<HeadPose>> {
+        self.socket.set_nonblocking(true)?;
+        let mut latest = None;
+        let mut buffer = [0u8; 64];
+        let result = loop {
+            match self.socket.recv(&mut buffer) {
+                Ok(len) => {
+                    if let Some(pose) = HeadPose::from_datagram(&buffer[..len]) {
+                        latest = Some(pose);
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(latest),
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_nonblocking(false)?;
+        result
+    }
+}
+
+/// Request writing the pose into the view offsets from `offset`.
+/// The block is written as six doubles: the position in metres followed by the pitch, bank and
+/// heading in degrees.
+pub fn write_view<S: Session>(session: &mut S, offset: u16, pose: &HeadPose) -> io::Result<()> {
+    let view = [
+        pose.x / 100.0,
+        pose.y / 100.0,
+        pose.z / 100.0,
+        pose.pitch,
+        pose.roll,
+        pose.yaw,
+    ];
+    session.write(offset, &view)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    fn pose() -> HeadPose {
+        HeadPose {
+            x: 1.5,
+            y: -2.0,
+            z: 10.0,
+            yaw: 30.0,
+            pitch: -5.0,
+            roll: 2.5,
+        }
+    }
+
+    #[test]
+    fn should_receive_latest_pose() {
+        let tracker = HeadTracker::bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = tracker.local_addr().unwrap();
+        assert_eq!(tracker.latest().unwrap(), None);
+
+        sender.send_to(b"not a pose", target).unwrap();
+        sender.send_to(&pose().to_datagram(), target).unwrap();
+        let timeout = Some(Duration::from_secs(5));
+        assert_eq!(tracker.receive(timeout).unwrap(), pose());
+
+        let later = HeadPose {
+            yaw: 45.0,
+            ..pose()
+        };
+        sender.send_to(&pose().to_datagram(), target).unwrap();
+        sender.send_to(&later.to_datagram(), target).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(tracker.latest().unwrap(), Some(later));
+    }
+
+    #[test]
+    fn should_write_view_offsets() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_view(&mut session, 0x86A0, &pose()).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(
+            handle.value::<[f64; 6]>(0x86A0),
+            [0.015, -0.02, 0.1, -5.0, 2.5, 30.0]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Icing and surface conditions
+//! `IcingState` reads the ice accumulated on the airframe, the outside air temperature, the
+//! condition of the surface under the aircraft and the position of the anti-ice and de-ice
+//! switches, which is what anti-ice automation rules are written against.
+
+use std::io;
+
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The engines whose anti-ice switch is read.
+pub const ENGINES: usize = 4;
+
+/// The distance between the offsets of consecutive engines.
+const ENGINE_STRIDE: u16 = 0x98;
+
+/// The condition of the surface under the aircraft
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceCondition {
+    Normal,
+    Wet,
+    Icy,
+    Snow,
+    Other(u32),
+}
+
+impl From<u32> for SurfaceCondition {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => SurfaceCondition::Normal,
+            1 => SurfaceCondition::Wet,
+            2 => SurfaceCondition::Icy,
+            3 => SurfaceCondition::Snow,
+            other => SurfaceCondition::Other(other),
+        }
+    }
+}
+
+/// The icing state of the aircraft
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IcingState {
+    pub structural_ice: u16,
+    pub outside_air_temperature: i16,
+    pub surface_condition: u32,
+    pub structural_deice: u8,
+    pub propeller_deice: u8,
+    pub engine_anti_ice: [u16; ENGINES],
+}
+
+impl IcingState {
+    /// The structural ice, from 0 to 100.
+    pub fn structural_ice_percent(&self) -> f64 {
+        self.structural_ice as f64 * 100.0 / 16384.0
+    }
+
+    /// The outside air temperature, in degrees Celsius.
+    pub fn outside_air_temperature_celsius(&self) -> f64 {
+        self.outside_air_temperature as f64 / 256.0
+    }
+
+    /// The condition of the surface under the aircraft.
+    pub fn surface_condition(&self) -> SurfaceCondition {
+        SurfaceCondition::from(self.surface_condition)
+    }
+
+    /// Whether the anti-ice of the given engine, from 0, is on.
+    pub fn engine_anti_ice_on(&self, engine: usize) -> bool {
+        self.engine_anti_ice.get(engine).copied().unwrap_or(0) != 0
+    }
+
+    /// Whether ice is building up on the airframe with the structural de-ice off.
+    pub fn unprotected_ice(&self) -> bool {
+        self.structural_ice > 0 && self.structural_deice == 0
+    }
+}
+
+impl FsuipcStruct for IcingState {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::STRUCTURAL_ICE, &mut self.structural_ice)?;
+        session.read_offset(
+            offsets::OUTSIDE_AIR_TEMPERATURE,
+            &mut self.outside_air_temperature,
+        )?;
+        session.read_offset(offsets::SURFACE_CONDITION, &mut self.surface_condition)?;
+        session.read_offset(offsets::STRUCTURAL_DEICE, &mut self.structural_deice)?;
+        session.read_offset(offsets::PROPELLER_DEICE, &mut self.propeller_deice)?;
+        let first = offsets::ENGINE1_ANTI_ICE.address();
+        for (engine, switch) in self.engine_anti_ice.iter_mut().enumerate() {
+            session.read(first + engine as u16 * ENGINE_STRIDE, switch)?;
+        }
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_read_icing_state() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0348, &4096u16);
+        handle.seed_value(0x0E8C, &(-5i16 * 256));
+        handle.seed_value(0x31EC, &2u32);
+        handle.seed_value(0x08B2 + 0x98, &1u16);
+        let mut state = IcingState::default();
+        {
+            let mut session = handle.session();
+            state.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(state.structural_ice_percent(), 25.0);
+        assert_eq!(state.outside_air_temperature_celsius(), -5.0);
+        assert_eq!(state.surface_condition(), SurfaceCondition::Icy);
+        assert!(!state.engine_anti_ice_on(0));
+        assert!(state.engine_anti_ice_on(1));
+        assert!(!state.engine_anti_ice_on(7));
+        assert!(state.unprotected_ice());
+        assert_eq!(SurfaceCondition::from(9), SurfaceCondition::Other(9));
+    }
+}
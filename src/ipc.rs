@@ -8,23 +8,42 @@
 
 use std::io;
 use std::io::{Read, Write};
+use std::time::Duration;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
-use super::raw::RawBytes;
+use super::raw::{MutRawBytes, RawBytes};
+use super::report::{ProcessReport, RequestKind, RequestStatus};
 
-pub type WinUInt = usize;
-pub type WinInt = isize;
+pub(crate) type WinUInt = usize;
+pub(crate) type WinInt = isize;
+
+/// The time to wait for FSUIPC to process the requests unless told otherwise.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The timeout in milliseconds, as expected by `SendMessageTimeoutA`.
+pub(crate) fn timeout_millis(timeout: Duration) -> u32 {
+    timeout.as_millis().min(u32::MAX as u128) as u32
+}
+
+/// The length of a read request, not counting the bytes read.
+pub(crate) const RSD_HEADER_LEN: usize = 16;
+/// The length of a write request, not counting the bytes written.
+pub(crate) const WSD_HEADER_LEN: usize = 12;
+/// The length of the mark terminating the requests.
+pub(crate) const TERMINATION_MARK_LEN: usize = 4;
 
 /// The header of a message sent to FSUIPC module via IPC
 #[derive(Debug, PartialEq)]
 pub enum MsgHeader {
     /// Read state data message header
     /// Read `len` bytes from given offset and prepar to store `data` in `target`.
+    /// The target is the index of the destination in the table of targets of the session, as
+    /// the field is 32 bits wide and cannot hold a pointer of a 64-bit host.
     ReadStateData {
         offset: u16,
         len: usize,
-        target: *mut u8,
+        target: u32,
     },
     /// Write state data message header
     /// Write `len` bytes from given `source` to given offset.
@@ -43,7 +62,7 @@ pub trait MsgRead: Read {
             FS6IPC_READSTATEDATA_ID => {
                 let offset = self.read_u32::<LittleEndian>()? as u16;
                 let len = self.read_u32::<LittleEndian>()? as usize;
-                let target = self.read_u32::<LittleEndian>()? as *mut u8;
+                let target = self.read_u32::<LittleEndian>()?;
                 Ok(MsgHeader::ReadStateData {
                     offset,
                     len,
@@ -103,7 +122,7 @@ pub trait MsgWrite: Write {
                 self.write_u32::<LittleEndian>(FS6IPC_READSTATEDATA_ID)?;
                 self.write_u32::<LittleEndian>(*offset as u32)?;
                 self.write_u32::<LittleEndian>(*len as u32)?;
-                self.write_u32::<LittleEndian>(*target as u32)?;
+                self.write_u32::<LittleEndian>(*target)?;
                 Ok(16)
             }
             MsgHeader::WriteStateData { offset, len } => {
@@ -141,11 +160,11 @@ pub trait MsgWrite: Write {
         }
     }
 
-    fn write_rsd(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+    fn write_rsd(&mut self, offset: u16, target: u32, len: usize) -> io::Result<usize> {
         let header = MsgHeader::ReadStateData {
             offset,
             len,
-            target: dest,
+            target,
         };
         let hdr_bytes = self.write_header(&header)?;
         let body_bytes = self.write_body(&header, &mut io::repeat(0))?;
@@ -162,6 +181,54 @@ pub trait MsgWrite: Write {
 
 impl<W: Write + ?Sized> MsgWrite for W {}
 
+/// Decode the response of FSUIPC into the targets of the reads, recording each request.
+/// The target of each read is resolved from its index in `targets`. The report keeps the
+/// requests decoded before any failure.
+pub(crate) fn read_response<R: MsgRead>(
+    input: &mut R,
+    targets: &[*mut u8],
+    report: &mut ProcessReport,
+) -> io::Result<()> {
+    loop {
+        let header = input.read_header()?;
+        let (kind, offset, len, result) = match header {
+            MsgHeader::ReadStateData {
+                offset,
+                len,
+                target,
+            } => {
+                let dest = *targets.get(target as usize).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "unknown target {} of a read of offset 0x{:04X}",
+                            target, offset
+                        ),
+                    )
+                })?;
+                let mut output = MutRawBytes::new(dest, len);
+                let result = input.read_body(&header, &mut output);
+                (RequestKind::Read, offset, len, result)
+            }
+            MsgHeader::WriteStateData { offset, len } => {
+                let result = input.read_body(&header, &mut io::sink());
+                (RequestKind::Write, offset, len, result)
+            }
+            MsgHeader::TerminationMark => {
+                report.terminated = true;
+                return Ok(());
+            }
+        };
+        report.requests.push(RequestStatus {
+            kind,
+            offset,
+            requested: len,
+            returned: *result.as_ref().unwrap_or(&0),
+        });
+        result?;
+    }
+}
+
 const FS6IPC_TERMINATIONMARK_ID: u32 = 0;
 const FS6IPC_READSTATEDATA_ID: u32 = 1;
 const FS6IPC_WRITESTATEDATA_ID: u32 = 2;
@@ -184,7 +251,7 @@ mod test {
         let expected = MsgHeader::ReadStateData {
             offset: 0x1000,
             len: 4,
-            target: 0x2000 as *mut u8,
+            target: 0x2000,
         };
         assert_eq!(buff.read_header().unwrap(), expected);
     }
@@ -195,7 +262,7 @@ mod test {
         let header = MsgHeader::ReadStateData {
             offset: 0x1000,
             len: 4,
-            target: 0x2000 as *mut u8,
+            target: 0x2000,
         };
         let mut data = Vec::new();
         assert_eq!(buff.read_body(&header, &mut data).unwrap(), 4);
@@ -264,7 +331,7 @@ mod test {
         let msg = MsgHeader::ReadStateData {
             offset: 0x1000,
             len: 4,
-            target: 0x2000 as *mut u8,
+            target: 0x2000,
         };
         assert_eq!(buff.write_header(&msg).unwrap(), 16);
         buff.set_position(0);
@@ -282,7 +349,7 @@ mod test {
         let msg = MsgHeader::ReadStateData {
             offset: 0x1000,
             len: 4,
-            target: 0x2000 as *mut u8,
+            target: 0x2000,
         };
 
         assert_eq!(buff.write_body(&msg, &mut input).unwrap(), 4);
@@ -295,7 +362,7 @@ mod test {
     #[test]
     fn should_write_rsd() {
         let mut buff = Cursor::new(Vec::new());
-        assert_eq!(buff.write_rsd(0x1000, 0x2000 as *mut u8, 4).unwrap(), 20);
+        assert_eq!(buff.write_rsd(0x1000, 0x2000, 4).unwrap(), 20);
         buff.set_position(0);
         assert_eq!(buff.get_ref().len(), 20);
         assert_eq!(buff.read_u32::<LittleEndian>().unwrap(), 1);
@@ -366,16 +433,67 @@ mod test {
                 .unwrap(),
             0
         );
+        assert_eq!(buff.len(), 0);
+    }
 
+    #[test]
+    fn should_decode_reads_into_their_targets() {
+        let mut first = 0u32;
+        let mut second = 0u16;
+        let targets = [
+            &mut first as *mut u32 as *mut u8,
+            &mut second as *mut u16 as *mut u8,
+        ];
+        let mut buff = Cursor::new(Vec::new());
+        buff.write_header(&MsgHeader::ReadStateData {
+            offset: 0x1000,
+            len: 4,
+            target: 0,
+        })
+        .unwrap();
+        buff.write_all(&[0x04, 0x03, 0x02, 0x01]).unwrap();
+        buff.write_header(&MsgHeader::ReadStateData {
+            offset: 0x1004,
+            len: 2,
+            target: 1,
+        })
+        .unwrap();
+        buff.write_all(&[0x06, 0x05]).unwrap();
+        buff.write_header(&MsgHeader::TerminationMark).unwrap();
         buff.set_position(0);
-        assert_eq!(buff.read_u32::<LittleEndian>().unwrap(), 0);
+        let mut report = ProcessReport::default();
+        read_response(&mut buff, &targets, &mut report).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(first, 0x01020304);
+        assert_eq!(second, 0x0506);
+
+        buff.set_position(0);
+        assert_eq!(
+            read_response(&mut buff, &targets[..1], &mut report)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidData
+        );
     }
 
     #[test]
-    fn should_write_tm_body() {
-        let mut buff = Vec::new();
-        let mut input = Cursor::new(vec![ 0x01u8, 0x02, 0x03, 0x04 ]);
-        assert_eq!(buff.write_body(&MsgHeader::TerminationMark, &mut input).unwrap(), 0);
-        assert_eq!(buff.len(), 0);
+    fn should_report_truncated_responses() {
+        let mut first = 0u32;
+        let mut second = 0u32;
+        let targets = [
+            &mut first as *mut u32 as *mut u8,
+            &mut second as *mut u32 as *mut u8,
+        ];
+        let mut buff = Cursor::new(Vec::new());
+        buff.write_rsd(0x1000, 0, 4).unwrap();
+        buff.write_rsd(0x1004, 1, 4).unwrap();
+        buff.get_mut().truncate(RSD_HEADER_LEN * 2 + 4 + 2);
+        buff.set_position(0);
+        let mut report = ProcessReport::default();
+        assert!(read_response(&mut buff, &targets, &mut report).is_err());
+        assert!(!report.terminated);
+        assert_eq!(report.requests.len(), 2);
+        assert!(report.requests[0].is_fulfilled());
+        assert_eq!(report.requests[1].returned, 0);
     }
 }
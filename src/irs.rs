@@ -0,0 +1,263 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Simulation of inertial reference units (IRS/ADIRU)
+//! Most default aircraft do not model IRS alignment. This module provides the alignment timing
+//! and the status lights from the position of the mode selector and the electrical power,
+//! so applications can drive cockpit hardware with them. The state is kept in a `ValueStore`,
+//! so alignment survives restarts and can be mirrored into user offsets.
+
+use std::io;
+use std::time::Duration;
+
+use super::store::ValueStore;
+
+/// The position of the IRS mode selector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrsMode {
+    Off,
+    Align,
+    Nav,
+    Att,
+}
+
+/// The operating status of an IRS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IrsStatus {
+    /// Unpowered or switched off. Any previous alignment is lost.
+    Off,
+    /// Alignment in progress.
+    Aligning,
+    /// Aligned and providing navigation data.
+    Aligned,
+    /// Attitude mode: attitude and heading only, no position.
+    Attitude,
+}
+
+impl IrsStatus {
+    /// A numeric code for the status, as stored in the value store.
+    pub fn code(self) -> i32 {
+        match self {
+            IrsStatus::Off => 0,
+            IrsStatus::Aligning => 1,
+            IrsStatus::Aligned => 2,
+            IrsStatus::Attitude => 3,
+        }
+    }
+}
+
+/// The inputs that drive an IRS on every update
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IrsInputs {
+    /// The position of the mode selector.
+    pub mode: IrsMode,
+    /// Whether the aircraft AC bus is powered.
+    pub ac_power: bool,
+    /// Whether the battery is on (e.g. from `offsets::BATTERY_MASTER`).
+    pub battery: bool,
+    /// Whether the aircraft is moving. Motion during alignment restarts it.
+    pub moving: bool,
+}
+
+/// The status lights of an IRS
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IrsLights {
+    pub align: bool,
+    pub on_battery: bool,
+    pub fault: bool,
+}
+
+/// An inertial reference unit
+#[derive(Clone, Debug)]
+pub struct Irs {
+    name: String,
+    alignment_time: Duration,
+    elapsed: Duration,
+    status: IrsStatus,
+    lights: IrsLights,
+}
+
+impl Irs {
+    /// Create a new unit that takes `alignment_time` to align.
+    /// The name identifies the unit in the value store (e.g. `left`).
+    pub fn new(name: &str, alignment_time: Duration) -> Self {
+        Irs {
+            name: name.to_string(),
+            alignment_time,
+            elapsed: Duration::from_secs(0),
+            status: IrsStatus::Off,
+            lights: IrsLights::default(),
+        }
+    }
+
+    /// Advance the simulation by `dt` with the given inputs.
+    pub fn update(&mut self, inputs: &IrsInputs, dt: Duration) -> IrsStatus {
+        let powered = inputs.ac_power || inputs.battery;
+        let mut fault = false;
+        self.status = if !powered || inputs.mode == IrsMode::Off {
+            self.elapsed = Duration::from_secs(0);
+            IrsStatus::Off
+        } else if inputs.mode == IrsMode::Att {
+            IrsStatus::Attitude
+        } else if self.elapsed >= self.alignment_time {
+            IrsStatus::Aligned
+        } else if inputs.moving {
+            fault = true;
+            self.elapsed = Duration::from_secs(0);
+            IrsStatus::Aligning
+        } else {
+            self.elapsed = (self.elapsed + dt).min(self.alignment_time);
+            if self.elapsed >= self.alignment_time {
+                IrsStatus::Aligned
+            } else {
+                IrsStatus::Aligning
+            }
+        };
+        self.lights = IrsLights {
+            align: self.status == IrsStatus::Aligning,
+            on_battery: self.status != IrsStatus::Off && !inputs.ac_power,
+            fault,
+        };
+        self.status
+    }
+
+    /// The current status.
+    pub fn status(&self) -> IrsStatus {
+        self.status
+    }
+
+    /// The current status lights.
+    pub fn lights(&self) -> IrsLights {
+        self.lights
+    }
+
+    /// The time left to complete the alignment.
+    pub fn remaining(&self) -> Duration {
+        self.alignment_time - self.elapsed
+    }
+
+    /// Save the state into the store, under names prefixed with `irs.<name>.`.
+    /// Besides the alignment progress, it saves the status code, the remaining seconds and
+    /// the lights, so they can be mirrored into user offsets for display hardware.
+    pub fn save(&self, store: &mut ValueStore) -> io::Result<()> {
+        let elapsed = self.elapsed.as_millis().min(i32::MAX as u128) as i32;
+        store.set(&self.key("elapsed_ms"), elapsed)?;
+        store.set(&self.key("status"), self.status.code())?;
+        store.set(&self.key("remaining_s"), self.remaining().as_secs() as i32)?;
+        store.set(&self.key("align_light"), self.lights.align as i32)?;
+        store.set(&self.key("on_battery_light"), self.lights.on_battery as i32)?;
+        store.set(&self.key("fault_light"), self.lights.fault as i32)
+    }
+
+    /// Restore the alignment progress saved by `save()`.
+    /// The status is recomputed on the next update.
+    pub fn restore(&mut self, store: &ValueStore) {
+        if let Some(elapsed) = store.get(&self.key("elapsed_ms")) {
+            let elapsed = Duration::from_millis(elapsed.max(0) as u64);
+            self.elapsed = elapsed.min(self.alignment_time);
+        }
+    }
+
+    fn key(&self, field: &str) -> String {
+        format!("irs.{}.{}", self.name, field)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn inputs(mode: IrsMode) -> IrsInputs {
+        IrsInputs {
+            mode,
+            ac_power: true,
+            battery: true,
+            moving: false,
+        }
+    }
+
+    #[test]
+    fn should_align_after_alignment_time() {
+        let mut irs = Irs::new("left", Duration::from_secs(600));
+        let nav = inputs(IrsMode::Nav);
+        assert_eq!(
+            irs.update(&nav, Duration::from_secs(300)),
+            IrsStatus::Aligning
+        );
+        assert!(irs.lights().align);
+        assert_eq!(irs.remaining(), Duration::from_secs(300));
+        assert_eq!(
+            irs.update(&nav, Duration::from_secs(300)),
+            IrsStatus::Aligned
+        );
+        assert!(!irs.lights().align);
+    }
+
+    #[test]
+    fn should_lose_alignment_when_switched_off_or_unpowered() {
+        let mut irs = Irs::new("left", Duration::from_secs(60));
+        irs.update(&inputs(IrsMode::Nav), Duration::from_secs(60));
+        assert_eq!(
+            irs.update(&inputs(IrsMode::Off), Duration::from_secs(1)),
+            IrsStatus::Off
+        );
+        irs.update(&inputs(IrsMode::Nav), Duration::from_secs(60));
+        let unpowered = IrsInputs {
+            ac_power: false,
+            battery: false,
+            ..inputs(IrsMode::Nav)
+        };
+        assert_eq!(
+            irs.update(&unpowered, Duration::from_secs(1)),
+            IrsStatus::Off
+        );
+        assert_eq!(irs.remaining(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn should_restart_alignment_on_motion() {
+        let mut irs = Irs::new("left", Duration::from_secs(60));
+        irs.update(&inputs(IrsMode::Align), Duration::from_secs(30));
+        let moving = IrsInputs {
+            moving: true,
+            ..inputs(IrsMode::Align)
+        };
+        irs.update(&moving, Duration::from_secs(1));
+        assert!(irs.lights().fault);
+        assert_eq!(irs.remaining(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn should_light_on_battery_without_ac_power() {
+        let mut irs = Irs::new("left", Duration::from_secs(60));
+        let battery = IrsInputs {
+            ac_power: false,
+            ..inputs(IrsMode::Att)
+        };
+        assert_eq!(
+            irs.update(&battery, Duration::from_secs(1)),
+            IrsStatus::Attitude
+        );
+        assert!(irs.lights().on_battery);
+    }
+
+    #[test]
+    fn should_persist_alignment_in_store() {
+        let mut store = ValueStore::in_memory();
+        let mut irs = Irs::new("left", Duration::from_secs(600));
+        irs.update(&inputs(IrsMode::Nav), Duration::from_secs(450));
+        irs.save(&mut store).unwrap();
+        assert_eq!(store.get("irs.left.status"), Some(1));
+        assert_eq!(store.get("irs.left.remaining_s"), Some(150));
+
+        let mut restored = Irs::new("left", Duration::from_secs(600));
+        restored.restore(&store);
+        assert_eq!(restored.remaining(), Duration::from_secs(150));
+    }
+}
@@ -0,0 +1,189 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Keystroke injection
+//! FSUIPC sends the window message written to offset 0x3200 to the main window of the
+//! simulator, and then clears the message field. That field is the handshake: a new message
+//! can only be written once the previous one was consumed. `send_key()` presses and releases
+//! a key with its modifiers as a sequence of messages, waiting for each one to be consumed.
+
+use std::io;
+use std::ops::BitOr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::FsuipcError;
+use super::offsets;
+use super::{Handle, Session};
+
+const WM_KEYDOWN: u32 = 0x0100;
+const WM_KEYUP: u32 = 0x0101;
+const WM_SYSKEYDOWN: u32 = 0x0104;
+const WM_SYSKEYUP: u32 = 0x0105;
+
+const VK_SHIFT: u8 = 0x10;
+const VK_CONTROL: u8 = 0x11;
+const VK_MENU: u8 = 0x12;
+
+/// The lParam of a key down message: a repeat count of 1.
+const KEY_DOWN_PARAM: u32 = 0x0000_0001;
+/// The lParam of a key up message: a repeat count of 1, previously down and being released.
+const KEY_UP_PARAM: u32 = 0xC000_0001;
+
+/// The time between checks of the handshake.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A set of modifier keys held while a key is pressed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const CTRL: Modifiers = Modifiers(2);
+    pub const ALT: Modifiers = Modifiers(4);
+
+    /// Whether all the given modifiers are in the set.
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The virtual key codes of the modifiers, in the order they are pressed.
+    fn key_codes(self) -> Vec<u8> {
+        [
+            (Modifiers::SHIFT, VK_SHIFT),
+            (Modifiers::CTRL, VK_CONTROL),
+            (Modifiers::ALT, VK_MENU),
+        ]
+        .iter()
+        .filter(|(modifier, _)| self.contains(*modifier))
+        .map(|(_, key_code)| *key_code)
+        .collect()
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, other: Modifiers) -> Modifiers {
+        Modifiers(self.0 | other.0)
+    }
+}
+
+/// The window messages that press and release the key with the given virtual key code.
+/// The modifiers are pressed first and released last. With Alt held, the messages are the
+/// system key ones, as Windows would send them.
+pub fn key_messages(key_code: u8, modifiers: Modifiers) -> Vec<[u32; 3]> {
+    let (down, up) = if modifiers.contains(Modifiers::ALT) {
+        (WM_SYSKEYDOWN, WM_SYSKEYUP)
+    } else {
+        (WM_KEYDOWN, WM_KEYUP)
+    };
+    let modifier_codes = modifiers.key_codes();
+    let mut messages = Vec::new();
+    for code in modifier_codes.iter().chain(Some(&key_code)) {
+        messages.push([down, *code as u32, KEY_DOWN_PARAM]);
+    }
+    for code in Some(&key_code)
+        .into_iter()
+        .chain(modifier_codes.iter().rev())
+    {
+        messages.push([up, *code as u32, KEY_UP_PARAM]);
+    }
+    messages
+}
+
+/// Request writing a window message, to be sent once the previous one was consumed.
+pub fn write_message<S: Session>(session: &mut S, message: &[u32; 3]) -> io::Result<usize> {
+    session.write_offset(offsets::KEY_MESSAGE, message)
+}
+
+/// Whether the simulator consumed the last message written.
+pub fn is_consumed<H>(handle: &mut H) -> io::Result<bool>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut message = [0u32; 3];
+    let mut session = handle.session();
+    session.read_offset(offsets::KEY_MESSAGE, &mut message)?;
+    session.process()?;
+    Ok(message[0] == 0)
+}
+
+/// Press and release a key in the simulator, waiting for each message to be consumed.
+/// It fails with `FsuipcError::Timeout` if a message is not consumed within `timeout`, e.g.
+/// because the simulator window is not responding.
+pub fn send_key<H>(
+    handle: &mut H,
+    key_code: u8,
+    modifiers: Modifiers,
+    timeout: Duration,
+) -> io::Result<()>
+where
+    H: for<'h> Handle<'h>,
+{
+    for message in key_messages(key_code, modifiers) {
+        wait_consumed(handle, timeout)?;
+        let mut session = handle.session();
+        write_message(&mut session, &message)?;
+        session.process()?;
+    }
+    wait_consumed(handle, timeout)
+}
+
+fn wait_consumed<H>(handle: &mut H, timeout: Duration) -> io::Result<()>
+where
+    H: for<'h> Handle<'h>,
+{
+    let start = Instant::now();
+    while !is_consumed(handle)? {
+        if start.elapsed() >= timeout {
+            return Err(FsuipcError::Timeout(timeout).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_press_modifiers_around_the_key() {
+        assert_eq!(
+            key_messages(0x47, Modifiers::NONE),
+            vec![[0x100, 0x47, 1], [0x101, 0x47, 0xC000_0001]]
+        );
+        assert_eq!(
+            key_messages(0x50, Modifiers::SHIFT | Modifiers::ALT),
+            vec![
+                [0x104, 0x10, 1],
+                [0x104, 0x12, 1],
+                [0x104, 0x50, 1],
+                [0x105, 0x50, 0xC000_0001],
+                [0x105, 0x12, 0xC000_0001],
+                [0x105, 0x10, 0xC000_0001],
+            ]
+        );
+    }
+
+    #[test]
+    fn should_time_out_when_the_message_is_not_consumed() {
+        let mut handle = MockHandle::new();
+        let timeout = Duration::from_millis(50);
+        let error = send_key(&mut handle, 0x47, Modifiers::CTRL, timeout)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(handle.writes().len(), 1);
+        assert_eq!(handle.value::<[u32; 3]>(0x3200), [0x100, 0x11, 1]);
+    }
+}
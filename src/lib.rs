@@ -5,18 +5,94 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
+#[cfg_attr(not(windows), allow(dead_code))]
 mod ipc;
+#[cfg_attr(not(windows), allow(dead_code))]
 mod raw;
 
-#[cfg(all(windows))]
+pub mod accessibility;
+pub mod adsb;
+pub mod aircraft;
+pub mod analysis;
+pub mod aoa;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod autopilot;
+pub mod batch;
+pub mod braking;
+pub mod calc;
+pub mod cancel;
+pub mod chunked;
+pub mod claims;
+pub mod consistency;
+pub mod controls;
+pub mod display;
+pub mod engines;
+pub mod error;
+pub mod fault;
+pub mod fuel;
+pub mod gdl90;
+pub mod geo;
+pub mod gestures;
+pub mod haptics;
+pub mod headtrack;
+pub mod icing;
+pub mod irs;
+pub mod keys;
+pub mod loading;
+pub mod logic;
+pub mod lua;
+pub mod lvars;
+pub mod mock;
+pub mod motion;
+pub mod offsets;
+pub mod panel;
+pub mod payload;
+#[cfg(feature = "pmdg")]
+pub mod pmdg;
+pub mod poller;
+pub mod presets;
+pub mod radios;
+pub mod reconnect;
+pub mod recorder;
+pub mod registration;
+pub mod report;
+pub mod sim;
+pub mod speech;
+pub mod store;
+pub mod strings;
+pub mod traffic;
+pub mod units;
+pub mod verify;
+#[cfg(feature = "voice")]
+pub mod voice;
+pub mod weather;
+#[cfg(feature = "ws")]
+pub mod ws;
+pub mod xgps;
+pub mod xplane;
+
+#[cfg(windows)]
+pub mod compat;
+
+#[cfg(windows)]
 pub mod local;
 
-#[cfg(all(windows))]
+#[cfg(windows)]
 pub mod user;
 
+#[cfg(windows)]
+mod sys;
+
 use std::io;
 use std::mem::size_of;
 
+pub use error::FsuipcError;
+pub use offsets::{FsuipcStruct, Offset, OffsetValue};
+
+#[cfg(feature = "derive")]
+pub use fsuipc_derive::FsuipcStruct;
+
 /// A handle to FSUIPC
 /// This type represents a handle to FSUIPC. It cannot be used directly to read of write from or
 /// to FSUIPC offsets. A `Session` object is created from the handle instead.
@@ -45,4 +121,87 @@ pub trait Session {
     fn write<T>(&mut self, offset: u16, value: &T) -> io::Result<usize> {
         self.write_bytes(offset, value as *const T as *const u8, size_of::<T>())
     }
+
+    /// Read a catalog offset into `result`, whose type must match the one of the offset.
+    fn read_offset<'a, T>(&'a mut self, offset: Offset<T>, result: &'a mut T) -> io::Result<usize> {
+        self.read(offset.address(), result)
+    }
+
+    /// Write `value` into a catalog offset, whose type must match the one of the value.
+    fn write_offset<T>(&mut self, offset: Offset<T>, value: &T) -> io::Result<usize> {
+        self.write(offset.address(), value)
+    }
+
+    /// Write a string into a string offset of `len` bytes.
+    /// The string is zero-terminated, truncated and padded as needed to fit in the offset.
+    fn write_string(&mut self, offset: u16, value: &str, len: usize) -> io::Result<usize> {
+        let bytes = strings::encode(value, len);
+        self.write_bytes(offset, bytes.as_ptr(), bytes.len())
+    }
+
+    /// Read the offset a value is bound to into that value.
+    fn read_value<'a, T>(&'a mut self, value: &'a mut OffsetValue<T>) -> io::Result<usize> {
+        let offset = value.offset();
+        self.read_offset(offset, value.get_mut())
+    }
+
+    /// Write a value into the offset it is bound to.
+    fn write_value<T>(&mut self, value: &OffsetValue<T>) -> io::Result<usize> {
+        self.write_offset(value.offset(), value.get())
+    }
+
+    /// Turn the session into a `VerifiedSession`, to request writes that are read back.
+    /// Its `process()` fails with `FsuipcError::WriteMismatch` if any value read back differs.
+    fn verified(self) -> verify::VerifiedSession<Self>
+    where
+        Self: Sized,
+    {
+        verify::VerifiedSession::new(self)
+    }
+
+    /// Read the attitude, position and speeds of the aircraft into `state` in this session.
+    /// The state is decoded once the session is processed.
+    fn read_aircraft_state<'a>(
+        &'a mut self,
+        state: &'a mut aircraft::AircraftState,
+    ) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        state.register_reads(self)
+    }
+
+    /// Read the latitude, longitude and altitude of the aircraft into `state` in this session.
+    /// The position is decoded once the session is processed.
+    fn read_position<'a>(&'a mut self, state: &'a mut geo::PositionState) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        state.register_reads(self)
+    }
+
+    /// Send a control to the simulator with the given parameter.
+    fn send_control(&mut self, event: controls::EventId, param: i32) -> io::Result<usize> {
+        controls::send(self, event, param)
+    }
+
+    /// Run the Lua plugin with the given name, with the parameter set by `set_lua_param()`.
+    fn run_lua(&mut self, name: &str) -> io::Result<usize> {
+        lua::run_lua(self, name)
+    }
+
+    /// Run a macro of the given macro file.
+    fn run_macro(&mut self, file: &str, name: &str) -> io::Result<usize> {
+        lua::run_macro(self, file, name)
+    }
+
+    /// Execute MSFS calculator code, such as `1 (>K:TOGGLE_BEACON_LIGHTS)`.
+    fn execute_calc(&mut self, code: &str) -> io::Result<usize> {
+        calc::execute_calc(self, code)
+    }
+
+    /// Set the parameter of the next Lua plugin run.
+    fn set_lua_param(&mut self, value: i32) -> io::Result<usize> {
+        self.write_offset(offsets::LUA_PARAM, &value)
+    }
 }
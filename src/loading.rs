@@ -0,0 +1,141 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Control loading data
+//! Force feedback yokes and control loading systems compute the force on the controls from
+//! the airspeed, the angle of attack and the deflection of the surfaces and their trim. The
+//! force lags behind the aircraft by the latency of these values, so `ControlLoadingSample`
+//! can be fed from the fast lane of a `Poller`, which reads them ahead of any other offset.
+
+use std::convert::TryInto;
+use std::io;
+use std::time::Duration;
+
+use super::offsets::{self, FsuipcStruct};
+use super::poller::{OffsetChanged, PollerBuilder};
+use super::Session;
+
+/// The values a control loading system computes the forces from
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ControlLoadingSample {
+    /// Indicated airspeed, in knots * 128.
+    pub indicated_airspeed: u32,
+    /// Angle of attack, in radians.
+    pub angle_of_attack: f64,
+    /// Deflection of the elevator, its trim, the ailerons and the rudder, in radians.
+    pub elevator: f64,
+    pub elevator_trim: f64,
+    pub aileron: f64,
+    pub rudder: f64,
+}
+
+impl ControlLoadingSample {
+    /// The indicated airspeed, in knots.
+    pub fn airspeed_knots(&self) -> f64 {
+        self.indicated_airspeed as f64 / 128.0
+    }
+
+    /// Watch the offsets of the sample in the fast lane of the poller.
+    /// The surfaces are contiguous, so they are polled as a single block.
+    pub fn watch(builder: PollerBuilder, interval: Duration) -> PollerBuilder {
+        builder
+            .watch_priority(offsets::INDICATED_AIRSPEED.address(), 4, interval)
+            .watch_priority(offsets::ANGLE_OF_ATTACK.address(), 8, interval)
+            .watch_priority(offsets::ELEVATOR_DEFLECTION.address(), 32, interval)
+    }
+
+    /// Update the sample from a change of the offsets watched by `watch()`.
+    /// It returns false if the change is not of one of those offsets.
+    pub fn update(&mut self, change: &OffsetChanged) -> bool {
+        let value = |i: usize| -> Option<f64> {
+            let bytes = change.new.get(8 * i..8 * i + 8)?;
+            Some(f64::from_le_bytes(bytes.try_into().ok()?))
+        };
+        if change.offset == offsets::INDICATED_AIRSPEED.address() {
+            match change.new.get(..4) {
+                Some(bytes) => {
+                    self.indicated_airspeed = u32::from_le_bytes(bytes.try_into().unwrap())
+                }
+                None => return false,
+            }
+        } else if change.offset == offsets::ANGLE_OF_ATTACK.address() {
+            match value(0) {
+                Some(aoa) => self.angle_of_attack = aoa,
+                None => return false,
+            }
+        } else if change.offset == offsets::ELEVATOR_DEFLECTION.address() {
+            match (value(0), value(1), value(2), value(3)) {
+                (Some(elevator), Some(trim), Some(aileron), Some(rudder)) => {
+                    self.elevator = elevator;
+                    self.elevator_trim = trim;
+                    self.aileron = aileron;
+                    self.rudder = rudder;
+                }
+                _ => return false,
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+impl FsuipcStruct for ControlLoadingSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(offsets::ANGLE_OF_ATTACK, &mut self.angle_of_attack)?;
+        session.read_offset(offsets::ELEVATOR_DEFLECTION, &mut self.elevator)?;
+        session.read_offset(offsets::ELEVATOR_TRIM_DEFLECTION, &mut self.elevator_trim)?;
+        session.read_offset(offsets::AILERON_DEFLECTION, &mut self.aileron)?;
+        session.read_offset(offsets::RUDDER_DEFLECTION, &mut self.rudder)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_update_sample_from_changes() {
+        let mut sample = ControlLoadingSample::default();
+        let change = |offset: u16, new: Vec<u8>| OffsetChanged {
+            offset,
+            old: None,
+            new,
+        };
+
+        assert!(sample.update(&change(0x02BC, (120u32 * 128).to_le_bytes().to_vec())));
+        assert!(sample.update(&change(0x2ED0, 0.1f64.to_le_bytes().to_vec())));
+        let surfaces: Vec<u8> = [0.2f64, -0.05, 0.3, -0.1]
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        assert!(sample.update(&change(0x2E98, surfaces)));
+        assert!(!sample.update(&change(0x0238, vec![1])));
+        assert!(!sample.update(&change(0x2E98, vec![0; 8])));
+
+        assert_eq!(sample.airspeed_knots(), 120.0);
+        assert_eq!(
+            sample,
+            ControlLoadingSample {
+                indicated_airspeed: 120 * 128,
+                angle_of_attack: 0.1,
+                elevator: 0.2,
+                elevator_trim: -0.05,
+                aileron: 0.3,
+                rudder: -0.1,
+            }
+        );
+    }
+}
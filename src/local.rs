@@ -8,64 +8,71 @@
 
 use std::ffi::CString;
 use std::io;
-use std::ptr;
-
-use winapi::shared::windef::HWND;
-use winapi::um::winuser::{FindWindowExA, SendMessageTimeoutA, SMTO_BLOCK, WM_USER};
+use std::time::Duration;
 
+use super::compat::connection_hint;
+use super::error::FsuipcError;
 use super::ipc::*;
-use super::raw::MutRawBytes;
+use super::report::ProcessReport;
+use super::sys::{self, SendFailure, WindowHandle};
 use super::{Handle, Session};
 
 /// A handle to FSUIPc that uses local IPC communication to the FSUIPC module
 /// This kind of handle must be used from code running in the same process as FSUIPC does.
 #[derive(Clone)]
 pub struct LocalHandle {
-    handle: HWND,
+    handle: WindowHandle,
+    timeout: Duration,
 }
 
-unsafe impl Send for LocalHandle {}
-
 impl LocalHandle {
     pub fn new() -> io::Result<Self> {
-        unsafe {
-            let win_name = CString::new("UIPCMAIN").unwrap();
-            let handle = FindWindowExA(
-                ptr::null_mut(),
-                ptr::null_mut(),
-                win_name.as_ptr(),
-                ptr::null_mut(),
-            );
-            if !handle.is_null() {
-                Ok(LocalHandle { handle })
-            } else {
-                Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to local FSUIPC: cannot create window handle",
-                ))
-            }
+        let win_name = CString::new("UIPCMAIN").unwrap();
+        let handle = sys::find_window(&win_name, WindowHandle::null());
+        if !handle.is_null() {
+            Ok(LocalHandle {
+                handle,
+                timeout: DEFAULT_TIMEOUT,
+            })
+        } else {
+            Err(FsuipcError::SimNotRunning(format!(
+                "cannot find the FSUIPC window{}",
+                connection_hint()
+            ))
+            .into())
         }
     }
+
+    /// Set the time to wait for FSUIPC to process a session, 10 seconds by default.
+    /// Processing fails with `FsuipcError::Timeout` when FSUIPC does not answer in time.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
 }
 
 impl<'a> Handle<'a> for LocalHandle {
     type Sess = LocalSession;
 
     fn session(&'a mut self) -> LocalSession {
-        LocalSession::new(self.handle)
+        LocalSession::new(self.handle, self.timeout)
     }
 }
 
 pub struct LocalSession {
-    handle: HWND,
+    handle: WindowHandle,
+    timeout: Duration,
     buffer: io::Cursor<Vec<u8>>,
+    targets: Vec<*mut u8>,
 }
 
 impl LocalSession {
-    fn new(handle: HWND) -> Self {
+    fn new(handle: WindowHandle, timeout: Duration) -> Self {
         let mut session = LocalSession {
             handle,
+            timeout,
             buffer: io::Cursor::new(Vec::with_capacity(4096)),
+            targets: Vec::new(),
         };
         // First 4-bytes seems to be for a stack frame pointer that is not actually used
         session.buffer.set_position(4);
@@ -75,7 +82,10 @@ impl LocalSession {
 
 impl Session for LocalSession {
     fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
-        self.buffer.write_rsd(offset, dest, len)
+        self.buffer
+            .write_rsd(offset, self.targets.len() as u32, len)?;
+        self.targets.push(dest);
+        Ok(RSD_HEADER_LEN + len)
     }
 
     fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
@@ -83,73 +93,90 @@ impl Session for LocalSession {
     }
 
     fn process(mut self) -> io::Result<usize> {
-        unsafe {
-            self.buffer.write_header(&MsgHeader::TerminationMark)?;
-            let nbytes = self.buffer.position() as usize;
-            let buff = self.buffer.get_ref().as_ptr() as WinInt;
-            let mut process_result: WinUInt = 0;
-            let send_result = SendMessageTimeoutA(
-                self.handle,
-                WM_IPCTHREADACCESS,
-                nbytes as WinUInt,
-                buff,
-                SMTO_BLOCK,
-                WM_IPC_TIMEOUT,
-                &mut process_result as *mut WinUInt,
-            );
-            if send_result == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::TimedOut,
-                    "timed out while waiting for a response from FSUIPC",
-                ));
-            }
-            if process_result != FS6IPC_MESSAGE_SUCCESS {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
-                    "FSUIPC rejected the requests with error {}; possible buffer corruption in bytes: {:?}",
-                    send_result, self.buffer.get_ref())));
-            }
-            // First 4-bytes seems to be for a stack frame pointer that is not actually used
-            self.buffer.set_position(4);
-            loop {
-                let header = self.buffer.read_header()?;
-                match header {
-                    MsgHeader::ReadStateData {
-                        offset: _,
-                        len,
-                        target,
-                    } => {
-                        let mut output = MutRawBytes::new(target, len);
-                        self.buffer.read_body(&header, &mut output)?;
-                    }
-                    MsgHeader::WriteStateData { offset: _, len: _ } => {
-                        let mut output = io::sink();
-                        self.buffer.read_body(&header, &mut output)?;
-                    }
-                    MsgHeader::TerminationMark => return Ok(nbytes),
-                }
+        self.execute()
+    }
+}
+
+impl LocalSession {
+    /// Process the requests and rewind the session, so it can be refilled with new requests.
+    /// The session is rewound even if processing fails.
+    pub fn process_reset(&mut self) -> io::Result<usize> {
+        let result = self.execute();
+        self.buffer.get_mut().truncate(4);
+        self.buffer.set_position(4);
+        self.targets.clear();
+        result
+    }
+
+    /// Process the requests, reporting the outcome of each one.
+    /// Failures to reach FSUIPC are still errors, but a response that cannot be fully decoded
+    /// gives a report that is not complete.
+    pub fn process_report(mut self) -> io::Result<ProcessReport> {
+        let mut report = ProcessReport::default();
+        self.send_requests()?;
+        let _ = read_response(&mut self.buffer, &self.targets, &mut report);
+        Ok(report)
+    }
+
+    fn execute(&mut self) -> io::Result<usize> {
+        let nbytes = self.send_requests()?;
+        read_response(
+            &mut self.buffer,
+            &self.targets,
+            &mut ProcessReport::default(),
+        )?;
+        Ok(nbytes)
+    }
+
+    /// Send the requests to FSUIPC, leaving the buffer at the start of the response.
+    fn send_requests(&mut self) -> io::Result<usize> {
+        self.buffer.write_header(&MsgHeader::TerminationMark)?;
+        let nbytes = self.buffer.position() as usize;
+        let buff = self.buffer.get_ref().as_ptr() as WinInt;
+        let process_result = match sys::send_message_timeout(
+            self.handle,
+            WM_IPCTHREADACCESS,
+            nbytes as WinUInt,
+            buff,
+            timeout_millis(self.timeout),
+        ) {
+            Ok(process_result) => process_result,
+            Err(SendFailure::Timeout) => return Err(FsuipcError::Timeout(self.timeout).into()),
+            Err(SendFailure::WindowGone) => {
+                return Err(
+                    FsuipcError::SimNotRunning("the FSUIPC window is gone".to_string()).into(),
+                )
             }
+        };
+        if process_result != FS6IPC_MESSAGE_SUCCESS {
+            return Err(FsuipcError::ProtocolRejection(process_result as isize).into());
         }
+        // First 4-bytes seems to be for a stack frame pointer that is not actually used
+        self.buffer.set_position(4);
+        Ok(nbytes)
     }
 }
 
 const FS6IPC_MESSAGE_SUCCESS: WinUInt = 1;
 const WM_IPCTHREADACCESS: u32 = WM_USER + 130;
-const WM_IPC_TIMEOUT: u32 = 10000;
+const WM_USER: u32 = 0x0400;
 
 #[cfg(test)]
 mod test {
     use super::*;
     use std::thread;
-    use winapi::windef::HWND;
 
     #[test]
     fn test_local_handler_can_be_shared() {
-        let handler = LocalHandle { handle: 0 as HWND };
+        let handler = LocalHandle {
+            handle: WindowHandle::null(),
+            timeout: DEFAULT_TIMEOUT,
+        };
         let handler_copy = handler.clone();
         let child = thread::spawn(move || {
-            assert_eq!(0 as HWND, handler_copy.handle);
+            assert!(handler_copy.handle.is_null());
         });
         child.join().unwrap();
-        assert_eq!(0 as HWND, handler.handle);
+        assert!(handler.handle.is_null());
     }
 }
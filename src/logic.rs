@@ -0,0 +1,360 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Composable logic blocks for annunciators
+//! A `Logic` network combines the bits of monitored offsets with AND, OR, NOT, latch, timer and
+//! flasher blocks, and writes the results to user offsets. This covers annunciator logic that
+//! cannot be expressed as a 1:1 mapping, such as master caution aggregation.
+//!
+//! Blocks can only take signals created before them as inputs, so the network never has cycles
+//! and it is evaluated in creation order.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::{Handle, Session};
+
+static NEXT_NETWORK_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A boolean signal produced by a block of a `Logic` network
+/// A signal can only be used with the network that created it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signal {
+    network: usize,
+    index: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Block {
+    Offset {
+        offset: u16,
+        len: usize,
+        mask: u32,
+    },
+    And(Vec<Signal>),
+    Or(Vec<Signal>),
+    Not(Signal),
+    Latch {
+        set: Signal,
+        reset: Signal,
+    },
+    Timer {
+        input: Signal,
+        delay: Duration,
+        elapsed: Duration,
+    },
+    Flasher {
+        input: Signal,
+        period: Duration,
+        phase: Duration,
+    },
+}
+
+/// A network of logic blocks
+/// The methods taking signals panic if a signal was created by a different network.
+#[derive(Clone, Debug)]
+pub struct Logic {
+    id: usize,
+    blocks: Vec<Block>,
+    values: Vec<bool>,
+    raw: Vec<u32>,
+    outputs: Vec<(Signal, u16)>,
+    output_values: Vec<u8>,
+}
+
+impl Default for Logic {
+    fn default() -> Self {
+        Logic {
+            id: NEXT_NETWORK_ID.fetch_add(1, Ordering::Relaxed),
+            blocks: Vec::new(),
+            values: Vec::new(),
+            raw: Vec::new(),
+            outputs: Vec::new(),
+            output_values: Vec::new(),
+        }
+    }
+}
+
+impl Logic {
+    pub fn new() -> Self {
+        Logic::default()
+    }
+
+    /// A signal that is on when any of the `mask` bits of the offset is set.
+    /// The offset is `len` bytes long, from 1 to 4.
+    pub fn offset(&mut self, offset: u16, len: usize, mask: u32) -> io::Result<Signal> {
+        if len == 0 || len > 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid length {} for offset 0x{:04X}", len, offset),
+            ));
+        }
+        Ok(self.push(Block::Offset { offset, len, mask }))
+    }
+
+    /// A signal that is on when all the inputs are on.
+    pub fn and(&mut self, inputs: &[Signal]) -> Signal {
+        inputs.iter().for_each(|&input| self.check(input));
+        self.push(Block::And(inputs.to_vec()))
+    }
+
+    /// A signal that is on when any of the inputs is on.
+    pub fn or(&mut self, inputs: &[Signal]) -> Signal {
+        inputs.iter().for_each(|&input| self.check(input));
+        self.push(Block::Or(inputs.to_vec()))
+    }
+
+    /// A signal that is on when the input is off.
+    pub fn not(&mut self, input: Signal) -> Signal {
+        self.check(input);
+        self.push(Block::Not(input))
+    }
+
+    /// A signal that turns on with `set` and stays on until `reset`, which takes precedence.
+    pub fn latch(&mut self, set: Signal, reset: Signal) -> Signal {
+        self.check(set);
+        self.check(reset);
+        self.push(Block::Latch { set, reset })
+    }
+
+    /// A signal that turns on once the input has been on for `delay`.
+    pub fn timer(&mut self, input: Signal, delay: Duration) -> Signal {
+        self.check(input);
+        self.push(Block::Timer {
+            input,
+            delay,
+            elapsed: Duration::from_secs(0),
+        })
+    }
+
+    /// A signal that flashes while the input is on.
+    /// It is on for the first half of every `period` and off for the second one.
+    pub fn flasher(&mut self, input: Signal, period: Duration) -> Signal {
+        self.check(input);
+        self.push(Block::Flasher {
+            input,
+            period,
+            phase: Duration::from_secs(0),
+        })
+    }
+
+    /// Write the signal into the given offset, as a byte set to 1 or 0, on `write_outputs()`.
+    pub fn output(&mut self, signal: Signal, offset: u16) {
+        self.check(signal);
+        self.outputs.push((signal, offset));
+        self.output_values.push(0);
+    }
+
+    /// The current value of the signal.
+    pub fn value(&self, signal: Signal) -> bool {
+        self.check(signal);
+        self.values[signal.index]
+    }
+
+    /// Set the raw value of an offset signal, as if it was read from FSUIPC.
+    pub fn set_raw(&mut self, signal: Signal, value: u32) {
+        self.check(signal);
+        self.raw[signal.index] = value;
+    }
+
+    /// Read all the offsets the network depends on in a new session of the handle.
+    /// The values are used by the next `update()`. If the read fails, the values of the
+    /// previous read are kept.
+    pub fn read_inputs<H>(&mut self, handle: &mut H) -> io::Result<usize>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut raw = vec![0u32; self.raw.len()];
+        let processed = {
+            let mut session = handle.session();
+            for (block, value) in self.blocks.iter().zip(raw.iter_mut()) {
+                if let Block::Offset { offset, len, .. } = *block {
+                    session.read_bytes(offset, value as *mut u32 as *mut u8, len)?;
+                }
+            }
+            session.process()?
+        };
+        self.raw = raw;
+        Ok(processed)
+    }
+
+    /// Evaluate all the blocks, `dt` after the previous evaluation.
+    pub fn update(&mut self, dt: Duration) {
+        for i in 0..self.blocks.len() {
+            let values = &self.values;
+            let value = match self.blocks[i] {
+                Block::Offset { mask, .. } => u32::from_le(self.raw[i]) & mask != 0,
+                Block::And(ref inputs) => inputs.iter().all(|s| values[s.index]),
+                Block::Or(ref inputs) => inputs.iter().any(|s| values[s.index]),
+                Block::Not(input) => !values[input.index],
+                Block::Latch { set, reset } => {
+                    !values[reset.index] && (values[set.index] || values[i])
+                }
+                Block::Timer {
+                    input,
+                    delay,
+                    ref mut elapsed,
+                } => {
+                    if values[input.index] {
+                        *elapsed = (*elapsed + dt).min(delay);
+                        *elapsed >= delay
+                    } else {
+                        *elapsed = Duration::from_secs(0);
+                        false
+                    }
+                }
+                Block::Flasher {
+                    input,
+                    period,
+                    ref mut phase,
+                } => {
+                    if values[input.index] && period > Duration::from_secs(0) {
+                        let on = *phase < period / 2;
+                        let nanos = (phase.as_nanos() + dt.as_nanos()) % period.as_nanos();
+                        *phase = Duration::from_nanos(nanos as u64);
+                        on
+                    } else {
+                        *phase = Duration::from_secs(0);
+                        false
+                    }
+                }
+            };
+            self.values[i] = value;
+        }
+        for (n, (signal, _)) in self.outputs.iter().enumerate() {
+            self.output_values[n] = self.values[signal.index] as u8;
+        }
+    }
+
+    /// Request the writes of all the outputs.
+    pub fn write_outputs<S: Session>(&self, session: &mut S) -> io::Result<()> {
+        for ((_, offset), value) in self.outputs.iter().zip(self.output_values.iter()) {
+            session.write(*offset, value)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, block: Block) -> Signal {
+        self.blocks.push(block);
+        self.values.push(false);
+        self.raw.push(0);
+        Signal {
+            network: self.id,
+            index: self.blocks.len() - 1,
+        }
+    }
+
+    fn check(&self, signal: Signal) {
+        assert_eq!(
+            signal.network, self.id,
+            "signal from a different logic network"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn should_combine_offset_bits() {
+        let mut logic = Logic::new();
+        let low_oil = logic.offset(0x3590, 1, 0x01).unwrap();
+        let low_fuel = logic.offset(0x3591, 2, 0x0100).unwrap();
+        let any = logic.or(&[low_oil, low_fuel]);
+        let all = logic.and(&[low_oil, low_fuel]);
+        let none = logic.not(any);
+        logic.set_raw(low_fuel, 0x0100);
+        logic.update(ms(0));
+        assert!(logic.value(any));
+        assert!(!logic.value(all));
+        assert!(!logic.value(none));
+    }
+
+    #[test]
+    fn should_latch_until_reset() {
+        let mut logic = Logic::new();
+        let set = logic.offset(0x66C0, 1, 1).unwrap();
+        let reset = logic.offset(0x66C1, 1, 1).unwrap();
+        let caution = logic.latch(set, reset);
+        logic.set_raw(set, 1);
+        logic.update(ms(0));
+        logic.set_raw(set, 0);
+        logic.update(ms(0));
+        assert!(logic.value(caution));
+        logic.set_raw(reset, 1);
+        logic.update(ms(0));
+        assert!(!logic.value(caution));
+    }
+
+    #[test]
+    fn should_delay_with_timer() {
+        let mut logic = Logic::new();
+        let input = logic.offset(0x66C0, 1, 1).unwrap();
+        let delayed = logic.timer(input, ms(500));
+        logic.set_raw(input, 1);
+        logic.update(ms(300));
+        assert!(!logic.value(delayed));
+        logic.update(ms(300));
+        assert!(logic.value(delayed));
+        logic.set_raw(input, 0);
+        logic.update(ms(10));
+        assert!(!logic.value(delayed));
+    }
+
+    #[test]
+    fn should_flash_while_on() {
+        let mut logic = Logic::new();
+        let input = logic.offset(0x66C0, 1, 1).unwrap();
+        let flash = logic.flasher(input, ms(1000));
+        logic.set_raw(input, 1);
+        let states: Vec<bool> = (0..4)
+            .map(|_| {
+                logic.update(ms(500));
+                logic.value(flash)
+            })
+            .collect();
+        assert_eq!(states, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn should_reject_invalid_offset_lengths() {
+        let mut logic = Logic::new();
+        assert!(logic.offset(0x66C0, 0, 1).is_err());
+        assert!(logic.offset(0x66C0, 8, 1).is_err());
+    }
+
+    #[test]
+    fn should_read_inputs_from_handle() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x3591, &[0x00, 0x01]);
+        let mut logic = Logic::new();
+        let low_oil = logic.offset(0x3590, 1, 0x01).unwrap();
+        let low_fuel = logic.offset(0x3591, 2, 0x0100).unwrap();
+        logic.read_inputs(&mut handle).unwrap();
+        logic.update(ms(0));
+        assert!(!logic.value(low_oil));
+        assert!(logic.value(low_fuel));
+    }
+
+    #[test]
+    #[should_panic(expected = "signal from a different logic network")]
+    fn should_refuse_signals_of_other_networks() {
+        let mut other = Logic::new();
+        let foreign = other.offset(0x66C0, 1, 1).unwrap();
+        let mut logic = Logic::new();
+        logic.not(foreign);
+    }
+}
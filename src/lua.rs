@@ -0,0 +1,122 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Lua plugins and macros
+//! FSUIPC runs the command written to offset 0x0D70: `Lua <name>` runs the Lua plugin
+//! `<name>.lua`, and `<file>:<macro>` runs a macro of the macro file `<file>.mcro`. The Lua
+//! plugin obtains the value at 0x0D6C as its `ipcPARAM`, so the parameter must be written
+//! before the command, as `Session::set_lua_param()` followed by `Session::run_lua()` does.
+
+use std::io;
+
+use super::offsets::{self, Offset};
+use super::Session;
+
+/// Request running the Lua plugin with the given name.
+/// It fails with `InvalidInput` if the name is empty or too long for the command offset.
+pub fn run_lua<S: Session + ?Sized>(session: &mut S, name: &str) -> io::Result<usize> {
+    check_name("Lua plugin", name)?;
+    write_command(session, offsets::MACRO_COMMAND, &format!("Lua {}", name))
+}
+
+/// Request running a macro of the given macro file.
+/// It fails with `InvalidInput` if a name is empty or the command is too long for its offset.
+pub fn run_macro<S: Session + ?Sized>(
+    session: &mut S,
+    file: &str,
+    name: &str,
+) -> io::Result<usize> {
+    check_name("macro file", file)?;
+    check_name("macro", name)?;
+    write_command(
+        session,
+        offsets::MACRO_COMMAND,
+        &format!("{}:{}", file, name),
+    )
+}
+
+fn check_name(what: &str, name: &str) -> io::Result<()> {
+    if name.trim().is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the {} name is empty", what),
+        ));
+    }
+    Ok(())
+}
+
+/// Write a command into a string offset, zero-terminated.
+/// It fails with `InvalidInput` if the command does not fit in the offset with its terminator.
+pub(crate) fn write_command<S: Session + ?Sized, T>(
+    session: &mut S,
+    offset: Offset<T>,
+    command: &str,
+) -> io::Result<usize> {
+    // A truncated command would run something else, so it is refused rather than truncated
+    let max_len = offset.len() - 1;
+    if command.len() > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the command takes {} bytes out of {}",
+                command.len(),
+                max_len
+            ),
+        ));
+    }
+    let mut bytes = command.as_bytes().to_vec();
+    bytes.push(0);
+    session.write_bytes(offset.address(), bytes.as_ptr(), bytes.len())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::strings;
+    use crate::Handle;
+
+    #[test]
+    fn should_write_parameter_and_lua_command() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            session.set_lua_param(3).unwrap();
+            session.run_lua("doors").unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<i32>(0x0D6C), 3);
+        assert_eq!(strings::decode(&handle.bytes(0x0D70, 128)), "Lua doors");
+    }
+
+    #[test]
+    fn should_write_macro_command() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            session.run_macro("a320", "apu start").unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(
+            strings::decode(&handle.bytes(0x0D70, 128)),
+            "a320:apu start"
+        );
+    }
+
+    #[test]
+    fn should_refuse_invalid_commands() {
+        let mut handle = MockHandle::new();
+        let mut session = handle.session();
+        assert!(session.run_lua("").is_err());
+        assert!(session.run_macro("a320", " ").is_err());
+        let error = session.run_lua(&"x".repeat(124)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(session.run_lua(&"x".repeat(123)).is_ok());
+    }
+}
@@ -0,0 +1,388 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Local variables of the aircraft (LVars)
+//! Add-on aircraft keep most of their state in LVars rather than in the classic offsets. FSUIPC
+//! copies an LVar from or to an offset through the command offset 0x0D70: `:<name>` copies the
+//! LVar into the offset, and `::<name>` copies the offset into the LVar. The offset and the
+//! type of its value are given at 0x0D6C, with the offset in the low 16 bits and the type in
+//! the high ones. The copy takes place when FSUIPC runs the command, so a read needs a second
+//! transaction to obtain the value.
+//!
+//! `LVarMap` maps LVars to offsets of the general use area, claimed through `OffsetClaims` so
+//! they do not collide with the offsets of other components.
+//!
+//! Copying a block of LVars through the command offset takes one command per LVar, each a write
+//! of the parameter at 0x0D6C and one of the command at 0x0D70, and a lookup of the LVar by name
+//! in the simulator. The requests of a block share a transaction, but the work grows with the
+//! number of LVars. FSUIPC7 can instead keep LVars in offsets itself: the LVars listed in the
+//! `[LvarOffsets]` section of its ini file are refreshed as a block through its WAPI, with no
+//! command at all. `LVarMap::lvars()` gives the mappings to list there, and
+//! `PollerBuilder::watch_mapped_lvars()` then polls them as plain offsets.
+
+use std::io;
+
+use super::claims::{OffsetClaims, GENERAL_USE_OFFSETS};
+use super::lua::write_command;
+use super::offsets;
+use super::{Handle, Session};
+
+/// The type of the value an LVar is copied as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LVarType {
+    F64,
+    F32,
+    I32,
+    U32,
+    I16,
+    U16,
+    I8,
+    U8,
+}
+
+impl LVarType {
+    /// The code of the type for offset 0x0D6C.
+    pub fn code(self) -> u32 {
+        match self {
+            LVarType::F64 => 0,
+            LVarType::F32 => 1,
+            LVarType::I32 => 2,
+            LVarType::U32 => 3,
+            LVarType::I16 => 4,
+            LVarType::U16 => 5,
+            LVarType::I8 => 6,
+            LVarType::U8 => 7,
+        }
+    }
+
+    /// The bytes a value of the type takes.
+    pub fn size(self) -> usize {
+        match self {
+            LVarType::F64 => 8,
+            LVarType::F32 | LVarType::I32 | LVarType::U32 => 4,
+            LVarType::I16 | LVarType::U16 => 2,
+            LVarType::I8 | LVarType::U8 => 1,
+        }
+    }
+
+    /// The bytes of a value, converted to the type with saturation.
+    pub fn encode(self, value: f64) -> Vec<u8> {
+        match self {
+            LVarType::F64 => value.to_le_bytes().to_vec(),
+            LVarType::F32 => (value as f32).to_le_bytes().to_vec(),
+            LVarType::I32 => (value as i32).to_le_bytes().to_vec(),
+            LVarType::U32 => (value as u32).to_le_bytes().to_vec(),
+            LVarType::I16 => (value as i16).to_le_bytes().to_vec(),
+            LVarType::U16 => (value as u16).to_le_bytes().to_vec(),
+            LVarType::I8 => (value as i8).to_le_bytes().to_vec(),
+            LVarType::U8 => vec![value as u8],
+        }
+    }
+
+    /// The value of the given bytes, which must be `size()` long.
+    pub fn decode(self, bytes: &[u8]) -> f64 {
+        let mut buffer = [0u8; 8];
+        buffer[..self.size()].copy_from_slice(&bytes[..self.size()]);
+        match self {
+            LVarType::F64 => f64::from_le_bytes(buffer),
+            LVarType::F32 => {
+                f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            LVarType::I32 => {
+                i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            LVarType::U32 => {
+                u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            LVarType::I16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            LVarType::U16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            LVarType::I8 => buffer[0] as i8 as f64,
+            LVarType::U8 => buffer[0] as f64,
+        }
+    }
+}
+
+/// Request copying an LVar into the offset, as a value of the given type.
+/// The name may have the `L:` prefix or not.
+pub fn request_read<S: Session + ?Sized>(
+    session: &mut S,
+    name: &str,
+    offset: u16,
+    kind: LVarType,
+) -> io::Result<usize> {
+    let name = check_name(name)?;
+    session.write_offset(offsets::LUA_PARAM, &param(offset, kind))?;
+    write_command(session, offsets::MACRO_COMMAND, &format!(":{}", name))
+}
+
+/// Request writing a value into an LVar, through the offset.
+/// The name may have the `L:` prefix or not.
+pub fn request_write<S: Session + ?Sized>(
+    session: &mut S,
+    name: &str,
+    offset: u16,
+    kind: LVarType,
+    value: f64,
+) -> io::Result<usize> {
+    let name = check_name(name)?;
+    let bytes = kind.encode(value);
+    session.write_bytes(offset, bytes.as_ptr(), bytes.len())?;
+    session.write_offset(offsets::LUA_PARAM, &param(offset, kind))?;
+    write_command(session, offsets::MACRO_COMMAND, &format!("::{}", name))
+}
+
+/// Read an LVar through the offset, as a value of the given type.
+/// It takes two transactions: one for FSUIPC to copy the LVar and one to read the copy.
+pub fn read_lvar<H>(handle: &mut H, name: &str, offset: u16, kind: LVarType) -> io::Result<f64>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut session = handle.session();
+    request_read(&mut session, name, offset, kind)?;
+    session.process()?;
+    let mut bytes = vec![0u8; kind.size()];
+    let mut session = handle.session();
+    session.read_bytes(offset, bytes.as_mut_ptr(), bytes.len())?;
+    session.process()?;
+    Ok(kind.decode(&bytes))
+}
+
+fn param(offset: u16, kind: LVarType) -> i32 {
+    (kind.code() << 16 | offset as u32) as i32
+}
+
+fn bare_name(name: &str) -> &str {
+    let name = name.trim();
+    name.strip_prefix("L:").unwrap_or(name)
+}
+
+fn check_name(name: &str) -> io::Result<&str> {
+    let name = bare_name(name);
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid LVar name '{}'", name),
+        ));
+    }
+    Ok(name)
+}
+
+#[derive(Clone)]
+struct Mapping {
+    name: String,
+    offset: u16,
+    kind: LVarType,
+    value: Option<f64>,
+}
+
+/// A set of LVars mapped to offsets of the general use area
+#[derive(Clone, Default)]
+pub struct LVarMap {
+    mappings: Vec<Mapping>,
+}
+
+impl LVarMap {
+    pub fn new() -> Self {
+        LVarMap::default()
+    }
+
+    /// Map an LVar to a free offset of the general use area, returning the offset.
+    /// The offset is claimed in `claims` as `lvar:<name>`. It fails with `AddrNotAvailable`
+    /// if the area has no room left for the value.
+    pub fn map(
+        &mut self,
+        claims: &mut OffsetClaims,
+        name: &str,
+        kind: LVarType,
+    ) -> io::Result<u16> {
+        let name = check_name(name)?.to_string();
+        if let Some(mapping) = self.mappings.iter().find(|m| m.name == name) {
+            return Ok(mapping.offset);
+        }
+        let owner = format!("lvar:{}", name);
+        let offset = claims.allocate(&owner, kind.size(), GENERAL_USE_OFFSETS)?;
+        self.mappings.push(Mapping {
+            name,
+            offset,
+            kind,
+            value: None,
+        });
+        Ok(offset)
+    }
+
+    /// Unmap an LVar, releasing its offset.
+    pub fn unmap(&mut self, claims: &mut OffsetClaims, name: &str) {
+        let name = bare_name(name);
+        claims.release(&format!("lvar:{}", name));
+        self.mappings.retain(|m| m.name != name);
+    }
+
+    /// The offsets the LVars are mapped to, with the size of their values.
+    pub fn offsets(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.mappings.iter().map(|m| (m.offset, m.kind.size()))
+    }
+
+    /// The name, offset and type of the mapped LVars, e.g. to list them in the `[LvarOffsets]`
+    /// section of FSUIPC7 so it keeps them in their offsets itself.
+    pub fn lvars(&self) -> impl Iterator<Item = (&str, u16, LVarType)> + '_ {
+        self.mappings
+            .iter()
+            .map(|m| (m.name.as_str(), m.offset, m.kind))
+    }
+
+    /// The name of the LVar mapped to the given offset, if any.
+    pub fn name_at(&self, offset: u16) -> Option<&str> {
+        self.mappings
+            .iter()
+            .find(|m| m.offset == offset)
+            .map(|m| m.name.as_str())
+    }
+
+    /// Request copying all the mapped LVars into their offsets, as a block.
+    /// It requests one 0x0D70 command per LVar, all in the same transaction.
+    pub fn request_copies<S: Session + ?Sized>(&self, session: &mut S) -> io::Result<()> {
+        for mapping in self.mappings.iter() {
+            request_read(session, &mapping.name, mapping.offset, mapping.kind)?;
+        }
+        Ok(())
+    }
+
+    /// Read all the mapped LVars, in two transactions.
+    pub fn refresh<H>(&mut self, handle: &mut H) -> io::Result<()>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut session = handle.session();
+        self.request_copies(&mut session)?;
+        session.process()?;
+
+        let mut buffers: Vec<Vec<u8>> = self
+            .mappings
+            .iter()
+            .map(|m| vec![0u8; m.kind.size()])
+            .collect();
+        let mut session = handle.session();
+        for (mapping, buffer) in self.mappings.iter().zip(buffers.iter_mut()) {
+            session.read_bytes(mapping.offset, buffer.as_mut_ptr(), buffer.len())?;
+        }
+        session.process()?;
+        for (mapping, buffer) in self.mappings.iter_mut().zip(buffers.iter()) {
+            mapping.value = Some(mapping.kind.decode(buffer));
+        }
+        Ok(())
+    }
+
+    /// The value of an LVar as of the last refresh, if it is mapped and was refreshed.
+    pub fn value(&self, name: &str) -> Option<f64> {
+        let name = bare_name(name);
+        self.mappings
+            .iter()
+            .find(|m| m.name == name)
+            .and_then(|m| m.value)
+    }
+
+    /// Request writing a value into a mapped LVar.
+    /// It fails with `NotFound` if the LVar is not mapped.
+    pub fn write<S: Session + ?Sized>(
+        &self,
+        session: &mut S,
+        name: &str,
+        value: f64,
+    ) -> io::Result<usize> {
+        let name = check_name(name)?;
+        let mapping = self
+            .mappings
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("LVar '{}' is not mapped", name),
+                )
+            })?;
+        request_write(session, &mapping.name, mapping.offset, mapping.kind, value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::strings;
+
+    #[test]
+    fn should_request_lvar_copies() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            request_write(&mut session, "L:A32NX_APU_START", 0x66C0, LVarType::U8, 1.0).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u8>(0x66C0), 1);
+        assert_eq!(handle.value::<i32>(0x0D6C), 0x0007_66C0);
+        assert_eq!(
+            strings::decode(&handle.bytes(0x0D70, 128)),
+            "::A32NX_APU_START"
+        );
+
+        handle.seed(0x66C8, &2.5f64.to_le_bytes());
+        let value = read_lvar(&mut handle, "A32NX_FLAPS", 0x66C8, LVarType::F64).unwrap();
+        assert_eq!(value, 2.5);
+        assert_eq!(handle.value::<i32>(0x0D6C), 0x66C8);
+        assert_eq!(strings::decode(&handle.bytes(0x0D70, 128)), ":A32NX_FLAPS");
+        assert!(read_lvar(&mut handle, "L:", 0x66C8, LVarType::F64).is_err());
+    }
+
+    #[test]
+    fn should_map_lvars_to_free_offsets() {
+        let mut claims = OffsetClaims::new();
+        claims.claim("store", 0x66C0, 4).unwrap();
+        let mut lvars = LVarMap::new();
+        assert_eq!(
+            lvars.map(&mut claims, "L:BEACON", LVarType::U8).unwrap(),
+            0x66C4
+        );
+        assert_eq!(
+            lvars.map(&mut claims, "FLAPS", LVarType::F64).unwrap(),
+            0x66C5
+        );
+        assert_eq!(
+            lvars.map(&mut claims, "BEACON", LVarType::U8).unwrap(),
+            0x66C4
+        );
+        assert_eq!(
+            lvars.lvars().collect::<Vec<_>>(),
+            vec![
+                ("BEACON", 0x66C4, LVarType::U8),
+                ("FLAPS", 0x66C5, LVarType::F64)
+            ]
+        );
+
+        let mut handle = MockHandle::new();
+        handle.seed(0x66C4, &[1]);
+        handle.seed(0x66C5, &0.5f64.to_le_bytes());
+        assert_eq!(lvars.value("BEACON"), None);
+        lvars.refresh(&mut handle).unwrap();
+        assert_eq!(handle.transactions(), 2);
+        assert_eq!(lvars.value("L:BEACON"), Some(1.0));
+        assert_eq!(lvars.value("FLAPS"), Some(0.5));
+
+        {
+            let mut session = handle.session();
+            lvars.write(&mut session, "FLAPS", 1.0).unwrap();
+            assert!(lvars.write(&mut session, "GEAR", 1.0).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<f64>(0x66C5), 1.0);
+
+        lvars.unmap(&mut claims, "BEACON");
+        assert!(claims.claim_at(0x66C4).is_none());
+        assert_eq!(lvars.value("BEACON"), None);
+    }
+}
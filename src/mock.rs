@@ -0,0 +1,314 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::io;
+use std::mem::size_of;
+use std::ptr;
+use std::slice;
+
+use super::report::{ProcessReport, RequestKind, RequestStatus};
+use super::{Handle, Session};
+
+/// An in-memory handle that needs no simulator
+/// It is intended to unit-test code using FSUIPC on machines with no simulator installed. The
+/// offsets are kept in memory: they can be seeded with values before the code under test runs,
+/// and every write done by the code is logged to be inspected afterwards. Offsets never written
+/// nor seeded read as zero.
+#[derive(Clone, Debug, Default)]
+pub struct MockHandle {
+    offsets: HashMap<u16, Vec<u8>>,
+    writes: Vec<(u16, Vec<u8>)>,
+    transactions: usize,
+    scheduled: Vec<(usize, u16, Vec<u8>)>,
+}
+
+impl MockHandle {
+    pub fn new() -> Self {
+        MockHandle::default()
+    }
+
+    /// Seed the given bytes from `offset`. Seeding is not logged as a write.
+    pub fn seed(&mut self, offset: u16, bytes: &[u8]) {
+        self.store(offset, bytes);
+    }
+
+    /// Seed a value from `offset`, e.g. `handle.seed_value(0x0238, &12u8)`.
+    pub fn seed_value<T: Copy>(&mut self, offset: u16, value: &T) {
+        let bytes =
+            unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+        self.store(offset, bytes);
+    }
+
+    /// Seed the given bytes from `offset` once `transaction` sessions have been processed.
+    /// It simulates FSUIPC changing an offset in reaction to the requests, as in handshakes.
+    pub fn schedule_seed(&mut self, transaction: usize, offset: u16, bytes: &[u8]) {
+        self.scheduled.push((transaction, offset, bytes.to_vec()));
+    }
+
+    /// Obtain `len` bytes from `offset`.
+    pub fn bytes(&self, offset: u16, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| self.byte_at(offset as usize + i).unwrap_or(0))
+            .collect()
+    }
+
+    /// Obtain the value stored from `offset`.
+    pub fn value<T: Copy>(&self, offset: u16) -> T {
+        let bytes = self.bytes(offset, size_of::<T>());
+        unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+    }
+
+    /// The raw contents of the offsets, indexed by the offset each block was seeded or written to.
+    pub fn offsets(&self) -> &HashMap<u16, Vec<u8>> {
+        &self.offsets
+    }
+
+    /// The writes processed so far, in order.
+    pub fn writes(&self) -> &[(u16, Vec<u8>)] {
+        &self.writes
+    }
+
+    /// Forget the writes processed so far.
+    pub fn clear_writes(&mut self) {
+        self.writes.clear();
+    }
+
+    /// The number of sessions processed so far.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    fn byte_at(&self, address: usize) -> Option<u8> {
+        self.offsets.iter().find_map(|(start, data)| {
+            let start = *start as usize;
+            if address >= start && address < start + data.len() {
+                Some(data[address - start])
+            } else {
+                None
+            }
+        })
+    }
+
+    fn apply_scheduled(&mut self) {
+        let transactions = self.transactions;
+        let (due, pending) = std::mem::take(&mut self.scheduled)
+            .into_iter()
+            .partition(|(transaction, _, _)| *transaction <= transactions);
+        self.scheduled = pending;
+        for (_, offset, bytes) in due {
+            self.store(offset, &bytes);
+        }
+    }
+
+    fn store(&mut self, offset: u16, bytes: &[u8]) {
+        // Patch every block overlapping the new bytes, so all of them agree on their contents
+        let begin = offset as usize;
+        let end = begin + bytes.len();
+        for (start, data) in self.offsets.iter_mut() {
+            let start = *start as usize;
+            let (from, to) = (begin.max(start), end.min(start + data.len()));
+            if from < to {
+                data[from - start..to - start].copy_from_slice(&bytes[from - begin..to - begin]);
+            }
+        }
+        let data = self.offsets.entry(offset).or_default();
+        if data.len() < bytes.len() {
+            *data = bytes.to_vec();
+        }
+    }
+}
+
+impl<'a> Handle<'a> for MockHandle {
+    type Sess = MockSession<'a>;
+
+    fn session(&'a mut self) -> MockSession<'a> {
+        MockSession {
+            handle: self,
+            requests: Vec::new(),
+        }
+    }
+}
+
+enum Request {
+    Read {
+        offset: u16,
+        dest: *mut u8,
+        len: usize,
+    },
+    Write {
+        offset: u16,
+        data: Vec<u8>,
+    },
+}
+
+pub struct MockSession<'a> {
+    handle: &'a mut MockHandle,
+    requests: Vec<Request>,
+}
+
+impl<'a> Session for MockSession<'a> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.requests.push(Request::Read { offset, dest, len });
+        Ok(len)
+    }
+
+    // As for any other session, the caller guarantees `src` points to `len` readable bytes
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        let data = unsafe { slice::from_raw_parts(src, len) }.to_vec();
+        self.requests.push(Request::Write { offset, data });
+        Ok(len)
+    }
+
+    fn process(mut self) -> io::Result<usize> {
+        self.process_reset()
+    }
+}
+
+impl<'a> MockSession<'a> {
+    /// Process the requests and rewind the session, so it can be refilled with new requests.
+    pub fn process_reset(&mut self) -> io::Result<usize> {
+        Ok(self.execute().bytes())
+    }
+
+    /// Process the requests, reporting the outcome of each one.
+    /// Every request of a mock session is fulfilled.
+    pub fn process_report(mut self) -> io::Result<ProcessReport> {
+        Ok(self.execute())
+    }
+
+    fn execute(&mut self) -> ProcessReport {
+        let mut report = ProcessReport {
+            terminated: true,
+            ..ProcessReport::default()
+        };
+        for request in self.requests.drain(..) {
+            let (kind, offset, len) = match request {
+                Request::Read { offset, dest, len } => {
+                    let bytes = self.handle.bytes(offset, len);
+                    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), dest, len) };
+                    (RequestKind::Read, offset, len)
+                }
+                Request::Write { offset, data } => {
+                    self.handle.store(offset, &data);
+                    let len = data.len();
+                    self.handle.writes.push((offset, data));
+                    (RequestKind::Write, offset, len)
+                }
+            };
+            report.requests.push(RequestStatus {
+                kind,
+                offset,
+                requested: len,
+                returned: len,
+            });
+        }
+        self.handle.transactions += 1;
+        self.handle.apply_scheduled();
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_read_seeded_values() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x0238, &12u8);
+        handle.seed_value(0x3324, &1500i32);
+        let mut hour = 0u8;
+        let mut altitude = 0i32;
+        let mut unknown = 0xFFu8;
+        {
+            let mut session = handle.session();
+            session.read(0x0238, &mut hour).unwrap();
+            session.read(0x3324, &mut altitude).unwrap();
+            session.read(0x0239, &mut unknown).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(hour, 12);
+        assert_eq!(altitude, 1500);
+        assert_eq!(unknown, 0);
+        assert_eq!(handle.transactions(), 1);
+    }
+
+    #[test]
+    fn should_log_writes_and_read_them_back() {
+        let mut handle = MockHandle::new();
+        let mut qnh = 0u16;
+        {
+            let mut session = handle.session();
+            session.write(0x0330, &0x3FC0u16).unwrap();
+            session.read(0x0330, &mut qnh).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(qnh, 0x3FC0);
+        assert_eq!(handle.writes(), &[(0x0330, vec![0xC0, 0x3F])]);
+        assert_eq!(handle.value::<u16>(0x0330), 0x3FC0);
+        handle.clear_writes();
+        assert!(handle.writes().is_empty());
+    }
+
+    #[test]
+    fn should_reuse_sessions_after_reset() {
+        let mut handle = MockHandle::new();
+        let mut session = handle.session();
+        let mut hour = 0u8;
+        for value in 1..=3u8 {
+            session.write(0x0238, &value).unwrap();
+            session.read(0x0238, &mut hour).unwrap();
+            assert_eq!(session.process_reset().unwrap(), 2);
+            assert_eq!(hour, value);
+        }
+        assert_eq!(session.process_reset().unwrap(), 0);
+        drop(session);
+        assert_eq!(handle.writes().len(), 3);
+        assert_eq!(handle.transactions(), 4);
+    }
+
+    #[test]
+    fn should_report_each_request() {
+        let mut handle = MockHandle::new();
+        let mut hour = 0u8;
+        let mut session = handle.session();
+        session.write(0x0330, &0x3FC0u16).unwrap();
+        session.read(0x0238, &mut hour).unwrap();
+        let report = session.process_report().unwrap();
+        assert!(report.is_complete());
+        assert_eq!(
+            report.requests,
+            vec![
+                RequestStatus {
+                    kind: RequestKind::Write,
+                    offset: 0x0330,
+                    requested: 2,
+                    returned: 2,
+                },
+                RequestStatus {
+                    kind: RequestKind::Read,
+                    offset: 0x0238,
+                    requested: 1,
+                    returned: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_overlapping_blocks_consistent() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x3D00, b"Cessna 172");
+        handle.seed(0x3D07, b"208");
+        assert_eq!(handle.bytes(0x3D00, 10), b"Cessna 208".to_vec());
+        assert_eq!(handle.bytes(0x3D07, 4), b"208\0".to_vec());
+    }
+}
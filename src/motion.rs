@@ -0,0 +1,346 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Motion platform output
+//! A motion platform can only move a few centimetres and degrees, so it cannot follow the
+//! aircraft. `WashoutFilter` implements the classical washout: the onset of accelerations and
+//! rotations moves the platform, which then drifts back to neutral below the threshold of
+//! perception, while sustained accelerations are rendered by slowly tilting the platform so
+//! gravity pushes the pilot as the acceleration would (tilt coordination). The result is a set
+//! of actuator targets from -1 to 1, sent to a `MotionOutput`.
+
+use std::io::{self, Write};
+
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The standard gravity, in ft/s².
+const GRAVITY: f64 = 32.174;
+
+/// The dynamics of the aircraft the motion is computed from
+/// The sample is read in a single transaction, so all its values come from the same frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionSample {
+    /// Accelerations along the lateral, vertical and longitudinal axes, in ft/s².
+    pub acceleration: [f64; 3],
+    /// Rotation rates about the lateral (pitch), vertical (yaw) and longitudinal (roll) axes,
+    /// in rad/s.
+    pub rotation: [f64; 3],
+}
+
+impl FsuipcStruct for MotionSample {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::BODY_ACCELERATION_X, &mut self.acceleration[0])?;
+        session.read_offset(offsets::BODY_ACCELERATION_Y, &mut self.acceleration[1])?;
+        session.read_offset(offsets::BODY_ACCELERATION_Z, &mut self.acceleration[2])?;
+        session.read_offset(offsets::BODY_ROTATION_X, &mut self.rotation[0])?;
+        session.read_offset(offsets::BODY_ROTATION_Y, &mut self.rotation[1])?;
+        session.read_offset(offsets::BODY_ROTATION_Z, &mut self.rotation[2])?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The position the actuators of a six degrees of freedom platform should move to
+/// Each value goes from -1 to 1, the limits of travel of the platform. Surge is forward, sway
+/// right and heave up; roll is right side down, pitch nose up and yaw nose right.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MotionTargets {
+    pub surge: f64,
+    pub sway: f64,
+    pub heave: f64,
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+/// The tuning of a `WashoutFilter`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WashoutParams {
+    translation_scale: f64,
+    translation_washout: f64,
+    rotation_scale: f64,
+    rotation_washout: f64,
+    tilt_scale: f64,
+    tilt_smoothing: f64,
+    max_tilt_rate: f64,
+}
+
+impl Default for WashoutParams {
+    /// Full travel at 1 G of acceleration onset or 15 degrees of rotation, both washed out in
+    /// about a second, and sustained accelerations tilted in with 2 seconds of smoothing, at
+    /// most 3 degrees per second.
+    fn default() -> Self {
+        WashoutParams {
+            translation_scale: GRAVITY,
+            translation_washout: 1.0,
+            rotation_scale: 15f64.to_radians(),
+            rotation_washout: 1.0,
+            tilt_scale: 1.0,
+            tilt_smoothing: 2.0,
+            max_tilt_rate: 3f64.to_radians(),
+        }
+    }
+}
+
+impl WashoutParams {
+    pub fn new() -> Self {
+        WashoutParams::default()
+    }
+
+    /// Reach full travel at the given acceleration onset, in ft/s², washed out with the given
+    /// time constant, in seconds.
+    pub fn with_translation(mut self, full_travel: f64, washout: f64) -> Self {
+        self.translation_scale = full_travel;
+        self.translation_washout = washout;
+        self
+    }
+
+    /// Reach full travel at the given rotation, in radians, washed out with the given time
+    /// constant, in seconds.
+    pub fn with_rotation(mut self, full_travel: f64, washout: f64) -> Self {
+        self.rotation_scale = full_travel;
+        self.rotation_washout = washout;
+        self
+    }
+
+    /// Tilt the platform for sustained accelerations, multiplied by `scale` (0 disables it),
+    /// smoothed with the given time constant, in seconds, and at most `max_rate` rad/s, which
+    /// should stay below the rate the pilot perceives as rotation.
+    pub fn with_tilt(mut self, scale: f64, smoothing: f64, max_rate: f64) -> Self {
+        self.tilt_scale = scale;
+        self.tilt_smoothing = smoothing;
+        self.max_tilt_rate = max_rate;
+        self
+    }
+}
+
+/// A first order high-pass filter
+#[derive(Clone, Copy, Debug, Default)]
+struct HighPass {
+    input: f64,
+    output: f64,
+}
+
+impl HighPass {
+    fn update(&mut self, input: f64, tau: f64, dt: f64) -> f64 {
+        let alpha = tau / (tau + dt);
+        self.output = alpha * (self.output + input - self.input);
+        self.input = input;
+        self.output
+    }
+}
+
+/// A first order low-pass filter
+#[derive(Clone, Copy, Debug, Default)]
+struct LowPass {
+    output: f64,
+}
+
+impl LowPass {
+    fn update(&mut self, input: f64, tau: f64, dt: f64) -> f64 {
+        self.output += dt / (tau + dt) * (input - self.output);
+        self.output
+    }
+}
+
+/// The classical washout filter of a motion platform
+/// It is fed with a sample of the dynamics at every frame, with the time elapsed since the
+/// previous one.
+#[derive(Clone, Debug)]
+pub struct WashoutFilter {
+    params: WashoutParams,
+    translation: [HighPass; 3],
+    /// The rotations integrated from the rates, washed out: pitch, yaw and roll.
+    rotation: [f64; 3],
+    tilt_input: [LowPass; 2],
+    /// The tilt angles rendering sustained accelerations: pitch and roll.
+    tilt: [f64; 2],
+}
+
+impl WashoutFilter {
+    pub fn new(params: WashoutParams) -> Self {
+        WashoutFilter {
+            params,
+            translation: [HighPass::default(); 3],
+            rotation: [0.0; 3],
+            tilt_input: [LowPass::default(); 2],
+            tilt: [0.0; 2],
+        }
+    }
+
+    /// Return the platform to neutral, e.g. after a pause or a slew.
+    pub fn reset(&mut self) {
+        *self = WashoutFilter::new(self.params);
+    }
+
+    /// The targets of the actuators after `dt` seconds with the given dynamics.
+    pub fn update(&mut self, sample: &MotionSample, dt: f64) -> MotionTargets {
+        let p = &self.params;
+        if dt <= 0.0 {
+            return self.targets();
+        }
+
+        for (axis, filter) in self.translation.iter_mut().enumerate() {
+            filter.update(sample.acceleration[axis], p.translation_washout, dt);
+        }
+
+        // Integrating the rate and high-passing the result washes the angle out
+        let alpha = p.rotation_washout / (p.rotation_washout + dt);
+        for (axis, angle) in self.rotation.iter_mut().enumerate() {
+            *angle = alpha * (*angle + sample.rotation[axis] * dt);
+        }
+
+        // Forward acceleration pushes into the seat as pitching up does; an acceleration to
+        // the right pushes to the left as rolling left does.
+        let sustained = [
+            self.tilt_input[0].update(sample.acceleration[2], p.tilt_smoothing, dt),
+            -self.tilt_input[1].update(sample.acceleration[0], p.tilt_smoothing, dt),
+        ];
+        let max_step = p.max_tilt_rate * dt;
+        for (tilt, acceleration) in self.tilt.iter_mut().zip(sustained.iter()) {
+            let target = (p.tilt_scale * acceleration / GRAVITY)
+                .clamp(-1.0, 1.0)
+                .asin();
+            *tilt += (target - *tilt).clamp(-max_step, max_step);
+        }
+
+        self.targets()
+    }
+
+    fn targets(&self) -> MotionTargets {
+        let p = &self.params;
+        let translation = |axis: usize| scale(self.translation[axis].output, p.translation_scale);
+        MotionTargets {
+            surge: translation(2),
+            sway: translation(0),
+            heave: translation(1),
+            roll: scale(self.rotation[2] + self.tilt[1], p.rotation_scale),
+            pitch: scale(self.rotation[0] + self.tilt[0], p.rotation_scale),
+            yaw: scale(self.rotation[1], p.rotation_scale),
+        }
+    }
+}
+
+fn scale(value: f64, full_travel: f64) -> f64 {
+    if full_travel <= 0.0 {
+        return 0.0;
+    }
+    (value / full_travel).clamp(-1.0, 1.0)
+}
+
+/// A motion platform
+pub trait MotionOutput {
+    /// Move the actuators towards the targets until the next call.
+    fn move_to(&mut self, targets: &MotionTargets) -> io::Result<()>;
+}
+
+/// A platform driven by a board on a serial port
+/// Each set of targets is sent as a line `M<surge>,<sway>,<heave>,<roll>,<pitch>,<yaw>`, with
+/// the values from -1000 to 1000. The port is anything writable, e.g. the file of a COM port
+/// already set up with the baud rate of the board.
+pub struct SerialPlatform<W> {
+    port: W,
+}
+
+impl<W: Write> SerialPlatform<W> {
+    pub fn new(port: W) -> Self {
+        SerialPlatform { port }
+    }
+
+    /// Obtain the port back.
+    pub fn into_inner(self) -> W {
+        self.port
+    }
+}
+
+impl<W: Write> MotionOutput for SerialPlatform<W> {
+    fn move_to(&mut self, targets: &MotionTargets) -> io::Result<()> {
+        let values: Vec<String> = [
+            targets.surge,
+            targets.sway,
+            targets.heave,
+            targets.roll,
+            targets.pitch,
+            targets.yaw,
+        ]
+        .iter()
+        .map(|value| ((value * 1000.0).round() as i32).to_string())
+        .collect();
+        writeln!(self.port, "M{}", values.join(","))?;
+        self.port.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    const DT: f64 = 0.02;
+
+    fn run(filter: &mut WashoutFilter, sample: &MotionSample, seconds: f64) -> MotionTargets {
+        let mut targets = MotionTargets::default();
+        for _ in 0..(seconds / DT) as usize {
+            targets = filter.update(sample, DT);
+        }
+        targets
+    }
+
+    #[test]
+    fn should_wash_out_sustained_acceleration_into_tilt() {
+        let mut filter = WashoutFilter::new(WashoutParams::new());
+        let takeoff_roll = MotionSample {
+            acceleration: [0.0, 0.0, 8.0],
+            rotation: [0.0; 3],
+        };
+        let onset = filter.update(&takeoff_roll, DT);
+        assert!(onset.surge > 0.2);
+        assert_eq!(onset.sway, 0.0);
+
+        let sustained = run(&mut filter, &takeoff_roll, 20.0);
+        assert!(sustained.surge.abs() < 0.01);
+        let tilt = (8.0 / GRAVITY).asin() / 15f64.to_radians();
+        assert!((sustained.pitch - tilt).abs() < 0.05);
+        assert_eq!(sustained.roll, 0.0);
+    }
+
+    #[test]
+    fn should_wash_out_rotations_and_limit_tilt_rate() {
+        let params = WashoutParams::new().with_tilt(1.0, 0.0, 1f64.to_radians());
+        let mut filter = WashoutFilter::new(params);
+        let turn = MotionSample {
+            acceleration: [GRAVITY, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.2],
+        };
+        let targets = run(&mut filter, &turn, 0.5);
+        assert!(targets.roll > 0.0);
+        assert!(filter.tilt[1] >= -0.5f64.to_radians() - 1e-9);
+
+        filter.reset();
+        assert_eq!(
+            filter.update(&MotionSample::default(), DT),
+            MotionTargets::default()
+        );
+    }
+
+    #[test]
+    fn should_send_targets_to_serial_platform() {
+        let mut platform = SerialPlatform::new(Vec::new());
+        let targets = MotionTargets {
+            surge: 0.5,
+            pitch: -1.0,
+            ..MotionTargets::default()
+        };
+        platform.move_to(&targets).unwrap();
+        assert_eq!(platform.into_inner(), b"M500,0,0,0,-1000,0\n".to_vec());
+    }
+}
@@ -0,0 +1,452 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Catalog of standard FSUIPC offsets
+//! Each constant bundles the address of the offset with the Rust type that matches its size,
+//! so `Session::read_offset()` and `Session::write_offset()` are type-checked against it.
+//! The comments give the units used by FSUIPC to encode the values.
+
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+use super::Session;
+
+/// A FSUIPC offset holding a value of type `T`
+pub struct Offset<T> {
+    address: u16,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T> Offset<T> {
+    pub const fn new(address: u16) -> Self {
+        Offset {
+            address,
+            _type: PhantomData,
+        }
+    }
+
+    /// The address of the offset.
+    pub const fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// The number of bytes of the offset.
+    pub const fn len(&self) -> usize {
+        size_of::<T>()
+    }
+
+    /// Whether the offset is zero-sized.
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Offset<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Offset<T> {}
+
+impl<T> PartialEq for Offset<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> fmt::Debug for Offset<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Offset(0x{:04X}, {} bytes)", self.address, self.len())
+    }
+}
+
+/// A value bound to the FSUIPC offset it is read from or written to
+/// It is used with `Session::read_value()` and `Session::write_value()`, so the offset and the
+/// variable that receives its contents cannot get mixed up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OffsetValue<T> {
+    offset: Offset<T>,
+    value: T,
+}
+
+impl<T: Default> OffsetValue<T> {
+    /// Create a value for the given offset, initialized to the default of `T`.
+    pub fn new(offset: Offset<T>) -> Self {
+        OffsetValue::with_value(offset, T::default())
+    }
+}
+
+impl<T> OffsetValue<T> {
+    /// Create a value for the given offset, initialized to `value`.
+    pub fn with_value(offset: Offset<T>, value: T) -> Self {
+        OffsetValue { offset, value }
+    }
+
+    /// The offset this value is bound to.
+    pub fn offset(&self) -> Offset<T> {
+        self.offset
+    }
+
+    /// The current value, i.e. the last one read or set.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Obtain a mutable reference to the value.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    /// Set the value to be written by the next `Session::write_value()`.
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+    }
+
+    /// Obtain the value, dropping the offset.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+/// A struct whose fields are mapped to FSUIPC offsets
+/// With the `derive` feature it can be implemented with `#[derive(FsuipcStruct)]`, annotating
+/// each mapped field with `#[offset(0x0560)]` or `#[offset(0x0560, len = 8)]`. The derive also
+/// generates an `apply()` method decoding the fields from `RawOffsets`.
+pub trait FsuipcStruct {
+    /// Request reading all the mapped offsets into their fields.
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()>;
+
+    /// Request writing all the mapped fields into their offsets.
+    fn register_writes<S: Session>(&self, session: &mut S) -> io::Result<()>;
+}
+
+/// The raw contents of a range of offsets
+/// It holds the bytes of the offsets from `start`, e.g. read in a single request or loaded from
+/// a dump of the offsets, for the `apply()` method of derived `FsuipcStruct`s to decode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawOffsets<'a> {
+    start: u16,
+    bytes: &'a [u8],
+}
+
+impl<'a> RawOffsets<'a> {
+    pub fn new(start: u16, bytes: &'a [u8]) -> Self {
+        RawOffsets { start, bytes }
+    }
+
+    /// The `len` bytes from the given offset, or `None` if they are not all held.
+    pub fn get(&self, offset: u16, len: usize) -> Option<&'a [u8]> {
+        let position = offset.checked_sub(self.start)? as usize;
+        self.bytes.get(position..position.checked_add(len)?)
+    }
+}
+
+/// Ground altitude, in metres * 256.
+pub const GROUND_ALTITUDE: Offset<i32> = Offset::new(0x0020);
+
+/// Simulation local time, hours.
+pub const SIM_LOCAL_HOUR: Offset<u8> = Offset::new(0x0238);
+/// Simulation local time, minutes.
+pub const SIM_LOCAL_MINUTE: Offset<u8> = Offset::new(0x0239);
+/// Simulation local time, seconds.
+pub const SIM_LOCAL_SECOND: Offset<u8> = Offset::new(0x023A);
+/// Simulation zulu time, hours.
+pub const ZULU_HOUR: Offset<u8> = Offset::new(0x023B);
+/// Simulation zulu time, minutes.
+pub const ZULU_MINUTE: Offset<u8> = Offset::new(0x023C);
+/// Simulation day of the year (1-365/366).
+pub const DAY_OF_YEAR: Offset<u16> = Offset::new(0x023E);
+/// Simulation year.
+pub const YEAR: Offset<u16> = Offset::new(0x0240);
+
+/// Pause control: write 1 to pause, 0 to unpause.
+pub const PAUSE: Offset<u16> = Offset::new(0x0262);
+/// Pause indicator: non-zero while paused.
+pub const PAUSE_INDICATOR: Offset<u16> = Offset::new(0x0264);
+
+/// Pitot heat switch: 1 on, 0 off.
+pub const PITOT_HEAT: Offset<u8> = Offset::new(0x029C);
+/// Magnetic variation, in degrees * 65536 / 360 (negative to the west).
+pub const MAGNETIC_VARIATION: Offset<i16> = Offset::new(0x02A0);
+/// Ground speed, in metres/second * 65536.
+pub const GROUND_SPEED: Offset<u32> = Offset::new(0x02B4);
+/// True airspeed, in knots * 128.
+pub const TRUE_AIRSPEED: Offset<u32> = Offset::new(0x02B8);
+/// Indicated airspeed, in knots * 128.
+pub const INDICATED_AIRSPEED: Offset<u32> = Offset::new(0x02BC);
+/// Barber pole (maximum) airspeed, in knots * 128.
+pub const BARBER_POLE_AIRSPEED: Offset<u32> = Offset::new(0x02C4);
+/// Vertical speed, in metres/second * 256.
+pub const VERTICAL_SPEED: Offset<i32> = Offset::new(0x02C8);
+/// Tick counter of the simulator, incremented 18 times per second (about every 55 ms), not per
+/// frame (see `batch`).
+pub const TICK18: Offset<u32> = Offset::new(0x0310);
+
+/// Altimeter pressure setting, in millibars * 16.
+pub const ALTIMETER_SETTING: Offset<u16> = Offset::new(0x0330);
+/// Structural ice, from 0 to 16384 (100%).
+pub const STRUCTURAL_ICE: Offset<u16> = Offset::new(0x0348);
+/// ADF1 frequency, hundreds, tens and units of kHz in BCD (see `ADF1_EXTENDED`).
+pub const ADF1_FREQUENCY: Offset<u16> = Offset::new(0x034C);
+/// COM1 active frequency, in BCD without the leading 1 (e.g. 0x2345 for 123.45).
+pub const COM1_FREQUENCY: Offset<u16> = Offset::new(0x034E);
+/// NAV1 active frequency, in BCD without the leading 1.
+pub const NAV1_FREQUENCY: Offset<u16> = Offset::new(0x0350);
+/// NAV2 active frequency, in BCD without the leading 1.
+pub const NAV2_FREQUENCY: Offset<u16> = Offset::new(0x0352);
+/// Transponder code, in BCD (e.g. 0x7000).
+pub const TRANSPONDER_CODE: Offset<u16> = Offset::new(0x0354);
+/// ADF1 extended frequency: thousands of kHz in the high byte, tenths of kHz in the low one.
+pub const ADF1_EXTENDED: Offset<u16> = Offset::new(0x0356);
+/// On ground flag: 1 on ground, 0 airborne.
+pub const ON_GROUND: Offset<u16> = Offset::new(0x0366);
+/// Stall warning: 1 while the stall warning is on, 0 otherwise.
+pub const STALL_WARNING: Offset<u8> = Offset::new(0x036C);
+
+/// Latitude, in FS units (degrees * 10001750 * 65536 * 65536 / 90).
+pub const LATITUDE: Offset<i64> = Offset::new(0x0560);
+/// Longitude, in FS units (degrees * 65536 * 65536 * 65536 * 65536 / 360).
+pub const LONGITUDE: Offset<i64> = Offset::new(0x0568);
+/// Altitude, in metres * 65536 * 65536.
+pub const ALTITUDE: Offset<i64> = Offset::new(0x0570);
+/// Pitch, in degrees * 65536 * 65536 / 360 (negative is nose up).
+pub const PITCH: Offset<i32> = Offset::new(0x0578);
+/// Bank, in degrees * 65536 * 65536 / 360 (negative is bank right).
+pub const BANK: Offset<i32> = Offset::new(0x057C);
+/// True heading, in degrees * 65536 * 65536 / 360.
+pub const HEADING: Offset<u32> = Offset::new(0x0580);
+/// COM1 active frequency, in Hz, with the 8.33 kHz channels (e.g. 118005000 for 118.005).
+pub const COM1_FREQUENCY_HZ: Offset<u32> = Offset::new(0x05C4);
+/// COM2 active frequency, in Hz.
+pub const COM2_FREQUENCY_HZ: Offset<u32> = Offset::new(0x05C8);
+/// COM1 standby frequency, in Hz.
+pub const COM1_STANDBY_FREQUENCY_HZ: Offset<u32> = Offset::new(0x05CC);
+/// COM2 standby frequency, in Hz.
+pub const COM2_STANDBY_FREQUENCY_HZ: Offset<u32> = Offset::new(0x05D0);
+
+/// Autopilot master switch: 1 on, 0 off.
+pub const AUTOPILOT_MASTER: Offset<u32> = Offset::new(0x07BC);
+/// Autopilot heading hold: 1 on, 0 off.
+pub const AUTOPILOT_HEADING_HOLD: Offset<u32> = Offset::new(0x07C8);
+/// Autopilot heading, in degrees * 65536 / 360.
+pub const AUTOPILOT_HEADING: Offset<u16> = Offset::new(0x07CC);
+/// Autopilot altitude hold: 1 on, 0 off.
+pub const AUTOPILOT_ALTITUDE_HOLD: Offset<u32> = Offset::new(0x07D0);
+/// Autopilot altitude, in metres * 65536.
+pub const AUTOPILOT_ALTITUDE: Offset<u32> = Offset::new(0x07D4);
+/// Autopilot airspeed hold: 1 on, 0 off.
+pub const AUTOPILOT_AIRSPEED_HOLD: Offset<u32> = Offset::new(0x07DC);
+/// Autopilot airspeed, in knots.
+pub const AUTOPILOT_AIRSPEED: Offset<u16> = Offset::new(0x07E2);
+/// Autopilot Mach hold: 1 on, 0 off.
+pub const AUTOPILOT_MACH_HOLD: Offset<u32> = Offset::new(0x07E4);
+/// Autopilot Mach number, * 65536.
+pub const AUTOPILOT_MACH: Offset<u32> = Offset::new(0x07E8);
+/// Autopilot vertical speed hold: 1 on, 0 off.
+pub const AUTOPILOT_VERTICAL_SPEED_HOLD: Offset<u32> = Offset::new(0x07EC);
+/// Autopilot vertical speed, in feet per minute.
+pub const AUTOPILOT_VERTICAL_SPEED: Offset<i16> = Offset::new(0x07F2);
+/// Autopilot approach hold: 1 on, 0 off.
+pub const AUTOPILOT_APPROACH_HOLD: Offset<u32> = Offset::new(0x0800);
+
+/// Fuel weight, in pounds per US gallon * 256.
+/// The levels and capacities of the tanks are in `fuel`.
+pub const FUEL_WEIGHT: Offset<u16> = Offset::new(0x0AF4);
+
+/// Elevator control input, from -16383 to 16383.
+pub const ELEVATOR_CONTROL: Offset<i16> = Offset::new(0x0BB2);
+/// Aileron control input, from -16383 to 16383.
+pub const AILERON_CONTROL: Offset<i16> = Offset::new(0x0BB6);
+/// Rudder control input, from -16383 to 16383.
+pub const RUDDER_CONTROL: Offset<i16> = Offset::new(0x0BBA);
+/// Left and right toe brake inputs, from 0 to 16383.
+pub const LEFT_BRAKE: Offset<i16> = Offset::new(0x0BC4);
+pub const RIGHT_BRAKE: Offset<i16> = Offset::new(0x0BC6);
+/// Parking brake: 0 off, 32767 on.
+pub const PARKING_BRAKE: Offset<u16> = Offset::new(0x0BC8);
+/// Flaps control, from 0 (up) to 16383 (full).
+pub const FLAPS_CONTROL: Offset<u32> = Offset::new(0x0BDC);
+/// Gear control, 0 up, 16383 down.
+pub const GEAR_CONTROL: Offset<u32> = Offset::new(0x0BE8);
+
+/// Engine 1 throttle lever, from -4096 (full reverse) to 16384 (full).
+/// The offsets of the other engines follow every 0x98 bytes (see `engines`).
+pub const ENGINE1_THROTTLE: Offset<i16> = Offset::new(0x088C);
+/// Engine 1 propeller lever, from -4096 to 16384 (full).
+pub const ENGINE1_PROPELLER: Offset<i16> = Offset::new(0x088E);
+/// Engine 1 mixture lever, from 0 (cut-off) to 16384 (full rich).
+pub const ENGINE1_MIXTURE: Offset<u16> = Offset::new(0x0890);
+/// Engine 1 magnetos switch: 0 off, 1 right, 2 left, 3 both, 4 start.
+pub const ENGINE1_MAGNETOS: Offset<u16> = Offset::new(0x0892);
+/// Engine 1 combustion flag: 1 running, 0 stopped.
+pub const ENGINE1_COMBUSTION: Offset<u16> = Offset::new(0x0894);
+/// Engine 1 jet N2, from 0 to 16384 (100%).
+pub const ENGINE1_N2: Offset<u16> = Offset::new(0x0896);
+/// Engine 1 jet N1, from 0 to 16384 (100%), or propeller RPM divided by the RPM scaler.
+pub const ENGINE1_N1: Offset<u16> = Offset::new(0x0898);
+/// Engine 1 anti-ice or carburettor heat switch: 1 on, 0 off.
+pub const ENGINE1_ANTI_ICE: Offset<u16> = Offset::new(0x08B2);
+/// Engine 1 oil temperature, from 0 to 16384 (140 °C).
+pub const ENGINE1_OIL_TEMPERATURE: Offset<u16> = Offset::new(0x08B8);
+/// Engine 1 oil pressure, from 0 to 16384 (55 psi).
+pub const ENGINE1_OIL_PRESSURE: Offset<u16> = Offset::new(0x08BA);
+/// Engine 1 exhaust gas temperature, from 0 to 16384 (860 °C).
+pub const ENGINE1_EGT: Offset<u16> = Offset::new(0x08BE);
+/// Engine 1 RPM scaler: the propeller RPM is the N1 offset * scaler / 65536.
+pub const ENGINE1_RPM_SCALER: Offset<u16> = Offset::new(0x08C8);
+/// Engine 1 fuel flow, in pounds per hour.
+pub const ENGINE1_FUEL_FLOW: Offset<f64> = Offset::new(0x0918);
+/// Number of engines of the aircraft.
+pub const ENGINE_COUNT: Offset<u16> = Offset::new(0x0AEC);
+
+/// Units of measure set in the simulator: 0 English, 1 metric with feet, 2 metric with metres.
+pub const UNITS: Offset<u16> = Offset::new(0x0C18);
+/// NAV1 localizer needle, from -127 (left) to 127 (right).
+pub const NAV1_LOCALIZER_NEEDLE: Offset<i8> = Offset::new(0x0C48);
+/// NAV1 glideslope needle, from -119 (up) to 119 (down).
+pub const NAV1_GLIDESLOPE_NEEDLE: Offset<i8> = Offset::new(0x0C49);
+
+/// Lights, one bit each: navigation (0), beacon (1), landing (2), taxi (3), strobes (4),
+/// instruments (5), recognition (6), wing (7), logo (8) and cabin (9).
+pub const LIGHTS: Offset<u16> = Offset::new(0x0D0C);
+/// Parameter passed to the next Lua command written to 0x0D70.
+pub const LUA_PARAM: Offset<i32> = Offset::new(0x0D6C);
+/// Lua or macro command for FSUIPC to run, zero-terminated ASCII (see `lua`).
+pub const MACRO_COMMAND: Offset<[u8; 128]> = Offset::new(0x0D70);
+
+/// Outside air temperature, in degrees Celsius * 256.
+pub const OUTSIDE_AIR_TEMPERATURE: Offset<i16> = Offset::new(0x0E8C);
+
+/// G force, in G * 625.
+pub const G_FORCE: Offset<i16> = Offset::new(0x11BA);
+
+/// Number of payload stations; their data follows from 0x1400 (see `payload`).
+pub const PAYLOAD_STATION_COUNT: Offset<u32> = Offset::new(0x13FC);
+
+/// Battery master switch: 1 on, 0 off.
+pub const BATTERY_MASTER: Offset<u32> = Offset::new(0x281C);
+/// Avionics master switch: 1 on, 0 off.
+pub const AVIONICS_MASTER: Offset<u32> = Offset::new(0x2E80);
+
+/// Elevator deflection, in radians (positive up), as a double.
+pub const ELEVATOR_DEFLECTION: Offset<f64> = Offset::new(0x2E98);
+/// Elevator trim deflection, in radians (positive up), as a double.
+pub const ELEVATOR_TRIM_DEFLECTION: Offset<f64> = Offset::new(0x2EA0);
+/// Aileron deflection, in radians, as a double.
+pub const AILERON_DEFLECTION: Offset<f64> = Offset::new(0x2EA8);
+/// Rudder deflection, in radians, as a double.
+pub const RUDDER_DEFLECTION: Offset<f64> = Offset::new(0x2EB0);
+/// Angle of attack (incidence alpha), in radians, as a double.
+pub const ANGLE_OF_ATTACK: Offset<f64> = Offset::new(0x2ED0);
+
+/// Acceleration along the lateral axis of the aircraft (right), in ft/s², as a double.
+pub const BODY_ACCELERATION_X: Offset<f64> = Offset::new(0x3060);
+/// Acceleration along the vertical axis of the aircraft (up), in ft/s², as a double.
+pub const BODY_ACCELERATION_Y: Offset<f64> = Offset::new(0x3068);
+/// Acceleration along the longitudinal axis of the aircraft (forward), in ft/s², as a double.
+pub const BODY_ACCELERATION_Z: Offset<f64> = Offset::new(0x3070);
+/// Rotation rate about the lateral axis of the aircraft (pitch), in rad/s, as a double.
+pub const BODY_ROTATION_X: Offset<f64> = Offset::new(0x30A8);
+/// Rotation rate about the vertical axis of the aircraft (yaw), in rad/s, as a double.
+pub const BODY_ROTATION_Y: Offset<f64> = Offset::new(0x30B0);
+/// Rotation rate about the longitudinal axis of the aircraft (roll), in rad/s, as a double.
+pub const BODY_ROTATION_Z: Offset<f64> = Offset::new(0x30B8);
+
+/// Fuel pump switch: 1 on, 0 off.
+pub const FUEL_PUMP: Offset<u8> = Offset::new(0x3104);
+/// Control to send to the simulator, followed by its parameter at 0x3114.
+/// Both are written together, since writing the control sends it (see `controls`).
+pub const CONTROL: Offset<[u32; 2]> = Offset::new(0x3110);
+/// COM2 active frequency, in BCD without the leading 1.
+pub const COM2_FREQUENCY: Offset<u16> = Offset::new(0x3118);
+/// COM1 standby frequency, in BCD without the leading 1.
+pub const COM1_STANDBY_FREQUENCY: Offset<u16> = Offset::new(0x311A);
+/// COM2 standby frequency, in BCD without the leading 1.
+pub const COM2_STANDBY_FREQUENCY: Offset<u16> = Offset::new(0x311C);
+/// NAV1 standby frequency, in BCD without the leading 1.
+pub const NAV1_STANDBY_FREQUENCY: Offset<u16> = Offset::new(0x311E);
+/// NAV2 standby frequency, in BCD without the leading 1.
+pub const NAV2_STANDBY_FREQUENCY: Offset<u16> = Offset::new(0x3120);
+/// Radio swap toggles: writing bit 3 swaps COM1, 2 COM2, 1 NAV1 and 0 NAV2.
+pub const RADIO_SWAP: Offset<u8> = Offset::new(0x3123);
+/// ATC aircraft identifier (tail number), zero-terminated ASCII.
+pub const ATC_ID: Offset<[u8; 12]> = Offset::new(0x313C);
+/// Type of the surface under the aircraft: 0 concrete, 1 grass, 2 water, 4 asphalt, 12 dirt,
+/// 14 gravel, 23 tarmac, among others.
+pub const SURFACE_TYPE: Offset<u32> = Offset::new(0x31E8);
+/// Condition of the surface under the aircraft: 0 normal, 1 wet, 2 icy, 3 snow.
+pub const SURFACE_CONDITION: Offset<u32> = Offset::new(0x31EC);
+/// Window message to send to the simulator: the message, its wParam and its lParam.
+/// FSUIPC clears the message once it has been sent (see `keys`).
+pub const KEY_MESSAGE: Offset<[u32; 3]> = Offset::new(0x3200);
+/// Message display control: the seconds to show the text at 0x3380 for (0 until replaced).
+/// FSUIPC changes it while a menu is shown (see `display`).
+pub const MESSAGE_CONTROL: Offset<i16> = Offset::new(0x32FA);
+/// FSUIPC version, as 0xVVVVBBBB with the version in BCD.
+pub const FSUIPC_VERSION: Offset<u32> = Offset::new(0x3304);
+/// Simulator version (e.g. 7 for FS2004, 10 for P3D, 12 for P3D 64-bit, 13 for MSFS).
+pub const FS_VERSION: Offset<u16> = Offset::new(0x3308);
+/// FSUIPC status flags: bit 0 set if FSUIPC is registered (see `registration`).
+pub const FSUIPC_STATUS: Offset<u16> = Offset::new(0x330A);
+/// Altimeter reading, in feet.
+pub const ALTIMETER_READING: Offset<i32> = Offset::new(0x3324);
+/// Propeller de-ice switch: 1 on, 0 off.
+pub const PROPELLER_DEICE: Offset<u8> = Offset::new(0x337C);
+/// Structural de-ice switch: 1 on, 0 off.
+pub const STRUCTURAL_DEICE: Offset<u8> = Offset::new(0x337D);
+/// Message text to show in the simulator, zero-terminated ASCII.
+pub const MESSAGE_TEXT: Offset<[u8; 128]> = Offset::new(0x3380);
+/// Aircraft title, zero-terminated ASCII.
+pub const AIRCRAFT_NAME: Offset<[u8; 256]> = Offset::new(0x3D00);
+/// Calculator code for MSFS to execute, zero-terminated ASCII (see `calc`).
+pub const CALCULATOR_CODE: Offset<[u8; 256]> = Offset::new(0x7C50);
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_bundle_address_and_size() {
+        assert_eq!(SIM_LOCAL_HOUR.address(), 0x0238);
+        assert_eq!(SIM_LOCAL_HOUR.len(), 1);
+        assert_eq!(INDICATED_AIRSPEED.len(), 4);
+        assert_eq!(LATITUDE.len(), 8);
+        assert_eq!(AIRCRAFT_NAME.len(), 256);
+    }
+
+    #[test]
+    fn should_bind_values_to_offsets() {
+        let mut hour = OffsetValue::new(SIM_LOCAL_HOUR);
+        assert_eq!(*hour.get(), 0);
+        hour.set(12);
+        *hour.get_mut() += 1;
+        assert_eq!(hour.offset(), SIM_LOCAL_HOUR);
+        assert_eq!(hour.into_inner(), 13);
+    }
+
+    #[test]
+    fn should_format_offsets() {
+        assert_eq!(
+            format!("{:?}", ALTIMETER_SETTING),
+            "Offset(0x0330, 2 bytes)"
+        );
+    }
+
+    #[test]
+    fn should_get_raw_offsets_in_range() {
+        let bytes = [1, 2, 3, 4];
+        let raw = RawOffsets::new(0x0238, &bytes);
+        assert_eq!(raw.get(0x0239, 2), Some(&bytes[1..3]));
+        assert_eq!(raw.get(0x023A, 3), None);
+        assert_eq!(raw.get(0x0237, 1), None);
+    }
+}
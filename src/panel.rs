@@ -0,0 +1,144 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Cockpit panel persistence
+//! `PanelState` saves the position of the switches and levers reachable through offsets into
+//! a `ValueStore` when a flight ends, and restores them when the same aircraft is loaded
+//! again, so users resume with their cockpit as they left it. The positions are kept per
+//! aircraft title, under names prefixed with `panel.<title>.`.
+
+use std::io;
+
+use super::offsets::{self, Offset};
+use super::store::{aircraft_key, ValueStore};
+use super::strings;
+use super::{Handle, Session};
+
+/// The set of panel controls saved and restored, one per profile
+#[derive(Clone, Debug, Default)]
+pub struct PanelState {
+    controls: Vec<(String, u16, usize)>,
+}
+
+impl PanelState {
+    /// A panel with no controls, to be configured with `with_control()`.
+    pub fn new() -> Self {
+        PanelState::default()
+    }
+
+    /// A panel with the switches and levers of the offsets catalog.
+    pub fn with_defaults() -> Self {
+        PanelState::new()
+            .with_control("parking_brake", offsets::PARKING_BRAKE)
+            .with_control("battery_master", offsets::BATTERY_MASTER)
+            .with_control("avionics_master", offsets::AVIONICS_MASTER)
+            .with_control("lights", offsets::LIGHTS)
+            .with_control("fuel_pump", offsets::FUEL_PUMP)
+            .with_control("pitot_heat", offsets::PITOT_HEAT)
+            .with_control("flaps", offsets::FLAPS_CONTROL)
+            .with_control("engine1_magnetos", offsets::ENGINE1_MAGNETOS)
+            .with_control("engine1_mixture", offsets::ENGINE1_MIXTURE)
+    }
+
+    /// Save and restore the given offset under the given name.
+    /// It panics if the offset is wider than 4 bytes, since the store keeps 32-bit values.
+    pub fn with_control<T>(mut self, name: &str, offset: Offset<T>) -> Self {
+        assert!(
+            offset.len() <= 4,
+            "panel controls cannot be wider than 4 bytes"
+        );
+        self.controls
+            .push((name.to_string(), offset.address(), offset.len()));
+        self
+    }
+
+    /// Read the controls and save them into the store for the loaded aircraft.
+    /// It returns the title of the aircraft. The store is not saved to disk.
+    pub fn save<H>(&self, handle: &mut H, store: &mut ValueStore) -> io::Result<String>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut title = [0u8; 256];
+        let mut values = vec![[0u8; 4]; self.controls.len()];
+        let mut session = handle.session();
+        session.read_offset(offsets::AIRCRAFT_NAME, &mut title)?;
+        for ((_, offset, len), value) in self.controls.iter().zip(values.iter_mut()) {
+            session.read_bytes(*offset, value.as_mut_ptr(), *len)?;
+        }
+        session.process()?;
+
+        let aircraft = strings::decode(&title);
+        for ((name, _, _), value) in self.controls.iter().zip(values.iter()) {
+            store.set(&key(&aircraft, name), i32::from_le_bytes(*value))?;
+        }
+        Ok(aircraft)
+    }
+
+    /// Write the controls saved for the loaded aircraft, if any.
+    /// It returns the number of controls restored, which is 0 for aircraft never saved.
+    pub fn restore<H>(&self, handle: &mut H, store: &ValueStore) -> io::Result<usize>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut title = [0u8; 256];
+        let mut session = handle.session();
+        session.read_offset(offsets::AIRCRAFT_NAME, &mut title)?;
+        session.process()?;
+
+        let aircraft = strings::decode(&title);
+        let mut restored = 0;
+        let mut session = handle.session();
+        for (name, offset, len) in self.controls.iter() {
+            if let Some(value) = store.get(&key(&aircraft, name)) {
+                session.write_bytes(*offset, value.to_le_bytes().as_ptr(), *len)?;
+                restored += 1;
+            }
+        }
+        session.process()?;
+        Ok(restored)
+    }
+}
+
+/// The store name of a control of the given aircraft.
+fn key(aircraft: &str, control: &str) -> String {
+    aircraft_key("panel", aircraft, control)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_restore_the_panel_of_the_same_aircraft() {
+        let panel = PanelState::with_defaults();
+        let mut store = ValueStore::in_memory();
+        let mut handle = MockHandle::new();
+        handle.seed(0x3D00, b"Cessna Skyhawk #2\0");
+        handle.seed_value(0x0D0C, &0x0013u16);
+        handle.seed_value(0x281C, &1u32);
+        assert_eq!(
+            panel.save(&mut handle, &mut store).unwrap(),
+            "Cessna Skyhawk #2"
+        );
+        assert_eq!(store.get("panel.Cessna Skyhawk _2.lights"), Some(0x0013));
+
+        let mut other = MockHandle::new();
+        other.seed(0x3D00, b"Beechcraft King Air\0");
+        assert_eq!(panel.restore(&mut other, &store).unwrap(), 0);
+        assert!(other.writes().is_empty());
+
+        let mut same = MockHandle::new();
+        same.seed(0x3D00, b"Cessna Skyhawk #2\0");
+        assert_eq!(panel.restore(&mut same, &store).unwrap(), 9);
+        assert_eq!(same.value::<u16>(0x0D0C), 0x0013);
+        assert_eq!(same.value::<u32>(0x281C), 1);
+        assert_eq!(same.bytes(0x0D0E, 1), vec![0]);
+    }
+}
@@ -0,0 +1,190 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Payload stations
+//! FSUIPC gives the number of payload stations of the aircraft at 0x13FC, followed from 0x1400
+//! by a block of 48 bytes per station with its weight, in pounds, its position and its name.
+//! `read_payload()` reads the stations along with the units of measure set in the simulator,
+//! so their weights can be shown, and written with `write_station_weight()`, in the units the
+//! user expects.
+
+use std::io;
+use std::mem::size_of;
+
+use super::geo::KILOGRAMS_PER_POUND;
+use super::offsets;
+use super::strings;
+use super::{Handle, Session};
+
+/// The offset of the first payload station.
+pub const PAYLOAD_STATIONS: u16 = 0x1400;
+
+/// The maximum number of payload stations.
+pub const MAX_STATIONS: usize = 61;
+
+/// A unit of weight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeightUnit {
+    Pounds,
+    Kilograms,
+}
+
+impl WeightUnit {
+    /// The unit of the units of measure set in the simulator (offset 0x0C18).
+    pub fn from_units(units: u16) -> Self {
+        match units {
+            0 => WeightUnit::Pounds,
+            _ => WeightUnit::Kilograms,
+        }
+    }
+
+    /// Convert a weight in pounds to this unit.
+    pub fn from_pounds(self, pounds: f64) -> f64 {
+        match self {
+            WeightUnit::Pounds => pounds,
+            WeightUnit::Kilograms => pounds * KILOGRAMS_PER_POUND,
+        }
+    }
+
+    /// Convert a weight in this unit to pounds.
+    pub fn to_pounds(self, weight: f64) -> f64 {
+        match self {
+            WeightUnit::Pounds => weight,
+            WeightUnit::Kilograms => weight / KILOGRAMS_PER_POUND,
+        }
+    }
+}
+
+/// A payload station, as laid out in the offsets
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PayloadStation {
+    /// The weight, in pounds.
+    pub weight: f64,
+    /// The distances from the reference point, in feet: lateral, vertical and longitudinal.
+    pub position: [f64; 3],
+    name: [u8; 16],
+}
+
+impl PayloadStation {
+    pub fn name(&self) -> String {
+        strings::decode(&self.name)
+    }
+}
+
+/// The payload stations of the aircraft
+#[derive(Clone, Debug, PartialEq)]
+pub struct Payload {
+    /// The unit of the units of measure set in the simulator.
+    pub unit: WeightUnit,
+    pub stations: Vec<PayloadStation>,
+}
+
+impl Payload {
+    /// The weight of the station of the given index, from 0, in `unit`.
+    pub fn weight(&self, index: usize) -> Option<f64> {
+        let station = self.stations.get(index)?;
+        Some(self.unit.from_pounds(station.weight))
+    }
+
+    /// The weight of all the stations, in `unit`.
+    pub fn total_weight(&self) -> f64 {
+        let pounds = self.stations.iter().map(|station| station.weight).sum();
+        self.unit.from_pounds(pounds)
+    }
+}
+
+/// Read the payload stations of the aircraft.
+/// It takes two transactions: one for the number of stations and one for their data.
+pub fn read_payload<H>(handle: &mut H) -> io::Result<Payload>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut count = 0u32;
+    let mut units = 0u16;
+    {
+        let mut session = handle.session();
+        session.read_offset(offsets::PAYLOAD_STATION_COUNT, &mut count)?;
+        session.read_offset(offsets::UNITS, &mut units)?;
+        session.process()?;
+    }
+    let mut stations = vec![PayloadStation::default(); (count as usize).min(MAX_STATIONS)];
+    if !stations.is_empty() {
+        let mut session = handle.session();
+        session.read_bytes(
+            PAYLOAD_STATIONS,
+            stations.as_mut_ptr() as *mut u8,
+            stations.len() * size_of::<PayloadStation>(),
+        )?;
+        session.process()?;
+    }
+    Ok(Payload {
+        unit: WeightUnit::from_units(units),
+        stations,
+    })
+}
+
+/// Request setting the weight of the station of the given index, from 0, in the given unit.
+pub fn write_station_weight<S: Session>(
+    session: &mut S,
+    index: usize,
+    weight: f64,
+    unit: WeightUnit,
+) -> io::Result<usize> {
+    if index >= MAX_STATIONS || !(0.0..=f64::MAX).contains(&weight) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid weight {} for payload station {}", weight, index),
+        ));
+    }
+    let offset = PAYLOAD_STATIONS + (index * size_of::<PayloadStation>()) as u16;
+    session.write(offset, &unit.to_pounds(weight))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_match_offsets_layout() {
+        assert_eq!(size_of::<PayloadStation>(), 48);
+    }
+
+    #[test]
+    fn should_read_payload() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x13FC, &2u32);
+        handle.seed_value(0x0C18, &1u16);
+        handle.seed_value(0x1400, &170.0f64);
+        handle.seed(0x1420, b"PILOT\0");
+        handle.seed_value(0x1430, &330.0f64);
+        handle.seed(0x1450, b"CARGO\0");
+        let payload = read_payload(&mut handle).unwrap();
+        assert_eq!(payload.unit, WeightUnit::Kilograms);
+        assert_eq!(payload.stations.len(), 2);
+        assert_eq!(payload.stations[1].name(), "CARGO");
+        assert!((payload.weight(0).unwrap() - 77.111).abs() < 0.001);
+        assert!((payload.total_weight() - 226.796).abs() < 0.001);
+        assert_eq!(payload.weight(2), None);
+    }
+
+    #[test]
+    fn should_write_station_weights() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_station_weight(&mut session, 1, 100.0, WeightUnit::Kilograms).unwrap();
+            assert!(write_station_weight(&mut session, 61, 1.0, WeightUnit::Pounds).is_err());
+            assert!(write_station_weight(&mut session, 0, -1.0, WeightUnit::Pounds).is_err());
+            session.process().unwrap();
+        }
+        assert!((handle.value::<f64>(0x1430) - 220.462).abs() < 0.001);
+    }
+}
@@ -0,0 +1,241 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! PMDG aircraft data
+//! PMDG aircraft publish the state of their cockpit through their SDK, and FSUIPC maps it into
+//! offsets from 0x6420 once the SDK broadcast is enabled in the options of the aircraft. The
+//! structs of this module mirror blocks of the SDK headers byte for byte, packed as PMDG lays
+//! them out. The switches and annunciators are kept as bytes, as the aircraft may write any
+//! value into them, and read as booleans through their accessors.
+//!
+//! `read_ngx_data()` reads the whole data area of the 737NGX in a single request. Its mode
+//! control panel is decoded into `NgxMcp`; the rest of the switches and annunciators are read
+//! from `NgxData::raw()` at the offsets FSUIPC documents for them, e.g. into a derived
+//! `FsuipcStruct` with its `apply()` method.
+//!
+//! The 777 is out of the scope of this module: it publishes a different data area, with its
+//! own SDK headers, that is not mapped here.
+
+use std::io;
+use std::mem::size_of;
+use std::ops::Range;
+use std::ptr;
+
+use super::offsets::{Offset, RawOffsets};
+use super::{Handle, Session};
+
+/// The offsets FSUIPC maps the data area of the PMDG 737NGX into.
+pub const NGX_DATA: Range<u16> = 0x6420..0x6C00;
+
+/// The mode control panel of the PMDG 737NGX.
+pub const NGX_MCP: Offset<NgxMcp> = Offset::new(0x6520);
+
+/// The mode control panel of the PMDG 737NGX, as in `PMDG_NGX_Data`
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NgxMcp {
+    /// The course of the captain and first officer, in degrees.
+    pub course: [u16; 2],
+    /// The speed, in knots, or Mach number below 10.
+    pub ias_mach: f32,
+    pub ias_blank: u8,
+    pub ias_overspeed_flash: u8,
+    pub ias_underspeed_flash: u8,
+    unused: u8,
+    /// The heading, in degrees.
+    pub heading: u16,
+    /// The altitude, in feet.
+    pub altitude: u16,
+    /// The vertical speed, in feet per minute.
+    pub vertical_speed: i16,
+    pub vertical_speed_blank: u8,
+    pub flight_director_switches: [u8; 2],
+    pub autothrottle_arm_switch: u8,
+    /// The bank limit selector, from 0 (10 degrees) to 4 (30 degrees).
+    pub bank_limit: u8,
+    pub disengage_bar: u8,
+    pub annunciator_flight_director: [u8; 2],
+    pub annunciator_autothrottle_arm: u8,
+    pub annunciator_n1: u8,
+    pub annunciator_speed: u8,
+    pub annunciator_vnav: u8,
+    pub annunciator_level_change: u8,
+    pub annunciator_heading_select: u8,
+    pub annunciator_lnav: u8,
+    pub annunciator_vor_loc: u8,
+    pub annunciator_approach: u8,
+    pub annunciator_altitude_hold: u8,
+    pub annunciator_vertical_speed: u8,
+    pub annunciator_command_a: u8,
+    pub annunciator_cws_a: u8,
+    pub annunciator_command_b: u8,
+    pub annunciator_cws_b: u8,
+}
+
+/// The autopilot modes annunciated on the mode control panel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct McpModes {
+    pub n1: bool,
+    pub speed: bool,
+    pub vnav: bool,
+    pub level_change: bool,
+    pub heading_select: bool,
+    pub lnav: bool,
+    pub vor_loc: bool,
+    pub approach: bool,
+    pub altitude_hold: bool,
+    pub vertical_speed: bool,
+}
+
+impl NgxMcp {
+    /// Decode the block from its bytes, or `None` if there are not enough of them.
+    /// Any trailing bytes are ignored, so a larger dump of the offsets can be given.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < size_of::<NgxMcp>() {
+            return None;
+        }
+        Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const NgxMcp) })
+    }
+
+    /// Whether the speed window is blank, as when VNAV controls the speed.
+    pub fn speed_blank(&self) -> bool {
+        self.ias_blank != 0
+    }
+
+    /// Whether the selected speed is a Mach number rather than knots.
+    pub fn is_mach(&self) -> bool {
+        self.ias_mach < 10.0
+    }
+
+    /// Whether the autopilot A or B is engaged in command mode.
+    pub fn autopilot_engaged(&self) -> bool {
+        self.annunciator_command_a != 0 || self.annunciator_command_b != 0
+    }
+
+    /// The modes annunciated.
+    pub fn modes(&self) -> McpModes {
+        McpModes {
+            n1: self.annunciator_n1 != 0,
+            speed: self.annunciator_speed != 0,
+            vnav: self.annunciator_vnav != 0,
+            level_change: self.annunciator_level_change != 0,
+            heading_select: self.annunciator_heading_select != 0,
+            lnav: self.annunciator_lnav != 0,
+            vor_loc: self.annunciator_vor_loc != 0,
+            approach: self.annunciator_approach != 0,
+            altitude_hold: self.annunciator_altitude_hold != 0,
+            vertical_speed: self.annunciator_vertical_speed != 0,
+        }
+    }
+}
+
+/// The data area of the PMDG 737NGX, as read at once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NgxData {
+    bytes: Vec<u8>,
+}
+
+impl NgxData {
+    /// The offsets of the data area, to decode the blocks not mapped by this module.
+    pub fn raw(&self) -> RawOffsets<'_> {
+        RawOffsets::new(NGX_DATA.start, &self.bytes)
+    }
+
+    /// The mode control panel.
+    pub fn mcp(&self) -> NgxMcp {
+        self.raw()
+            .get(NGX_MCP.address(), size_of::<NgxMcp>())
+            .and_then(NgxMcp::decode)
+            .unwrap_or_default()
+    }
+}
+
+/// Read the whole data area of the PMDG 737NGX in a single request.
+/// The area reads as zeroes unless the aircraft is loaded with its SDK broadcast enabled.
+pub fn read_ngx_data<H>(handle: &mut H) -> io::Result<NgxData>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut bytes = vec![0u8; (NGX_DATA.end - NGX_DATA.start) as usize];
+    let mut session = handle.session();
+    session.read_bytes(NGX_DATA.start, bytes.as_mut_ptr(), bytes.len())?;
+    session.process()?;
+    Ok(NgxData { bytes })
+}
+
+/// Read the mode control panel of the PMDG 737NGX.
+/// The block reads as zeroes unless the aircraft is loaded with its SDK broadcast enabled.
+pub fn read_ngx_mcp<H>(handle: &mut H) -> io::Result<NgxMcp>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut bytes = [0u8; size_of::<NgxMcp>()];
+    let mut session = handle.session();
+    session.read_bytes(NGX_MCP.address(), bytes.as_mut_ptr(), bytes.len())?;
+    session.process()?;
+    Ok(NgxMcp::decode(&bytes).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_match_sdk_layout() {
+        assert_eq!(size_of::<NgxMcp>(), 0x29);
+        let mcp = NgxMcp::default();
+        let base = &mcp as *const NgxMcp as usize;
+        let heading = ptr::addr_of!(mcp.heading) as usize;
+        let command_b = ptr::addr_of!(mcp.annunciator_command_b) as usize;
+        assert_eq!(heading - base, 0x652C - 0x6520);
+        assert_eq!(command_b - base, 0x6547 - 0x6520);
+    }
+
+    #[test]
+    fn should_read_ngx_mcp() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x6524, &0.78f32);
+        handle.seed_value(0x652C, &270u16);
+        handle.seed_value(0x652E, &35000u16);
+        handle.seed_value(0x6530, &(-1500i16));
+        handle.seed(0x653D, &[1, 0, 0, 1]);
+        handle.seed(0x6545, &[1]);
+
+        let mcp = read_ngx_mcp(&mut handle).unwrap();
+        let (heading, altitude, vertical_speed) = (mcp.heading, mcp.altitude, mcp.vertical_speed);
+        assert_eq!((heading, altitude, vertical_speed), (270, 35000, -1500));
+        assert!(mcp.is_mach());
+        assert!(mcp.autopilot_engaged());
+        let modes = mcp.modes();
+        assert!(modes.vnav && modes.lnav && !modes.heading_select);
+        assert_eq!(NgxMcp::decode(&[0; 40]), None);
+    }
+
+    #[test]
+    fn should_read_whole_ngx_data_area() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x6420, &[2]);
+        handle.seed_value(0x652C, &270u16);
+        handle.seed(0x6BFF, &[1]);
+
+        let data = read_ngx_data(&mut handle).unwrap();
+        assert_eq!(handle.transactions(), 1);
+        assert_eq!({ data.mcp().heading }, 270);
+        let raw = data.raw();
+        assert_eq!(raw.get(0x6420, 1), Some(&[2][..]));
+        assert_eq!(raw.get(0x6BFF, 1), Some(&[1][..]));
+        assert_eq!(raw.get(0x6C00, 1), None);
+        assert_eq!(
+            raw.get(NGX_MCP.address(), size_of::<NgxMcp>())
+                .map(<[u8]>::len),
+            Some(0x29)
+        );
+    }
+}
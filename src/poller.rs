@@ -0,0 +1,530 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::cancel::CancellationToken;
+use super::lvars::LVarMap;
+use super::{Handle, Session};
+
+/// The contents of a polled offset changed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OffsetChanged {
+    /// The polled offset.
+    pub offset: u16,
+    /// The previous contents, or `None` for the first value read.
+    pub old: Option<Vec<u8>>,
+    /// The new contents.
+    pub new: Vec<u8>,
+}
+
+struct Watch {
+    offset: u16,
+    interval: Duration,
+    priority: bool,
+    next: Instant,
+    value: Option<Vec<u8>>,
+}
+
+struct LVarBlock {
+    lvars: LVarMap,
+    interval: Duration,
+    next: Instant,
+}
+
+/// A builder of `Poller` objects
+#[derive(Default)]
+pub struct PollerBuilder {
+    watches: Vec<(u16, usize, Duration, bool)>,
+    lvars: Vec<(LVarMap, Duration)>,
+    cancel: CancellationToken,
+}
+
+impl PollerBuilder {
+    pub fn new() -> Self {
+        PollerBuilder::default()
+    }
+
+    /// Poll `len` bytes from `offset` every `interval`.
+    pub fn watch(mut self, offset: u16, len: usize, interval: Duration) -> Self {
+        self.watches.push((offset, len, interval, false));
+        self
+    }
+
+    /// Poll `len` bytes from `offset` every `interval` in the fast lane.
+    /// The due offsets of the fast lane are read in a session of their own, processed and
+    /// delivered before the other due offsets are requested, so their latency does not depend
+    /// on how many other offsets are polled. It suits the few offsets that drive hardware.
+    pub fn watch_priority(mut self, offset: u16, len: usize, interval: Duration) -> Self {
+        self.watches.push((offset, len, interval, true));
+        self
+    }
+
+    /// Poll the LVars of the map every `interval`, as a block.
+    /// The LVars are copied into their offsets in a single session right before the offsets
+    /// are read, so their changes are delivered as the ones of the offsets they are mapped to
+    /// (see `LVarMap::name_at()`). LVars mapped afterwards are not polled. Each cycle costs
+    /// one 0x0D70 command per LVar; see `watch_mapped_lvars()` for LVars FSUIPC7 refreshes.
+    pub fn watch_lvars(mut self, lvars: &LVarMap, interval: Duration) -> Self {
+        self = self.watch_mapped_lvars(lvars, interval);
+        self.lvars.push((lvars.clone(), interval));
+        self
+    }
+
+    /// Poll the offsets of the LVars of the map every `interval`, without copying the LVars.
+    /// It suits LVars FSUIPC7 keeps in their offsets itself, refreshed as a block through its
+    /// WAPI, once listed in the `[LvarOffsets]` section of its ini file (see `LVarMap::lvars()`).
+    /// Their changes are delivered as the ones of any other offset.
+    pub fn watch_mapped_lvars(mut self, lvars: &LVarMap, interval: Duration) -> Self {
+        for (offset, len) in lvars.offsets() {
+            self.watches.push((offset, len, interval, false));
+        }
+        self
+    }
+
+    /// Stop polling when the given token is cancelled.
+    pub fn with_cancellation(mut self, token: &CancellationToken) -> Self {
+        self.cancel = token.child();
+        self
+    }
+
+    /// Start polling with the given handle in a worker thread.
+    /// Changes are delivered through the returned receiver, as well as the errors found while
+    /// processing the sessions. Polling goes on after an error.
+    pub fn start<H>(self, handle: H) -> io::Result<(Poller, Changes)>
+    where
+        H: for<'h> Handle<'h> + Send + 'static,
+    {
+        let now = Instant::now();
+        let watches = self
+            .watches
+            .into_iter()
+            .map(|(offset, len, interval, priority)| {
+                (
+                    Watch {
+                        offset,
+                        interval,
+                        priority,
+                        next: now,
+                        value: None,
+                    },
+                    vec![0; len],
+                )
+            })
+            .collect();
+        let blocks = self
+            .lvars
+            .into_iter()
+            .map(|(lvars, interval)| LVarBlock {
+                lvars,
+                interval,
+                next: now,
+            })
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        let cancel = self.cancel;
+        let thread_cancel = cancel.clone();
+        let thread = thread::Builder::new()
+            .name("fsuipc-poller".to_string())
+            .spawn(move || poll(handle, watches, blocks, sender, thread_cancel))?;
+        Ok((
+            Poller {
+                cancel: cancel.clone(),
+                thread: Some(thread),
+            },
+            Changes { receiver, cancel },
+        ))
+    }
+}
+
+/// The receiver of the changes found by a `Poller`
+/// It is used as the `Receiver` it wraps. Dropping it stops the polling on the next cycle of the
+/// worker thread, without waiting for a change to deliver.
+pub struct Changes {
+    receiver: Receiver<io::Result<OffsetChanged>>,
+    cancel: CancellationToken,
+}
+
+impl Deref for Changes {
+    type Target = Receiver<io::Result<OffsetChanged>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl<'a> IntoIterator for &'a Changes {
+    type Item = io::Result<OffsetChanged>;
+    type IntoIter = mpsc::Iter<'a, io::Result<OffsetChanged>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.receiver.iter()
+    }
+}
+
+impl Drop for Changes {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// A worker thread polling offsets for changes
+/// Polling stops when this object is dropped, when the receiver of the changes is dropped or
+/// when the token given to `PollerBuilder::with_cancellation()` is cancelled.
+pub struct Poller {
+    cancel: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Poller {
+    /// Stop polling and wait for the worker thread to finish.
+    pub fn stop(self) {}
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn poll<H>(
+    mut handle: H,
+    mut watches: Vec<(Watch, Vec<u8>)>,
+    mut blocks: Vec<LVarBlock>,
+    sender: Sender<io::Result<OffsetChanged>>,
+    cancel: CancellationToken,
+) where
+    H: for<'h> Handle<'h>,
+{
+    while !cancel.is_cancelled() {
+        let now = Instant::now();
+        for block in blocks.iter_mut().filter(|block| block.next <= now) {
+            let result = {
+                let mut session = handle.session();
+                block
+                    .lvars
+                    .request_copies(&mut session)
+                    .and_then(|_| session.process())
+            };
+            block.next = now + block.interval;
+            if let Err(error) = result {
+                if sender.send(Err(error)).is_err() {
+                    return;
+                }
+            }
+        }
+        for lane in [true, false].iter() {
+            let now = Instant::now();
+            let due: Vec<usize> = (0..watches.len())
+                .filter(|i| watches[*i].0.priority == *lane && watches[*i].0.next <= now)
+                .collect();
+            if !due.is_empty() && !poll_due(&mut handle, &mut watches, &due, now, &sender) {
+                return;
+            }
+        }
+        let next = watches.iter().map(|(watch, _)| watch.next);
+        match next.chain(blocks.iter().map(|block| block.next)).min() {
+            Some(next) => {
+                let now = Instant::now();
+                if next > now {
+                    cancel.wait_timeout(next - now);
+                }
+            }
+            None => cancel.wait(),
+        }
+    }
+}
+
+/// Read the due watches in a session and deliver their changes.
+/// It returns false once the receiver of the changes is gone.
+fn poll_due<H>(
+    handle: &mut H,
+    watches: &mut [(Watch, Vec<u8>)],
+    due: &[usize],
+    now: Instant,
+    sender: &Sender<io::Result<OffsetChanged>>,
+) -> bool
+where
+    H: for<'h> Handle<'h>,
+{
+    let result = {
+        let mut session = handle.session();
+        due.iter()
+            .try_for_each(|i| {
+                let (watch, buffer) = &mut watches[*i];
+                session
+                    .read_bytes(watch.offset, buffer.as_mut_ptr(), buffer.len())
+                    .map(|_| ())
+            })
+            .and_then(|_| session.process())
+    };
+    let mut events = Vec::new();
+    match result {
+        Ok(_) => {
+            for i in due.iter() {
+                let (watch, buffer) = &mut watches[*i];
+                if watch.value.as_ref() != Some(buffer) {
+                    events.push(Ok(OffsetChanged {
+                        offset: watch.offset,
+                        old: watch.value.replace(buffer.clone()),
+                        new: buffer.clone(),
+                    }));
+                }
+            }
+        }
+        Err(error) => events.push(Err(error)),
+    }
+    for i in due.iter() {
+        let watch = &mut watches[*i].0;
+        watch.next = now + watch.interval;
+    }
+    events.into_iter().all(|event| sender.send(event).is_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::claims::OffsetClaims;
+    use crate::lvars::LVarType;
+
+    type Memory = Arc<Mutex<HashMap<u16, u8>>>;
+
+    // The offsets read by each processed session
+    type SessionLog = Arc<Mutex<Vec<Vec<u16>>>>;
+
+    struct FakeHandle {
+        memory: Memory,
+        log: SessionLog,
+    }
+
+    impl FakeHandle {
+        fn new(memory: &Memory) -> Self {
+            FakeHandle {
+                memory: memory.clone(),
+                log: SessionLog::default(),
+            }
+        }
+    }
+
+    struct FakeSession<'a> {
+        memory: &'a Memory,
+        log: &'a SessionLog,
+        reads: Vec<(u16, *mut u8, usize)>,
+    }
+
+    impl<'a> Handle<'a> for FakeHandle {
+        type Sess = FakeSession<'a>;
+
+        fn session(&'a mut self) -> FakeSession<'a> {
+            FakeSession {
+                memory: &self.memory,
+                log: &self.log,
+                reads: Vec::new(),
+            }
+        }
+    }
+
+    impl<'a> Session for FakeSession<'a> {
+        fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            self.reads.push((offset, dest, len));
+            Ok(len)
+        }
+
+        fn write_bytes(&mut self, _offset: u16, _src: *const u8, len: usize) -> io::Result<usize> {
+            Ok(len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            let memory = self.memory.lock().unwrap();
+            let offsets = self.reads.iter().map(|(offset, _, _)| *offset).collect();
+            self.log.lock().unwrap().push(offsets);
+            for (offset, dest, len) in self.reads {
+                for i in 0..len {
+                    let value = memory.get(&(offset + i as u16)).cloned();
+                    unsafe { *dest.add(i) = value.unwrap_or(0) };
+                }
+            }
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn should_deliver_changes() {
+        let memory = Memory::default();
+        memory.lock().unwrap().insert(0x0238, 10);
+        let handle = FakeHandle::new(&memory);
+        let (poller, changes) = PollerBuilder::new()
+            .watch(0x0238, 1, Duration::from_millis(1))
+            .start(handle)
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            changes.recv_timeout(timeout).unwrap().unwrap(),
+            OffsetChanged {
+                offset: 0x0238,
+                old: None,
+                new: vec![10]
+            }
+        );
+        memory.lock().unwrap().insert(0x0238, 11);
+        assert_eq!(
+            changes.recv_timeout(timeout).unwrap().unwrap(),
+            OffsetChanged {
+                offset: 0x0238,
+                old: Some(vec![10]),
+                new: vec![11]
+            }
+        );
+        poller.stop();
+        assert!(changes.recv().is_err());
+    }
+
+    #[test]
+    fn should_poll_each_offset_at_its_interval() {
+        let memory = Memory::default();
+        let handle = FakeHandle::new(&memory);
+        let (_poller, changes) = PollerBuilder::new()
+            .watch(0x0238, 1, Duration::from_millis(1))
+            .watch(0x3D00, 1, Duration::from_secs(3600))
+            .start(handle)
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        let first: Vec<u16> = (0..2)
+            .map(|_| changes.recv_timeout(timeout).unwrap().unwrap().offset)
+            .collect();
+        assert!(first.contains(&0x0238) && first.contains(&0x3D00));
+        memory.lock().unwrap().insert(0x3D00, 1);
+        memory.lock().unwrap().insert(0x0238, 1);
+        let change = changes.recv_timeout(timeout).unwrap().unwrap();
+        assert_eq!(change.offset, 0x0238);
+    }
+
+    #[test]
+    fn should_poll_fast_lane_in_its_own_session_first() {
+        let memory = Memory::default();
+        let handle = FakeHandle::new(&memory);
+        let log = handle.log.clone();
+        let (poller, changes) = PollerBuilder::new()
+            .watch(0x0238, 1, Duration::from_secs(3600))
+            .watch_priority(0x2E98, 1, Duration::from_secs(3600))
+            .watch(0x3D00, 1, Duration::from_secs(3600))
+            .start(handle)
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        let offsets: Vec<u16> = (0..3)
+            .map(|_| changes.recv_timeout(timeout).unwrap().unwrap().offset)
+            .collect();
+        assert_eq!(offsets, vec![0x2E98, 0x0238, 0x3D00]);
+        poller.stop();
+        assert_eq!(
+            log.lock().unwrap()[..2].to_vec(),
+            vec![vec![0x2E98], vec![0x0238, 0x3D00]]
+        );
+    }
+
+    #[test]
+    fn should_stop_when_cancelled() {
+        let memory = Memory::default();
+        let token = CancellationToken::new();
+        let (_poller, changes) = PollerBuilder::new()
+            .watch(0x0238, 1, Duration::from_secs(3600))
+            .with_cancellation(&token)
+            .start(FakeHandle::new(&memory))
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        assert!(changes.recv_timeout(timeout).unwrap().is_ok());
+        token.cancel();
+        assert_eq!(
+            changes.recv_timeout(timeout).unwrap_err(),
+            mpsc::RecvTimeoutError::Disconnected
+        );
+    }
+
+    #[test]
+    fn should_stop_when_changes_are_dropped() {
+        let memory = Memory::default();
+        let (_poller, changes) = PollerBuilder::new()
+            .watch(0x0238, 1, Duration::from_secs(3600))
+            .start(FakeHandle::new(&memory))
+            .unwrap();
+        assert!(changes
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .is_ok());
+        drop(changes);
+        // The worker thread drops its handle, and its reference to the memory, once it stops
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Arc::strong_count(&memory) > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(Arc::strong_count(&memory), 1);
+    }
+
+    #[test]
+    fn should_copy_lvars_before_reading_them() {
+        let memory = Memory::default();
+        let handle = FakeHandle::new(&memory);
+        let log = handle.log.clone();
+        let mut claims = OffsetClaims::new();
+        let mut lvars = LVarMap::new();
+        let offset = lvars
+            .map(&mut claims, "L:A32NX_APU_START", LVarType::U8)
+            .unwrap();
+        memory.lock().unwrap().insert(offset, 1);
+        let (poller, changes) = PollerBuilder::new()
+            .watch_lvars(&lvars, Duration::from_secs(3600))
+            .start(handle)
+            .unwrap();
+        let change = changes
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(lvars.name_at(change.offset), Some("A32NX_APU_START"));
+        assert_eq!(change.new, vec![1]);
+        poller.stop();
+        assert_eq!(
+            log.lock().unwrap()[..2].to_vec(),
+            vec![vec![], vec![offset]]
+        );
+    }
+
+    #[test]
+    fn should_poll_mapped_lvars_without_copying_them() {
+        let memory = Memory::default();
+        let handle = FakeHandle::new(&memory);
+        let log = handle.log.clone();
+        let mut claims = OffsetClaims::new();
+        let mut lvars = LVarMap::new();
+        let offset = lvars
+            .map(&mut claims, "L:A32NX_APU_START", LVarType::U8)
+            .unwrap();
+        memory.lock().unwrap().insert(offset, 1);
+        let (poller, changes) = PollerBuilder::new()
+            .watch_mapped_lvars(&lvars, Duration::from_secs(3600))
+            .start(handle)
+            .unwrap();
+        let change = changes
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(lvars.name_at(change.offset), Some("A32NX_APU_START"));
+        poller.stop();
+        assert_eq!(log.lock().unwrap().to_vec(), vec![vec![offset]]);
+    }
+}
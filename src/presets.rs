@@ -0,0 +1,196 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Canned aircraft states
+//! The simulators offer no reliable way to load a cockpit state from outside, so `StateApplier`
+//! drives the aircraft to a preset state by writing the offsets of its switches and levers.
+//! Aircraft that do not follow the default offsets can be given per-aircraft overrides.
+//!
+//! The presets set switches and levers only: they do not run an engine start sequence, so
+//! engines already running keep running and stopped ones are left stopped.
+
+use std::io;
+use std::mem::size_of;
+use std::slice;
+
+use super::offsets::{self, Offset};
+use super::strings;
+use super::{Handle, Session};
+
+/// A canned aircraft state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Everything off, mixture cut-off, parking brake set.
+    ColdAndDark,
+    /// Battery and navigation lights on for boarding, everything else off.
+    Turnaround,
+    /// Electrics, avionics, fuel pump and pitot heat on, mixture rich, taxi and takeoff lights
+    /// on, parking brake still set.
+    ReadyForTakeoff,
+}
+
+impl Preset {
+    /// The offsets written by default for this preset.
+    pub fn writes(self) -> Vec<(u16, Vec<u8>)> {
+        let (battery, avionics, lights, magnetos, mixture, pump, pitot) = match self {
+            Preset::ColdAndDark => (0u32, 0u32, 0u16, 0u16, 0u16, 0u8, 0u8),
+            Preset::Turnaround => (1, 0, 0x0001, 0, 0, 0, 0),
+            Preset::ReadyForTakeoff => (1, 1, 0x001F, 3, 16384, 1, 1),
+        };
+        vec![
+            write(offsets::PARKING_BRAKE, 32767),
+            write(offsets::BATTERY_MASTER, battery),
+            write(offsets::AVIONICS_MASTER, avionics),
+            write(offsets::LIGHTS, lights),
+            write(offsets::ENGINE1_MAGNETOS, magnetos),
+            write(offsets::ENGINE1_MIXTURE, mixture),
+            write(offsets::FUEL_PUMP, pump),
+            write(offsets::PITOT_HEAT, pitot),
+        ]
+    }
+}
+
+struct Override {
+    aircraft: String,
+    preset: Preset,
+    offset: u16,
+    data: Option<Vec<u8>>,
+}
+
+/// Applies presets, with per-aircraft overrides
+/// An override applies to the aircraft whose title contains the given text, ignoring case.
+#[derive(Default)]
+pub struct StateApplier {
+    overrides: Vec<Override>,
+}
+
+impl StateApplier {
+    pub fn new() -> Self {
+        StateApplier::default()
+    }
+
+    /// Write `value` into `offset` when applying `preset` to the given aircraft.
+    /// It replaces the default write to the same offset, if any.
+    pub fn with_override<T: Copy>(
+        mut self,
+        aircraft: &str,
+        preset: Preset,
+        offset: Offset<T>,
+        value: T,
+    ) -> Self {
+        let (offset, data) = write(offset, value);
+        self.overrides.push(Override {
+            aircraft: aircraft.to_lowercase(),
+            preset,
+            offset,
+            data: Some(data),
+        });
+        self
+    }
+
+    /// Do not write `offset` when applying `preset` to the given aircraft.
+    /// This is useful for aircraft whose systems ignore or misbehave with the default offsets.
+    pub fn with_skip(mut self, aircraft: &str, preset: Preset, offset: u16) -> Self {
+        self.overrides.push(Override {
+            aircraft: aircraft.to_lowercase(),
+            preset,
+            offset,
+            data: None,
+        });
+        self
+    }
+
+    /// The offsets written when applying `preset` to the aircraft with the given title.
+    pub fn writes(&self, aircraft: &str, preset: Preset) -> Vec<(u16, Vec<u8>)> {
+        let aircraft = aircraft.to_lowercase();
+        let mut writes = preset.writes();
+        let overrides = self
+            .overrides
+            .iter()
+            .filter(|o| o.preset == preset && aircraft.contains(&o.aircraft));
+        for o in overrides {
+            writes.retain(|(offset, _)| *offset != o.offset);
+            if let Some(data) = &o.data {
+                writes.push((o.offset, data.clone()));
+            }
+        }
+        writes
+    }
+
+    /// Drive the aircraft loaded in the simulator to `preset`.
+    /// It reads the aircraft title to pick the overrides, which it returns.
+    pub fn apply<H>(&self, handle: &mut H, preset: Preset) -> io::Result<String>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut title = [0u8; 256];
+        let mut session = handle.session();
+        session.read_offset(offsets::AIRCRAFT_NAME, &mut title)?;
+        session.process()?;
+        let aircraft = strings::decode(&title);
+
+        let writes = self.writes(&aircraft, preset);
+        let mut session = handle.session();
+        for (offset, data) in writes.iter() {
+            session.write_bytes(*offset, data.as_ptr(), data.len())?;
+        }
+        session.process()?;
+        Ok(aircraft)
+    }
+}
+
+fn write<T: Copy>(offset: Offset<T>, value: T) -> (u16, Vec<u8>) {
+    let bytes = unsafe { slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+    (offset.address(), bytes.to_vec())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_apply_default_presets() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x3D00, b"Cessna Skyhawk\0");
+        let aircraft = StateApplier::new()
+            .apply(&mut handle, Preset::ReadyForTakeoff)
+            .unwrap();
+        assert_eq!(aircraft, "Cessna Skyhawk");
+        assert_eq!(handle.value::<u16>(0x0BC8), 32767);
+        assert_eq!(handle.value::<u32>(0x281C), 1);
+        assert_eq!(handle.value::<u16>(0x0D0C), 0x001F);
+        assert_eq!(handle.value::<u16>(0x0890), 16384);
+
+        StateApplier::new()
+            .apply(&mut handle, Preset::ColdAndDark)
+            .unwrap();
+        assert_eq!(handle.value::<u32>(0x281C), 0);
+        assert_eq!(handle.value::<u16>(0x0D0C), 0);
+        assert_eq!(handle.transactions(), 4);
+    }
+
+    #[test]
+    fn should_apply_overrides_of_matching_aircraft() {
+        let applier = StateApplier::new()
+            .with_override("king air", Preset::ReadyForTakeoff, offsets::LIGHTS, 0x0013)
+            .with_skip("king air", Preset::ReadyForTakeoff, 0x0890)
+            .with_override("king air", Preset::Turnaround, offsets::LIGHTS, 0x0003);
+
+        let writes = applier.writes("Beechcraft King Air 350i", Preset::ReadyForTakeoff);
+        assert!(writes.contains(&(0x0D0C, vec![0x13, 0x00])));
+        assert!(!writes.contains(&(0x0D0C, vec![0x1F, 0x00])));
+        assert!(writes.iter().all(|(offset, _)| *offset != 0x0890));
+
+        assert_eq!(
+            applier.writes("Cessna Skyhawk", Preset::ReadyForTakeoff),
+            Preset::ReadyForTakeoff.writes()
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A facade over the radio offsets
+//! `Radios` reads all the radios at once and decodes their BCD frequencies, along with the COM
+//! channels in Hz, which hold the 8.33 kHz channels the BCD offsets cannot. The `write_*()`
+//! functions encode and write a single radio, and `swap()` exchanges the active and standby
+//! frequencies of a radio.
+
+use std::io;
+
+use super::offsets::{self, FsuipcStruct, Offset};
+use super::units::bcd::{self, AdfFrequency, Frequency};
+use super::units::com::ComFrequency;
+use super::Session;
+
+/// The state of the radios of the aircraft
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Radios {
+    com1: u16,
+    com1_standby: u16,
+    com2: u16,
+    com2_standby: u16,
+    nav1: u16,
+    nav1_standby: u16,
+    nav2: u16,
+    nav2_standby: u16,
+    adf1: u16,
+    adf1_extended: u16,
+    transponder: u16,
+    com1_hz: u32,
+    com1_standby_hz: u32,
+    com2_hz: u32,
+    com2_standby_hz: u32,
+}
+
+/// A radio with active and standby frequencies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Radio {
+    Com1,
+    Com2,
+    Nav1,
+    Nav2,
+}
+
+impl Radio {
+    /// The bit of the radio in the swap toggles.
+    fn swap_bit(self) -> u8 {
+        match self {
+            Radio::Com1 => 1 << 3,
+            Radio::Com2 => 1 << 2,
+            Radio::Nav1 => 1 << 1,
+            Radio::Nav2 => 1,
+        }
+    }
+}
+
+impl Radios {
+    /// The active COM1 frequency.
+    pub fn com1(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.com1)
+    }
+
+    /// The standby COM1 frequency.
+    pub fn com1_standby(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.com1_standby)
+    }
+
+    /// The active COM2 frequency.
+    pub fn com2(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.com2)
+    }
+
+    /// The standby COM2 frequency.
+    pub fn com2_standby(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.com2_standby)
+    }
+
+    /// The active NAV1 frequency.
+    pub fn nav1(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.nav1)
+    }
+
+    /// The standby NAV1 frequency.
+    pub fn nav1_standby(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.nav1_standby)
+    }
+
+    /// The active NAV2 frequency.
+    pub fn nav2(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.nav2)
+    }
+
+    /// The standby NAV2 frequency.
+    pub fn nav2_standby(&self) -> Option<Frequency> {
+        Frequency::from_bcd(self.nav2_standby)
+    }
+
+    /// The active COM1 channel, or `None` if FSUIPC does not give the COM frequencies in Hz.
+    pub fn com1_channel(&self) -> Option<ComFrequency> {
+        ComFrequency::from_hz(self.com1_hz)
+    }
+
+    /// The standby COM1 channel.
+    pub fn com1_standby_channel(&self) -> Option<ComFrequency> {
+        ComFrequency::from_hz(self.com1_standby_hz)
+    }
+
+    /// The active COM2 channel.
+    pub fn com2_channel(&self) -> Option<ComFrequency> {
+        ComFrequency::from_hz(self.com2_hz)
+    }
+
+    /// The standby COM2 channel.
+    pub fn com2_standby_channel(&self) -> Option<ComFrequency> {
+        ComFrequency::from_hz(self.com2_standby_hz)
+    }
+
+    /// The ADF1 frequency.
+    pub fn adf1(&self) -> Option<AdfFrequency> {
+        AdfFrequency::from_bcd(self.adf1, self.adf1_extended)
+    }
+
+    /// The transponder code (e.g. 7000).
+    pub fn transponder(&self) -> Option<u16> {
+        bcd::decode(self.transponder)
+    }
+}
+
+impl FsuipcStruct for Radios {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(offsets::COM1_FREQUENCY, &mut self.com1)?;
+        session.read_offset(offsets::COM1_STANDBY_FREQUENCY, &mut self.com1_standby)?;
+        session.read_offset(offsets::COM2_FREQUENCY, &mut self.com2)?;
+        session.read_offset(offsets::COM2_STANDBY_FREQUENCY, &mut self.com2_standby)?;
+        session.read_offset(offsets::NAV1_FREQUENCY, &mut self.nav1)?;
+        session.read_offset(offsets::NAV1_STANDBY_FREQUENCY, &mut self.nav1_standby)?;
+        session.read_offset(offsets::NAV2_FREQUENCY, &mut self.nav2)?;
+        session.read_offset(offsets::NAV2_STANDBY_FREQUENCY, &mut self.nav2_standby)?;
+        session.read_offset(offsets::ADF1_FREQUENCY, &mut self.adf1)?;
+        session.read_offset(offsets::ADF1_EXTENDED, &mut self.adf1_extended)?;
+        session.read_offset(offsets::TRANSPONDER_CODE, &mut self.transponder)?;
+        session.read_offset(offsets::COM1_FREQUENCY_HZ, &mut self.com1_hz)?;
+        session.read_offset(
+            offsets::COM1_STANDBY_FREQUENCY_HZ,
+            &mut self.com1_standby_hz,
+        )?;
+        session.read_offset(offsets::COM2_FREQUENCY_HZ, &mut self.com2_hz)?;
+        session.read_offset(
+            offsets::COM2_STANDBY_FREQUENCY_HZ,
+            &mut self.com2_standby_hz,
+        )?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, session: &mut S) -> io::Result<()> {
+        session.write_offset(offsets::COM1_FREQUENCY, &self.com1)?;
+        session.write_offset(offsets::COM1_STANDBY_FREQUENCY, &self.com1_standby)?;
+        session.write_offset(offsets::COM2_FREQUENCY, &self.com2)?;
+        session.write_offset(offsets::COM2_STANDBY_FREQUENCY, &self.com2_standby)?;
+        session.write_offset(offsets::NAV1_FREQUENCY, &self.nav1)?;
+        session.write_offset(offsets::NAV1_STANDBY_FREQUENCY, &self.nav1_standby)?;
+        session.write_offset(offsets::NAV2_FREQUENCY, &self.nav2)?;
+        session.write_offset(offsets::NAV2_STANDBY_FREQUENCY, &self.nav2_standby)?;
+        session.write_offset(offsets::ADF1_FREQUENCY, &self.adf1)?;
+        session.write_offset(offsets::ADF1_EXTENDED, &self.adf1_extended)?;
+        session.write_offset(offsets::TRANSPONDER_CODE, &self.transponder)?;
+        // The channels in Hz read as zero where FSUIPC does not give them.
+        let channels = [
+            (offsets::COM1_FREQUENCY_HZ, self.com1_hz),
+            (offsets::COM1_STANDBY_FREQUENCY_HZ, self.com1_standby_hz),
+            (offsets::COM2_FREQUENCY_HZ, self.com2_hz),
+            (offsets::COM2_STANDBY_FREQUENCY_HZ, self.com2_standby_hz),
+        ];
+        for (offset, hz) in channels.iter().filter(|(_, hz)| *hz != 0) {
+            session.write_offset(*offset, hz)?;
+        }
+        Ok(())
+    }
+}
+
+/// Request setting the active COM1 frequency.
+pub fn write_com1<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::COM1_FREQUENCY, frequency)
+}
+
+/// Request setting the standby COM1 frequency.
+pub fn write_com1_standby<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::COM1_STANDBY_FREQUENCY, frequency)
+}
+
+/// Request setting the active COM2 frequency.
+pub fn write_com2<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::COM2_FREQUENCY, frequency)
+}
+
+/// Request setting the standby COM2 frequency.
+pub fn write_com2_standby<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::COM2_STANDBY_FREQUENCY, frequency)
+}
+
+/// Request setting the active NAV1 frequency.
+pub fn write_nav1<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::NAV1_FREQUENCY, frequency)
+}
+
+/// Request setting the standby NAV1 frequency.
+pub fn write_nav1_standby<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::NAV1_STANDBY_FREQUENCY, frequency)
+}
+
+/// Request setting the active NAV2 frequency.
+pub fn write_nav2<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::NAV2_FREQUENCY, frequency)
+}
+
+/// Request setting the standby NAV2 frequency.
+pub fn write_nav2_standby<S: Session>(session: &mut S, frequency: Frequency) -> io::Result<usize> {
+    write_frequency(session, offsets::NAV2_STANDBY_FREQUENCY, frequency)
+}
+
+/// Request setting the active COM1 channel, which may be an 8.33 kHz one.
+pub fn write_com1_channel<S: Session>(session: &mut S, channel: ComFrequency) -> io::Result<usize> {
+    session.write_offset(offsets::COM1_FREQUENCY_HZ, &channel.hz())
+}
+
+/// Request setting the standby COM1 channel, which may be an 8.33 kHz one.
+pub fn write_com1_standby_channel<S: Session>(
+    session: &mut S,
+    channel: ComFrequency,
+) -> io::Result<usize> {
+    session.write_offset(offsets::COM1_STANDBY_FREQUENCY_HZ, &channel.hz())
+}
+
+/// Request setting the active COM2 channel, which may be an 8.33 kHz one.
+pub fn write_com2_channel<S: Session>(session: &mut S, channel: ComFrequency) -> io::Result<usize> {
+    session.write_offset(offsets::COM2_FREQUENCY_HZ, &channel.hz())
+}
+
+/// Request setting the standby COM2 channel, which may be an 8.33 kHz one.
+pub fn write_com2_standby_channel<S: Session>(
+    session: &mut S,
+    channel: ComFrequency,
+) -> io::Result<usize> {
+    session.write_offset(offsets::COM2_STANDBY_FREQUENCY_HZ, &channel.hz())
+}
+
+/// Request exchanging the active and standby frequencies of a radio.
+pub fn swap<S: Session>(session: &mut S, radio: Radio) -> io::Result<usize> {
+    session.write_offset(offsets::RADIO_SWAP, &radio.swap_bit())
+}
+
+/// Request setting the ADF1 frequency.
+pub fn write_adf1<S: Session>(session: &mut S, frequency: AdfFrequency) -> io::Result<usize> {
+    let (main, extended) = frequency.bcd();
+    Ok(session.write_offset(offsets::ADF1_FREQUENCY, &main)?
+        + session.write_offset(offsets::ADF1_EXTENDED, &extended)?)
+}
+
+/// Request setting the transponder code, which must have 4 octal digits.
+pub fn write_transponder<S: Session>(session: &mut S, code: u16) -> io::Result<usize> {
+    match bcd::encode(code) {
+        Some(raw) if raw & 0x8888 == 0 => session.write_offset(offsets::TRANSPONDER_CODE, &raw),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid transponder code {}", code),
+        )),
+    }
+}
+
+fn write_frequency<S: Session>(
+    session: &mut S,
+    offset: Offset<u16>,
+    frequency: Frequency,
+) -> io::Result<usize> {
+    session.write_offset(offset, &frequency.bcd())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_decode_radios() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x034E, &0x2345u16);
+        handle.seed_value(0x0350, &0x1030u16);
+        handle.seed_value(0x034C, &0x0379u16);
+        handle.seed_value(0x0354, &0x7000u16);
+        let mut radios = Radios::default();
+        {
+            let mut session = handle.session();
+            radios.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(radios.com1().unwrap().mhz(), 123.45);
+        assert_eq!(radios.nav1().unwrap().mhz(), 110.30);
+        assert_eq!(radios.adf1().unwrap().khz(), 379.0);
+        assert_eq!(radios.transponder(), Some(7000));
+    }
+
+    #[test]
+    fn should_write_radios() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_com1(&mut session, Frequency::from_mhz(121.5).unwrap()).unwrap();
+            write_adf1(&mut session, AdfFrequency::from_khz(1234.5).unwrap()).unwrap();
+            write_transponder(&mut session, 7700).unwrap();
+            assert!(write_transponder(&mut session, 7800).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u16>(0x034E), 0x2150);
+        assert_eq!(handle.value::<u16>(0x034C), 0x0234);
+        assert_eq!(handle.value::<u16>(0x0356), 0x0105);
+        assert_eq!(handle.value::<u16>(0x0354), 0x7700);
+    }
+
+    #[test]
+    fn should_handle_standby_and_833_channels() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x311E, &0x1050u16);
+        handle.seed_value(0x05C4, &132_815_000u32);
+        let mut radios = Radios::default();
+        {
+            let mut session = handle.session();
+            radios.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(radios.nav1_standby().unwrap().mhz(), 110.50);
+        assert!(radios.com1_channel().unwrap().is_833());
+        assert_eq!(radios.com2_channel(), None);
+
+        {
+            let mut session = handle.session();
+            radios.register_writes(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        assert!(handle.writes().iter().all(|(offset, _)| *offset != 0x05C8));
+
+        handle.clear_writes();
+        {
+            let mut session = handle.session();
+            let channel = ComFrequency::from_mhz(118.005).unwrap();
+            write_com2_standby_channel(&mut session, channel).unwrap();
+            swap(&mut session, Radio::Nav1).unwrap();
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u32>(0x05D0), 118_005_000);
+        assert_eq!(handle.value::<u8>(0x3123), 0b10);
+    }
+}
@@ -49,6 +49,11 @@ impl MutRawBytes {
     pub fn new(data: *mut u8, len: usize) -> Self {
         MutRawBytes { data, len }
     }
+
+    /// The number of bytes that can still be written.
+    pub fn remaining(&self) -> usize {
+        self.len
+    }
 }
 
 impl io::Write for MutRawBytes {
@@ -117,9 +122,9 @@ mod test {
         let src = [1u8, 2, 3, 4];
         let mut dest = [0, 0];
         let mut raw = RawBytes::new(&src as *const u8, 4);
-        raw.read(&mut dest).unwrap();
+        assert_eq!(raw.read(&mut dest).unwrap(), 2);
         assert_eq!(raw.consumed(), 2);
-        raw.read(&mut dest).unwrap();
+        assert_eq!(raw.read(&mut dest).unwrap(), 2);
         assert_eq!(raw.consumed(), 4);
     }
 
@@ -158,4 +163,13 @@ mod test {
         assert_eq!(dest[0], 1);
         assert_eq!(dest[1], 2);
     }
+
+    #[test]
+    fn should_count_remaining_for_mutrawbytes() {
+        let mut dest = vec![0u8; 6];
+        let mut raw = MutRawBytes::new(dest.as_mut_ptr(), 6);
+        assert_eq!(raw.remaining(), 6);
+        raw.write_all(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(raw.remaining(), 2);
+    }
 }
@@ -0,0 +1,375 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::cancel::CancellationToken;
+use super::error::FsuipcError;
+use super::{Handle, Session};
+
+/// The state of the connection of a `ReconnectingHandle`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// A connection was (re-)established.
+    Connected,
+    /// The connection was lost, or the last attempt to establish it failed.
+    Disconnected,
+}
+
+/// The delay between reconnection attempts
+/// The delay starts at `initial` after the connection is lost, and it is multiplied by
+/// `factor` after every failed attempt up to `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub factor: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            factor: 2.0,
+        }
+    }
+}
+
+struct Status {
+    lost: bool,
+    delay: Duration,
+    next_attempt: Option<Instant>,
+    callback: Option<Box<dyn FnMut(ConnectionState) + Send>>,
+}
+
+impl Status {
+    fn notify(&mut self, state: ConnectionState) {
+        if let Some(callback) = self.callback.as_mut() {
+            callback(state);
+        }
+    }
+}
+
+/// A handle that reconnects to FSUIPC when the connection is lost
+/// When the simulator restarts, the window of FSUIPC held by a handle is gone and every
+/// transaction fails. This handle wraps another one created by the `connect` function: when a
+/// transaction fails because the connection is lost, the wrapped handle is dropped and a new one
+/// is connected on the next session, waiting the configured backoff between attempts.
+///
+/// Sessions obtained while disconnected fail with `NotConnected` on their first request.
+pub struct ReconnectingHandle<H, F> {
+    inner: Option<H>,
+    connect: F,
+    backoff: Backoff,
+    status: Status,
+    cancel: Option<CancellationToken>,
+}
+
+/// A reconnecting handle using the user mode of FSUIPC
+#[cfg(windows)]
+pub type ReconnectingUserHandle =
+    ReconnectingHandle<super::user::UserHandle, fn() -> io::Result<super::user::UserHandle>>;
+
+#[cfg(windows)]
+impl ReconnectingUserHandle {
+    /// Create a handle that connects with `UserHandle::new()`.
+    pub fn user() -> Self {
+        ReconnectingHandle::new(super::user::UserHandle::new as fn() -> _)
+    }
+}
+
+impl<H, F> ReconnectingHandle<H, F>
+where
+    F: FnMut() -> io::Result<H>,
+{
+    /// Create a handle that connects with the given function.
+    /// The first connection is established on the first session.
+    pub fn new(connect: F) -> Self {
+        ReconnectingHandle {
+            inner: None,
+            connect,
+            backoff: Backoff::default(),
+            status: Status {
+                lost: false,
+                delay: Duration::from_secs(0),
+                next_attempt: None,
+                callback: None,
+            },
+            cancel: None,
+        }
+    }
+
+    /// Set the delay between reconnection attempts.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Call `callback` every time the connection is established or lost.
+    pub fn on_state_change<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(ConnectionState) + Send + 'static,
+    {
+        self.status.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Stop reconnecting once the given token is cancelled.
+    /// The connection is then closed, and sessions fail with `FsuipcError::Cancelled`.
+    pub fn with_cancellation(mut self, token: &CancellationToken) -> Self {
+        self.cancel = Some(token.clone());
+        self
+    }
+
+    /// Whether the handle is connected, as far as known from the last transaction.
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_some() && !self.status.lost
+    }
+
+    fn ensure_connected(&mut self) -> io::Result<()> {
+        if let Some(true) = self.cancel.as_ref().map(CancellationToken::is_cancelled) {
+            self.inner = None;
+            return Err(FsuipcError::Cancelled.into());
+        }
+        if self.status.lost {
+            self.inner = None;
+            self.status.lost = false;
+            self.status.delay = self.backoff.initial;
+            self.status.next_attempt = Some(Instant::now() + self.status.delay);
+            self.status.notify(ConnectionState::Disconnected);
+        }
+        if self.inner.is_some() {
+            return Ok(());
+        }
+        if let Some(next_attempt) = self.status.next_attempt {
+            if Instant::now() < next_attempt {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "not connected to FSUIPC: waiting to reconnect",
+                ));
+            }
+        }
+        match (self.connect)() {
+            Ok(inner) => {
+                self.inner = Some(inner);
+                self.status.next_attempt = None;
+                self.status.notify(ConnectionState::Connected);
+                Ok(())
+            }
+            Err(error) => {
+                let delay = self.status.delay.as_secs_f64() * self.backoff.factor;
+                self.status.delay = Duration::from_secs_f64(delay)
+                    .max(self.backoff.initial)
+                    .min(self.backoff.max);
+                self.status.next_attempt = Some(Instant::now() + self.status.delay);
+                self.status.notify(ConnectionState::Disconnected);
+                Err(error)
+            }
+        }
+    }
+}
+
+impl<'a, H, F> Handle<'a> for ReconnectingHandle<H, F>
+where
+    H: Handle<'a> + 'a,
+    F: FnMut() -> io::Result<H>,
+{
+    type Sess = ReconnectingSession<'a, H::Sess>;
+
+    fn session(&'a mut self) -> Self::Sess {
+        match self.ensure_connected() {
+            Ok(()) => {
+                let ReconnectingHandle { inner, status, .. } = self;
+                ReconnectingSession(SessionState::Connected {
+                    inner: inner.as_mut().unwrap().session(),
+                    status,
+                })
+            }
+            Err(error) => ReconnectingSession(SessionState::Disconnected(Some(error))),
+        }
+    }
+}
+
+/// A session of a `ReconnectingHandle`
+pub struct ReconnectingSession<'a, S>(SessionState<'a, S>);
+
+enum SessionState<'a, S> {
+    Connected { inner: S, status: &'a mut Status },
+    Disconnected(Option<io::Error>),
+}
+
+impl<'a, S: Session> ReconnectingSession<'a, S> {
+    fn disconnected(error: &mut Option<io::Error>) -> io::Error {
+        error.take().unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "not connected to FSUIPC")
+        })
+    }
+}
+
+impl<'a, S: Session> Session for ReconnectingSession<'a, S> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        match &mut self.0 {
+            SessionState::Connected { inner, .. } => inner.read_bytes(offset, dest, len),
+            SessionState::Disconnected(error) => Err(Self::disconnected(error)),
+        }
+    }
+
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        match &mut self.0 {
+            SessionState::Connected { inner, .. } => inner.write_bytes(offset, src, len),
+            SessionState::Disconnected(error) => Err(Self::disconnected(error)),
+        }
+    }
+
+    fn process(self) -> io::Result<usize> {
+        match self.0 {
+            SessionState::Connected { inner, status } => {
+                let result = inner.process();
+                if let Err(ref error) = result {
+                    status.lost = is_connection_lost(error);
+                }
+                result
+            }
+            SessionState::Disconnected(mut error) => Err(Self::disconnected(&mut error)),
+        }
+    }
+}
+
+/// Whether the error means the connection to FSUIPC is lost.
+/// A window that is gone makes FSUIPC reject the transactions, so rejections count as such.
+fn is_connection_lost(error: &io::Error) -> bool {
+    match FsuipcError::from_io(error) {
+        Some(FsuipcError::BufferOverflow { .. })
+        | Some(FsuipcError::AccessDenied(_))
+        | Some(FsuipcError::Cancelled) => false,
+        Some(_) => true,
+        None => matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::TimedOut
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::fault::{Fault, FaultInjector, FaultyHandle};
+    use crate::mock::MockHandle;
+
+    fn no_backoff() -> Backoff {
+        Backoff {
+            initial: Duration::from_secs(0),
+            max: Duration::from_secs(0),
+            factor: 2.0,
+        }
+    }
+
+    fn process_read<'a, H: Handle<'a>>(handle: &'a mut H) -> io::Result<u8> {
+        let mut value = 0u8;
+        let mut session = handle.session();
+        session.read(0x0238, &mut value)?;
+        session.process()?;
+        Ok(value)
+    }
+
+    #[test]
+    fn should_reconnect_after_connection_lost() {
+        let mut connections = 0;
+        let states = Arc::new(Mutex::new(Vec::new()));
+        let recorded = states.clone();
+        let mut handle = ReconnectingHandle::new(move || {
+            connections += 1;
+            let mut mock = MockHandle::new();
+            mock.seed_value(0x0238, &(connections as u8));
+            let injector = if connections == 1 {
+                FaultInjector::new(0).on_transaction(1, Fault::Disconnect)
+            } else {
+                FaultInjector::new(0)
+            };
+            Ok(FaultyHandle::new(mock, injector))
+        })
+        .with_backoff(no_backoff())
+        .on_state_change(move |state| recorded.lock().unwrap().push(state));
+
+        assert_eq!(process_read(&mut handle).unwrap(), 1);
+        assert!(process_read(&mut handle).is_err());
+        assert!(!handle.is_connected());
+        assert_eq!(process_read(&mut handle).unwrap(), 2);
+        assert_eq!(
+            *states.lock().unwrap(),
+            vec![
+                ConnectionState::Connected,
+                ConnectionState::Disconnected,
+                ConnectionState::Connected
+            ]
+        );
+    }
+
+    #[test]
+    fn should_wait_backoff_between_attempts() {
+        let mut attempts = 0;
+        let mut handle = ReconnectingHandle::new(|| -> io::Result<MockHandle> {
+            attempts += 1;
+            Err(FsuipcError::SimNotRunning("not yet".to_string()).into())
+        })
+        .with_backoff(Backoff {
+            initial: Duration::from_secs(3600),
+            max: Duration::from_secs(3600),
+            factor: 2.0,
+        });
+        let error = process_read(&mut handle).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+        let error = process_read(&mut handle).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotConnected);
+        drop(handle);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn should_stop_reconnecting_when_cancelled() {
+        let token = CancellationToken::new();
+        let mut attempts = 0;
+        let mut handle = ReconnectingHandle::new(|| {
+            attempts += 1;
+            Ok(MockHandle::new())
+        })
+        .with_backoff(no_backoff())
+        .with_cancellation(&token);
+        assert!(process_read(&mut handle).is_ok());
+        token.cancel();
+        let error = process_read(&mut handle).err().unwrap();
+        assert!(matches!(
+            FsuipcError::from_io(&error),
+            Some(FsuipcError::Cancelled)
+        ));
+        assert!(!handle.is_connected());
+        drop(handle);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn should_keep_connection_on_other_errors() {
+        let overflow = || FsuipcError::BufferOverflow {
+            needed: 2,
+            available: 1,
+        };
+        assert!(!is_connection_lost(&overflow().into()));
+        assert!(is_connection_lost(
+            &FsuipcError::ProtocolRejection(0).into()
+        ));
+        assert!(!is_connection_lost(&io::Error::other("other")));
+    }
+}
@@ -0,0 +1,220 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Merge of recordings made on different machines
+//! The time of each recording counts from its own start, so recordings made on the master PC
+//! and on WideFS clients can't be compared directly. Recording the `zulu_channels()` gives them
+//! a common clock: `merge()` aligns the recordings on the simulator zulu time.
+
+use std::io;
+use std::time::Duration;
+
+use super::{Channel, Encoding, Recording, Sample};
+use crate::offsets;
+
+/// The name of the channel of the zulu hour.
+pub const ZULU_HOUR: &str = "zulu_hour";
+/// The name of the channel of the zulu minute.
+pub const ZULU_MINUTE: &str = "zulu_minute";
+/// The name of the channel of the seconds, which are the same in local and zulu time.
+pub const ZULU_SECOND: &str = "zulu_second";
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// The channels to record for the recording to be merged with others.
+pub fn zulu_channels() -> Vec<Channel> {
+    vec![
+        Channel::new(ZULU_HOUR, offsets::ZULU_HOUR.address(), Encoding::U8),
+        Channel::new(ZULU_MINUTE, offsets::ZULU_MINUTE.address(), Encoding::U8),
+        Channel::new(
+            ZULU_SECOND,
+            offsets::SIM_LOCAL_SECOND.address(),
+            Encoding::U8,
+        ),
+    ]
+}
+
+/// Merge recordings of different sources into one
+/// The columns of the merged recording are named `<source>.<column>`, and its time counts from
+/// the earliest sample of all the sources. There is a sample for every sample of the sources,
+/// with the last known values of the other sources (NaN before their first sample).
+///
+/// Zulu time has a resolution of one second, so the sources are aligned to the sampling period
+/// when they cover a change of second, and to one second otherwise.
+pub fn merge(sources: &[(&str, &Recording)]) -> io::Result<Recording> {
+    let mut clocks = Vec::with_capacity(sources.len());
+    for (name, recording) in sources.iter() {
+        let clock = zulu_clock(recording).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot merge recording {}: {}", name, e))
+        })?;
+        clocks.push(clock);
+    }
+    // Sources starting on different sides of midnight are on the same day.
+    if let Some(&reference) = clocks.first() {
+        for clock in clocks.iter_mut() {
+            if *clock - reference > SECONDS_PER_DAY / 2.0 {
+                *clock -= SECONDS_PER_DAY;
+            } else if reference - *clock > SECONDS_PER_DAY / 2.0 {
+                *clock += SECONDS_PER_DAY;
+            }
+        }
+    }
+
+    let mut events = Vec::new();
+    for (source, ((_, recording), clock)) in sources.iter().zip(clocks.iter()).enumerate() {
+        for sample in recording.samples.iter() {
+            events.push((sample.time.as_secs_f64() + clock, source, sample));
+        }
+    }
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut columns = Vec::new();
+    let mut starts = Vec::with_capacity(sources.len());
+    for (name, recording) in sources.iter() {
+        starts.push(columns.len());
+        columns.extend(recording.columns.iter().map(|c| format!("{}.{}", name, c)));
+    }
+    let start = events.first().map(|e| e.0).unwrap_or(0.0);
+    let mut values = vec![f64::NAN; columns.len()];
+    let mut samples: Vec<Sample> = Vec::with_capacity(events.len());
+    for (time, source, sample) in events {
+        let first = starts[source];
+        values[first..first + sample.values.len()].copy_from_slice(&sample.values);
+        let time = Duration::from_secs_f64(time - start);
+        match samples.last_mut() {
+            Some(last) if last.time == time => last.values.copy_from_slice(&values),
+            _ => samples.push(Sample {
+                time,
+                values: values.clone(),
+            }),
+        }
+    }
+    Ok(Recording { columns, samples })
+}
+
+/// The zulu time of the start of the recording, in seconds of the day.
+fn zulu_clock(recording: &Recording) -> io::Result<f64> {
+    let column = |name| {
+        recording.column(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("missing {} column", name),
+            )
+        })
+    };
+    let (hour, minute, second) = (
+        column(ZULU_HOUR)?,
+        column(ZULU_MINUTE)?,
+        column(ZULU_SECOND)?,
+    );
+    let zulu = |sample: &Sample| {
+        sample.values[hour] * 3600.0 + sample.values[minute] * 60.0 + sample.values[second]
+    };
+    let first = recording
+        .samples
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "empty recording"))?;
+    if let Some(sample) = recording.samples.iter().find(|s| !zulu(s).is_finite()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid zulu time at {:?}", sample.time),
+        ));
+    }
+    // The first change of second tells the clock to the sampling period.
+    for pair in recording.samples.windows(2) {
+        if zulu(&pair[1]) != zulu(&pair[0]) {
+            return Ok(zulu(&pair[1]) - pair[1].time.as_secs_f64());
+        }
+    }
+    Ok(zulu(first) - first.time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn recording(column: &str, start: Duration, zulu_start: f64, values: &[f64]) -> Recording {
+        let samples = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let elapsed = i as f64 * 0.5;
+                let zulu = (zulu_start + elapsed).floor();
+                Sample {
+                    time: start + Duration::from_secs_f64(elapsed),
+                    values: vec![
+                        (zulu / 3600.0).floor(),
+                        (zulu % 3600.0 / 60.0).floor(),
+                        zulu % 60.0,
+                        *value,
+                    ],
+                }
+            })
+            .collect();
+        Recording {
+            columns: vec![
+                ZULU_HOUR.to_string(),
+                ZULU_MINUTE.to_string(),
+                ZULU_SECOND.to_string(),
+                column.to_string(),
+            ],
+            samples,
+        }
+    }
+
+    #[test]
+    fn should_align_recordings_on_zulu_time() {
+        let master = recording(
+            "ias",
+            Duration::from_secs(100),
+            43200.0,
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        let client = recording("vs", Duration::from_secs(5), 43201.0, &[10.0, 20.0]);
+        let merged = merge(&[("master", &master), ("client", &client)]).unwrap();
+        assert_eq!(merged.column("master.ias"), Some(3));
+        assert_eq!(merged.column("client.vs"), Some(7));
+        let rows: Vec<(f64, f64, f64)> = merged
+            .samples
+            .iter()
+            .map(|s| (s.time.as_secs_f64(), s.values[3], s.values[7]))
+            .collect();
+        assert_eq!((rows[0].0, rows[0].1), (0.0, 1.0));
+        assert_eq!((rows[1].0, rows[1].1), (0.5, 2.0));
+        assert!(rows[0].2.is_nan() && rows[1].2.is_nan());
+        assert_eq!(&rows[2..], &[(1.0, 3.0, 10.0), (1.5, 4.0, 20.0)]);
+    }
+
+    #[test]
+    fn should_align_recordings_across_midnight() {
+        let before = recording("a", Duration::from_secs(0), 86399.0, &[1.0, 2.0, 3.0]);
+        let after = recording("b", Duration::from_secs(0), 0.0, &[4.0]);
+        let merged = merge(&[("before", &before), ("after", &after)]).unwrap();
+        let last = merged.samples.last().unwrap();
+        assert_eq!(last.time, Duration::from_secs(1));
+        assert_eq!((last.values[3], last.values[7]), (3.0, 4.0));
+    }
+
+    #[test]
+    fn should_fail_without_zulu_columns() {
+        let recording = Recording {
+            columns: vec!["a".to_string()],
+            samples: vec![],
+        };
+        assert!(merge(&[("master", &recording)]).is_err());
+    }
+
+    #[test]
+    fn should_fail_with_invalid_zulu_time() {
+        let mut recording = recording("a", Duration::from_secs(0), 0.0, &[0.0, 0.0]);
+        recording.samples[1].values[1] = f64::NAN;
+        let error = merge(&[("master", &recording)]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
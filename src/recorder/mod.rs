@@ -0,0 +1,400 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Recording of offsets
+//! A `RingRecorder` keeps the samples of the last few minutes in memory, like a dashcam, and
+//! saves them to disk on demand (e.g. after a hard landing or when the user presses a key).
+//! The recordings are CSV files with a `time` column in seconds followed by a column per
+//! channel. Saved recordings are loaded back as `Recording`s.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::Session;
+
+pub mod merge;
+pub mod query;
+
+/// How the value of a channel is encoded in its offset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    I64,
+    F64,
+}
+
+impl Encoding {
+    /// The number of bytes of the encoded values.
+    pub fn size(self) -> usize {
+        match self {
+            Encoding::U8 | Encoding::I8 => 1,
+            Encoding::U16 | Encoding::I16 => 2,
+            Encoding::U32 | Encoding::I32 => 4,
+            Encoding::I64 | Encoding::F64 => 8,
+        }
+    }
+
+    fn decode(self, raw: &[u8; 8]) -> f64 {
+        let mut bytes = [0u8; 8];
+        bytes[..self.size()].copy_from_slice(&raw[..self.size()]);
+        match self {
+            Encoding::U8 => bytes[0] as f64,
+            Encoding::I8 => bytes[0] as i8 as f64,
+            Encoding::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Encoding::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Encoding::U32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Encoding::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            Encoding::I64 => i64::from_le_bytes(bytes) as f64,
+            Encoding::F64 => f64::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// An offset recorded as a column of the recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    name: String,
+    offset: u16,
+    encoding: Encoding,
+    scale: f64,
+}
+
+impl Channel {
+    pub fn new(name: &str, offset: u16, encoding: Encoding) -> Self {
+        Channel {
+            name: name.to_string(),
+            offset,
+            encoding,
+            scale: 1.0,
+        }
+    }
+
+    /// Multiply the raw values by `scale` (e.g. 1.0 / 128.0 for airspeeds in knots).
+    pub fn scaled(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The name of the channel, used as column header.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A sample of all the channels of a recorder
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// The time of the sample, from the start of the recording.
+    pub time: Duration,
+    /// The values of the channels, in the order they were given to the recorder.
+    pub values: Vec<f64>,
+}
+
+/// A recorder that keeps the samples of a sliding time window
+#[derive(Clone, Debug)]
+pub struct RingRecorder {
+    channels: Vec<Channel>,
+    window: Duration,
+    samples: VecDeque<Sample>,
+    raw: Vec<[u8; 8]>,
+}
+
+impl RingRecorder {
+    /// Create a recorder of the given channels keeping the samples of the last `window`.
+    pub fn new(channels: Vec<Channel>, window: Duration) -> Self {
+        let raw = vec![[0; 8]; channels.len()];
+        RingRecorder {
+            channels,
+            window,
+            samples: VecDeque::new(),
+            raw,
+        }
+    }
+
+    /// The recorded channels.
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    /// Request the reads of all the channels.
+    /// Once the session is processed, `capture()` records them as a new sample.
+    pub fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        for (channel, raw) in self.channels.iter().zip(self.raw.iter_mut()) {
+            session.read_bytes(channel.offset, raw.as_mut_ptr(), channel.encoding.size())?;
+        }
+        Ok(())
+    }
+
+    /// Record the values read by the last processed session as a sample taken at `time`.
+    pub fn capture(&mut self, time: Duration) {
+        let values = self
+            .channels
+            .iter()
+            .zip(self.raw.iter())
+            .map(|(channel, raw)| channel.encoding.decode(raw) * channel.scale)
+            .collect();
+        self.push(Sample { time, values });
+    }
+
+    /// Record a sample obtained by other means.
+    /// It fails if the sample has not one value per channel.
+    pub fn record(&mut self, sample: Sample) -> io::Result<()> {
+        if sample.values.len() != self.channels.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "sample with {} values for {} channels",
+                    sample.values.len(),
+                    self.channels.len()
+                ),
+            ));
+        }
+        self.push(sample);
+        Ok(())
+    }
+
+    /// Iterate over the samples in the window, from the oldest one.
+    pub fn samples(&self) -> impl Iterator<Item = &Sample> {
+        self.samples.iter()
+    }
+
+    /// The number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Whether there are no samples in the window.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Take the samples in the window as a recording.
+    pub fn recording(&self) -> Recording {
+        Recording {
+            columns: self.channels.iter().map(|c| c.name.clone()).collect(),
+            samples: self.samples.iter().cloned().collect(),
+        }
+    }
+
+    /// Write the samples in the window as CSV.
+    pub fn write_csv<W: Write>(&self, output: W) -> io::Result<()> {
+        let names: Vec<&str> = self.channels.iter().map(Channel::name).collect();
+        write_csv(&names, self.samples.iter(), output)
+    }
+
+    /// Save the samples in the window to a CSV file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_csv(BufWriter::new(File::create(path)?))
+    }
+
+    fn push(&mut self, sample: Sample) {
+        let time = sample.time;
+        self.samples.push_back(sample);
+        while let Some(oldest) = self.samples.front() {
+            if time.saturating_sub(oldest.time) > self.window {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// A recording, as saved to disk
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    /// The names of the channels.
+    pub columns: Vec<String>,
+    /// The samples, from the oldest one.
+    pub samples: Vec<Sample>,
+}
+
+impl Recording {
+    /// Read a recording written as CSV by `write_csv()`.
+    pub fn read_csv<R: BufRead>(input: R) -> io::Result<Self> {
+        let mut lines = input.lines();
+        let header = match lines.next() {
+            Some(header) => header?,
+            None => return Err(invalid_data("missing CSV header".to_string())),
+        };
+        let mut names = header.trim().split(',');
+        if names.next() != Some("time") {
+            return Err(invalid_data(format!("invalid CSV header: {}", header)));
+        }
+        let columns: Vec<String> = names.map(str::to_string).collect();
+        let mut samples = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = line
+                .trim()
+                .split(',')
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|e| invalid_data(format!("line {}: {}", number + 2, e)))?;
+            // Times beyond the range of a `Duration` would make it panic
+            if fields.len() != columns.len() + 1 || !(0.0..u64::MAX as f64).contains(&fields[0]) {
+                return Err(invalid_data(format!("line {}: invalid row", number + 2)));
+            }
+            samples.push(Sample {
+                time: Duration::from_secs_f64(fields[0]),
+                values: fields[1..].to_vec(),
+            });
+        }
+        Ok(Recording { columns, samples })
+    }
+
+    /// Load a recording from a CSV file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_csv(BufReader::new(File::open(path)?))
+    }
+
+    /// Write the recording as CSV.
+    pub fn write_csv<W: Write>(&self, output: W) -> io::Result<()> {
+        let names: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+        write_csv(&names, self.samples.iter(), output)
+    }
+
+    /// Save the recording to a CSV file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_csv(BufWriter::new(File::create(path)?))
+    }
+
+    /// The index of the values of the given channel in the samples.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column == name)
+    }
+}
+
+fn write_csv<'a, W, I>(names: &[&str], samples: I, mut output: W) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = &'a Sample>,
+{
+    writeln!(output, "time,{}", names.join(","))?;
+    for sample in samples {
+        write!(output, "{:.3}", sample.time.as_secs_f64())?;
+        for value in sample.values.iter() {
+            write!(output, ",{}", value)?;
+        }
+        writeln!(output)?;
+    }
+    output.flush()
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn should_capture_channels_from_session() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x02BC, &(140u32 * 128));
+        handle.seed_value(0x02C8, &-1280i32);
+        let mut recorder = RingRecorder::new(
+            vec![
+                Channel::new("ias", 0x02BC, Encoding::U32).scaled(1.0 / 128.0),
+                Channel::new("vs", 0x02C8, Encoding::I32).scaled(1.0 / 256.0),
+            ],
+            secs(60),
+        );
+        {
+            let mut session = handle.session();
+            recorder.register_reads(&mut session).unwrap();
+            session.process().unwrap();
+        }
+        recorder.capture(secs(1));
+        let sample = recorder.samples().next().unwrap();
+        assert_eq!(sample.values, vec![140.0, -5.0]);
+    }
+
+    #[test]
+    fn should_keep_only_the_window() {
+        let mut recorder = RingRecorder::new(vec![Channel::new("a", 0, Encoding::U8)], secs(10));
+        for t in 0..30 {
+            recorder
+                .record(Sample {
+                    time: secs(t),
+                    values: vec![t as f64],
+                })
+                .unwrap();
+        }
+        assert_eq!(recorder.len(), 11);
+        assert_eq!(recorder.samples().next().unwrap().time, secs(19));
+        assert!(recorder
+            .record(Sample {
+                time: secs(31),
+                values: vec![],
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn should_write_csv() {
+        let mut recorder = RingRecorder::new(
+            vec![
+                Channel::new("a", 0, Encoding::U8),
+                Channel::new("b", 1, Encoding::U8),
+            ],
+            secs(10),
+        );
+        recorder
+            .record(Sample {
+                time: Duration::from_millis(1500),
+                values: vec![1.0, 2.5],
+            })
+            .unwrap();
+        let mut csv = Vec::new();
+        recorder.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "time,a,b\n1.500,1,2.5\n");
+    }
+
+    #[test]
+    fn should_read_written_csv() {
+        let mut recorder = RingRecorder::new(vec![Channel::new("a", 0, Encoding::U8)], secs(10));
+        for t in 0..3 {
+            recorder
+                .record(Sample {
+                    time: Duration::from_millis(t * 250),
+                    values: vec![t as f64 / 2.0],
+                })
+                .unwrap();
+        }
+        let mut csv = Vec::new();
+        recorder.write_csv(&mut csv).unwrap();
+        let recording = Recording::read_csv(&csv[..]).unwrap();
+        assert_eq!(recording, recorder.recording());
+        assert_eq!(recording.column("a"), Some(0));
+        assert!(Recording::read_csv(&b"time,a\n1.0,2,3\n"[..]).is_err());
+        assert!(Recording::read_csv(&b"a,b\n"[..]).is_err());
+        let error = Recording::read_csv(&b"time,a\n1e30,1\n"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(Recording::read_csv(&b"time,a\nNaN,1\n"[..]).is_err());
+    }
+}
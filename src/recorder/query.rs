@@ -0,0 +1,153 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Queries over recordings
+//! Enough to display or analyse historical data without a dataframe library: the queries
+//! return new recordings, so they can be chained and saved.
+
+use std::io;
+use std::ops::Range;
+use std::time::Duration;
+
+use super::{Recording, Sample};
+
+impl Recording {
+    /// Keep only the given columns, in the given order.
+    /// It fails if any of the columns is not in the recording.
+    pub fn select(&self, columns: &[&str]) -> io::Result<Recording> {
+        let indexes = columns
+            .iter()
+            .map(|name| {
+                self.column(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("no column {} in recording", name),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<usize>>>()?;
+        Ok(Recording {
+            columns: columns.iter().map(|name| name.to_string()).collect(),
+            samples: self
+                .samples
+                .iter()
+                .map(|sample| Sample {
+                    time: sample.time,
+                    values: indexes.iter().map(|&i| sample.values[i]).collect(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Keep only the samples taken within the given time range.
+    pub fn between(&self, range: Range<Duration>) -> Recording {
+        Recording {
+            columns: self.columns.clone(),
+            samples: self
+                .samples
+                .iter()
+                .filter(|sample| range.contains(&sample.time))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Resample the recording every `period` from its first sample.
+    /// The values are interpolated linearly between the samples around each new one.
+    pub fn resample(&self, period: Duration) -> io::Result<Recording> {
+        if period == Duration::from_secs(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot resample with a zero period",
+            ));
+        }
+        let mut samples = Vec::new();
+        if let (Some(first), Some(last)) = (self.samples.first(), self.samples.last()) {
+            let mut next = 0;
+            let mut time = first.time;
+            while time <= last.time {
+                while matches!(self.samples.get(next + 1), Some(s) if s.time <= time) {
+                    next += 1;
+                }
+                samples.push(Sample {
+                    time,
+                    values: self.interpolate(next, time),
+                });
+                time += period;
+            }
+        }
+        Ok(Recording {
+            columns: self.columns.clone(),
+            samples,
+        })
+    }
+
+    /// The values at `time`, between the sample at `index` and the next one.
+    fn interpolate(&self, index: usize, time: Duration) -> Vec<f64> {
+        let before = &self.samples[index];
+        let after = match self.samples.get(index + 1) {
+            Some(after) if after.time > before.time => after,
+            _ => return before.values.clone(),
+        };
+        let fraction =
+            (time - before.time).as_secs_f64() / (after.time - before.time).as_secs_f64();
+        before
+            .values
+            .iter()
+            .zip(after.values.iter())
+            .map(|(a, b)| a + (b - a) * fraction)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn recording() -> Recording {
+        Recording {
+            columns: vec!["a".to_string(), "b".to_string()],
+            samples: (0..5)
+                .map(|t| Sample {
+                    time: Duration::from_secs(t),
+                    values: vec![t as f64, t as f64 * 10.0],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn should_select_columns() {
+        let selected = recording().select(&["b"]).unwrap();
+        assert_eq!(selected.columns, vec!["b"]);
+        assert_eq!(selected.samples[2].values, vec![20.0]);
+        assert!(recording().select(&["c"]).is_err());
+    }
+
+    #[test]
+    fn should_filter_time_range() {
+        let range = recording().between(Duration::from_secs(1)..Duration::from_secs(3));
+        let times: Vec<u64> = range.samples.iter().map(|s| s.time.as_secs()).collect();
+        assert_eq!(times, vec![1, 2]);
+    }
+
+    #[test]
+    fn should_resample_interpolating() {
+        let resampled = recording()
+            .between(Duration::from_secs(1)..Duration::from_secs(4))
+            .resample(Duration::from_millis(750))
+            .unwrap();
+        let values: Vec<Vec<f64>> = resampled.samples.into_iter().map(|s| s.values).collect();
+        assert_eq!(
+            values,
+            vec![vec![1.0, 10.0], vec![1.75, 17.5], vec![2.5, 25.0]]
+        );
+        assert!(recording().resample(Duration::from_secs(0)).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The facilities available with an unregistered FSUIPC
+//! An unregistered FSUIPC serves the offsets and sends the controls of the simulator, but
+//! silently ignores Lua plugins, macros and its own added controls. `Capabilities` reads the
+//! registration status from offset 0x330A, so applications can tell their users what will
+//! work, and a `RestrictedSession` fails the requests of the facilities not available with
+//! `FsuipcError::Unregistered` rather than sending them to be ignored.
+
+use std::fmt;
+use std::io;
+
+use super::controls::EventId;
+use super::error::FsuipcError;
+use super::offsets;
+use super::{Handle, Session};
+
+/// The bit of the status offset set when FSUIPC is registered.
+const REGISTERED: u16 = 0x0001;
+
+/// The first control of the simulator: FSUIPC numbers its added controls below it.
+const FIRST_SIM_CONTROL: u32 = 0x10000;
+
+/// A facility of FSUIPC used by the crate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Facility {
+    /// Reading and writing offsets.
+    Offsets,
+    /// Sending the controls of the simulator.
+    Controls,
+    /// Sending the controls added by FSUIPC.
+    FsuipcControls,
+    /// Running Lua plugins.
+    LuaPlugins,
+    /// Running macros.
+    Macros,
+}
+
+impl Facility {
+    /// All the facilities, in the order of `Capabilities::matrix()`.
+    pub const ALL: [Facility; 5] = [
+        Facility::Offsets,
+        Facility::Controls,
+        Facility::FsuipcControls,
+        Facility::LuaPlugins,
+        Facility::Macros,
+    ];
+
+    /// Whether the facility needs a registered FSUIPC.
+    pub fn requires_registration(self) -> bool {
+        match self {
+            Facility::Offsets | Facility::Controls => false,
+            Facility::FsuipcControls | Facility::LuaPlugins | Facility::Macros => true,
+        }
+    }
+
+    /// The facility a control is sent through.
+    pub fn of_control(event: EventId) -> Self {
+        if event.number() < FIRST_SIM_CONTROL {
+            Facility::FsuipcControls
+        } else {
+            Facility::Controls
+        }
+    }
+}
+
+impl fmt::Display for Facility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let facility = match self {
+            Facility::Offsets => "offsets",
+            Facility::Controls => "simulator controls",
+            Facility::FsuipcControls => "FSUIPC controls",
+            Facility::LuaPlugins => "Lua plugins",
+            Facility::Macros => "macros",
+        };
+        write!(f, "{}", facility)
+    }
+}
+
+/// The version and registration status of the FSUIPC connected to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    version: u32,
+    status: u16,
+}
+
+impl Capabilities {
+    /// Read the capabilities of the FSUIPC the handle is connected to.
+    pub fn read<H>(handle: &mut H) -> io::Result<Self>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut capabilities = Capabilities::default();
+        let mut session = handle.session();
+        session.read_offset(offsets::FSUIPC_VERSION, &mut capabilities.version)?;
+        session.read_offset(offsets::FSUIPC_STATUS, &mut capabilities.status)?;
+        session.process()?;
+        Ok(capabilities)
+    }
+
+    /// The capabilities of a registered FSUIPC of the given version, e.g. for tests.
+    pub fn registered(version: u32) -> Self {
+        Capabilities {
+            version,
+            status: REGISTERED,
+        }
+    }
+
+    /// The capabilities of an unregistered FSUIPC of the given version.
+    pub fn unregistered(version: u32) -> Self {
+        Capabilities { version, status: 0 }
+    }
+
+    /// The FSUIPC version, as 0xVVVVBBBB with the version in BCD.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.status & REGISTERED != 0
+    }
+
+    /// Whether the given facility is available.
+    pub fn supports(&self, facility: Facility) -> bool {
+        self.is_registered() || !facility.requires_registration()
+    }
+
+    /// Check the given facility is available.
+    /// It fails with `FsuipcError::Unregistered` if it needs a registered FSUIPC.
+    pub fn check(&self, facility: Facility) -> io::Result<()> {
+        if self.supports(facility) {
+            Ok(())
+        } else {
+            Err(FsuipcError::Unregistered(facility.to_string()).into())
+        }
+    }
+
+    /// Whether each facility is available, to present to the users.
+    pub fn matrix(&self) -> Vec<(Facility, bool)> {
+        Facility::ALL
+            .iter()
+            .map(|&facility| (facility, self.supports(facility)))
+            .collect()
+    }
+}
+
+/// A session that refuses the requests FSUIPC would ignore
+/// Running Lua plugins or macros and sending FSUIPC controls fail with
+/// `FsuipcError::Unregistered` when the capabilities do not include them. Other requests are
+/// passed to the inner session.
+pub struct RestrictedSession<S> {
+    inner: S,
+    capabilities: Capabilities,
+}
+
+impl<S: Session> RestrictedSession<S> {
+    pub fn new(inner: S, capabilities: Capabilities) -> Self {
+        RestrictedSession {
+            inner,
+            capabilities,
+        }
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+}
+
+impl<S: Session> Session for RestrictedSession<S> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.inner.read_bytes(offset, dest, len)
+    }
+
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        self.inner.write_bytes(offset, src, len)
+    }
+
+    fn process(self) -> io::Result<usize> {
+        self.inner.process()
+    }
+
+    fn send_control(&mut self, event: EventId, param: i32) -> io::Result<usize> {
+        self.capabilities.check(Facility::of_control(event))?;
+        self.inner.send_control(event, param)
+    }
+
+    fn run_lua(&mut self, name: &str) -> io::Result<usize> {
+        self.capabilities.check(Facility::LuaPlugins)?;
+        self.inner.run_lua(name)
+    }
+
+    fn run_macro(&mut self, file: &str, name: &str) -> io::Result<usize> {
+        self.capabilities.check(Facility::Macros)?;
+        self.inner.run_macro(file, name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    #[test]
+    fn should_read_capabilities() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0x3304, &0x7000_0000u32);
+        handle.seed_value(0x330A, &0x0001u16);
+        let capabilities = Capabilities::read(&mut handle).unwrap();
+        assert_eq!(capabilities.version(), 0x7000_0000);
+        assert!(capabilities.is_registered());
+        assert!(capabilities
+            .matrix()
+            .iter()
+            .all(|&(_, supported)| supported));
+
+        let unregistered = Capabilities::unregistered(0x7000_0000);
+        assert_eq!(
+            unregistered.matrix(),
+            vec![
+                (Facility::Offsets, true),
+                (Facility::Controls, true),
+                (Facility::FsuipcControls, false),
+                (Facility::LuaPlugins, false),
+                (Facility::Macros, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_refuse_unregistered_facilities() {
+        let mut handle = MockHandle::new();
+        {
+            let session = handle.session();
+            let capabilities = Capabilities::unregistered(0x7000_0000);
+            let mut session = RestrictedSession::new(session, capabilities);
+            session.send_control(EventId::GearToggle, 0).unwrap();
+            let error = session.run_lua("gear").unwrap_err();
+            match FsuipcError::from_io(&error) {
+                Some(FsuipcError::Unregistered(facility)) => assert_eq!(facility, "Lua plugins"),
+                other => panic!("unexpected error {:?}", other),
+            }
+            assert!(session.run_macro("pmdg", "gear").is_err());
+            assert!(session.send_control(EventId::Other(1070), 0).is_err());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.writes().len(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+/// The kind of a request of a session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    Read,
+    Write,
+}
+
+/// The outcome of a request of a processed session
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestStatus {
+    pub kind: RequestKind,
+    pub offset: u16,
+    /// The number of bytes requested.
+    pub requested: usize,
+    /// The number of bytes FSUIPC answered with.
+    pub returned: usize,
+}
+
+impl RequestStatus {
+    /// Whether FSUIPC answered with all the bytes requested.
+    pub fn is_fulfilled(&self) -> bool {
+        self.returned == self.requested
+    }
+}
+
+/// The outcome of each request of a processed session, in the order they were made
+/// It is obtained from the `process_report()` method of the sessions. Unlike `process()`, a
+/// response that cannot be fully decoded does not fail, so callers can tell which requests of
+/// a partially fulfilled batch have valid data.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProcessReport {
+    /// The requests decoded from the response.
+    pub requests: Vec<RequestStatus>,
+    /// Whether the response was decoded up to its termination mark.
+    pub terminated: bool,
+}
+
+impl ProcessReport {
+    /// Whether the whole response was decoded and every request was fulfilled.
+    pub fn is_complete(&self) -> bool {
+        self.terminated && self.requests.iter().all(RequestStatus::is_fulfilled)
+    }
+
+    /// The requests that were not fulfilled.
+    /// Requests past a response that could not be decoded are missing from the report, so
+    /// check `terminated` too.
+    pub fn unfulfilled(&self) -> impl Iterator<Item = &RequestStatus> {
+        self.requests.iter().filter(|r| !r.is_fulfilled())
+    }
+
+    /// The total bytes answered by FSUIPC.
+    pub fn bytes(&self) -> usize {
+        self.requests.iter().map(|r| r.returned).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_list_unfulfilled_requests() {
+        let read = |offset, returned| RequestStatus {
+            kind: RequestKind::Read,
+            offset,
+            requested: 4,
+            returned,
+        };
+        let mut report = ProcessReport {
+            requests: vec![read(0x0560, 4), read(0x0568, 0)],
+            terminated: true,
+        };
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.unfulfilled().collect::<Vec<_>>(),
+            vec![&read(0x0568, 0)]
+        );
+        assert_eq!(report.bytes(), 4);
+
+        report.requests.pop();
+        assert!(report.is_complete());
+        report.terminated = false;
+        assert!(!report.is_complete());
+    }
+}
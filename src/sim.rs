@@ -0,0 +1,139 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+#[cfg(windows)]
+use std::ffi::CString;
+
+#[cfg(windows)]
+use super::sys;
+#[cfg(windows)]
+pub use super::sys::WindowHandle;
+
+/// The simulator a handle connects to
+/// Several FSUIPC builds may be running at the same time (e.g. FSUIPC4 inside a 32-bit
+/// simulator and FSUIPC7 next to MSFS). All of them register a window of the same class,
+/// so they are told apart by the executable that owns the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimTarget {
+    /// Whatever FSUIPC window is found first.
+    Any,
+    /// Microsoft Flight Simulator X (FSUIPC4).
+    Fsx,
+    /// Lockheed Martin Prepar3D (FSUIPC4 to FSUIPC6).
+    Prepar3d,
+    /// Microsoft Flight Simulator 2020 and later (FSUIPC7, a separate process).
+    Msfs,
+}
+
+impl SimTarget {
+    /// Identify the simulator from the path of the executable owning the FSUIPC window.
+    pub fn from_image_name(path: &str) -> Option<SimTarget> {
+        let file_name = path
+            .rsplit(['\\', '/'])
+            .next()
+            .unwrap_or(path)
+            .to_ascii_lowercase();
+        match file_name.as_str() {
+            "fsx.exe" => Some(SimTarget::Fsx),
+            "prepar3d.exe" => Some(SimTarget::Prepar3d),
+            "fsuipc7.exe" => Some(SimTarget::Msfs),
+            _ => None,
+        }
+    }
+
+    /// Check whether a window owned by the given (possibly unknown) simulator fits this target.
+    pub fn accepts(self, sim: Option<SimTarget>) -> bool {
+        self == SimTarget::Any || sim == Some(self)
+    }
+}
+
+/// A FSUIPC window found in the system
+/// Each running FSUIPC instance (or simulator instance) owns one of these windows.
+#[cfg(windows)]
+#[derive(Clone, Debug)]
+pub struct SimWindow {
+    window: WindowHandle,
+    process_id: u32,
+    sim: Option<SimTarget>,
+}
+
+#[cfg(windows)]
+impl SimWindow {
+    /// The FSUIPC window.
+    pub fn window(&self) -> WindowHandle {
+        self.window
+    }
+
+    /// The id of the process owning the window.
+    pub fn process_id(&self) -> u32 {
+        self.process_id
+    }
+
+    /// The simulator owning the window, if it could be identified.
+    pub fn sim(&self) -> Option<SimTarget> {
+        self.sim
+    }
+}
+
+/// Enumerate all the windows of the given class, in Z order.
+#[cfg(windows)]
+pub(crate) fn find_windows(class_name: &str) -> Vec<SimWindow> {
+    let mut windows = Vec::new();
+    let class_name = match CString::new(class_name) {
+        Ok(class_name) => class_name,
+        Err(_) => return windows,
+    };
+    let mut window = WindowHandle::null();
+    loop {
+        window = sys::find_window(&class_name, window);
+        if window.is_null() {
+            return windows;
+        }
+        let process_id = sys::window_process_id(window);
+        let sim = sys::process_image_name(process_id)
+            .as_ref()
+            .and_then(|name| SimTarget::from_image_name(name));
+        windows.push(SimWindow {
+            window,
+            process_id,
+            sim,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_identify_sim_from_image_name() {
+        assert_eq!(
+            SimTarget::from_image_name("C:\\FSX\\fsx.exe"),
+            Some(SimTarget::Fsx)
+        );
+        assert_eq!(
+            SimTarget::from_image_name("D:\\Lockheed Martin\\Prepar3D v5\\Prepar3D.exe"),
+            Some(SimTarget::Prepar3d)
+        );
+        assert_eq!(
+            SimTarget::from_image_name("C:\\FSUIPC7\\FSUIPC7.exe"),
+            Some(SimTarget::Msfs)
+        );
+        assert_eq!(SimTarget::from_image_name("WideClient.exe"), None);
+    }
+
+    #[test]
+    fn should_accept_matching_sims() {
+        assert!(SimTarget::Any.accepts(None));
+        assert!(SimTarget::Any.accepts(Some(SimTarget::Fsx)));
+        assert!(SimTarget::Msfs.accepts(Some(SimTarget::Msfs)));
+        assert!(!SimTarget::Msfs.accepts(Some(SimTarget::Fsx)));
+        assert!(!SimTarget::Msfs.accepts(None));
+    }
+}
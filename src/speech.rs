@@ -0,0 +1,277 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Spoken announcements
+//! An `Announcer` keeps the text of each announcement as a `Template`, such as
+//! `"descend to {altitude} feet"`, whose placeholders are filled with the monitored values
+//! when the announcement is made. The text is then spoken by a `SpeechOutput`, which on
+//! Windows is the SAPI voice of the system, with the `sapi` feature.
+
+use std::collections::HashMap;
+use std::io;
+
+/// A piece of a template
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Value { name: String, decimals: usize },
+}
+
+/// The text of an announcement, with placeholders for values
+/// A placeholder is the name of a value in braces, `{altitude}`, optionally followed by the
+/// number of decimals to say, `{fuel:1}`. Values are said rounded to units by default. Braces
+/// are written doubled, `{{` and `}}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let placeholder: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&placeholder)?);
+                }
+                '}' => return Err(invalid_template("unmatched '}'")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// The names of the values the template refers to.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Value { name, .. } => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// The text with the placeholders replaced by the given values.
+    /// It fails with `InvalidInput` if a value is missing.
+    pub fn render(&self, values: &HashMap<String, f64>) -> io::Result<String> {
+        let mut text = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(literal) => text.push_str(literal),
+                Part::Value { name, decimals } => match values.get(name) {
+                    Some(value) => text.push_str(&format!("{:.*}", decimals, value)),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("no value for placeholder '{}'", name),
+                        ))
+                    }
+                },
+            }
+        }
+        Ok(text)
+    }
+}
+
+fn parse_placeholder(placeholder: &str) -> io::Result<Part> {
+    let mut fields = placeholder.splitn(2, ':');
+    let name = fields.next().unwrap_or("").trim();
+    if name.is_empty() {
+        return Err(invalid_template("empty placeholder"));
+    }
+    let decimals = match fields.next() {
+        Some(decimals) => decimals
+            .trim()
+            .parse()
+            .map_err(|_| invalid_template("invalid number of decimals"))?,
+        None => 0,
+    };
+    Ok(Part::Value {
+        name: name.to_string(),
+        decimals,
+    })
+}
+
+fn invalid_template(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid template: {}", reason),
+    )
+}
+
+/// A device that speaks text
+pub trait SpeechOutput {
+    /// Start speaking the text, interrupting the text being spoken if `interrupt` is set.
+    fn speak(&mut self, text: &str, interrupt: bool) -> io::Result<()>;
+}
+
+/// The announcements of an application, by name, and the output that speaks them
+pub struct Announcer<O> {
+    output: O,
+    templates: HashMap<String, Template>,
+}
+
+impl<O: SpeechOutput> Announcer<O> {
+    pub fn new(output: O) -> Self {
+        Announcer {
+            output,
+            templates: HashMap::new(),
+        }
+    }
+
+    /// Add an announcement with the given name and template text.
+    pub fn with_announcement(mut self, name: &str, template: &str) -> io::Result<Self> {
+        self.templates
+            .insert(name.to_string(), Template::parse(template)?);
+        Ok(self)
+    }
+
+    /// Speak the announcement with the given name, filled with the given values.
+    /// Urgent announcements, such as warnings, interrupt the one being spoken.
+    pub fn announce(
+        &mut self,
+        name: &str,
+        values: &HashMap<String, f64>,
+        urgent: bool,
+    ) -> io::Result<()> {
+        let template = self.templates.get(name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no announcement named '{}'", name),
+            )
+        })?;
+        let text = template.render(values)?;
+        self.output.speak(&text, urgent)
+    }
+
+    /// Obtain the output back.
+    pub fn into_inner(self) -> O {
+        self.output
+    }
+}
+
+/// The SAPI voice of the system
+/// The text is spoken in the background, so announcing does not hold the poll loop.
+#[cfg(all(windows, feature = "sapi"))]
+pub struct SapiVoice {
+    voice: Option<super::sys::ComObject>,
+}
+
+#[cfg(all(windows, feature = "sapi"))]
+impl SapiVoice {
+    /// Create the default voice of the system.
+    pub fn new() -> io::Result<Self> {
+        match super::sys::create_voice() {
+            Some(voice) => Ok(SapiVoice { voice: Some(voice) }),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "cannot create the SAPI voice",
+            )),
+        }
+    }
+}
+
+#[cfg(all(windows, feature = "sapi"))]
+impl SpeechOutput for SapiVoice {
+    fn speak(&mut self, text: &str, interrupt: bool) -> io::Result<()> {
+        let wide: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+        let voice = self.voice.as_ref().expect("voice released before drop");
+        if !super::sys::speak(voice, &wide, interrupt) {
+            return Err(io::Error::other("the SAPI voice rejected the text"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(windows, feature = "sapi"))]
+impl Drop for SapiVoice {
+    fn drop(&mut self) {
+        if let Some(voice) = self.voice.take() {
+            super::sys::release(voice);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Transcript(Vec<(String, bool)>);
+
+    impl SpeechOutput for Transcript {
+        fn speak(&mut self, text: &str, interrupt: bool) -> io::Result<()> {
+            self.0.push((text.to_string(), interrupt));
+            Ok(())
+        }
+    }
+
+    fn values(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn should_render_templates() {
+        let template = Template::parse("descend to {altitude} feet, fuel {fuel:1} {{kg}}").unwrap();
+        assert_eq!(
+            template.names().collect::<Vec<_>>(),
+            vec!["altitude", "fuel"]
+        );
+        let text = template
+            .render(&values(&[("altitude", 3000.2), ("fuel", 1250.25)]))
+            .unwrap();
+        assert_eq!(text, "descend to 3000 feet, fuel 1250.2 {kg}");
+        assert!(template.render(&values(&[("altitude", 3000.0)])).is_err());
+        assert!(Template::parse("bad {}").is_err());
+        assert!(Template::parse("bad }").is_err());
+        assert!(Template::parse("{fuel:x}").is_err());
+    }
+
+    #[test]
+    fn should_speak_announcements() {
+        let mut announcer = Announcer::new(Transcript::default())
+            .with_announcement("descend", "descend to {altitude} feet")
+            .unwrap()
+            .with_announcement("low_fuel", "low fuel")
+            .unwrap();
+        announcer
+            .announce("descend", &values(&[("altitude", 3000.0)]), false)
+            .unwrap();
+        announcer
+            .announce("low_fuel", &HashMap::new(), true)
+            .unwrap();
+        let error = announcer.announce("gear", &HashMap::new(), false).err();
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            announcer.into_inner().0,
+            vec![
+                ("descend to 3000 feet".to_string(), false),
+                ("low fuel".to_string(), true),
+            ]
+        );
+    }
+}
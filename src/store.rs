@@ -0,0 +1,252 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::claims::{OffsetClaims, GENERAL_USE_OFFSETS};
+use super::Session;
+
+/// The name under which the store claims the offsets of its mirrored values.
+pub const STORE_OWNER: &str = "store";
+
+/// A persistent store of named values
+/// It keeps cockpit state that the simulator does not model (e.g. alignment timers or the
+/// position of custom switches), so it survives application restarts. The values are saved
+/// to a text file with one `name=value` line per entry.
+///
+/// Values can also be mirrored into FSUIPC offsets, so other FSUIPC clients can see them.
+/// Each mirrored value takes 4 bytes claimed from the general use area.
+#[derive(Clone, Debug, Default)]
+pub struct ValueStore {
+    path: Option<PathBuf>,
+    values: BTreeMap<String, i32>,
+    mirrors: BTreeMap<String, u16>,
+}
+
+impl ValueStore {
+    /// Create a store that is never saved to disk.
+    pub fn in_memory() -> Self {
+        ValueStore::default()
+    }
+
+    /// Open the store persisted in the given file.
+    /// The store starts empty if the file does not exist yet.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let values = match fs::read_to_string(&path) {
+            Ok(content) => parse(&content)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(ValueStore {
+            path: Some(path),
+            values,
+            mirrors: BTreeMap::new(),
+        })
+    }
+
+    /// Obtain the value with the given name, if any.
+    pub fn get(&self, name: &str) -> Option<i32> {
+        self.values.get(name).cloned()
+    }
+
+    /// Set the value with the given name.
+    /// Names cannot be empty nor contain `=`, `#` or line breaks.
+    pub fn set(&mut self, name: &str, value: i32) -> io::Result<()> {
+        if name.is_empty() || name.contains(['=', '#', '\n', '\r']) || name.trim() != name {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid value name `{}`", name),
+            ));
+        }
+        self.values.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Remove the value with the given name, returning it.
+    pub fn remove(&mut self, name: &str) -> Option<i32> {
+        self.values.remove(name)
+    }
+
+    /// Iterate over the stored values, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// Save the store to its file. It does nothing for in-memory stores.
+    /// The file is replaced atomically, so a crash while saving never leaves it truncated.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut content = String::new();
+        for (name, value) in self.iter() {
+            content.push_str(&format!("{}={}\n", name, value));
+        }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Mirror the value with the given name into 4 bytes of the general use offsets.
+    /// It returns the offset where the value is written by `sync()`. Mirroring the same value
+    /// twice returns the offset allocated the first time.
+    pub fn mirror(&mut self, name: &str, claims: &mut OffsetClaims) -> io::Result<u16> {
+        if let Some(offset) = self.mirrors.get(name) {
+            return Ok(*offset);
+        }
+        let offset = claims.allocate(STORE_OWNER, 4, GENERAL_USE_OFFSETS)?;
+        self.mirrors.insert(name.to_string(), offset);
+        Ok(offset)
+    }
+
+    /// The offset the value with the given name is mirrored to, if any.
+    pub fn mirror_offset(&self, name: &str) -> Option<u16> {
+        self.mirrors.get(name).cloned()
+    }
+
+    /// Request writing all the mirrored values into their offsets.
+    /// Mirrored values not present in the store are written as 0.
+    pub fn sync<S: Session>(&self, session: &mut S) -> io::Result<()> {
+        for (name, offset) in self.mirrors.iter() {
+            let value = self.get(name).unwrap_or(0);
+            session.write(*offset, &value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of a value of the given aircraft, as `<section>.<aircraft>.<name>`.
+/// The characters of the aircraft title the store cannot keep in a name are replaced by `_`.
+pub fn aircraft_key(section: &str, aircraft: &str, name: &str) -> String {
+    let aircraft: String = aircraft
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '=' | '#' | '\n' | '\r' => '_',
+            c => c,
+        })
+        .collect();
+    format!("{}.{}.{}", section, aircraft, name)
+}
+
+fn parse(content: &str) -> io::Result<BTreeMap<String, i32>> {
+    let mut values = BTreeMap::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = line.split_once('=').and_then(|(name, value)| {
+            value
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .map(|value| (name.trim().to_string(), value))
+        });
+        match entry {
+            Some((name, value)) if !name.is_empty() => {
+                values.insert(name, value);
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid entry in line {}: `{}`", n + 1, line),
+                ))
+            }
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::env;
+
+    use super::*;
+
+    /// A session that records the written values
+    struct WriteSession {
+        writes: Vec<(u16, i32)>,
+    }
+
+    impl Session for WriteSession {
+        fn read_bytes(&mut self, _offset: u16, _dest: *mut u8, len: usize) -> io::Result<usize> {
+            Ok(len)
+        }
+
+        fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+            assert_eq!(len, 4);
+            let value = unsafe { *(src as *const i32) };
+            self.writes.push((offset, value));
+            Ok(len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn should_persist_values() {
+        let path = env::temp_dir().join(format!("fsuipc-store-{}.txt", std::process::id()));
+        let mut store = ValueStore::open(&path).unwrap();
+        assert!(store.get("irs.left.align").is_none());
+        store.set("irs.left.align", 420).unwrap();
+        store.set("panel.beacon", -1).unwrap();
+        store.save().unwrap();
+
+        let store = ValueStore::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(store.get("irs.left.align"), Some(420));
+        assert_eq!(store.get("panel.beacon"), Some(-1));
+        assert_eq!(store.iter().count(), 2);
+    }
+
+    #[test]
+    fn should_parse_comments_and_reject_garbage() {
+        let values = parse("# saved state\n\n a = 1\nb=2\n").unwrap();
+        assert_eq!(values.get("a"), Some(&1));
+        assert_eq!(values.get("b"), Some(&2));
+        let error = parse("a=1\nb=two\n").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn should_reject_invalid_names() {
+        let mut store = ValueStore::in_memory();
+        assert!(store.set("", 1).is_err());
+        assert!(store.set("a=b", 1).is_err());
+        assert!(store.set(" a", 1).is_err());
+        assert!(store.set("a.b", 1).is_ok());
+    }
+
+    #[test]
+    fn should_mirror_values_into_offsets() {
+        let mut claims = OffsetClaims::new();
+        let mut store = ValueStore::in_memory();
+        store.set("a", 7).unwrap();
+        assert_eq!(store.mirror("a", &mut claims).unwrap(), 0x66C0);
+        assert_eq!(store.mirror("b", &mut claims).unwrap(), 0x66C4);
+        assert_eq!(store.mirror("a", &mut claims).unwrap(), 0x66C0);
+        assert_eq!(claims.claim_at(0x66C4).unwrap().owner(), STORE_OWNER);
+
+        let mut session = WriteSession { writes: Vec::new() };
+        store.sync(&mut session).unwrap();
+        assert_eq!(session.writes, vec![(0x66C0, 7), (0x66C4, 0)]);
+    }
+}
@@ -0,0 +1,52 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Conversions of string offsets
+//! Offsets such as the aircraft name (0x3D00) or the ATC identifier (0x313C) are fixed-size
+//! buffers holding zero-terminated ASCII text.
+
+/// Decode the contents of a string offset.
+/// The text ends at the first zero byte, if any. Invalid UTF-8 is replaced lossily.
+pub fn decode(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Encode a string for an offset of `len` bytes.
+/// It is truncated to leave room for the zero terminator, and padded with zeros up to `len`.
+/// Truncation never splits a UTF-8 character.
+pub fn encode(value: &str, len: usize) -> Vec<u8> {
+    let mut end = value.len().min(len.saturating_sub(1));
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut bytes = value.as_bytes()[..end].to_vec();
+    bytes.resize(len, 0);
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_decode_zero_terminated_strings() {
+        assert_eq!(decode(b"N172SP\0\0\0\0\0\0"), "N172SP");
+        assert_eq!(decode(b"FULL"), "FULL");
+        assert_eq!(decode(b"A\xFFB\0"), "A\u{FFFD}B");
+    }
+
+    #[test]
+    fn should_encode_padded_and_truncated_strings() {
+        assert_eq!(encode("N172", 6), b"N172\0\0".to_vec());
+        assert_eq!(encode("N172SP", 4), b"N17\0".to_vec());
+        assert_eq!(encode("día", 3), b"d\0\0".to_vec());
+        assert!(encode("N172", 0).is_empty());
+    }
+}
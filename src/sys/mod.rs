@@ -0,0 +1,58 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+// Thin bindings to the Win32 functions used by the crate
+// The rest of the crate goes through these functions instead of calling Win32 directly, so the
+// crate used to bind Win32 (`winapi` by default, `windows` with the `windows-rs` feature) can
+// be switched without touching the handles.
+
+#[cfg(all(feature = "winapi", not(feature = "windows-rs")))]
+mod winapi_backend;
+#[cfg(all(feature = "winapi", not(feature = "windows-rs")))]
+pub(crate) use self::winapi_backend::*;
+
+#[cfg(feature = "windows-rs")]
+mod windows_backend;
+#[cfg(feature = "windows-rs")]
+pub(crate) use self::windows_backend::*;
+
+#[cfg(not(any(feature = "winapi", feature = "windows-rs")))]
+compile_error!("enable the `winapi` or the `windows-rs` feature to bind Win32");
+
+/// An opaque handle to a FSUIPC window
+/// It identifies a window without exposing the underlying Win32 type to client code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WindowHandle(usize);
+
+impl WindowHandle {
+    pub(crate) fn null() -> Self {
+        WindowHandle(0)
+    }
+
+    pub(crate) fn is_null(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Why a message could not be sent to a window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SendFailure {
+    /// The window did not answer in time.
+    Timeout,
+    /// The window no longer exists, e.g. because the simulator was closed.
+    WindowGone,
+}
+
+/// An opaque handle to a kernel object (e.g. a file mapping)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct KernelHandle(usize);
+
+/// An opaque reference to a COM object (e.g. a SAPI voice)
+#[cfg(feature = "sapi")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ComObject(usize);
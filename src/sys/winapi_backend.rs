@@ -0,0 +1,392 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::c_char;
+use std::ptr;
+
+use winapi::shared::minwindef::{FALSE, LPCVOID, LPVOID};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::ERROR_INVALID_WINDOW_HANDLE;
+#[cfg(feature = "sapi")]
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CLSCTX_ALL};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+use winapi::um::memoryapi::{MapViewOfFile, UnmapViewOfFile, FILE_MAP_WRITE};
+#[cfg(feature = "sapi")]
+use winapi::um::objbase::COINIT_MULTITHREADED;
+use winapi::um::processthreadsapi::{GetCurrentProcessId, OpenProcess, OpenProcessToken};
+#[cfg(feature = "sapi")]
+use winapi::um::sapi51::{CLSID_SpVoice, ISpVoice, SPF_ASYNC, SPF_PURGEBEFORESPEAK};
+use winapi::um::securitybaseapi::{
+    GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation,
+};
+#[cfg(feature = "sapi")]
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winbase::{
+    CreateFileMappingA, GlobalAddAtomA, GlobalDeleteAtom, QueryFullProcessImageNameA,
+};
+use winapi::um::winnt::{
+    TokenIntegrityLevel, HANDLE, PAGE_READWRITE, PROCESS_QUERY_LIMITED_INFORMATION,
+    TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+};
+use winapi::um::winuser::{
+    FindWindowExA, GetWindowThreadProcessId, IsWindow, RegisterWindowMessageA, SendMessageTimeoutA,
+    SMTO_BLOCK,
+};
+#[cfg(feature = "xinput")]
+use winapi::um::xinput::{XInputSetState, XINPUT_VIBRATION};
+#[cfg(feature = "sapi")]
+use winapi::Interface;
+
+#[cfg(feature = "sapi")]
+use super::ComObject;
+use super::{KernelHandle, SendFailure, WindowHandle};
+
+fn hwnd(window: WindowHandle) -> HWND {
+    window.0 as HWND
+}
+
+pub fn find_window(class_name: &CStr, after: WindowHandle) -> WindowHandle {
+    let window = unsafe {
+        FindWindowExA(
+            ptr::null_mut(),
+            hwnd(after),
+            class_name.as_ptr(),
+            ptr::null(),
+        )
+    };
+    WindowHandle(window as usize)
+}
+
+pub fn window_process_id(window: WindowHandle) -> u32 {
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(hwnd(window), &mut process_id) };
+    process_id
+}
+
+/// Whether the handle identifies an existing window.
+pub fn is_window(window: WindowHandle) -> bool {
+    unsafe { IsWindow(hwnd(window)) != 0 }
+}
+
+pub fn current_process_id() -> u32 {
+    unsafe { GetCurrentProcessId() }
+}
+
+pub fn register_window_message(name: &CStr) -> u32 {
+    unsafe { RegisterWindowMessageA(name.as_ptr()) }
+}
+
+/// Send a message waiting at most `timeout` milliseconds. It returns `None` on failure.
+pub fn send_message_timeout(
+    window: WindowHandle,
+    msg: u32,
+    wparam: usize,
+    lparam: isize,
+    timeout: u32,
+) -> Result<usize, SendFailure> {
+    let mut result = 0;
+    let sent = unsafe {
+        SendMessageTimeoutA(
+            hwnd(window),
+            msg,
+            wparam,
+            lparam,
+            SMTO_BLOCK,
+            timeout,
+            &mut result,
+        )
+    };
+    if sent != 0 {
+        Ok(result)
+    } else if unsafe { GetLastError() } == ERROR_INVALID_WINDOW_HANDLE {
+        Err(SendFailure::WindowGone)
+    } else {
+        Err(SendFailure::Timeout)
+    }
+}
+
+pub fn add_atom(name: &CStr) -> u16 {
+    unsafe { GlobalAddAtomA(name.as_ptr()) }
+}
+
+pub fn delete_atom(atom: u16) {
+    unsafe { GlobalDeleteAtom(atom) };
+}
+
+pub fn create_file_mapping(name: &CStr, len: usize) -> Option<KernelHandle> {
+    let handle = unsafe {
+        CreateFileMappingA(
+            INVALID_HANDLE_VALUE,
+            ptr::null_mut(),
+            PAGE_READWRITE,
+            0,
+            len as u32,
+            name.as_ptr(),
+        )
+    };
+    if handle.is_null() {
+        None
+    } else {
+        Some(KernelHandle(handle as usize))
+    }
+}
+
+pub fn map_view(mapping: KernelHandle) -> *mut u8 {
+    unsafe { MapViewOfFile(mapping.0 as HANDLE, FILE_MAP_WRITE, 0, 0, 0) as *mut u8 }
+}
+
+pub fn unmap_view(data: *mut u8) {
+    unsafe { UnmapViewOfFile(data as LPCVOID) };
+}
+
+pub fn close_handle(handle: KernelHandle) {
+    unsafe { CloseHandle(handle.0 as HANDLE) };
+}
+
+pub fn process_image_name(process_id: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, process_id);
+        if process.is_null() {
+            return None;
+        }
+        let mut buffer = [0u8; 1024];
+        let mut len = buffer.len() as u32;
+        let ok = QueryFullProcessImageNameA(process, 0, buffer.as_mut_ptr() as *mut i8, &mut len);
+        CloseHandle(process);
+        if ok == 0 {
+            return None;
+        }
+        Some(String::from_utf8_lossy(&buffer[..len as usize]).into_owned())
+    }
+}
+
+pub fn integrity_level(process_id: u32) -> Option<u32> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, process_id);
+        if process.is_null() {
+            return None;
+        }
+        let mut token = ptr::null_mut();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        CloseHandle(process);
+        if opened == 0 {
+            return None;
+        }
+        let mut len = 0;
+        GetTokenInformation(token, TokenIntegrityLevel, ptr::null_mut(), 0, &mut len);
+        // Use u64 items to get the buffer aligned for the SID pointer
+        let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+        let queried = GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            buffer.as_mut_ptr() as LPVOID,
+            len,
+            &mut len,
+        );
+        CloseHandle(token);
+        if queried == 0 {
+            return None;
+        }
+        let label = &*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+        let count = *GetSidSubAuthorityCount(label.Label.Sid);
+        if count == 0 {
+            return None;
+        }
+        Some(*GetSidSubAuthority(label.Label.Sid, count as u32 - 1))
+    }
+}
+
+pub fn wine_version() -> Option<String> {
+    unsafe {
+        let module_name = CString::new("ntdll.dll").unwrap();
+        let module = GetModuleHandleA(module_name.as_ptr());
+        if module.is_null() {
+            return None;
+        }
+        let proc_name = CString::new("wine_get_version").unwrap();
+        let proc = GetProcAddress(module, proc_name.as_ptr());
+        if proc.is_null() {
+            return None;
+        }
+        let wine_get_version: extern "C" fn() -> *const c_char = mem::transmute(proc);
+        let version = wine_get_version();
+        if version.is_null() {
+            return Some(String::new());
+        }
+        Some(CStr::from_ptr(version).to_string_lossy().into_owned())
+    }
+}
+
+/// Set the speed of the motors of an XInput controller, returning whether it is connected.
+#[cfg(feature = "xinput")]
+pub fn set_rumble(user: u32, low: u16, high: u16) -> bool {
+    let mut vibration = XINPUT_VIBRATION {
+        wLeftMotorSpeed: low,
+        wRightMotorSpeed: high,
+    };
+    unsafe { XInputSetState(user, &mut vibration) == 0 }
+}
+
+/// Create a SAPI voice, initializing COM in the calling thread if needed.
+#[cfg(feature = "sapi")]
+pub fn create_voice() -> Option<ComObject> {
+    let mut voice: *mut ISpVoice = ptr::null_mut();
+    let result = unsafe {
+        CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED);
+        CoCreateInstance(
+            &CLSID_SpVoice,
+            ptr::null_mut(),
+            CLSCTX_ALL,
+            &ISpVoice::uuidof(),
+            &mut voice as *mut *mut ISpVoice as *mut LPVOID,
+        )
+    };
+    if result < 0 || voice.is_null() {
+        return None;
+    }
+    Some(ComObject(voice as usize))
+}
+
+/// Start speaking the zero-terminated UTF-16 text, interrupting the text being spoken if
+/// `interrupt` is set. It returns whether the voice accepted the text.
+#[cfg(feature = "sapi")]
+pub fn speak(voice: &ComObject, text: &[u16], interrupt: bool) -> bool {
+    let mut flags = SPF_ASYNC;
+    if interrupt {
+        flags |= SPF_PURGEBEFORESPEAK;
+    }
+    let voice = voice.0 as *mut ISpVoice;
+    unsafe { (*voice).Speak(text.as_ptr(), flags, ptr::null_mut()) >= 0 }
+}
+
+/// Release a COM object.
+#[cfg(feature = "sapi")]
+pub fn release(object: ComObject) {
+    let object = object.0 as *mut IUnknown;
+    unsafe { (*object).Release() };
+}
+
+/// Create a recognition context of the shared recognizer listening for the given phrases,
+/// each a zero-terminated UTF-16 string. It returns the context and its grammar.
+#[cfg(feature = "voice")]
+pub fn create_recognizer(phrases: &[Vec<u16>]) -> Option<(ComObject, ComObject)> {
+    use winapi::shared::minwindef::TRUE;
+    use winapi::um::sapi51::{
+        CLSID_SpSharedRecoContext, ISpRecoContext, ISpRecoGrammar, SPRAF_Active, SPRAF_TopLevel,
+        SPEI_RECOGNITION, SPFEI, SPRS_ACTIVE, SPSTATEHANDLE, SPWT_LEXICAL,
+    };
+
+    let rule_name: Vec<u16> = "commands\0".encode_utf16().collect();
+    let separators: Vec<u16> = " \0".encode_utf16().collect();
+    let mut context: *mut ISpRecoContext = ptr::null_mut();
+    let result = unsafe {
+        CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED);
+        CoCreateInstance(
+            &CLSID_SpSharedRecoContext,
+            ptr::null_mut(),
+            CLSCTX_ALL,
+            &ISpRecoContext::uuidof(),
+            &mut context as *mut *mut ISpRecoContext as *mut LPVOID,
+        )
+    };
+    if result < 0 || context.is_null() {
+        return None;
+    }
+    let mut grammar: *mut ISpRecoGrammar = ptr::null_mut();
+    let mut rule: SPSTATEHANDLE = ptr::null_mut();
+    let interest = SPFEI(SPEI_RECOGNITION);
+    let ready = unsafe {
+        (*context).SetNotifyWin32Event() >= 0
+            && (*context).SetInterest(interest, interest) >= 0
+            && (*context).CreateGrammer(0, &mut grammar) >= 0
+            && !grammar.is_null()
+            && (*grammar).GetRule(
+                rule_name.as_ptr(),
+                1,
+                SPRAF_TopLevel | SPRAF_Active,
+                TRUE,
+                &mut rule,
+            ) >= 0
+            && phrases.iter().all(|phrase| {
+                (*grammar).AddWordTransition(
+                    rule,
+                    ptr::null_mut(),
+                    phrase.as_ptr(),
+                    separators.as_ptr(),
+                    SPWT_LEXICAL,
+                    1.0,
+                    ptr::null(),
+                ) >= 0
+            })
+            && (*grammar).Commit(0) >= 0
+            && (*grammar).SetRuleState(ptr::null(), ptr::null_mut(), SPRS_ACTIVE) >= 0
+    };
+    if !ready {
+        if !grammar.is_null() {
+            release(ComObject(grammar as usize));
+        }
+        release(ComObject(context as usize));
+        return None;
+    }
+    Some((ComObject(context as usize), ComObject(grammar as usize)))
+}
+
+/// Wait at most `timeout_millis` for the context to recognize a phrase, returning its text.
+#[cfg(feature = "voice")]
+pub fn wait_phrase(context: &ComObject, timeout_millis: u32) -> Option<String> {
+    use winapi::shared::minwindef::TRUE;
+    use winapi::um::combaseapi::CoTaskMemFree;
+    use winapi::um::sapi51::{
+        ISpRecoContext, ISpRecoResult, SPEI_RECOGNITION, SPET_LPARAM_IS_OBJECT,
+        SPET_LPARAM_IS_POINTER, SPET_LPARAM_IS_STRING, SPEVENT, SP_GETWHOLEPHRASE,
+    };
+    use winapi::um::winnt::LPWSTR;
+
+    let context = context.0 as *mut ISpRecoContext;
+    let mut phrase = None;
+    unsafe {
+        if (*context).WaitForNotifyEvent(timeout_millis) < 0 {
+            return None;
+        }
+        loop {
+            let mut event: SPEVENT = mem::zeroed();
+            let mut fetched = 0;
+            if (*context).GetEvents(1, &mut event, &mut fetched) < 0 || fetched == 0 {
+                break;
+            }
+            let param = event.lParam as LPVOID;
+            match event.elParamType() {
+                SPET_LPARAM_IS_OBJECT => {
+                    if event.eEventId() == SPEI_RECOGNITION && phrase.is_none() {
+                        let result = param as *mut ISpRecoResult;
+                        let mut text: LPWSTR = ptr::null_mut();
+                        let whole = SP_GETWHOLEPHRASE;
+                        if (*result).GetText(whole, whole, TRUE, &mut text, ptr::null_mut()) >= 0 {
+                            phrase = Some(wide_to_string(text));
+                            CoTaskMemFree(text as LPVOID);
+                        }
+                    }
+                    (*(param as *mut IUnknown)).Release();
+                }
+                SPET_LPARAM_IS_POINTER | SPET_LPARAM_IS_STRING => CoTaskMemFree(param),
+                _ => {}
+            }
+        }
+    }
+    phrase
+}
+
+#[cfg(feature = "voice")]
+unsafe fn wide_to_string(text: *const u16) -> String {
+    let len = (0..).take_while(|i| *text.add(*i) != 0).count();
+    String::from_utf16_lossy(std::slice::from_raw_parts(text, len))
+}
@@ -0,0 +1,355 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::os::raw::c_char;
+
+use windows::core::{s, PCSTR, PSTR};
+#[cfg(feature = "sapi")]
+use windows::core::{IUnknown, Interface, PCWSTR};
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_INVALID_WINDOW_HANDLE, HANDLE, HWND, INVALID_HANDLE_VALUE,
+    LPARAM, WPARAM,
+};
+#[cfg(feature = "sapi")]
+use windows::Win32::Media::Speech::{ISpVoice, SpVoice, SPF_ASYNC, SPF_PURGEBEFORESPEAK};
+use windows::Win32::Security::{
+    GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation, TokenIntegrityLevel,
+    TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+};
+#[cfg(feature = "sapi")]
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED,
+};
+use windows::Win32::System::DataExchange::{GlobalAddAtomA, GlobalDeleteAtom};
+use windows::Win32::System::LibraryLoader::{GetModuleHandleA, GetProcAddress};
+use windows::Win32::System::Memory::{
+    CreateFileMappingA, MapViewOfFile, UnmapViewOfFile, FILE_MAP_WRITE, MEMORY_MAPPED_VIEW_ADDRESS,
+    PAGE_READWRITE,
+};
+use windows::Win32::System::Threading::{
+    GetCurrentProcessId, OpenProcess, OpenProcessToken, QueryFullProcessImageNameA,
+    PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+#[cfg(feature = "xinput")]
+use windows::Win32::UI::Input::XboxController::{XInputSetState, XINPUT_VIBRATION};
+use windows::Win32::UI::WindowsAndMessaging::{
+    FindWindowExA, GetWindowThreadProcessId, IsWindow, RegisterWindowMessageA, SendMessageTimeoutA,
+    SMTO_BLOCK,
+};
+
+#[cfg(feature = "sapi")]
+use super::ComObject;
+use super::{KernelHandle, SendFailure, WindowHandle};
+
+fn hwnd(window: WindowHandle) -> HWND {
+    HWND(window.0 as *mut c_void)
+}
+
+fn pcstr(s: &CStr) -> PCSTR {
+    PCSTR(s.as_ptr() as *const u8)
+}
+
+pub fn find_window(class_name: &CStr, after: WindowHandle) -> WindowHandle {
+    let after = if after.is_null() {
+        None
+    } else {
+        Some(hwnd(after))
+    };
+    match unsafe { FindWindowExA(None, after, pcstr(class_name), PCSTR::null()) } {
+        Ok(window) => WindowHandle(window.0 as usize),
+        Err(_) => WindowHandle::null(),
+    }
+}
+
+pub fn window_process_id(window: WindowHandle) -> u32 {
+    let mut process_id = 0;
+    unsafe { GetWindowThreadProcessId(hwnd(window), Some(&mut process_id)) };
+    process_id
+}
+
+/// Whether the handle identifies an existing window.
+pub fn is_window(window: WindowHandle) -> bool {
+    unsafe { IsWindow(Some(hwnd(window))).as_bool() }
+}
+
+pub fn current_process_id() -> u32 {
+    unsafe { GetCurrentProcessId() }
+}
+
+pub fn register_window_message(name: &CStr) -> u32 {
+    unsafe { RegisterWindowMessageA(pcstr(name)) }
+}
+
+/// Send a message waiting at most `timeout` milliseconds. It returns `None` on failure.
+pub fn send_message_timeout(
+    window: WindowHandle,
+    msg: u32,
+    wparam: usize,
+    lparam: isize,
+    timeout: u32,
+) -> Result<usize, SendFailure> {
+    let mut result = 0;
+    let sent = unsafe {
+        SendMessageTimeoutA(
+            hwnd(window),
+            msg,
+            WPARAM(wparam),
+            LPARAM(lparam),
+            SMTO_BLOCK,
+            timeout,
+            Some(&mut result),
+        )
+    };
+    if sent.0 != 0 {
+        Ok(result)
+    } else if unsafe { GetLastError() } == ERROR_INVALID_WINDOW_HANDLE {
+        Err(SendFailure::WindowGone)
+    } else {
+        Err(SendFailure::Timeout)
+    }
+}
+
+pub fn add_atom(name: &CStr) -> u16 {
+    unsafe { GlobalAddAtomA(pcstr(name)) }
+}
+
+pub fn delete_atom(atom: u16) {
+    unsafe { GlobalDeleteAtom(atom) };
+}
+
+pub fn create_file_mapping(name: &CStr, len: usize) -> Option<KernelHandle> {
+    let handle = unsafe {
+        CreateFileMappingA(
+            INVALID_HANDLE_VALUE,
+            None,
+            PAGE_READWRITE,
+            0,
+            len as u32,
+            pcstr(name),
+        )
+    };
+    match handle {
+        Ok(handle) if !handle.is_invalid() => Some(KernelHandle(handle.0 as usize)),
+        _ => None,
+    }
+}
+
+pub fn map_view(mapping: KernelHandle) -> *mut u8 {
+    let view = unsafe { MapViewOfFile(HANDLE(mapping.0 as *mut c_void), FILE_MAP_WRITE, 0, 0, 0) };
+    view.Value as *mut u8
+}
+
+pub fn unmap_view(data: *mut u8) {
+    let view = MEMORY_MAPPED_VIEW_ADDRESS {
+        Value: data as *mut c_void,
+    };
+    let _ = unsafe { UnmapViewOfFile(view) };
+}
+
+pub fn close_handle(handle: KernelHandle) {
+    let _ = unsafe { CloseHandle(HANDLE(handle.0 as *mut c_void)) };
+}
+
+pub fn process_image_name(process_id: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+        let mut buffer = [0u8; 1024];
+        let mut len = buffer.len() as u32;
+        let queried = QueryFullProcessImageNameA(
+            process,
+            PROCESS_NAME_WIN32,
+            PSTR(buffer.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        queried.ok()?;
+        Some(String::from_utf8_lossy(&buffer[..len as usize]).into_owned())
+    }
+}
+
+pub fn integrity_level(process_id: u32) -> Option<u32> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+        let mut token = HANDLE::default();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        let _ = CloseHandle(process);
+        opened.ok()?;
+        let mut len = 0;
+        let _ = GetTokenInformation(token, TokenIntegrityLevel, None, 0, &mut len);
+        // Use u64 items to get the buffer aligned for the SID pointer
+        let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+        let queried = GetTokenInformation(
+            token,
+            TokenIntegrityLevel,
+            Some(buffer.as_mut_ptr() as *mut c_void),
+            len,
+            &mut len,
+        );
+        let _ = CloseHandle(token);
+        queried.ok()?;
+        let label = &*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+        let count = *GetSidSubAuthorityCount(label.Label.Sid);
+        if count == 0 {
+            return None;
+        }
+        Some(*GetSidSubAuthority(label.Label.Sid, count as u32 - 1))
+    }
+}
+
+pub fn wine_version() -> Option<String> {
+    unsafe {
+        let module = GetModuleHandleA(s!("ntdll.dll")).ok()?;
+        let proc = GetProcAddress(module, s!("wine_get_version"))?;
+        let wine_get_version: extern "C" fn() -> *const c_char = mem::transmute(proc);
+        let version = wine_get_version();
+        if version.is_null() {
+            return Some(String::new());
+        }
+        Some(CStr::from_ptr(version).to_string_lossy().into_owned())
+    }
+}
+
+/// Set the speed of the motors of an XInput controller, returning whether it is connected.
+#[cfg(feature = "xinput")]
+pub fn set_rumble(user: u32, low: u16, high: u16) -> bool {
+    let vibration = XINPUT_VIBRATION {
+        wLeftMotorSpeed: low,
+        wRightMotorSpeed: high,
+    };
+    unsafe { XInputSetState(user, &vibration) == 0 }
+}
+
+/// Create a SAPI voice, initializing COM in the calling thread if needed.
+#[cfg(feature = "sapi")]
+pub fn create_voice() -> Option<ComObject> {
+    let voice: ISpVoice = unsafe {
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        CoCreateInstance(&SpVoice, None, CLSCTX_ALL).ok()?
+    };
+    Some(ComObject(voice.into_raw() as usize))
+}
+
+/// Start speaking the zero-terminated UTF-16 text, interrupting the text being spoken if
+/// `interrupt` is set. It returns whether the voice accepted the text.
+#[cfg(feature = "sapi")]
+pub fn speak(voice: &ComObject, text: &[u16], interrupt: bool) -> bool {
+    let mut flags = SPF_ASYNC.0 as u32;
+    if interrupt {
+        flags |= SPF_PURGEBEFORESPEAK.0 as u32;
+    }
+    let raw = voice.0 as *mut c_void;
+    match unsafe { ISpVoice::from_raw_borrowed(&raw) } {
+        Some(voice) => unsafe { voice.Speak(PCWSTR(text.as_ptr()), flags, None) }.is_ok(),
+        None => false,
+    }
+}
+
+/// Release a COM object.
+#[cfg(feature = "sapi")]
+pub fn release(object: ComObject) {
+    drop(unsafe { IUnknown::from_raw(object.0 as *mut c_void) });
+}
+
+/// Create a recognition context of the shared recognizer listening for the given phrases,
+/// each a zero-terminated UTF-16 string. It returns the context and its grammar.
+#[cfg(feature = "voice")]
+pub fn create_recognizer(phrases: &[Vec<u16>]) -> Option<(ComObject, ComObject)> {
+    use std::ptr;
+    use windows::core::w;
+    use windows::Win32::Media::Speech::{
+        ISpRecoContext, SPRAF_Active, SPRAF_TopLevel, SpSharedRecoContext, SPEI_RECOGNITION,
+        SPEI_RESERVED1, SPEI_RESERVED2, SPRS_ACTIVE, SPSTATEHANDLE, SPWT_LEXICAL,
+    };
+
+    let interest = [SPEI_RECOGNITION, SPEI_RESERVED1, SPEI_RESERVED2]
+        .iter()
+        .fold(0u64, |interest, event| interest | (1 << event.0));
+    unsafe {
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        let context: ISpRecoContext =
+            CoCreateInstance(&SpSharedRecoContext, None, CLSCTX_ALL).ok()?;
+        context.SetNotifyWin32Event().ok()?;
+        context.SetInterest(interest, interest).ok()?;
+        let grammar = context.CreateGrammar(0).ok()?;
+        let mut rule = SPSTATEHANDLE(ptr::null_mut());
+        let attributes = (SPRAF_TopLevel.0 | SPRAF_Active.0) as u32;
+        grammar
+            .GetRule(w!("commands"), 1, attributes, true, &mut rule)
+            .ok()?;
+        for phrase in phrases {
+            grammar
+                .AddWordTransition(
+                    rule,
+                    SPSTATEHANDLE(ptr::null_mut()),
+                    PCWSTR(phrase.as_ptr()),
+                    w!(" "),
+                    SPWT_LEXICAL,
+                    1.0,
+                    ptr::null(),
+                )
+                .ok()?;
+        }
+        grammar.Commit(0).ok()?;
+        grammar
+            .SetRuleState(PCWSTR::null(), ptr::null_mut(), SPRS_ACTIVE)
+            .ok()?;
+        Some((
+            ComObject(context.into_raw() as usize),
+            ComObject(grammar.into_raw() as usize),
+        ))
+    }
+}
+
+/// Wait at most `timeout_millis` for the context to recognize a phrase, returning its text.
+#[cfg(feature = "voice")]
+pub fn wait_phrase(context: &ComObject, timeout_millis: u32) -> Option<String> {
+    use windows::core::PWSTR;
+    use windows::Win32::Media::Speech::{
+        ISpRecoContext, ISpRecoResult, SPEI_RECOGNITION, SPET_LPARAM_IS_OBJECT,
+        SPET_LPARAM_IS_POINTER, SPET_LPARAM_IS_STRING, SPEVENT, SPPR_ALL_ELEMENTS,
+    };
+    use windows::Win32::System::Com::CoTaskMemFree;
+
+    let raw = context.0 as *mut c_void;
+    let context = unsafe { ISpRecoContext::from_raw_borrowed(&raw) }?;
+    let mut phrase = None;
+    unsafe {
+        context.WaitForNotifyEvent(timeout_millis).ok()?;
+        loop {
+            let mut event = SPEVENT::default();
+            let mut fetched = 0;
+            if context.GetEvents(1, &mut event, &mut fetched).is_err() || fetched == 0 {
+                break;
+            }
+            let param = event.lParam.0 as *mut c_void;
+            let event_id = event._bitfield & 0xFFFF;
+            match (event._bitfield >> 16) & 0xFFFF {
+                t if t == SPET_LPARAM_IS_OBJECT.0 => {
+                    if event_id == SPEI_RECOGNITION.0 && phrase.is_none() {
+                        if let Some(result) = ISpRecoResult::from_raw_borrowed(&param) {
+                            let whole = SPPR_ALL_ELEMENTS.0 as u32;
+                            let mut text = PWSTR::null();
+                            if result.GetText(whole, whole, true, &mut text, None).is_ok() {
+                                phrase = text.to_string().ok();
+                                CoTaskMemFree(Some(text.0 as *const c_void));
+                            }
+                        }
+                    }
+                    drop(IUnknown::from_raw(param));
+                }
+                t if t == SPET_LPARAM_IS_POINTER.0 || t == SPET_LPARAM_IS_STRING.0 => {
+                    CoTaskMemFree(Some(param as *const c_void))
+                }
+                _ => {}
+            }
+        }
+    }
+    phrase
+}
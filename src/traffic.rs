@@ -0,0 +1,316 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! AI traffic
+//! FSUIPC keeps the AI aircraft around the user in two tables of `TCAS_DATA` slots: the
+//! airborne ones from offset 0xF080 and the ones on the ground from 0xE080. Each slot takes 40
+//! bytes: the id of the aircraft (0 for an empty slot), its latitude, longitude and altitude in
+//! feet as floats, its heading in 65536ths of a turn, its ground speed in knots, its vertical
+//! speed in feet per minute, its ATC callsign in 15 bytes, its state and its COM1 frequency.
+//! The tables have 96 slots each in the classic layout. `TrafficReader::with_slots()` reads a
+//! few more slots in place, up to the end of the offset space. The extended tables of the
+//! versions of FSUIPC that double the number of slots do not fit at the classic offsets, so
+//! `TrafficReader::extended()` reads them from the offsets given for each table.
+
+use std::convert::TryInto;
+use std::io;
+
+use super::gdl90;
+use super::strings;
+use super::{Handle, Session};
+
+/// The bytes of a slot of the traffic tables.
+pub const SLOT_LEN: usize = 40;
+
+/// The slots of each traffic table in the classic layout.
+pub const CLASSIC_SLOTS: usize = 96;
+
+/// The slots of each traffic table in the extended layout, twice the classic ones.
+pub const EXTENDED_SLOTS: usize = 2 * CLASSIC_SLOTS;
+
+/// A table of AI traffic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficTable {
+    Airborne,
+    Ground,
+}
+
+impl TrafficTable {
+    /// The offset of the first slot of the table in the classic layout.
+    pub fn address(self) -> u16 {
+        match self {
+            TrafficTable::Airborne => 0xF080,
+            TrafficTable::Ground => 0xE080,
+        }
+    }
+
+    /// The most slots the table can have at its classic offset before running out of the offset
+    /// space.
+    pub fn max_slots(self) -> usize {
+        (0x10000 - self.address() as usize) / SLOT_LEN
+    }
+}
+
+/// An AI aircraft of the traffic tables
+#[derive(Clone, Debug, PartialEq)]
+pub struct Traffic {
+    /// The id FSUIPC gives the aircraft, stable while it exists.
+    pub id: u32,
+    /// The latitude, in degrees.
+    pub latitude: f64,
+    /// The longitude, in degrees.
+    pub longitude: f64,
+    /// The altitude, in feet.
+    pub altitude: f64,
+    /// The true heading, in degrees.
+    pub heading: f64,
+    /// The ground speed, in knots.
+    pub ground_speed: f64,
+    /// The vertical speed, in feet per minute.
+    pub vertical_speed: f64,
+    /// The ATC callsign.
+    pub callsign: String,
+    /// The state of the aircraft, as reported by the simulator (e.g. taxiing or cruising).
+    pub state: u8,
+    /// The COM1 frequency, in BCD without the leading 1.
+    pub com1: u16,
+    pub airborne: bool,
+}
+
+impl Traffic {
+    /// Decode a slot, or `None` if the slot is empty or not `SLOT_LEN` bytes long.
+    pub fn from_slot(slot: &[u8], airborne: bool) -> Option<Self> {
+        if slot.len() != SLOT_LEN {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes(slot[i..i + 2].try_into().unwrap());
+        let f32_at = |i: usize| f32::from_le_bytes(slot[i..i + 4].try_into().unwrap()) as f64;
+        let id = u32::from_le_bytes(slot[0..4].try_into().unwrap());
+        if id == 0 {
+            return None;
+        }
+        Some(Traffic {
+            id,
+            latitude: f32_at(4),
+            longitude: f32_at(8),
+            altitude: f32_at(12),
+            heading: u16_at(16) as f64 * 360.0 / 65536.0,
+            ground_speed: u16_at(18) as f64,
+            vertical_speed: u16_at(20) as i16 as f64,
+            callsign: strings::decode(&slot[22..37]),
+            state: slot[37],
+            com1: u16_at(38),
+            airborne,
+        })
+    }
+
+    /// The aircraft as a GDL90 traffic report, addressed by the low 24 bits of its id.
+    pub fn report(&self) -> gdl90::Traffic {
+        gdl90::Traffic {
+            address: self.id & 0x00FF_FFFF,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            altitude: self.altitude,
+            ground_speed: self.ground_speed,
+            vertical_speed: self.vertical_speed,
+            track: self.heading,
+            callsign: self.callsign.clone(),
+            airborne: self.airborne,
+        }
+    }
+}
+
+/// A reader of the traffic tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrafficReader {
+    slots: usize,
+    airborne: u16,
+    ground: u16,
+}
+
+impl Default for TrafficReader {
+    fn default() -> Self {
+        TrafficReader {
+            slots: CLASSIC_SLOTS,
+            airborne: TrafficTable::Airborne.address(),
+            ground: TrafficTable::Ground.address(),
+        }
+    }
+}
+
+impl TrafficReader {
+    /// A reader of the classic layout, with `CLASSIC_SLOTS` slots per table.
+    pub fn new() -> Self {
+        TrafficReader::default()
+    }
+
+    /// Read the given number of slots of each table.
+    /// The tables stay at their classic offsets, so the number is capped by the offset space
+    /// after the airborne table: 99 slots. Use `extended()` for the extended tables.
+    pub fn with_slots(slots: usize) -> io::Result<Self> {
+        let max = TrafficTable::Airborne
+            .max_slots()
+            .min(TrafficTable::Ground.max_slots());
+        if slots == 0 || slots > max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the traffic tables have from 1 to {} slots", max),
+            ));
+        }
+        Ok(TrafficReader {
+            slots,
+            ..TrafficReader::default()
+        })
+    }
+
+    /// Read the extended tables, with `EXTENDED_SLOTS` slots each, from the given offsets.
+    /// It fails with `InvalidInput` if a table runs out of the offset space or the two tables
+    /// overlap.
+    pub fn extended(airborne: u16, ground: u16) -> io::Result<Self> {
+        let len = EXTENDED_SLOTS * SLOT_LEN;
+        let (first, second) = (airborne.min(ground) as usize, airborne.max(ground) as usize);
+        if second + len > 0x10000 || first + len > second {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the extended traffic tables at 0x{:04X} and 0x{:04X} do not fit in {} bytes each",
+                    airborne, ground, len
+                ),
+            ));
+        }
+        Ok(TrafficReader {
+            slots: EXTENDED_SLOTS,
+            airborne,
+            ground,
+        })
+    }
+
+    /// The offset this reader reads the given table from.
+    pub fn address(&self, table: TrafficTable) -> u16 {
+        match table {
+            TrafficTable::Airborne => self.airborne,
+            TrafficTable::Ground => self.ground,
+        }
+    }
+
+    /// Read the aircraft of both tables in a single transaction, the airborne ones first.
+    pub fn read<H>(&self, handle: &mut H) -> io::Result<Vec<Traffic>>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        self.read_tables(handle, &[TrafficTable::Airborne, TrafficTable::Ground])
+    }
+
+    /// Read the aircraft of the given tables in a single transaction.
+    pub fn read_tables<H>(
+        &self,
+        handle: &mut H,
+        tables: &[TrafficTable],
+    ) -> io::Result<Vec<Traffic>>
+    where
+        H: for<'h> Handle<'h>,
+    {
+        let mut buffers = vec![vec![0u8; self.slots * SLOT_LEN]; tables.len()];
+        let mut session = handle.session();
+        for (table, buffer) in tables.iter().zip(buffers.iter_mut()) {
+            session.read_bytes(self.address(*table), buffer.as_mut_ptr(), buffer.len())?;
+        }
+        session.process()?;
+
+        let traffic = tables
+            .iter()
+            .zip(buffers.iter())
+            .flat_map(|(table, buffer)| {
+                let airborne = *table == TrafficTable::Airborne;
+                buffer
+                    .chunks(SLOT_LEN)
+                    .filter_map(move |slot| Traffic::from_slot(slot, airborne))
+            })
+            .collect();
+        Ok(traffic)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    fn slot(id: u32, callsign: &str, vertical_speed: i16) -> Vec<u8> {
+        let mut slot = Vec::new();
+        slot.extend_from_slice(&id.to_le_bytes());
+        slot.extend_from_slice(&40.5f32.to_le_bytes());
+        slot.extend_from_slice(&(-3.5f32).to_le_bytes());
+        slot.extend_from_slice(&3500f32.to_le_bytes());
+        slot.extend_from_slice(&0x4000u16.to_le_bytes());
+        slot.extend_from_slice(&180u16.to_le_bytes());
+        slot.extend_from_slice(&vertical_speed.to_le_bytes());
+        slot.extend_from_slice(&strings::encode(callsign, 15));
+        slot.push(3);
+        slot.extend_from_slice(&0x1890u16.to_le_bytes());
+        slot
+    }
+
+    #[test]
+    fn should_decode_slots() {
+        let traffic = Traffic::from_slot(&slot(7, "IBE123", -500), true).unwrap();
+        assert_eq!(
+            traffic,
+            Traffic {
+                id: 7,
+                latitude: 40.5,
+                longitude: -3.5,
+                altitude: 3500.0,
+                heading: 90.0,
+                ground_speed: 180.0,
+                vertical_speed: -500.0,
+                callsign: "IBE123".to_string(),
+                state: 3,
+                com1: 0x1890,
+                airborne: true,
+            }
+        );
+        assert_eq!(traffic.report().address, 7);
+        assert_eq!(Traffic::from_slot(&slot(0, "", 0), true), None);
+        assert_eq!(Traffic::from_slot(&slot(7, "", 0)[..39], true), None);
+    }
+
+    #[test]
+    fn should_read_both_tables() {
+        let mut handle = MockHandle::new();
+        handle.seed(0xF080 + 2 * SLOT_LEN as u16, &slot(1, "AFR1", 0));
+        handle.seed(0xE080, &slot(2, "RYR2", 0));
+        handle.seed(0xE080 + 95 * SLOT_LEN as u16, &slot(3, "EZY3", 0));
+        let traffic = TrafficReader::new().read(&mut handle).unwrap();
+        assert_eq!(handle.transactions(), 1);
+        let found: Vec<(u32, bool)> = traffic.iter().map(|t| (t.id, t.airborne)).collect();
+        assert_eq!(found, vec![(1, true), (2, false), (3, false)]);
+
+        assert!(TrafficReader::with_slots(99).is_ok());
+        assert!(TrafficReader::with_slots(100).is_err());
+    }
+
+    #[test]
+    fn should_read_extended_tables() {
+        let mut handle = MockHandle::new();
+        handle.seed(0x8000 + 150 * SLOT_LEN as u16, &slot(1, "AFR1", 0));
+        handle.seed(0xA000 + 191 * SLOT_LEN as u16, &slot(2, "RYR2", 0));
+        let reader = TrafficReader::extended(0x8000, 0xA000).unwrap();
+        let traffic = reader.read(&mut handle).unwrap();
+        let found: Vec<(u32, bool)> = traffic.iter().map(|t| (t.id, t.airborne)).collect();
+        assert_eq!(found, vec![(1, true), (2, false)]);
+    }
+
+    #[test]
+    fn should_reject_extended_tables_that_do_not_fit() {
+        assert!(TrafficReader::extended(0xE080, 0xF080).is_err());
+        assert!(TrafficReader::extended(0x8000, 0x9000).is_err());
+        assert!(TrafficReader::extended(0xA000, 0x8000).is_ok());
+    }
+}
@@ -0,0 +1,168 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Binary coded decimal (BCD) values, as used by radio frequencies and transponder codes
+
+use std::fmt;
+
+/// Decode a 4-digit BCD value (e.g. 0x1234 into 1234).
+/// It returns `None` if any digit is not a decimal one.
+pub fn decode(raw: u16) -> Option<u16> {
+    let mut value = 0;
+    for shift in [12, 8, 4, 0].iter() {
+        let digit = (raw >> shift) & 0xF;
+        if digit > 9 {
+            return None;
+        }
+        value = value * 10 + digit;
+    }
+    Some(value)
+}
+
+/// Encode a value of up to 4 digits into BCD (e.g. 1234 into 0x1234).
+/// It returns `None` if the value has more than 4 digits.
+pub fn encode(value: u16) -> Option<u16> {
+    if value > 9999 {
+        return None;
+    }
+    let mut raw = 0;
+    let mut rest = value;
+    for shift in [0, 4, 8, 12].iter() {
+        raw |= (rest % 10) << shift;
+        rest /= 10;
+    }
+    Some(raw)
+}
+
+/// A VHF frequency of a COM or NAV radio
+/// FSUIPC encodes them in BCD without the leading 1, with a resolution of 10 kHz
+/// (e.g. 0x2345 for 123.45 MHz).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Frequency {
+    raw: u16,
+}
+
+impl Frequency {
+    /// Decode a frequency from its raw BCD value.
+    pub fn from_bcd(raw: u16) -> Option<Self> {
+        decode(raw).map(|_| Frequency { raw })
+    }
+
+    /// Obtain the frequency closest to the given MHz, which must be in 100.00..=199.99.
+    pub fn from_mhz(mhz: f64) -> Option<Self> {
+        let hundredths = (mhz * 100.0).round();
+        if !(10000.0..=19999.0).contains(&hundredths) {
+            return None;
+        }
+        encode(hundredths as u16 - 10000).map(|raw| Frequency { raw })
+    }
+
+    /// The raw BCD value.
+    pub fn bcd(&self) -> u16 {
+        self.raw
+    }
+
+    /// The frequency in MHz.
+    pub fn mhz(&self) -> f64 {
+        (10000 + decode(self.raw).unwrap_or(0)) as f64 / 100.0
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2}", self.mhz())
+    }
+}
+
+/// A frequency of an ADF radio
+/// FSUIPC splits them in two values: the main one holds the hundreds, tens and units of kHz in
+/// BCD, and the extended one holds the thousands in its high byte and the tenths of kHz in its
+/// low byte (e.g. 0x0234 and 0x0105 for 1234.5 kHz).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AdfFrequency {
+    tenths: u32,
+}
+
+impl AdfFrequency {
+    /// Decode a frequency from its raw main and extended values.
+    pub fn from_bcd(main: u16, extended: u16) -> Option<Self> {
+        let units = decode(main)? as u32;
+        let (thousands, tenths) = ((extended >> 8) as u32, (extended & 0xFF) as u32);
+        if units > 999 || thousands > 9 || tenths > 9 {
+            return None;
+        }
+        Some(AdfFrequency {
+            tenths: (thousands * 1000 + units) * 10 + tenths,
+        })
+    }
+
+    /// Obtain the frequency closest to the given kHz, which must be in 0.0..=9999.9.
+    pub fn from_khz(khz: f64) -> Option<Self> {
+        let tenths = (khz * 10.0).round();
+        if !(0.0..=99999.0).contains(&tenths) {
+            return None;
+        }
+        Some(AdfFrequency {
+            tenths: tenths as u32,
+        })
+    }
+
+    /// The raw main and extended values.
+    pub fn bcd(&self) -> (u16, u16) {
+        let khz = self.tenths / 10;
+        let main = encode((khz % 1000) as u16).unwrap_or(0);
+        let extended = (((khz / 1000) << 8) | (self.tenths % 10)) as u16;
+        (main, extended)
+    }
+
+    /// The frequency in kHz.
+    pub fn khz(&self) -> f64 {
+        self.tenths as f64 / 10.0
+    }
+}
+
+impl fmt::Display for AdfFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}", self.khz())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_decode_and_encode_bcd() {
+        assert_eq!(decode(0x7000), Some(7000));
+        assert_eq!(decode(0x0123), Some(123));
+        assert_eq!(decode(0x12A4), None);
+        assert_eq!(encode(7000), Some(0x7000));
+        assert_eq!(encode(10000), None);
+    }
+
+    #[test]
+    fn should_convert_vhf_frequencies() {
+        let frequency = Frequency::from_bcd(0x2345).unwrap();
+        assert_eq!(frequency.mhz(), 123.45);
+        assert_eq!(frequency.to_string(), "123.45");
+        assert_eq!(Frequency::from_mhz(118.0).unwrap().bcd(), 0x1800);
+        assert_eq!(Frequency::from_mhz(121.499).unwrap().bcd(), 0x2150);
+        assert!(Frequency::from_mhz(99.0).is_none());
+        assert!(Frequency::from_bcd(0x2F00).is_none());
+    }
+
+    #[test]
+    fn should_convert_adf_frequencies() {
+        let frequency = AdfFrequency::from_bcd(0x0234, 0x0105).unwrap();
+        assert_eq!(frequency.khz(), 1234.5);
+        assert_eq!(frequency.bcd(), (0x0234, 0x0105));
+        assert_eq!(AdfFrequency::from_khz(379.0).unwrap().bcd(), (0x0379, 0));
+        assert!(AdfFrequency::from_bcd(0x0234, 0x0A00).is_none());
+    }
+}
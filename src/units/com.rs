@@ -0,0 +1,98 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! COM channels with 8.33 kHz spacing
+//! The BCD COM offsets cannot hold the channels of the 8.33 kHz spacing, so FSUIPC also gives
+//! the COM frequencies in Hz. They hold the channel as dialled in the radio rather than the
+//! frequency actually transmitted (e.g. 118005000 for the channel 118.005, which transmits on
+//! 118.000 MHz). Every 25 kHz block has four channels: the 25 kHz one ending in 00, 25, 50 or
+//! 75 kHz and the 8.33 kHz ones ending 5, 10 and 15 kHz after it.
+
+// `is_multiple_of()` needs Rust 1.87, later than the crate otherwise requires
+#![allow(clippy::manual_is_multiple_of)]
+
+use std::fmt;
+
+use super::bcd::Frequency;
+
+/// A COM channel, with 25 or 8.33 kHz spacing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ComFrequency {
+    khz: u32,
+}
+
+impl ComFrequency {
+    /// Decode a channel from its value in Hz.
+    /// It returns `None` if it is not a whole channel of 118.000 to 136.990 MHz.
+    pub fn from_hz(hz: u32) -> Option<Self> {
+        if hz % 1000 != 0 {
+            return None;
+        }
+        ComFrequency::from_khz(hz / 1000)
+    }
+
+    /// Obtain the channel closest to the given MHz, which must be a channel of 118.000 to
+    /// 136.990 MHz (e.g. 118.005, but not 118.020).
+    pub fn from_mhz(mhz: f64) -> Option<Self> {
+        ComFrequency::from_khz((mhz * 1000.0).round() as u32)
+    }
+
+    fn from_khz(khz: u32) -> Option<Self> {
+        let valid = (118_000..=136_990).contains(&khz) && khz % 5 == 0 && khz % 25 != 20;
+        if valid {
+            Some(ComFrequency { khz })
+        } else {
+            None
+        }
+    }
+
+    /// The value in Hz.
+    pub fn hz(&self) -> u32 {
+        self.khz * 1000
+    }
+
+    /// The channel in MHz.
+    pub fn mhz(&self) -> f64 {
+        self.khz as f64 / 1000.0
+    }
+
+    /// Whether it is a channel of the 8.33 kHz spacing.
+    pub fn is_833(&self) -> bool {
+        self.khz % 25 != 0
+    }
+
+    /// The channel in the BCD offsets, which round 8.33 kHz channels down to 10 kHz.
+    pub fn to_bcd(&self) -> Option<Frequency> {
+        Frequency::from_mhz((self.khz / 10) as f64 / 100.0)
+    }
+}
+
+impl fmt::Display for ComFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}", self.mhz())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_convert_com_channels() {
+        let channel = ComFrequency::from_hz(118_005_000).unwrap();
+        assert!(channel.is_833());
+        assert_eq!(channel.to_string(), "118.005");
+        assert_eq!(channel.to_bcd().unwrap().bcd(), 0x1800);
+        assert!(!ComFrequency::from_mhz(121.5).unwrap().is_833());
+        assert_eq!(ComFrequency::from_mhz(132.815).unwrap().hz(), 132_815_000);
+        assert!(ComFrequency::from_mhz(118.02).is_none());
+        assert!(ComFrequency::from_mhz(137.0).is_none());
+        assert!(ComFrequency::from_hz(118_008_333).is_none());
+    }
+}
@@ -8,187 +8,502 @@
 
 use std::ffi::CString;
 use std::io;
+use std::mem::size_of;
 use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
+use super::compat::{connection_hint, integrity_mismatch};
+use super::error::FsuipcError;
 use super::ipc::*;
 use super::raw::{MutRawBytes, RawBytes};
+use super::report::ProcessReport;
+use super::sim::{find_windows, SimTarget, SimWindow};
+use super::sys::{self, KernelHandle, SendFailure, WindowHandle};
 use super::{Handle, Session};
-use winapi::shared::{minwindef::{ATOM, LPCVOID}, windef::HWND};
-use winapi::um::{
-    handleapi::{INVALID_HANDLE_VALUE, CloseHandle},
-    memoryapi::{FILE_MAP_WRITE, MapViewOfFile, UnmapViewOfFile},
-    winnt::{HANDLE, PAGE_READWRITE},
-    winuser::{FindWindowExA, RegisterWindowMessageA, SendMessageA},
-    processthreadsapi::GetCurrentProcessId,
-    winbase::{GlobalAddAtomA, CreateFileMappingA, GlobalDeleteAtom},
-};
+
+/// The result of a liveness check of a `UserHandle`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Liveness {
+    /// FSUIPC is there, and answered the probe if any.
+    Connected,
+    /// The FSUIPC window is gone, e.g. because the simulator was closed.
+    WindowGone,
+    /// The FSUIPC window exists but it did not answer the probe in time.
+    NotResponding,
+}
 
 pub struct UserHandle {
-    handle: HWND,
-    file_mapping_atom: ATOM,
-    file_mapping: HANDLE,
+    handle: WindowHandle,
+    file_mapping_atom: u16,
+    file_mapping: KernelHandle,
     msg_id: u32,
     data: *mut u8,
+    timeout: Duration,
+    capacity: usize,
 }
 
+// The handle exclusively owns its file mapping and atom, which are process-wide resources that
+// can be used from any thread.
+unsafe impl Send for UserHandle {}
+
 impl UserHandle {
+    /// Connect to the first FSUIPC window found, whatever simulator it belongs to.
     pub fn new() -> io::Result<Self> {
-        unsafe {
-            let win_name = CString::new("UIPCMAIN").unwrap();
-            let handle = FindWindowExA(
-                ptr::null_mut(),
-                ptr::null_mut(),
-                win_name.as_ptr(),
-                ptr::null_mut(),
-            );
-            if handle.is_null() {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to user FSUIPC: cannot create window handle",
-                ));
-            }
-            let msg_name = CString::new("FsasmLib:IPC").unwrap();
-            let msg_id = RegisterWindowMessageA(msg_name.as_ptr());
-            if msg_id == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to user FSUIPC: cannot register window message",
-                ));
-            }
+        UserHandle::for_sim(SimTarget::Any)
+    }
 
-            let file_mapping_name = CString::new(format!(
-                "FsasmLib:IPC:{:x}:{:x}",
-                GetCurrentProcessId(),
-                next_file_mapping_index()
+    /// Connect to the FSUIPC window that belongs to the given simulator.
+    /// This is useful when more than one FSUIPC build is running at the same time.
+    pub fn for_sim(target: SimTarget) -> io::Result<Self> {
+        match UserHandle::enumerate()
+            .into_iter()
+            .find(|window| target.accepts(window.sim()))
+        {
+            Some(window) => UserHandle::with_window(&window),
+            None => Err(FsuipcError::SimNotRunning(format!(
+                "cannot find a FSUIPC window for {:?}{}",
+                target,
+                connection_hint()
             ))
-            .unwrap();
-
-            let file_mapping_atom = GlobalAddAtomA(file_mapping_name.as_ptr());
-            if file_mapping_atom == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to user FSUIPC: cannot add global atom",
-                ));
+            .into()),
+        }
+    }
+
+    /// Connect to the first FSUIPC window found, sharing `capacity` bytes of memory with it.
+    /// The default 64 KB may not be enough to read large blocks (e.g. TCAS tables) in a single
+    /// session. The capacity must be between `MIN_CAPACITY` and `MAX_CAPACITY`.
+    pub fn with_capacity(capacity: usize) -> io::Result<Self> {
+        UserHandle::builder().capacity(capacity).connect()
+    }
+
+    /// Configure a handle for FSUIPC-compatible servers that differ from FSUIPC itself.
+    pub fn builder() -> UserHandleBuilder {
+        UserHandleBuilder::default()
+    }
+
+    /// The size of the memory shared with FSUIPC, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Enumerate the FSUIPC windows of all the running simulators, in window Z order.
+    pub fn enumerate() -> Vec<SimWindow> {
+        find_windows(WINDOW_CLASS)
+    }
+
+    /// Connect to the FSUIPC window owned by the process with the given id.
+    pub fn for_process(process_id: u32) -> io::Result<Self> {
+        match UserHandle::enumerate()
+            .into_iter()
+            .find(|window| window.process_id() == process_id)
+        {
+            Some(window) => UserHandle::with_window(&window),
+            None => Err(FsuipcError::SimNotRunning(format!(
+                "process {} owns no FSUIPC window",
+                process_id
+            ))
+            .into()),
+        }
+    }
+
+    /// Connect to the first window of the given class instead of `UIPCMAIN`.
+    /// This is useful to talk to a WideClient running on a networked PC, which emulates the
+    /// FSUIPC window under the class name set in its configuration.
+    pub fn for_class(class_name: &str) -> io::Result<Self> {
+        UserHandle::builder().window_class(class_name).connect()
+    }
+
+    /// Connect to a FSUIPC window obtained from `UserHandle::enumerate()`.
+    pub fn with_window(window: &SimWindow) -> io::Result<Self> {
+        UserHandle::connect(window.window(), MESSAGE_NAME, DEFAULT_CAPACITY)
+    }
+
+    /// Set the time to wait for FSUIPC to process a session, 10 seconds by default.
+    /// Processing fails with `FsuipcError::Timeout` when FSUIPC does not answer in time, e.g.
+    /// because the simulator is hung in a dialog, instead of blocking forever.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Check whether FSUIPC is still there without a full request.
+    /// It checks that the FSUIPC window still exists. With a `probe` timeout, it also reads one
+    /// byte of the FSUIPC version offset (0x3304), waiting at most that time for an answer.
+    pub fn is_alive(&mut self, probe: Option<Duration>) -> Liveness {
+        if !sys::is_window(self.handle) {
+            return Liveness::WindowGone;
+        }
+        let timeout = match probe {
+            Some(timeout) => timeout,
+            None => return Liveness::Connected,
+        };
+        let mut version = 0u8;
+        let mut session = self.session();
+        let result = session
+            .read(0x3304, &mut version)
+            .and_then(|_| session.process_within(timeout));
+        match result {
+            Ok(_) => Liveness::Connected,
+            Err(_) => Liveness::NotResponding,
+        }
+    }
+
+    fn connect(handle: WindowHandle, message_name: &str, capacity: usize) -> io::Result<Self> {
+        let msg_name = CString::new(message_name).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the message name cannot contain NUL characters",
+            )
+        })?;
+        let msg_id = sys::register_window_message(&msg_name);
+        if msg_id == 0 {
+            return Err(FsuipcError::ConnectionRefused(
+                "cannot register window message".to_string(),
+            )
+            .into());
+        }
+
+        let file_mapping_name = CString::new(format!(
+            "FsasmLib:IPC:{:x}:{:x}",
+            sys::current_process_id(),
+            next_file_mapping_index()
+        ))
+        .unwrap();
+
+        let file_mapping_atom = sys::add_atom(&file_mapping_name);
+        if file_mapping_atom == 0 {
+            return Err(FsuipcError::Mapping(format!(
+                "cannot add global atom{}",
+                connection_hint()
+            ))
+            .into());
+        }
+
+        let file_mapping = match sys::create_file_mapping(&file_mapping_name, capacity) {
+            Some(file_mapping) => file_mapping,
+            None => {
+                return Err(FsuipcError::Mapping("cannot create file mapping".to_string()).into())
             }
+        };
+        let data = sys::map_view(file_mapping);
+        if data.is_null() {
+            return Err(FsuipcError::Mapping("cannot map view of file".to_string()).into());
+        }
+        Ok(UserHandle {
+            handle,
+            file_mapping_atom,
+            file_mapping,
+            msg_id,
+            data,
+            timeout: DEFAULT_TIMEOUT,
+            capacity,
+        })
+    }
+
+    /// Lay out the given reads, as `(offset, len)` pairs, in the memory shared with FSUIPC.
+    /// The requests are written once, so every poll of the batch only has to message FSUIPC
+    /// and decode the results. The handle cannot be used for anything else while the batch
+    /// lives, since the batch owns the shared memory.
+    pub fn prepare_batch(&mut self, reads: &[(u16, usize)]) -> io::Result<PreparedBatch<'_>> {
+        let needed = reads
+            .iter()
+            .map(|(_, len)| RSD_HEADER_LEN + len)
+            .sum::<usize>()
+            + TERMINATION_MARK_LEN;
+        if needed > self.capacity {
+            return Err(FsuipcError::BufferOverflow {
+                needed,
+                available: self.capacity,
+            }
+            .into());
+        }
+        let mut buffer = MutRawBytes::new(self.data, self.capacity);
+        let mut positions = Vec::with_capacity(reads.len());
+        let mut position = 0;
+        for (index, (offset, len)) in reads.iter().enumerate() {
+            // The batch decodes the values from the shared memory, so the targets are unused
+            position += buffer.write_rsd(*offset, index as u32, *len)?;
+            positions.push((position - len, *len));
+        }
+        buffer.write_header(&MsgHeader::TerminationMark)?;
+        Ok(PreparedBatch {
+            handle: self,
+            reads: positions,
+        })
+    }
 
-            let file_mapping = CreateFileMappingA(
-                INVALID_HANDLE_VALUE,
-                ptr::null_mut(),
-                PAGE_READWRITE,
-                0,
-                FILE_MAPPING_LEN as u32,
-                file_mapping_name.as_ptr(),
-            );
-            if file_mapping.is_null() {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to user FSUIPC: cannot create file mapping",
-                ));
+    /// Send the requests in the shared memory to FSUIPC.
+    fn send(&self, timeout: Duration) -> io::Result<()> {
+        let atom = self.file_mapping_atom as WinUInt;
+        let send_result = match sys::send_message_timeout(
+            self.handle,
+            self.msg_id,
+            atom,
+            0,
+            timeout_millis(timeout),
+        ) {
+            Ok(send_result) => send_result as WinInt,
+            Err(SendFailure::Timeout) => return Err(FsuipcError::Timeout(timeout).into()),
+            Err(SendFailure::WindowGone) => {
+                return Err(
+                    FsuipcError::SimNotRunning("the FSUIPC window is gone".to_string()).into(),
+                )
             }
-            let data = MapViewOfFile(file_mapping, FILE_MAP_WRITE, 0, 0, 0) as *mut u8;
-            if data.is_null() {
-                return Err(io::Error::new(
-                    io::ErrorKind::ConnectionRefused,
-                    "cannot connect to user FSUIPC: cannot map view of file",
-                ));
+        };
+        if send_result != FS6IPC_MESSAGE_SUCCESS {
+            if let Some(reason) = integrity_mismatch(self.handle) {
+                return Err(FsuipcError::AccessDenied(reason).into());
             }
-            Ok(UserHandle {
-                handle,
-                file_mapping_atom,
-                file_mapping,
-                msg_id,
-                data,
-            })
+            return Err(FsuipcError::ProtocolRejection(send_result).into());
+        }
+        Ok(())
+    }
+}
+
+/// A builder of `UserHandle` objects
+/// Some FSUIPC-compatible servers (XPUIPC, older ESP builds, WideClient) register another
+/// window class or message name than FSUIPC. The defaults are those of FSUIPC.
+#[derive(Clone, Debug)]
+pub struct UserHandleBuilder {
+    window_class: String,
+    message_name: String,
+    timeout: Duration,
+    capacity: usize,
+}
+
+impl Default for UserHandleBuilder {
+    fn default() -> Self {
+        UserHandleBuilder {
+            window_class: WINDOW_CLASS.to_string(),
+            message_name: MESSAGE_NAME.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            capacity: DEFAULT_CAPACITY,
         }
     }
 }
 
+impl UserHandleBuilder {
+    /// Connect to the first window of the given class, `UIPCMAIN` by default.
+    pub fn window_class(mut self, window_class: &str) -> Self {
+        self.window_class = window_class.to_string();
+        self
+    }
+
+    /// Register the given window message to talk to the server, `FsasmLib:IPC` by default.
+    pub fn message_name(mut self, message_name: &str) -> Self {
+        self.message_name = message_name.to_string();
+        self
+    }
+
+    /// Set the time to wait for the server to process a session, 10 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Share `capacity` bytes of memory with the server, 64 KB by default.
+    /// The capacity must be between `MIN_CAPACITY` and `MAX_CAPACITY`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Connect to the server.
+    pub fn connect(self) -> io::Result<UserHandle> {
+        if !(MIN_CAPACITY..=MAX_CAPACITY).contains(&self.capacity) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid capacity of {} bytes, it must be between {} and {}",
+                    self.capacity, MIN_CAPACITY, MAX_CAPACITY
+                ),
+            ));
+        }
+        let window = match find_windows(&self.window_class).into_iter().next() {
+            Some(window) => window,
+            None => {
+                return Err(FsuipcError::SimNotRunning(format!(
+                    "cannot find a {} window{}",
+                    self.window_class,
+                    connection_hint()
+                ))
+                .into())
+            }
+        };
+        let handle = UserHandle::connect(window.window(), &self.message_name, self.capacity)?;
+        Ok(handle.with_timeout(self.timeout))
+    }
+}
+
 impl<'a> Handle<'a> for UserHandle {
     type Sess = UserSession<'a>;
 
     fn session(&'a mut self) -> UserSession<'a> {
         let data = self.data;
+        let capacity = self.capacity;
         UserSession {
             handle: self,
-            buffer: MutRawBytes::new(data, FILE_MAPPING_LEN),
+            buffer: MutRawBytes::new(data, capacity),
+            targets: Vec::new(),
         }
     }
 }
 
 impl Drop for UserHandle {
     fn drop(&mut self) {
-        unsafe {
-            GlobalDeleteAtom(self.file_mapping_atom);
-            UnmapViewOfFile(self.data as LPCVOID);
-            CloseHandle(self.file_mapping);
-        }
+        sys::delete_atom(self.file_mapping_atom);
+        sys::unmap_view(self.data);
+        sys::close_handle(self.file_mapping);
     }
 }
 
 pub struct UserSession<'a> {
     handle: &'a mut UserHandle,
     buffer: MutRawBytes,
+    targets: Vec<*mut u8>,
 }
 
+// The buffer points into the file mapping of the handle, which the session borrows mutably, so
+// no other thread can access it while the session is alive.
+unsafe impl<'a> Send for UserSession<'a> {}
+
 impl<'a> Session for UserSession<'a> {
     fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
-        self.buffer.write_rsd(offset, dest, len)
+        self.reserve(RSD_HEADER_LEN + len)?;
+        self.buffer
+            .write_rsd(offset, self.targets.len() as u32, len)?;
+        self.targets.push(dest);
+        Ok(RSD_HEADER_LEN + len)
     }
 
     fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        self.reserve(WSD_HEADER_LEN + len)?;
         self.buffer.write_wsd(offset, src, len)
     }
 
-    fn process(mut self) -> io::Result<usize> {
-        unsafe {
-            self.buffer.write_header(&MsgHeader::TerminationMark)?;
-            let send_result = SendMessageA(
-                self.handle.handle,
-                self.handle.msg_id,
-                self.handle.file_mapping_atom as WinUInt,
-                0,
-            );
-            if send_result != FS6IPC_MESSAGE_SUCCESS {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!(
-                        "FSUIPC rejected the requests with error {}; possible buffer corruption",
-                        send_result
-                    ),
-                ));
-            }
-            let mut buffer = RawBytes::new(self.handle.data, FILE_MAPPING_LEN);
-            loop {
-                let header = buffer.read_header()?;
-                match header {
-                    MsgHeader::ReadStateData {
-                        offset: _,
-                        len,
-                        target,
-                    } => {
-                        let mut output = MutRawBytes::new(target, len);
-                        buffer.read_body(&header, &mut output)?;
-                    }
-                    MsgHeader::WriteStateData { offset: _, len: _ } => {
-                        let mut output = io::sink();
-                        buffer.read_body(&header, &mut output)?;
-                    }
-                    MsgHeader::TerminationMark => return Ok(buffer.consumed()),
-                }
-            }
+    fn process(self) -> io::Result<usize> {
+        let timeout = self.handle.timeout;
+        self.process_within(timeout)
+    }
+}
+
+impl<'a> UserSession<'a> {
+    /// Process the requests and rewind the session, so it can be refilled with new requests.
+    /// Unlike `process()`, the session is not consumed, so a poll loop can reuse it instead of
+    /// creating a new one on every cycle. The session is rewound even if processing fails.
+    pub fn process_reset(&mut self) -> io::Result<usize> {
+        let timeout = self.handle.timeout;
+        let result = self.execute(timeout);
+        self.buffer = MutRawBytes::new(self.handle.data, self.handle.capacity);
+        self.targets.clear();
+        result
+    }
+
+    /// Check that a request of `len` bytes fits in the file mapping with the termination mark.
+    /// Otherwise the request is not added, so the session can still be processed.
+    fn reserve(&self, len: usize) -> io::Result<()> {
+        let needed = len + TERMINATION_MARK_LEN;
+        let available = self.buffer.remaining();
+        if needed > available {
+            return Err(FsuipcError::BufferOverflow { needed, available }.into());
         }
+        Ok(())
+    }
+
+    /// Process the session waiting at most `timeout` for FSUIPC.
+    fn process_within(mut self, timeout: Duration) -> io::Result<usize> {
+        self.execute(timeout)
+    }
+
+    /// Process the requests, reporting the outcome of each one.
+    /// Failures to reach FSUIPC are still errors, but a response that cannot be fully decoded
+    /// gives a report that is not complete.
+    pub fn process_report(mut self) -> io::Result<ProcessReport> {
+        let timeout = self.handle.timeout;
+        let mut report = ProcessReport::default();
+        self.send_requests(timeout)?;
+        let mut buffer = RawBytes::new(self.handle.data, self.handle.capacity);
+        let _ = read_response(&mut buffer, &self.targets, &mut report);
+        Ok(report)
+    }
+
+    fn execute(&mut self, timeout: Duration) -> io::Result<usize> {
+        self.send_requests(timeout)?;
+        let mut buffer = RawBytes::new(self.handle.data, self.handle.capacity);
+        read_response(&mut buffer, &self.targets, &mut ProcessReport::default())?;
+        Ok(buffer.consumed())
+    }
+
+    fn send_requests(&mut self, timeout: Duration) -> io::Result<()> {
+        self.buffer.write_header(&MsgHeader::TerminationMark)?;
+        self.handle.send(timeout)
     }
 }
 
-fn next_file_mapping_index() -> u32 {
-    unsafe {
-        let next = FILE_MAPPING_INDEX;
-        FILE_MAPPING_INDEX += 1;
-        next
+/// A set of reads laid out once in the memory shared with FSUIPC
+/// It is obtained from `UserHandle::prepare_batch()`. Each `poll()` refreshes all the values,
+/// which are then decoded directly from the shared memory.
+pub struct PreparedBatch<'a> {
+    handle: &'a mut UserHandle,
+    reads: Vec<(usize, usize)>,
+}
+
+// Like a session, the batch borrows the handle mutably, so no other thread can access the shared
+// memory while the batch is alive.
+unsafe impl<'a> Send for PreparedBatch<'a> {}
+
+impl<'a> PreparedBatch<'a> {
+    /// Refresh the values of all the reads.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let timeout = self.handle.timeout;
+        self.handle.send(timeout)
     }
+
+    /// The number of reads in the batch.
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Whether the batch has no reads.
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+
+    /// The bytes of the read at `index` as of the last poll.
+    /// It panics if there is no such read.
+    pub fn bytes(&self, index: usize) -> &[u8] {
+        let (position, len) = self.reads[index];
+        unsafe { slice::from_raw_parts(self.handle.data.add(position), len) }
+    }
+
+    /// The value of the read at `index` as of the last poll.
+    /// It panics if there is no such read or its length is not the size of `T`.
+    pub fn value<T: Copy>(&self, index: usize) -> T {
+        let bytes = self.bytes(index);
+        assert_eq!(bytes.len(), size_of::<T>(), "read of a different size");
+        unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+    }
+}
+
+fn next_file_mapping_index() -> u32 {
+    FILE_MAPPING_INDEX.fetch_add(1, Ordering::Relaxed)
 }
 
 const FS6IPC_MESSAGE_SUCCESS: WinInt = 1;
-const FILE_MAPPING_LEN: usize = 64 * 1024;
+const WINDOW_CLASS: &str = "UIPCMAIN";
+const MESSAGE_NAME: &str = "FsasmLib:IPC";
+
+/// The size of the memory shared with FSUIPC unless told otherwise.
+pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+/// The smallest memory that can be shared with FSUIPC, enough for a few small requests.
+pub const MIN_CAPACITY: usize = 1024;
+/// The largest memory that can be shared with FSUIPC.
+/// The lengths of the requests are 32-bit, but no transaction needs more than a few copies of
+/// the 64 KB offsets area, so larger mappings are most likely a mistake.
+pub const MAX_CAPACITY: usize = 16 * 1024 * 1024;
 
-static mut FILE_MAPPING_INDEX: u32 = 0;
+// Handles may be created from several threads at once, and each one needs a file mapping with
+// a name of its own.
+static FILE_MAPPING_INDEX: AtomicU32 = AtomicU32::new(0);
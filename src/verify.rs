@@ -0,0 +1,248 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Verified writes
+//! A verified write is queued along with a read of the same offset, and checked once the
+//! session is processed. Sessions send their requests as they are queued and keep no state of
+//! their own, so the checks live in a `VerifiedSession` wrapping the session, obtained with
+//! `Session::verified()`, rather than in `write_verified()` on every session: the values read
+//! back need a place that outlives the call and is seen by `process()`.
+
+use std::io;
+use std::mem::size_of;
+use std::slice;
+
+use super::error::FsuipcError;
+use super::offsets::Offset;
+use super::Session;
+
+/// A verified write whose value did not read back
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteMismatch {
+    pub offset: u16,
+    /// The bytes written.
+    pub expected: Vec<u8>,
+    /// The bytes read back right after the write.
+    pub actual: Vec<u8>,
+}
+
+struct Check {
+    offset: u16,
+    expected: Vec<u8>,
+    actual: Box<[u8]>,
+}
+
+/// A session able to verify that critical writes landed
+/// Each `write_verified()` queues the write followed by a read of the same offset in the same
+/// transaction. Once processed, the values read back are compared with the values written, and
+/// `process()` fails with `FsuipcError::WriteMismatch` if any differs, e.g. because the offset
+/// is read only or the aircraft overrides it. Other requests are passed to the inner session.
+/// A write whose read back cannot be queued poisons the session: `process()` then fails without
+/// processing the inner session, so the write is never sent unverified.
+pub struct VerifiedSession<S> {
+    inner: S,
+    checks: Vec<Check>,
+    poisoned: Option<io::Error>,
+}
+
+impl<S: Session> VerifiedSession<S> {
+    pub fn new(inner: S) -> Self {
+        VerifiedSession {
+            inner,
+            checks: Vec::new(),
+            poisoned: None,
+        }
+    }
+
+    /// Request writing a value and reading it back to check it landed.
+    pub fn write_verified<T>(&mut self, offset: u16, value: &T) -> io::Result<usize> {
+        let len = size_of::<T>();
+        let src = value as *const T as *const u8;
+        let expected = unsafe { slice::from_raw_parts(src, len) }.to_vec();
+        let mut actual = vec![0u8; len].into_boxed_slice();
+        self.inner.write_bytes(offset, src, len)?;
+        // The boxed buffer does not move when the check is stored, so the read can target it
+        if let Err(error) = self.inner.read_bytes(offset, actual.as_mut_ptr(), len) {
+            self.poisoned = Some(io::Error::new(
+                error.kind(),
+                format!(
+                    "the write to offset 0x{:04X} cannot be verified: {}",
+                    offset, error
+                ),
+            ));
+            return Err(error);
+        }
+        self.checks.push(Check {
+            offset,
+            expected,
+            actual,
+        });
+        Ok(len)
+    }
+
+    /// Request writing a catalog offset and reading it back to check it landed.
+    pub fn write_verified_offset<T>(&mut self, offset: Offset<T>, value: &T) -> io::Result<usize> {
+        self.write_verified(offset.address(), value)
+    }
+
+    /// Process the requests, obtaining the verified writes that did not read back.
+    pub fn process_verified(self) -> io::Result<Vec<WriteMismatch>> {
+        Ok(self.finish()?.1)
+    }
+
+    fn finish(self) -> io::Result<(usize, Vec<WriteMismatch>)> {
+        if let Some(error) = self.poisoned {
+            return Err(error);
+        }
+        let processed = self.inner.process()?;
+        let mismatches = self
+            .checks
+            .into_iter()
+            .filter(|check| check.expected[..] != check.actual[..])
+            .map(|check| WriteMismatch {
+                offset: check.offset,
+                expected: check.expected,
+                actual: check.actual.into_vec(),
+            })
+            .collect();
+        Ok((processed, mismatches))
+    }
+}
+
+impl<S: Session> Session for VerifiedSession<S> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.inner.read_bytes(offset, dest, len)
+    }
+
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        self.inner.write_bytes(offset, src, len)
+    }
+
+    fn process(self) -> io::Result<usize> {
+        let (processed, mismatches) = self.finish()?;
+        match mismatches.first() {
+            Some(mismatch) => Err(FsuipcError::WriteMismatch(mismatch.offset).into()),
+            None => Ok(processed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::{MockHandle, MockSession};
+    use crate::Handle;
+
+    // A session on offsets the simulator keeps at zero, like a read-only offset
+    struct ReadOnlySession<'a> {
+        inner: MockSession<'a>,
+    }
+
+    impl<'a> Session for ReadOnlySession<'a> {
+        fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            self.inner.read_bytes(offset, dest, len)
+        }
+
+        fn write_bytes(&mut self, _offset: u16, _src: *const u8, len: usize) -> io::Result<usize> {
+            Ok(len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            self.inner.process()
+        }
+    }
+
+    #[test]
+    fn should_verify_writes_that_landed() {
+        let mut handle = MockHandle::new();
+        let mut session = VerifiedSession::new(handle.session());
+        session.write_verified(0x07D4, &0x1000_0000u32).unwrap();
+        session.write(0x0330, &0x3FC0u16).unwrap();
+        assert_eq!(session.process_verified().unwrap(), vec![]);
+        assert_eq!(handle.value::<u32>(0x07D4), 0x1000_0000);
+        assert_eq!(handle.value::<u16>(0x0330), 0x3FC0);
+    }
+
+    #[test]
+    fn should_report_writes_that_did_not_read_back() {
+        let mut handle = MockHandle::new();
+        let inner = ReadOnlySession {
+            inner: handle.session(),
+        };
+        let mut session = VerifiedSession::new(inner);
+        session.write_verified(0x0B74, &1500u32).unwrap();
+        let error = session.process().err().unwrap();
+        match FsuipcError::from_io(&error) {
+            Some(FsuipcError::WriteMismatch(offset)) => assert_eq!(*offset, 0x0B74),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_verify_writes_from_any_session() {
+        let mut handle = MockHandle::new();
+        let mut session = handle.session().verified();
+        session.write_verified(0x07D4, &0x1000_0000u32).unwrap();
+        session.write_verified(0x0B74, &1500u32).unwrap();
+        assert_eq!(session.process_verified().unwrap(), vec![]);
+        assert_eq!(handle.value::<u32>(0x0B74), 1500);
+    }
+
+    // A session with room for a single request, like a nearly full buffer
+    struct OneRequestSession<'a> {
+        inner: MockSession<'a>,
+        requests: usize,
+    }
+
+    impl<'a> OneRequestSession<'a> {
+        fn request(&mut self) -> io::Result<()> {
+            self.requests += 1;
+            if self.requests > 1 {
+                return Err(FsuipcError::BufferOverflow {
+                    needed: self.requests,
+                    available: 1,
+                }
+                .into());
+            }
+            Ok(())
+        }
+    }
+
+    impl<'a> Session for OneRequestSession<'a> {
+        fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+            self.request()?;
+            self.inner.read_bytes(offset, dest, len)
+        }
+
+        fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+            self.request()?;
+            self.inner.write_bytes(offset, src, len)
+        }
+
+        fn process(self) -> io::Result<usize> {
+            self.inner.process()
+        }
+    }
+
+    #[test]
+    fn should_not_send_writes_that_cannot_be_verified() {
+        let mut handle = MockHandle::new();
+        let inner = OneRequestSession {
+            inner: handle.session(),
+            requests: 0,
+        };
+        let mut session = VerifiedSession::new(inner);
+        assert!(session.write_verified(0x0B74, &1500u32).is_err());
+        let error = session.process().err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("0x0B74"));
+        assert_eq!(handle.value::<u32>(0x0B74), 0);
+        assert_eq!(handle.transactions(), 0);
+    }
+}
@@ -0,0 +1,183 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Voice commands
+//! `VoiceCommands` maps simple phrases, such as "gear up" or "flaps two", to the control or
+//! offset write they stand for. On Windows, `VoiceRecognizer` listens for those phrases through
+//! the shared speech recognizer of the system, so the aircraft can be operated hands-free.
+
+use std::io;
+use std::mem::size_of;
+use std::slice;
+
+use super::controls::EventId;
+use super::Session;
+
+/// What a voice command does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandAction {
+    /// Send a control with its parameter.
+    Control(EventId, i32),
+    /// Write the bytes to the offset.
+    Write { offset: u16, bytes: Vec<u8> },
+}
+
+/// The phrases understood as commands and their actions
+/// Phrases are matched ignoring case and extra whitespace.
+#[derive(Clone, Debug, Default)]
+pub struct VoiceCommands {
+    commands: Vec<(String, CommandAction)>,
+}
+
+impl VoiceCommands {
+    pub fn new() -> Self {
+        VoiceCommands::default()
+    }
+
+    /// Send a control when the phrase is said.
+    pub fn with_control(self, phrase: &str, event: EventId, param: i32) -> Self {
+        self.with_action(phrase, CommandAction::Control(event, param))
+    }
+
+    /// Write a value into the offset when the phrase is said.
+    pub fn with_write<T: Copy>(self, phrase: &str, offset: u16, value: T) -> Self {
+        let bytes =
+            unsafe { slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        self.with_action(
+            phrase,
+            CommandAction::Write {
+                offset,
+                bytes: bytes.to_vec(),
+            },
+        )
+    }
+
+    /// Do the action when the phrase is said, replacing any previous action of the phrase.
+    pub fn with_action(mut self, phrase: &str, action: CommandAction) -> Self {
+        let phrase = normalize(phrase);
+        self.commands.retain(|(p, _)| *p != phrase);
+        self.commands.push((phrase, action));
+        self
+    }
+
+    /// The phrases of the commands, normalized.
+    pub fn phrases(&self) -> impl Iterator<Item = &str> {
+        self.commands.iter().map(|(phrase, _)| phrase.as_str())
+    }
+
+    /// The action of the given phrase, if any.
+    pub fn action(&self, phrase: &str) -> Option<&CommandAction> {
+        let phrase = normalize(phrase);
+        self.commands
+            .iter()
+            .find(|(p, _)| *p == phrase)
+            .map(|(_, action)| action)
+    }
+
+    /// Request the action of the given phrase, returning whether the phrase is a command.
+    pub fn apply<S: Session>(&self, session: &mut S, phrase: &str) -> io::Result<bool> {
+        match self.action(phrase) {
+            Some(CommandAction::Control(event, param)) => {
+                session.send_control(*event, *param)?;
+            }
+            Some(CommandAction::Write { offset, bytes }) => {
+                session.write_bytes(*offset, bytes.as_ptr(), bytes.len())?;
+            }
+            None => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn normalize(phrase: &str) -> String {
+    phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A listener for voice commands through the shared speech recognizer of Windows
+/// It only recognizes the phrases of the commands it was created with.
+#[cfg(windows)]
+pub struct VoiceRecognizer {
+    context: Option<super::sys::ComObject>,
+    grammar: Option<super::sys::ComObject>,
+}
+
+#[cfg(windows)]
+impl VoiceRecognizer {
+    /// Listen for the phrases of the given commands.
+    pub fn new(commands: &VoiceCommands) -> io::Result<Self> {
+        let phrases: Vec<Vec<u16>> = commands
+            .phrases()
+            .map(|phrase| phrase.encode_utf16().chain(Some(0)).collect())
+            .collect();
+        match super::sys::create_recognizer(&phrases) {
+            Some((context, grammar)) => Ok(VoiceRecognizer {
+                context: Some(context),
+                grammar: Some(grammar),
+            }),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "cannot set up the speech recognizer",
+            )),
+        }
+    }
+
+    /// Wait at most `timeout` for a phrase to be recognized.
+    pub fn recognize(&self, timeout: std::time::Duration) -> Option<String> {
+        let context = self
+            .context
+            .as_ref()
+            .expect("recognizer released before drop");
+        super::sys::wait_phrase(context, super::ipc::timeout_millis(timeout))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for VoiceRecognizer {
+    fn drop(&mut self) {
+        if let Some(grammar) = self.grammar.take() {
+            super::sys::release(grammar);
+        }
+        if let Some(context) = self.context.take() {
+            super::sys::release(context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::mock::MockHandle;
+    use crate::Handle;
+
+    #[test]
+    fn should_apply_commands_said() {
+        let commands = VoiceCommands::new()
+            .with_control("Gear  up", EventId::GearUp, 0)
+            .with_write("flaps two", 0x0BDC, 8192u32);
+        assert_eq!(
+            commands.phrases().collect::<Vec<_>>(),
+            vec!["gear up", "flaps two"]
+        );
+
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            assert!(commands.apply(&mut session, "gear up").unwrap());
+            assert!(commands.apply(&mut session, "Flaps Two").unwrap());
+            assert!(!commands.apply(&mut session, "gear down").unwrap());
+            session.process().unwrap();
+        }
+        assert_eq!(handle.value::<u32>(0x3110), 66079);
+        assert_eq!(handle.value::<u32>(0x0BDC), 8192);
+    }
+}
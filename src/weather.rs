@@ -0,0 +1,610 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! New Weather Interface
+//! FSUIPC exchanges the weather through three areas of the offset space, each holding a
+//! `NewWeather`: the weather at the aircraft from 0xCC00, which is read only; the weather of a
+//! station from 0xC400, requested by writing its ICAO code into the area and waiting for the
+//! timestamp to change; and the area from 0xC800, where weather engines write the weather to
+//! set along with the command that tells FSUIPC what to do with it. Altitudes are in metres
+//! above mean sea level.
+//! The METAR of a station is requested the same way, by writing its ICAO code to 0xB800, which
+//! FSUIPC replaces with the METAR once it has it.
+
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::FsuipcError;
+use super::offsets::Offset;
+use super::strings;
+use super::{Handle, Session};
+
+/// The most temperature layers a `NewWeather` holds.
+pub const MAX_TEMPERATURE_LAYERS: usize = 24;
+
+/// The most wind layers a `NewWeather` holds.
+pub const MAX_WIND_LAYERS: usize = 24;
+
+/// The most cloud layers a `NewWeather` holds.
+pub const MAX_CLOUD_LAYERS: usize = 16;
+
+/// The lowest and highest sea level pressures FSUIPC accepts, in millibars.
+pub const PRESSURE_RANGE: (f64, f64) = (850.0, 1100.0);
+
+/// The weather at the aircraft.
+pub const AIRCRAFT_WEATHER: Offset<NewWeather> = Offset::new(0xCC00);
+
+/// The weather of the station last requested.
+pub const STATION_WEATHER: Offset<NewWeather> = Offset::new(0xC400);
+
+/// The weather to set, along with its command.
+pub const WEATHER_WRITE: Offset<NewWeather> = Offset::new(0xC800);
+
+/// The METAR of the station last requested, zero-terminated ASCII.
+pub const METAR: Offset<[u8; 2048]> = Offset::new(0xB800);
+
+/// The time `read_metar()` waits for the METAR.
+pub const METAR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The position of the ICAO code and the timestamp within a `NewWeather`.
+const ICAO_FIELD: u16 = 8;
+const TIMESTAMP_FIELD: u16 = 36;
+
+/// The time between checks of the station weather.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What FSUIPC does with the weather of the write area
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeatherCommand {
+    /// Set the weather of the station, merged with the weather around it.
+    Set,
+    /// Set the weather of the station exactly as given.
+    SetExact,
+    /// Clear all the weather.
+    Clear,
+    /// Set the rate of change of the weather, from the `dynamics` field.
+    Dynamics,
+    /// Set the weather of the whole world.
+    Global,
+    /// Apply the weather set so far.
+    Activate,
+}
+
+impl WeatherCommand {
+    /// The code of the command in the `command` field.
+    pub fn code(self) -> u16 {
+        match self {
+            WeatherCommand::Set => 0x0C00,
+            WeatherCommand::SetExact => 0x0C01,
+            WeatherCommand::Clear => 0x0C02,
+            WeatherCommand::Dynamics => 0x0C03,
+            WeatherCommand::Global => 0x0C04,
+            WeatherCommand::Activate => 0x0C07,
+        }
+    }
+}
+
+/// The pressure at sea level
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pressure {
+    /// The pressure, in millibars * 16.
+    pub pressure: u16,
+    /// The random drift of the pressure, in millibars * 16.
+    pub drift: i16,
+}
+
+impl Pressure {
+    /// The pressure, in millibars.
+    pub fn millibars(&self) -> f64 {
+        self.pressure as f64 / 16.0
+    }
+}
+
+/// A layer of visibility
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VisibilityLayer {
+    pub upper_altitude: u16,
+    pub lower_altitude: u16,
+    /// The visibility, in hundredths of a statute mile.
+    pub range: u16,
+}
+
+/// A layer of temperature
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TemperatureLayer {
+    pub altitude: u16,
+    /// The temperature during the day, its variation at night and the dew point, in Celsius.
+    pub day: i16,
+    pub day_night_variation: i16,
+    pub dew_point: i16,
+}
+
+/// A layer of wind
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WindLayer {
+    /// The altitude of the top of the layer.
+    pub upper_altitude: u16,
+    /// The speed of the wind and its gusts, in knots.
+    pub speed: u16,
+    pub gust: u16,
+    /// The direction the wind comes from, in 65536ths of a turn from true north.
+    pub direction: u16,
+    /// The turbulence, from 0 (none) to 4 (severe).
+    pub turbulence: u8,
+    /// The shear, from 0 (gradual) to 3 (sudden).
+    pub shear: u8,
+}
+
+/// A layer of clouds
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CloudLayer {
+    pub upper_altitude: u16,
+    pub lower_altitude: u16,
+    /// The random variation of the altitudes, in metres.
+    pub deviation: u16,
+    /// The coverage, in oktas from 0 to 8.
+    pub coverage: u8,
+    /// The type of the clouds, as the simulator numbers them (e.g. 1 for cirrus).
+    pub kind: u8,
+    /// The turbulence and the icing, from 0 (none) to 4 (severe).
+    pub turbulence: u8,
+    pub icing: u8,
+    /// The altitude the precipitation starts from.
+    pub precipitation_base: u16,
+    /// The type of the precipitation (0 none, 1 rain, 2 snow) and its rate, from 0 to 5.
+    pub precipitation_type: u8,
+    pub precipitation_rate: u8,
+    pub top_shape: u8,
+    reserved: u8,
+}
+
+/// The weather of a station, the aircraft or the world
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NewWeather {
+    /// The command of the write area, written by `write_weather()`.
+    pub command: u16,
+    pub flags: u16,
+    pub signature: u32,
+    /// The ICAO code of the station, or `GLOB` for the global weather.
+    pub icao: [u8; 4],
+    /// The rate the weather changes at, from 0 (static) to 4 (fast).
+    pub dynamics: u16,
+    pub seconds: u16,
+    /// The position of the station, in degrees.
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The elevation of the station, in metres * 65536.
+    pub elevation: i32,
+    /// Changed by FSUIPC every time it updates the area.
+    pub timestamp: u32,
+    pub pressure: Pressure,
+    /// The visibility at the surface.
+    pub visibility: VisibilityLayer,
+    reserved: u16,
+    /// The layers in use of each kind, followed by the layers.
+    pub temperature_layers: i32,
+    pub temperatures: [TemperatureLayer; MAX_TEMPERATURE_LAYERS],
+    pub wind_layers: i32,
+    pub winds: [WindLayer; MAX_WIND_LAYERS],
+    pub cloud_layers: i32,
+    pub clouds: [CloudLayer; MAX_CLOUD_LAYERS],
+}
+
+impl NewWeather {
+    /// The ICAO code of the station.
+    pub fn icao(&self) -> String {
+        strings::decode(&self.icao)
+    }
+
+    /// Set the ICAO code of the station, which must have 4 letters or digits.
+    pub fn set_icao(&mut self, icao: &str) -> io::Result<()> {
+        self.icao = encode_icao(icao)?;
+        Ok(())
+    }
+
+    /// The temperature layers in use.
+    pub fn temperatures(&self) -> &[TemperatureLayer] {
+        &self.temperatures[..layers_in_use(self.temperature_layers, MAX_TEMPERATURE_LAYERS)]
+    }
+
+    /// The wind layers in use.
+    pub fn winds(&self) -> &[WindLayer] {
+        &self.winds[..layers_in_use(self.wind_layers, MAX_WIND_LAYERS)]
+    }
+
+    /// The cloud layers in use.
+    pub fn clouds(&self) -> &[CloudLayer] {
+        &self.clouds[..layers_in_use(self.cloud_layers, MAX_CLOUD_LAYERS)]
+    }
+
+    /// Replace the temperature layers.
+    /// It fails with `InvalidInput` if there are more than `MAX_TEMPERATURE_LAYERS`.
+    pub fn set_temperatures(&mut self, layers: &[TemperatureLayer]) -> io::Result<()> {
+        self.temperature_layers = replace_layers(&mut self.temperatures, layers, "temperature")?;
+        Ok(())
+    }
+
+    /// Replace the wind layers.
+    /// It fails with `InvalidInput` if there are more than `MAX_WIND_LAYERS`.
+    pub fn set_winds(&mut self, layers: &[WindLayer]) -> io::Result<()> {
+        self.wind_layers = replace_layers(&mut self.winds, layers, "wind")?;
+        Ok(())
+    }
+
+    /// Replace the cloud layers.
+    /// It fails with `InvalidInput` if there are more than `MAX_CLOUD_LAYERS`.
+    pub fn set_clouds(&mut self, layers: &[CloudLayer]) -> io::Result<()> {
+        self.cloud_layers = replace_layers(&mut self.clouds, layers, "cloud")?;
+        Ok(())
+    }
+
+    /// Check the weather can be given to FSUIPC.
+    /// It fails with `InvalidData` if the number of layers of any kind is out of range, the
+    /// layers are not in ascending order of altitude, a layer is upside down, any turbulence,
+    /// shear, icing or coverage is out of range, or the pressure is out of `PRESSURE_RANGE`.
+    pub fn validate(&self) -> io::Result<()> {
+        check_count(
+            self.temperature_layers,
+            MAX_TEMPERATURE_LAYERS,
+            "temperature",
+        )?;
+        check_count(self.wind_layers, MAX_WIND_LAYERS, "wind")?;
+        check_count(self.cloud_layers, MAX_CLOUD_LAYERS, "cloud")?;
+
+        let (min, max) = PRESSURE_RANGE;
+        let pressure = self.pressure.millibars();
+        check(
+            pressure >= min && pressure <= max,
+            format!("the pressure of {} mb is out of range", pressure),
+        )?;
+        check(
+            self.visibility.lower_altitude <= self.visibility.upper_altitude,
+            "the visibility layer is upside down".to_string(),
+        )?;
+        check(
+            ascending(self.temperatures().iter().map(|layer| layer.altitude)),
+            "the temperature layers are not in ascending order".to_string(),
+        )?;
+        check(
+            ascending(self.winds().iter().map(|layer| layer.upper_altitude)),
+            "the wind layers are not in ascending order".to_string(),
+        )?;
+        for (i, wind) in self.winds().iter().enumerate() {
+            check(
+                wind.turbulence <= 4 && wind.shear <= 3,
+                format!(
+                    "the turbulence or shear of wind layer {} is out of range",
+                    i
+                ),
+            )?;
+        }
+        check(
+            ascending(self.clouds().iter().map(|layer| layer.lower_altitude)),
+            "the cloud layers are not in ascending order".to_string(),
+        )?;
+        for (i, cloud) in self.clouds().iter().enumerate() {
+            check(
+                cloud.lower_altitude < cloud.upper_altitude,
+                format!("cloud layer {} is upside down", i),
+            )?;
+            check(
+                cloud.coverage <= 8 && cloud.turbulence <= 4 && cloud.icing <= 4,
+                format!(
+                    "the coverage, turbulence or icing of cloud layer {} is out of range",
+                    i
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the weather at the aircraft.
+pub fn read_at_aircraft<H>(handle: &mut H) -> io::Result<NewWeather>
+where
+    H: for<'h> Handle<'h>,
+{
+    let mut weather = NewWeather::default();
+    let mut session = handle.session();
+    session.read_offset(AIRCRAFT_WEATHER, &mut weather)?;
+    session.process()?;
+    Ok(weather)
+}
+
+/// Request the weather of the station with the given ICAO code, or `GLOB` for the global
+/// weather, and wait for FSUIPC to provide it, at most `timeout`.
+/// It fails with `InvalidInput` if the code does not have 4 letters or digits, and with
+/// `FsuipcError::Timeout` if the weather is not provided in time.
+pub fn read_station<H>(handle: &mut H, icao: &str, timeout: Duration) -> io::Result<NewWeather>
+where
+    H: for<'h> Handle<'h>,
+{
+    let code = encode_icao(icao)?;
+    let mut previous = 0u32;
+    let mut session = handle.session();
+    session.read(STATION_WEATHER.address() + TIMESTAMP_FIELD, &mut previous)?;
+    session.write(STATION_WEATHER.address() + ICAO_FIELD, &code)?;
+    session.process()?;
+
+    let start = Instant::now();
+    loop {
+        let mut weather = NewWeather::default();
+        let mut session = handle.session();
+        session.read_offset(STATION_WEATHER, &mut weather)?;
+        session.process()?;
+        if weather.timestamp != previous && weather.icao == code {
+            return Ok(weather);
+        }
+        if start.elapsed() >= timeout {
+            return Err(FsuipcError::Timeout(timeout).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Request the METAR of the station with the given ICAO code and wait for FSUIPC to provide
+/// it, at most `METAR_TIMEOUT`.
+/// It fails with `InvalidInput` if the code does not have 4 letters or digits, and with
+/// `FsuipcError::Timeout` if the METAR is not provided in time.
+pub fn read_metar<H>(handle: &mut H, icao: &str) -> io::Result<String>
+where
+    H: for<'h> Handle<'h>,
+{
+    let code = encode_icao(icao)?;
+    let mut session = handle.session();
+    session.write_string(METAR.address(), &strings::decode(&code), code.len() + 1)?;
+    session.process()?;
+
+    let start = Instant::now();
+    loop {
+        let mut metar = [0u8; 2048];
+        let mut session = handle.session();
+        session.read_offset(METAR, &mut metar)?;
+        session.process()?;
+        let metar = strings::decode(&metar);
+        if metar.as_bytes().starts_with(&code) && metar.len() > code.len() {
+            return Ok(metar);
+        }
+        if start.elapsed() >= METAR_TIMEOUT {
+            return Err(FsuipcError::Timeout(METAR_TIMEOUT).into());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Request writing the weather with the given command, once it is validated.
+pub fn write_weather<S: Session>(
+    session: &mut S,
+    weather: &NewWeather,
+    command: WeatherCommand,
+) -> io::Result<()> {
+    weather.validate()?;
+    let mut weather = *weather;
+    weather.command = command.code();
+    session.write_offset(WEATHER_WRITE, &weather)?;
+    Ok(())
+}
+
+/// Request clearing all the weather.
+pub fn clear_weather<S: Session>(session: &mut S) -> io::Result<()> {
+    session.write(WEATHER_WRITE.address(), &WeatherCommand::Clear.code())?;
+    Ok(())
+}
+
+fn encode_icao(icao: &str) -> io::Result<[u8; 4]> {
+    let bytes = icao.as_bytes();
+    if bytes.len() != 4 || !bytes.iter().all(|b| b.is_ascii_alphanumeric()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not an ICAO code", icao),
+        ));
+    }
+    let mut code = [0u8; 4];
+    code.copy_from_slice(&icao.to_ascii_uppercase().as_bytes()[..4]);
+    Ok(code)
+}
+
+fn layers_in_use(count: i32, max: usize) -> usize {
+    count.max(0).min(max as i32) as usize
+}
+
+fn replace_layers<T: Copy + Default>(slots: &mut [T], layers: &[T], kind: &str) -> io::Result<i32> {
+    if layers.len() > slots.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("there can be at most {} {} layers", slots.len(), kind),
+        ));
+    }
+    for (i, slot) in slots.iter_mut().enumerate() {
+        *slot = layers.get(i).copied().unwrap_or_default();
+    }
+    Ok(layers.len() as i32)
+}
+
+fn check_count(count: i32, max: usize, kind: &str) -> io::Result<()> {
+    check(
+        count >= 0 && count as usize <= max,
+        format!("{} {} layers out of {}", count, kind, max),
+    )
+}
+
+fn check(condition: bool, message: String) -> io::Result<()> {
+    if condition {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+}
+
+fn ascending<I: Iterator<Item = u16>>(mut altitudes: I) -> bool {
+    match altitudes.next() {
+        Some(first) => altitudes
+            .try_fold(
+                first,
+                |last, next| if next >= last { Some(next) } else { None },
+            )
+            .is_some(),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::mem::size_of;
+
+    use super::*;
+    use crate::mock::MockHandle;
+
+    fn weather() -> NewWeather {
+        let mut weather = NewWeather::default();
+        weather.set_icao("lemd").unwrap();
+        weather.pressure.pressure = 1013 * 16;
+        weather.visibility.upper_altitude = 2000;
+        weather.visibility.range = 1000;
+        weather
+            .set_winds(&[
+                WindLayer {
+                    upper_altitude: 600,
+                    speed: 10,
+                    direction: 0x4000,
+                    ..WindLayer::default()
+                },
+                WindLayer {
+                    upper_altitude: 3000,
+                    speed: 25,
+                    turbulence: 2,
+                    ..WindLayer::default()
+                },
+            ])
+            .unwrap();
+        weather
+            .set_clouds(&[CloudLayer {
+                lower_altitude: 900,
+                upper_altitude: 1500,
+                coverage: 5,
+                ..CloudLayer::default()
+            }])
+            .unwrap();
+        weather
+    }
+
+    #[test]
+    fn should_match_interface_layout() {
+        assert_eq!(size_of::<WindLayer>(), 10);
+        assert_eq!(size_of::<CloudLayer>(), 16);
+        assert_eq!(size_of::<TemperatureLayer>(), 8);
+        assert_eq!(size_of::<NewWeather>(), 752);
+
+        let weather = NewWeather::default();
+        let base = &weather as *const NewWeather as usize;
+        assert_eq!(
+            &weather.icao as *const _ as usize - base,
+            ICAO_FIELD as usize
+        );
+        assert_eq!(
+            &weather.timestamp as *const _ as usize - base,
+            TIMESTAMP_FIELD as usize
+        );
+        assert_eq!(&weather.clouds as *const _ as usize - base, 496);
+    }
+
+    #[test]
+    fn should_validate_weather() {
+        let weather = weather();
+        assert_eq!(weather.icao(), "LEMD");
+        assert_eq!(weather.winds().len(), 2);
+        assert!(weather.validate().is_ok());
+
+        let mut unordered = weather;
+        unordered.winds.swap(0, 1);
+        assert!(unordered.validate().is_err());
+
+        let mut upside_down = weather;
+        upside_down.clouds[0].upper_altitude = 800;
+        assert!(upside_down.validate().is_err());
+
+        let mut severe = weather;
+        severe.winds[1].turbulence = 5;
+        assert!(severe.validate().is_err());
+
+        let mut low = weather;
+        low.pressure.pressure = 800 * 16;
+        assert!(low.validate().is_err());
+
+        let mut overflowing = weather;
+        overflowing.cloud_layers = 17;
+        assert!(overflowing.validate().is_err());
+
+        let mut too_many = weather;
+        assert!(too_many.set_clouds(&[CloudLayer::default(); 17]).is_err());
+        assert!(too_many.set_icao("MAD").is_err());
+    }
+
+    #[test]
+    fn should_write_weather_with_command() {
+        let mut handle = MockHandle::new();
+        {
+            let mut session = handle.session();
+            write_weather(&mut session, &weather(), WeatherCommand::SetExact).unwrap();
+            session.process().unwrap();
+        }
+        let written: NewWeather = handle.value(0xC800);
+        assert_eq!(written.command, 0x0C01);
+        assert_eq!(written.winds(), weather().winds());
+
+        let mut invalid = weather();
+        invalid.clouds[0].coverage = 9;
+        let mut session = handle.session();
+        assert!(write_weather(&mut session, &invalid, WeatherCommand::Set).is_err());
+    }
+
+    #[test]
+    fn should_read_station_once_provided() {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0xC400 + TIMESTAMP_FIELD, &41u32);
+        let mut provided = weather();
+        provided.timestamp = 42;
+        handle.schedule_seed(2, 0xC400, &bytes_of(&provided));
+
+        let read = read_station(&mut handle, "lemd", Duration::from_secs(2)).unwrap();
+        assert_eq!(read, provided);
+        assert_eq!(handle.bytes(0xC408, 4), b"LEMD");
+        assert_eq!(handle.transactions(), 3);
+
+        let mut handle = MockHandle::new();
+        let error = read_station(&mut handle, "EGLL", Duration::from_millis(0)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn should_read_metar_once_provided() {
+        let mut handle = MockHandle::new();
+        let metar = b"LEMD 161200Z 36010KT CAVOK 18/05 Q1021\0";
+        handle.schedule_seed(2, 0xB800, metar);
+
+        let read = read_metar(&mut handle, "lemd").unwrap();
+        assert_eq!(read, "LEMD 161200Z 36010KT CAVOK 18/05 Q1021");
+        assert_eq!(handle.writes()[0], (0xB800, b"LEMD\0".to_vec()));
+        assert_eq!(handle.transactions(), 3);
+        assert!(read_metar(&mut handle, "LEMD 1").is_err());
+    }
+
+    fn bytes_of(weather: &NewWeather) -> Vec<u8> {
+        let mut handle = MockHandle::new();
+        handle.seed_value(0, weather);
+        handle.bytes(0, size_of::<NewWeather>())
+    }
+}
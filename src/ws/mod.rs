@@ -0,0 +1,427 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A backend for the WebSocket server of FSUIPC7
+//! FSUIPC7 ships a WebSocket server that exposes the offsets as JSON messages, so MSFS can be
+//! driven over the network with no WideClient. `WsHandle` implements `Handle` over that
+//! protocol: the same code reading and writing offsets through a `UserHandle` runs unchanged
+//! on any machine that can reach the server.
+//!
+//! The requests of a session are declared to the server as groups of unsigned offsets of 4, 2
+//! and 1 bytes, then read or written in the order they were requested. The groups are cached
+//! by the handle, so a session repeating the same requests is declared only once.
+
+mod json;
+mod socket;
+
+use std::collections::HashMap;
+use std::io;
+use std::ptr;
+use std::slice;
+
+use self::json::Value;
+use self::socket::WebSocket;
+use crate::error::FsuipcError;
+use crate::{Handle, Session};
+
+/// The URL the WebSocket server of FSUIPC7 listens to by default.
+pub const DEFAULT_URL: &str = "ws://localhost:2048/fsuipc/";
+
+const PROTOCOL: &str = "fsuipc";
+
+/// A handle to the WebSocket server of FSUIPC7
+pub struct WsHandle {
+    socket: WebSocket,
+    groups: HashMap<Vec<(u32, usize)>, String>,
+}
+
+impl WsHandle {
+    /// Connect to the server on this machine, at `DEFAULT_URL`.
+    pub fn new() -> io::Result<Self> {
+        Self::connect(DEFAULT_URL)
+    }
+
+    /// Connect to the server at the given `ws://` URL, e.g. `ws://192.168.1.10:2048/fsuipc/`.
+    pub fn connect(url: &str) -> io::Result<Self> {
+        let socket = WebSocket::connect(url, PROTOCOL).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidInput => e,
+            _ => io::Error::from(FsuipcError::ConnectionRefused(format!(
+                "no WebSocket server at {}: {}",
+                url, e
+            ))),
+        })?;
+        Ok(WsHandle {
+            socket,
+            groups: HashMap::new(),
+        })
+    }
+
+    fn request(&mut self, command: &str) -> io::Result<Value> {
+        self.socket.send(command)?;
+        let response = json::parse(&self.socket.receive()?)?;
+        if response.get("success").and_then(Value::as_bool) != Some(true) {
+            let message = response
+                .get("errorMessage")
+                .and_then(Value::as_str)
+                .unwrap_or("no error message");
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the WebSocket server rejected the request: {}", message),
+            ));
+        }
+        Ok(response)
+    }
+
+    /// The name of the group declaring the given offsets, declaring it if needed.
+    fn group(&mut self, layout: Vec<(u32, usize)>) -> io::Result<String> {
+        if let Some(name) = self.groups.get(&layout) {
+            return Ok(name.clone());
+        }
+        let name = format!("fsuipc-rs-{}", self.groups.len());
+        let offsets: Vec<String> = layout
+            .iter()
+            .enumerate()
+            .map(|(i, (address, size))| {
+                format!(
+                    r#"{{"name":"o{}","address":{},"type":"uint","size":{}}}"#,
+                    i, address, size
+                )
+            })
+            .collect();
+        self.request(&format!(
+            r#"{{"command":"offsets.declare","name":{},"offsets":[{}]}}"#,
+            json::quote(&name),
+            offsets.join(",")
+        ))?;
+        self.groups.insert(layout, name.clone());
+        Ok(name)
+    }
+}
+
+impl<'a> Handle<'a> for WsHandle {
+    type Sess = WsSession<'a>;
+
+    fn session(&'a mut self) -> WsSession<'a> {
+        WsSession {
+            handle: self,
+            requests: Vec::new(),
+        }
+    }
+}
+
+enum Request {
+    Read {
+        offset: u16,
+        dest: *mut u8,
+        len: usize,
+    },
+    Write {
+        offset: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl Request {
+    fn is_read(&self) -> bool {
+        matches!(self, Request::Read { .. })
+    }
+
+    fn chunks(&self) -> Vec<(u32, usize)> {
+        let (offset, len) = match self {
+            Request::Read { offset, len, .. } => (*offset, *len),
+            Request::Write { offset, data } => (*offset, data.len()),
+        };
+        let mut chunks = Vec::new();
+        let mut done = 0;
+        while done < len {
+            let size = match len - done {
+                1 => 1,
+                2 | 3 => 2,
+                _ => 4,
+            };
+            chunks.push((offset as u32 + done as u32, size));
+            done += size;
+        }
+        chunks
+    }
+}
+
+/// A session over the WebSocket server of FSUIPC7
+pub struct WsSession<'a> {
+    handle: &'a mut WsHandle,
+    requests: Vec<Request>,
+}
+
+impl<'a> WsSession<'a> {
+    fn read_run(&mut self, requests: &[Request]) -> io::Result<()> {
+        let layout: Vec<_> = requests.iter().flat_map(Request::chunks).collect();
+        let name = self.handle.group(layout)?;
+        let response = self.handle.request(&format!(
+            r#"{{"command":"offsets.read","name":{}}}"#,
+            json::quote(&name)
+        ))?;
+        let data = response.get("data");
+        let mut index = 0;
+        for request in requests {
+            if let Request::Read { dest, .. } = request {
+                let mut done = 0;
+                for (_, size) in request.chunks() {
+                    let value = data
+                        .and_then(|data| data.get(&format!("o{}", index)))
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("the WebSocket server sent no value for o{}", index),
+                            )
+                        })?;
+                    let bytes = value.to_le_bytes();
+                    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), dest.add(done), size) };
+                    done += size;
+                    index += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self, requests: &[Request]) -> io::Result<()> {
+        let layout: Vec<_> = requests.iter().flat_map(Request::chunks).collect();
+        let mut values = Vec::new();
+        for request in requests {
+            if let Request::Write { data, .. } = request {
+                let mut done = 0;
+                for (_, size) in request.chunks() {
+                    let mut bytes = [0u8; 8];
+                    bytes[..size].copy_from_slice(&data[done..done + size]);
+                    values.push(u64::from_le_bytes(bytes));
+                    done += size;
+                }
+            }
+        }
+        let name = self.handle.group(layout)?;
+        let values: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!(r#""o{}":{}"#, i, value))
+            .collect();
+        self.handle.request(&format!(
+            r#"{{"command":"offsets.write","name":{},"offsets":{{{}}}}}"#,
+            json::quote(&name),
+            values.join(",")
+        ))?;
+        Ok(())
+    }
+}
+
+impl<'a> Session for WsSession<'a> {
+    fn read_bytes(&mut self, offset: u16, dest: *mut u8, len: usize) -> io::Result<usize> {
+        self.requests.push(Request::Read { offset, dest, len });
+        Ok(len)
+    }
+
+    // As for any other session, the caller guarantees `src` points to `len` readable bytes
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn write_bytes(&mut self, offset: u16, src: *const u8, len: usize) -> io::Result<usize> {
+        let data = unsafe { slice::from_raw_parts(src, len) }.to_vec();
+        self.requests.push(Request::Write { offset, data });
+        Ok(len)
+    }
+
+    fn process(mut self) -> io::Result<usize> {
+        // Consecutive requests of the same kind are exchanged in a single message
+        let requests = std::mem::take(&mut self.requests);
+        let mut processed = 0;
+        let mut start = 0;
+        while start < requests.len() {
+            let is_read = requests[start].is_read();
+            let end = requests[start..]
+                .iter()
+                .position(|request| request.is_read() != is_read)
+                .map_or(requests.len(), |len| start + len);
+            let run = &requests[start..end];
+            if is_read {
+                self.read_run(run)?;
+            } else {
+                self.write_run(run)?;
+            }
+            processed += run
+                .iter()
+                .map(|request| request.chunks().iter().map(|(_, size)| size).sum::<usize>())
+                .sum::<usize>();
+            start = end;
+        }
+        Ok(processed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn read_message(stream: &mut BufReader<TcpStream>) -> Option<String> {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).ok()?;
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).ok()?;
+                u16::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask).ok()?;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).ok()?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        String::from_utf8(payload).ok()
+    }
+
+    fn write_message(stream: &mut TcpStream, text: &str) {
+        let mut frame = vec![0x81];
+        if text.len() < 126 {
+            frame.push(text.len() as u8);
+        } else {
+            frame.push(126);
+            frame.extend_from_slice(&(text.len() as u16).to_be_bytes());
+        }
+        frame.extend_from_slice(text.as_bytes());
+        stream.write_all(&frame).unwrap();
+    }
+
+    // A fake server keeping the offsets in memory, logging the commands it receives
+    fn serve(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut key = String::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Sec-WebSocket-Key:") {
+                key = value.trim().to_string();
+            }
+        }
+        write!(
+            stream.get_mut(),
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: fsuipc\r\n\r\n",
+            socket::accept_key(&key)
+        )
+        .unwrap();
+
+        let mut memory = vec![0u8; 0x10000];
+        let mut groups: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        let mut commands = Vec::new();
+        while let Some(message) = read_message(&mut stream) {
+            let request = json::parse(&message).unwrap();
+            let command = request
+                .get("command")
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string();
+            let name = request.get("name").unwrap().as_str().unwrap().to_string();
+            let mut data = Vec::new();
+            match (command.as_str(), request.get("offsets")) {
+                ("offsets.declare", Some(Value::Array(offsets))) => {
+                    let layout = offsets
+                        .iter()
+                        .map(|o| {
+                            let address = o.get("address").unwrap().as_u64().unwrap() as usize;
+                            (address, o.get("size").unwrap().as_u64().unwrap() as usize)
+                        })
+                        .collect();
+                    groups.insert(name.clone(), layout);
+                }
+                ("offsets.read", _) => {
+                    for (i, (address, size)) in groups[&name].iter().enumerate() {
+                        let mut bytes = [0u8; 8];
+                        bytes[..*size].copy_from_slice(&memory[*address..address + size]);
+                        data.push(format!(r#""o{}":{}"#, i, u64::from_le_bytes(bytes)));
+                    }
+                }
+                ("offsets.write", Some(offsets)) => {
+                    for (i, (address, size)) in groups[&name].iter().enumerate() {
+                        let value = offsets.get(&format!("o{}", i)).unwrap().as_u64().unwrap();
+                        memory[*address..address + size]
+                            .copy_from_slice(&value.to_le_bytes()[..*size]);
+                    }
+                }
+                _ => panic!("unexpected command {}", message),
+            }
+            write_message(
+                stream.get_mut(),
+                &format!(
+                    r#"{{"command":"{}","name":"{}","success":true,"data":{{{}}}}}"#,
+                    command,
+                    name,
+                    data.join(",")
+                ),
+            );
+            commands.push(command);
+        }
+        commands
+    }
+
+    #[test]
+    fn should_read_back_written_offsets() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/fsuipc/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve(listener));
+
+        let mut handle = WsHandle::connect(&url).unwrap();
+        let mut altitude = 0i64;
+        let mut title = [0u8; 7];
+        let mut qnh = 0u16;
+        for _ in 0..2 {
+            let mut session = handle.session();
+            session.write(0x0570, &-1234567890123i64).unwrap();
+            session.write_bytes(0x3D00, b"C172 SP".as_ptr(), 7).unwrap();
+            session.write(0x0330, &16211u16).unwrap();
+            session.read(0x0570, &mut altitude).unwrap();
+            session.read_bytes(0x3D00, title.as_mut_ptr(), 7).unwrap();
+            session.read(0x0330, &mut qnh).unwrap();
+            assert_eq!(session.process().unwrap(), 34);
+        }
+        drop(handle);
+
+        assert_eq!(altitude, -1234567890123);
+        assert_eq!(&title, b"C172 SP");
+        assert_eq!(qnh, 16211);
+        // The reads and the writes share the same offsets, so a single group is declared
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "offsets.declare",
+                "offsets.write",
+                "offsets.read",
+                "offsets.write",
+                "offsets.read"
+            ]
+        );
+    }
+
+    #[test]
+    fn should_fail_to_connect_with_no_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/fsuipc/", listener.local_addr().unwrap());
+        drop(listener);
+        let error = WsHandle::connect(&url).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,174 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The XGPS protocol of sim-to-EFB bridges
+//! Bridges such as FS2FF feed EFB apps (ForeFlight, Garmin Pilot, FltPlan Go...) with the
+//! `XGPS`, `XATT` and `XTRAFFIC` text packets of the ForeFlight simulator protocol, broadcast
+//! over UDP to port 49002. `XgpsBroadcaster` sends the same packets from the state read from
+//! FSUIPC. The apps expect the position once per second and the attitude several times per
+//! second.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use super::gdl90::{FlightState, Traffic};
+use super::geo::{FEET_PER_METRE, KNOTS_PER_METRE_PER_SECOND};
+
+/// The UDP port EFB apps listen to for XGPS packets.
+pub const XGPS_PORT: u16 = 49002;
+
+/// A broadcaster of XGPS packets over UDP
+pub struct XgpsBroadcaster {
+    socket: UdpSocket,
+    target: SocketAddr,
+    name: String,
+}
+
+impl XgpsBroadcaster {
+    /// Send the packets to `target`, which may be a broadcast address such as
+    /// `255.255.255.255:49002`, naming the simulator `name` in the apps.
+    pub fn new<A: ToSocketAddrs>(target: A, name: &str) -> io::Result<Self> {
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to send XGPS to")
+        })?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_broadcast(true)?;
+        Ok(XgpsBroadcaster {
+            socket,
+            target,
+            name: name.replace(',', " "),
+        })
+    }
+
+    /// Send the position of the own aircraft.
+    pub fn send_position(&self, state: &FlightState) -> io::Result<()> {
+        self.send(&xgps(&self.name, &state.report(0)))
+    }
+
+    /// Send the attitude of the own aircraft.
+    pub fn send_attitude(&self, state: &FlightState) -> io::Result<()> {
+        let heading = state.report(0).track;
+        self.send(&xatt(&self.name, heading, state.pitch(), state.roll()))
+    }
+
+    /// Send the given traffic.
+    pub fn send_traffic(&self, traffic: &[Traffic]) -> io::Result<()> {
+        for report in traffic {
+            self.send(&xtraffic(&self.name, report))?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, packet: &str) -> io::Result<()> {
+        self.socket.send_to(packet.as_bytes(), self.target)?;
+        Ok(())
+    }
+}
+
+/// Format the `XGPS` packet of the given position.
+pub fn xgps(name: &str, report: &Traffic) -> String {
+    format!(
+        "XGPS{},{:.6},{:.6},{:.1},{:.2},{:.1}",
+        name,
+        report.longitude,
+        report.latitude,
+        report.altitude / FEET_PER_METRE,
+        report.track.rem_euclid(360.0),
+        report.ground_speed / KNOTS_PER_METRE_PER_SECOND,
+    )
+}
+
+/// Format the `XATT` packet of the given attitude, in degrees.
+pub fn xatt(name: &str, heading: f64, pitch: f64, roll: f64) -> String {
+    format!(
+        "XATT{},{:.1},{:.1},{:.1}",
+        name,
+        heading.rem_euclid(360.0),
+        pitch,
+        roll
+    )
+}
+
+/// Format the `XTRAFFIC` packet of the given aircraft.
+pub fn xtraffic(name: &str, report: &Traffic) -> String {
+    let callsign: String = report
+        .callsign
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    format!(
+        "XTRAFFIC{},{},{:.6},{:.6},{:.0},{:.0},{},{:.1},{:.1},{}",
+        name,
+        report.address,
+        report.latitude,
+        report.longitude,
+        report.altitude,
+        report.vertical_speed,
+        if report.airborne { 1 } else { 0 },
+        report.track.rem_euclid(360.0),
+        report.ground_speed,
+        callsign,
+    )
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn report() -> Traffic {
+        Traffic {
+            address: 0xABCDEF,
+            latitude: 40.4719,
+            longitude: -3.5626,
+            altitude: 3280.84,
+            ground_speed: 194.3844,
+            vertical_speed: -500.0,
+            track: 360.0 + 45.0,
+            callsign: "IBE123".to_string(),
+            airborne: true,
+        }
+    }
+
+    #[test]
+    fn should_format_packets() {
+        assert_eq!(
+            xgps("MSFS", &report()),
+            "XGPSMSFS,-3.562600,40.471900,1000.0,45.00,100.0"
+        );
+        assert_eq!(xatt("MSFS", -90.0, 2.5, -10.0), "XATTMSFS,270.0,2.5,-10.0");
+        assert_eq!(
+            xtraffic("MSFS", &report()),
+            "XTRAFFICMSFS,11259375,40.471900,-3.562600,3281,-500,1,45.0,194.4,IBE123"
+        );
+    }
+
+    #[test]
+    fn should_broadcast_packets() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let broadcaster = XgpsBroadcaster::new(receiver.local_addr().unwrap(), "Sim, Inc").unwrap();
+        broadcaster.send_attitude(&FlightState::default()).unwrap();
+        let mut buffer = [0; 128];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"XATTSim  Inc,0.0,0.0,0.0");
+    }
+
+    #[test]
+    fn should_send_right_bank_as_positive_roll() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let broadcaster = XgpsBroadcaster::new(receiver.local_addr().unwrap(), "MSFS").unwrap();
+        let state = FlightState {
+            bank: -(1 << 26),
+            ..FlightState::default()
+        };
+        broadcaster.send_attitude(&state).unwrap();
+        let mut buffer = [0; 128];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"XATTMSFS,0.0,0.0,5.6");
+    }
+}
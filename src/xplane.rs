@@ -0,0 +1,430 @@
+//
+// FSUIPC library
+// Copyright (c) 2015 Alvaro Polo
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An emulation of the UDP output of X-Plane
+//! Hardware and apps built for X-Plane read its `DATA` packets or subscribe to datarefs with
+//! `RREF` requests. `XPlaneOutput` answers both from the state read from FSUIPC, so they work
+//! with the simulators FSUIPC supports. Only the data groups and datarefs listed in
+//! `DATA_GROUPS` and `DATAREFS` are emulated.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::geo::{
+    self, Position, RawPosition, FEET_PER_METRE, KNOTS_PER_METRE_PER_SECOND, POSITION,
+};
+use super::offsets::{self, FsuipcStruct};
+use super::Session;
+
+/// The UDP port X-Plane receives requests on.
+pub const XPLANE_PORT: u16 = 49000;
+
+/// The data groups of `DATA` packets that are emulated.
+pub const DATA_GROUPS: [u32; 4] = [3, 4, 17, 20];
+
+/// The datarefs that can be subscribed to with `RREF` requests.
+pub const DATAREFS: [&str; 13] = [
+    "sim/flightmodel/position/latitude",
+    "sim/flightmodel/position/longitude",
+    "sim/flightmodel/position/elevation",
+    "sim/flightmodel/position/y_agl",
+    "sim/flightmodel/position/theta",
+    "sim/flightmodel/position/phi",
+    "sim/flightmodel/position/psi",
+    "sim/flightmodel/position/mag_psi",
+    "sim/flightmodel/position/indicated_airspeed",
+    "sim/flightmodel/position/true_airspeed",
+    "sim/flightmodel/position/groundspeed",
+    "sim/flightmodel/position/vh_ind_fpm",
+    "sim/flightmodel/forces/g_nrml",
+];
+
+const UNUSED: f32 = -999.0;
+const DATAREF_LEN: usize = 400;
+
+/// The state of the aircraft sent as X-Plane output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimData {
+    pub position: RawPosition,
+    pub ground_altitude: i32,
+    pub pitch: i32,
+    pub bank: i32,
+    pub heading: u32,
+    pub magnetic_variation: i16,
+    pub indicated_airspeed: u32,
+    pub true_airspeed: u32,
+    pub ground_speed: u32,
+    pub vertical_speed: i32,
+    pub g_force: i16,
+    pub on_ground: u16,
+}
+
+impl SimData {
+    /// The value of the given dataref, in the units of X-Plane.
+    pub fn dataref(&self, name: &str) -> Option<f32> {
+        let position = Position::from(self.position);
+        let value = match name {
+            "sim/flightmodel/position/latitude" => position.latitude,
+            "sim/flightmodel/position/longitude" => position.longitude,
+            "sim/flightmodel/position/elevation" => position.altitude,
+            "sim/flightmodel/position/y_agl" => self.height(),
+            "sim/flightmodel/position/theta" => self.pitch_degrees(),
+            "sim/flightmodel/position/phi" => self.roll_degrees(),
+            "sim/flightmodel/position/psi" => self.true_heading(),
+            "sim/flightmodel/position/mag_psi" => self.magnetic_heading(),
+            "sim/flightmodel/position/indicated_airspeed" => self.indicated_airspeed as f64 / 128.0,
+            "sim/flightmodel/position/true_airspeed" => {
+                self.true_airspeed as f64 / 128.0 / KNOTS_PER_METRE_PER_SECOND
+            }
+            "sim/flightmodel/position/groundspeed" => self.ground_speed as f64 / 65536.0,
+            "sim/flightmodel/position/vh_ind_fpm" => self.vertical_speed_fpm(),
+            "sim/flightmodel/forces/g_nrml" => self.g_force as f64 / 625.0,
+            _ => return None,
+        };
+        Some(value as f32)
+    }
+
+    /// The values of the given data group, or `None` if it is not emulated.
+    pub fn data_group(&self, index: u32) -> Option<[f32; 8]> {
+        let position = Position::from(self.position);
+        let tas = self.true_airspeed as f64 / 128.0;
+        let gs = self.ground_speed as f64 / 65536.0 * KNOTS_PER_METRE_PER_SECOND;
+        let values = match index {
+            // Speeds
+            3 => [
+                self.indicated_airspeed as f64 / 128.0,
+                UNUSED as f64,
+                tas,
+                gs,
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+            ],
+            // Mach, VVI, g-load
+            4 => [
+                UNUSED as f64,
+                UNUSED as f64,
+                self.vertical_speed_fpm(),
+                UNUSED as f64,
+                self.g_force as f64 / 625.0,
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+            ],
+            // Pitch, roll, headings
+            17 => [
+                self.pitch_degrees(),
+                self.roll_degrees(),
+                self.true_heading(),
+                self.magnetic_heading(),
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+            ],
+            // Latitude, longitude, altitude
+            20 => [
+                position.latitude,
+                position.longitude,
+                position.altitude_feet(),
+                self.height() * FEET_PER_METRE,
+                if self.on_ground != 0 { 1.0 } else { 0.0 },
+                UNUSED as f64,
+                UNUSED as f64,
+                UNUSED as f64,
+            ],
+            _ => return None,
+        };
+        let mut group = [0.0; 8];
+        for (value, result) in values.iter().zip(group.iter_mut()) {
+            *result = *value as f32;
+        }
+        Some(group)
+    }
+
+    fn height(&self) -> f64 {
+        Position::from(self.position).altitude - self.ground_altitude as f64 / 256.0
+    }
+
+    fn pitch_degrees(&self) -> f64 {
+        geo::pitch_degrees(self.pitch)
+    }
+
+    fn roll_degrees(&self) -> f64 {
+        geo::bank_degrees(self.bank)
+    }
+
+    fn true_heading(&self) -> f64 {
+        geo::heading_degrees(self.heading)
+    }
+
+    fn magnetic_heading(&self) -> f64 {
+        let variation = self.magnetic_variation as f64 * 360.0 / 65536.0;
+        (self.true_heading() - variation).rem_euclid(360.0)
+    }
+
+    fn vertical_speed_fpm(&self) -> f64 {
+        self.vertical_speed as f64 / 256.0 * FEET_PER_METRE * 60.0
+    }
+}
+
+impl FsuipcStruct for SimData {
+    fn register_reads<S: Session>(&mut self, session: &mut S) -> io::Result<()> {
+        session.read_offset(POSITION, &mut self.position)?;
+        session.read_offset(offsets::GROUND_ALTITUDE, &mut self.ground_altitude)?;
+        session.read_offset(offsets::PITCH, &mut self.pitch)?;
+        session.read_offset(offsets::BANK, &mut self.bank)?;
+        session.read_offset(offsets::HEADING, &mut self.heading)?;
+        session.read_offset(offsets::MAGNETIC_VARIATION, &mut self.magnetic_variation)?;
+        session.read_offset(offsets::INDICATED_AIRSPEED, &mut self.indicated_airspeed)?;
+        session.read_offset(offsets::TRUE_AIRSPEED, &mut self.true_airspeed)?;
+        session.read_offset(offsets::GROUND_SPEED, &mut self.ground_speed)?;
+        session.read_offset(offsets::VERTICAL_SPEED, &mut self.vertical_speed)?;
+        session.read_offset(offsets::G_FORCE, &mut self.g_force)?;
+        session.read_offset(offsets::ON_GROUND, &mut self.on_ground)?;
+        Ok(())
+    }
+
+    fn register_writes<S: Session>(&self, _session: &mut S) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Build a `DATA` packet with the given data groups, skipping those not emulated.
+pub fn data_packet(data: &SimData, groups: &[u32]) -> Vec<u8> {
+    let mut packet = b"DATA\0".to_vec();
+    for &index in groups {
+        if let Some(values) = data.data_group(index) {
+            packet.extend_from_slice(&index.to_le_bytes());
+            for value in values.iter() {
+                packet.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    packet
+}
+
+/// A subscription to a dataref, as requested with `RREF`
+#[derive(Clone, Debug, PartialEq)]
+struct Subscription {
+    client: SocketAddr,
+    index: i32,
+    dataref: String,
+    period: Duration,
+    next: Instant,
+}
+
+/// An emulation of the UDP output of X-Plane
+/// It sends `DATA` packets to the configured targets, and answers the `RREF` subscriptions of
+/// the clients, every time `update()` is called.
+pub struct XPlaneOutput {
+    socket: UdpSocket,
+    targets: Vec<(SocketAddr, Vec<u32>)>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl XPlaneOutput {
+    /// Receive `RREF` requests at the given address (port `XPLANE_PORT` for clients that
+    /// expect X-Plane defaults).
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(XPlaneOutput {
+            socket,
+            targets: Vec::new(),
+            subscriptions: Vec::new(),
+        })
+    }
+
+    /// The address `RREF` requests are received at.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Send the given data groups to `target` on every update.
+    pub fn send_data<A: ToSocketAddrs>(&mut self, target: A, groups: &[u32]) -> io::Result<()> {
+        for target in target.to_socket_addrs()? {
+            self.targets.push((target, groups.to_vec()));
+        }
+        Ok(())
+    }
+
+    /// The number of active `RREF` subscriptions.
+    pub fn subscriptions(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    /// Process the pending requests, then send the `DATA` packets and the due subscriptions.
+    pub fn update(&mut self, data: &SimData) -> io::Result<()> {
+        let now = Instant::now();
+        self.receive_requests(now)?;
+        for (target, groups) in self.targets.iter() {
+            self.socket.send_to(&data_packet(data, groups), target)?;
+        }
+        let mut packets: Vec<(SocketAddr, Vec<u8>)> = Vec::new();
+        for subscription in self.subscriptions.iter_mut() {
+            if subscription.next > now {
+                continue;
+            }
+            subscription.next = now + subscription.period;
+            let value = data.dataref(&subscription.dataref).unwrap_or(0.0);
+            let packet = match packets.iter_mut().find(|(c, _)| *c == subscription.client) {
+                Some((_, packet)) => packet,
+                None => {
+                    packets.push((subscription.client, b"RREF,".to_vec()));
+                    &mut packets.last_mut().unwrap().1
+                }
+            };
+            packet.extend_from_slice(&subscription.index.to_le_bytes());
+            packet.extend_from_slice(&value.to_le_bytes());
+        }
+        for (client, packet) in packets {
+            self.socket.send_to(&packet, client)?;
+        }
+        Ok(())
+    }
+
+    fn receive_requests(&mut self, now: Instant) -> io::Result<()> {
+        let mut buffer = [0; 1024];
+        loop {
+            let (len, client) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // A client gone away (e.g. ICMP port unreachable) is not our problem.
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some((frequency, index, dataref)) = parse_rref(&buffer[..len]) {
+                self.subscriptions
+                    .retain(|s| s.client != client || s.index != index);
+                if frequency > 0 && DATAREFS.contains(&dataref.as_str()) {
+                    self.subscriptions.push(Subscription {
+                        client,
+                        index,
+                        dataref,
+                        period: Duration::from_secs(1) / frequency as u32,
+                        next: now,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Parse a `RREF` request into its frequency, index and dataref.
+fn parse_rref(request: &[u8]) -> Option<(i32, i32, String)> {
+    if request.len() < 13 || &request[..5] != b"RREF\0" {
+        return None;
+    }
+    let frequency = i32::from_le_bytes([request[5], request[6], request[7], request[8]]);
+    let index = i32::from_le_bytes([request[9], request[10], request[11], request[12]]);
+    let path = &request[13..request.len().min(13 + DATAREF_LEN)];
+    let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+    let dataref = String::from_utf8_lossy(&path[..end]).into_owned();
+    Some((frequency, index, dataref))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn sim_data() -> SimData {
+        SimData {
+            position: Position {
+                latitude: 40.4719,
+                longitude: -3.5626,
+                altitude: 1000.0,
+            }
+            .to_raw(),
+            ground_altitude: 600 * 256,
+            pitch: -(5 << 24),
+            bank: -(3 << 24),
+            heading: 1 << 30,
+            magnetic_variation: (-2 * 65536 / 360) as i16,
+            indicated_airspeed: 250 * 128,
+            g_force: 625,
+            ..SimData::default()
+        }
+    }
+
+    fn rref(frequency: i32, index: i32, dataref: &str) -> Vec<u8> {
+        let mut request = b"RREF\0".to_vec();
+        request.extend_from_slice(&frequency.to_le_bytes());
+        request.extend_from_slice(&index.to_le_bytes());
+        request.extend_from_slice(dataref.as_bytes());
+        request.resize(13 + DATAREF_LEN, 0);
+        request
+    }
+
+    #[test]
+    fn should_convert_datarefs() {
+        let data = sim_data();
+        assert_eq!(data.dataref("sim/flightmodel/position/y_agl"), Some(400.0));
+        assert_eq!(data.dataref("sim/flightmodel/position/psi"), Some(90.0));
+        let mag_psi = data.dataref("sim/flightmodel/position/mag_psi").unwrap();
+        assert!((mag_psi - 92.0).abs() < 0.01);
+        let theta = data.dataref("sim/flightmodel/position/theta").unwrap();
+        assert!((theta - 5.0 * 360.0 / 256.0).abs() < 0.01);
+        assert_eq!(data.dataref("sim/flightmodel/forces/g_nrml"), Some(1.0));
+        assert_eq!(data.dataref("sim/unknown"), None);
+    }
+
+    #[test]
+    fn should_convert_right_bank_to_positive_roll() {
+        let phi = sim_data().dataref("sim/flightmodel/position/phi").unwrap();
+        assert!((phi - 3.0 * 360.0 / 256.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn should_build_data_packet() {
+        let packet = data_packet(&sim_data(), &[3, 99]);
+        assert_eq!(packet.len(), 5 + 36);
+        assert_eq!(&packet[..9], b"DATA\0\x03\0\0\0");
+        assert_eq!(&packet[9..13], &250.0f32.to_le_bytes());
+        assert_eq!(&packet[13..17], &UNUSED.to_le_bytes());
+    }
+
+    #[test]
+    fn should_answer_rref_subscriptions() {
+        let mut output = XPlaneOutput::bind("127.0.0.1:0").unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = output.local_addr().unwrap();
+        client
+            .send_to(
+                &rref(10, 7, "sim/flightmodel/position/indicated_airspeed"),
+                server,
+            )
+            .unwrap();
+        client
+            .send_to(&rref(10, 8, "sim/unknown/dataref"), server)
+            .unwrap();
+        // Give the requests time to arrive.
+        std::thread::sleep(Duration::from_millis(50));
+        output.update(&sim_data()).unwrap();
+        assert_eq!(output.subscriptions(), 1);
+        let mut buffer = [0; 64];
+        let len = client.recv(&mut buffer).unwrap();
+        let mut expected = b"RREF,".to_vec();
+        expected.extend_from_slice(&7i32.to_le_bytes());
+        expected.extend_from_slice(&250.0f32.to_le_bytes());
+        assert_eq!(&buffer[..len], &expected[..]);
+
+        client
+            .send_to(
+                &rref(0, 7, "sim/flightmodel/position/indicated_airspeed"),
+                server,
+            )
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        output.update(&sim_data()).unwrap();
+        assert_eq!(output.subscriptions(), 0);
+    }
+}